@@ -0,0 +1,180 @@
+use af;
+use af::{Array, DType, HasAfEnum};
+use num::Zero;
+use std::cmp::{min, Ordering};
+
+use utils;
+use data::ArraySource;
+use device::Device;
+use error::HALError;
+use model::{Model, Sequential};
+
+/// Helper that scores the training range of the pool in chunks of batch_size samples
+///
+/// `score` maps the model & an input batch to the [batch, 1] scores of the batch.
+fn score_pool<T, F>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                    , batch_size: u64, mut score: F) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone, F: FnMut(&mut Sequential, &Array) -> Array
+{
+  assert!(batch_size > 0, "need a positive batch size");
+  let num_pool = pool.params.num_train;
+  let mut scores = Vec::with_capacity(num_pool as usize);
+  let mut first = 0;
+  while first < num_pool {
+    let last = min(first + batch_size, num_pool) - 1;
+    model.get_manager().swap_device(src_device);
+    let input = af::rows(&pool.input, first, last);
+    let batch_scores = score(model, &input);
+    scores.extend(utils::array_to_vec(&batch_scores).iter().map(|&s| s as f32));
+    first = last + 1;
+  }
+  scores
+}
+
+/// Helper that returns the f32 class probabilities of the last time-step
+fn last_probabilities<T>(model: &mut Sequential, input: &Array, src_device: Device) -> Array
+  where T: HasAfEnum + Zero + Clone
+{
+  let device = model.get_device();
+  let mut probabilities = model.predict_proba::<T>(input, src_device, device);
+  utils::cast(&probabilities.pop().unwrap(), DType::F32)
+}
+
+/// Scores every sample of the pool by the entropy of its predicted class distribution
+///
+/// The targets of the pool are ignored [the pool is unlabeled], only the
+/// predictions of the last time-step are scored.
+pub fn entropy_scores<T>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                         , batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  score_pool::<T, _>(model, pool, src_device, batch_size, |model, input| {
+    let p = last_probabilities::<T>(model, input, src_device);
+    let log_p = af::log(&utils::clip_by_value(&p, 1e-12, 1.0));
+    af::mul(&af::sum(&af::mul(&p, &log_p, false), 1), &-1.0f32, false)
+  })
+}
+
+/// Scores every sample of the pool by 1 - (p_1 - p_2), the margin between its two most probable classes
+pub fn margin_scores<T>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                        , batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  score_pool::<T, _>(model, pool, src_device, batch_size, |model, input| {
+    let p = last_probabilities::<T>(model, input, src_device);
+    assert!(p.dims()[1] > 1, "the margin needs at least two classes");
+    let sorted = af::sort(&p, 1, false);
+    af::sub(&1.0f32, &af::sub(&af::col(&sorted, 0), &af::col(&sorted, 1), false), false)
+  })
+}
+
+/// Scores every sample of the pool by the variance of its predicted probabilities
+/// under Monte-Carlo dropout [summed over the classes, see `Sequential::set_mc_dropout`]
+///
+/// The model needs dropout layers, MC dropout is disabled again afterwards.
+///
+/// # Parameters
+///
+/// - `mc_samples` is the number of stochastic forward passes per sample
+pub fn mc_dropout_scores<T>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                            , mc_samples: u64, batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  assert!(mc_samples > 1, "the variance needs at least two forward passes");
+  model.set_mc_dropout(true);
+  let scores = score_pool::<T, _>(model, pool, src_device, batch_size, |model, input| {
+    let p = last_probabilities::<T>(model, input, src_device);
+    let (mut sum, mut sum_sq) = (p.clone(), af::mul(&p, &p, false));
+    for _ in 1..mc_samples {
+      let p = last_probabilities::<T>(model, input, src_device);
+      sum = af::add(&sum, &p, false);
+      sum_sq = af::add(&sum_sq, &af::mul(&p, &p, false), false);
+    }
+    let mean = af::div(&sum, &(mc_samples as f32), false);
+    let variance = af::sub(&af::div(&sum_sq, &(mc_samples as f32), false)
+                           , &af::mul(&mean, &mean, false), false);
+    af::sum(&variance, 1)
+  });
+  model.set_mc_dropout(false);
+  scores
+}
+
+/// Scores the pool with the provided uncertainty strategy
+///
+/// # Parameters
+///
+/// - `strategy` is one of "entropy", "margin" or "mc_dropout"
+/// - `mc_samples` is the number of forward passes of "mc_dropout" [ignored otherwise]
+pub fn uncertainty_scores<T>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                             , strategy: &str, mc_samples: u64
+                             , batch_size: u64) -> Result<Vec<f32>, HALError>
+  where T: HasAfEnum + Zero + Clone
+{
+  match strategy {
+    "entropy"    => Ok(entropy_scores::<T>(model, pool, src_device, batch_size)),
+    "margin"     => Ok(margin_scores::<T>(model, pool, src_device, batch_size)),
+    "mc_dropout" => Ok(mc_dropout_scores::<T>(model, pool, src_device, mc_samples, batch_size)),
+    _            => Err(HALError::CONFIG),
+  }
+}
+
+/// Returns the indices of the `num_queries` most uncertain samples of the pool [most uncertain first]
+///
+/// Ties are broken by keeping the earlier sample.
+///
+/// eg: `let idx = active::query::<f32>(&mut model, &pool, device, "entropy", 0, 10, 64)?;`
+/// then label `pool.get_rows(&idx)` & train on them [see `learn`]
+pub fn query<T>(model: &mut Sequential, pool: &ArraySource, src_device: Device
+                , strategy: &str, mc_samples: u64, num_queries: usize
+                , batch_size: u64) -> Result<Vec<u32>, HALError>
+  where T: HasAfEnum + Zero + Clone
+{
+  let scores = try!(uncertainty_scores::<T>(model, pool, src_device, strategy, mc_samples, batch_size));
+  let mut order: Vec<usize> = (0..scores.len()).collect();
+  order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal));
+  order.truncate(num_queries);
+  Ok(order.iter().map(|&i| i as u32).collect())
+}
+
+/// Trains the model on the newly labeled samples of the pool [one `partial_fit` per batch]
+///
+/// # Parameters
+///
+/// - `indices` are the queried samples [see `query`]
+/// - `labels` are their targets [num_queries, output, time], on `src_device`
+///
+/// # Return Values
+///
+/// The mean loss of every optimization step
+pub fn learn<E>(model: &mut Sequential, pool: &ArraySource, indices: &Vec<u32>, labels: &Array
+                , src_device: Device, batch_size: u64) -> Vec<f32>
+  where E: HasAfEnum + Zero + Clone
+{
+  assert!(labels.dims()[0] == indices.len() as u64, "need a label per queried sample");
+  assert!(batch_size > 0, "need a positive batch size");
+  model.get_manager().swap_device(src_device);
+  let input = *pool.get_rows(indices).input.into_inner();
+  let num_labeled = indices.len() as u64;
+  let mut losses = Vec::new();
+  let mut first = 0;
+  while first < num_labeled {
+    let last = min(first + batch_size, num_labeled) - 1;
+    model.get_manager().swap_device(src_device);
+    let (batch_input, batch_target) = (af::rows(&input, first, last), af::rows(labels, first, last));
+    let loss = model.partial_fit::<E>(&batch_input, &batch_target, src_device, None, None);
+    losses.push(loss.iter().sum::<f32>() / loss.len() as f32);
+    first = last + 1;
+  }
+  losses
+}
+
+/// Returns the pool without the queried samples [the held out samples are kept, see `ArraySource::subset`]
+pub fn remove(pool: &ArraySource, indices: &Vec<u32>, batch_size: u64) -> ArraySource {
+  let mut queried = vec![false; pool.params.num_train as usize];
+  for &i in indices {
+    queried[i as usize] = true;
+  }
+  let remaining: Vec<u32> = (0..pool.params.num_train as u32)
+    .filter(|&i| !queried[i as usize]).collect();
+  pool.subset(&remaining, batch_size)
+}
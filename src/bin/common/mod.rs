@@ -0,0 +1,154 @@
+//! The config driven training shared by hal-train & `hal train`
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+use rustc_serialize::Decodable;
+use rustc_serialize::json;
+use af;
+use af::Backend;
+
+use hal::Model;
+use hal::utils;
+use hal::metrics;
+use hal::config;
+use hal::hub;
+use hal::config::ModelConfig;
+use hal::data::{ArraySource, read_csv_array, read_idx, read_npy};
+use hal::device::{DeviceManagerFactory, Device};
+
+/// The inputs & targets files ["csv", "idx" or "npy"], the split & the preprocessing
+#[derive(RustcDecodable, Clone, Debug)]
+struct DataConfig {
+  format: String,
+  inputs: String,
+  targets: String,
+  has_header: Option<bool>,
+  one_hot: Option<u64>,
+  test_fraction: Option<f32>,
+  validation_fraction: Option<f32>,
+  shuffle: Option<bool>,
+}
+
+#[derive(RustcDecodable, Clone, Debug)]
+struct TrainingConfig {
+  epochs: u64,
+  batch_size: u64,
+  backend: Option<String>,
+  device_id: Option<i32>,
+  metrics: Option<Vec<String>>,
+  checkpoint_dir: Option<String>,
+  metrics_file: Option<String>,
+  model_file: Option<String>,
+  verbose: Option<bool>,
+}
+
+/// The parsed sections of the config file [see `hal::config::ModelConfig` for the model]
+pub struct Config {
+  model: ModelConfig,
+  data: DataConfig,
+  training: TrainingConfig,
+}
+
+/// Helper to print the error and exit
+pub fn fail(message: &str) -> ! {
+  let program = env::args().next().and_then(|p| Path::new(&p).file_name()
+                                            .map(|f| f.to_string_lossy().into_owned()))
+    .unwrap_or("hal".to_string());
+  writeln!(&mut ::std::io::stderr(), "{}: {}", program, message).unwrap();
+  process::exit(1);
+}
+
+pub fn read_config(path: &str) -> Config {
+  let mut contents = String::new();
+  File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+    .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", path, e)));
+
+  let tree = match path.ends_with(".json") {
+    true  => config::parse_json(&contents),
+    false => config::parse_toml(&contents),
+  }.unwrap_or_else(|e| fail(&format!("unable to parse {}: {}", path, e)));
+  let section = |name: &str| tree.find(name).cloned()
+    .unwrap_or_else(|| fail(&format!("missing [{}] section", name)));
+
+  Config {
+    model: ModelConfig::from_value(&section("model"))
+      .unwrap_or_else(|e| fail(&format!("invalid [model] section: {}", e))),
+    data: Decodable::decode(&mut json::Decoder::new(section("data")))
+      .unwrap_or_else(|e| fail(&format!("invalid [data] section: {}", e))),
+    training: Decodable::decode(&mut json::Decoder::new(section("training")))
+      .unwrap_or_else(|e| fail(&format!("invalid [training] section: {}", e))),
+  }
+}
+
+pub fn get_backend(name: &str) -> Backend {
+  match name.to_lowercase().as_str() {
+    "cpu"     => Backend::CPU,
+    "cuda"    => Backend::CUDA,
+    "opencl"  => Backend::OPENCL,
+    "default" => Backend::DEFAULT,
+    _         => fail(&format!("unknown backend {}", name)),
+  }
+}
+
+/// Reads a data file in the "csv", "idx" or "npy" format
+pub fn read_format(path: &str, format: &str, has_header: bool) -> af::Array {
+  match format.to_lowercase().as_str() {
+    "csv" => read_csv_array(path, has_header),
+    "idx" => read_idx(path),
+    "npy" => read_npy(path),
+    _     => fail(&format!("unknown data format {}", format)),
+  }.unwrap_or_else(|e| fail(&format!("unable to load {}: {}", path, e)))
+}
+
+fn load_data(config: &DataConfig, batch_size: u64) -> ArraySource {
+  let read = |path: &str| read_format(path, &config.format, config.has_header.unwrap_or(false));
+  let input = read(&config.inputs);
+  let target = match config.one_hot {
+    Some(num_classes) => utils::one_hot(&read(&config.targets), num_classes),
+    None              => read(&config.targets),
+  };
+  ArraySource::new(input, target, batch_size
+                   , config.test_fraction.unwrap_or(0.0)
+                   , config.validation_fraction.unwrap_or(0.0)
+                   , config.shuffle.unwrap_or(false))
+}
+
+/// Trains the model of the config & writes the metrics & model files of the [training] section
+pub fn train(config: &Config) {
+  let training = &config.training;
+  let manager = DeviceManagerFactory::new();
+  let device = Device{ backend: get_backend(training.backend.as_ref().map_or("default", |b| b.as_str()))
+                       , id: training.device_id.unwrap_or(0) };
+  let cpu_device = Device{ backend: Backend::CPU, id: 0 };
+  let mut model = config.model.build(manager.clone(), device)
+    .unwrap_or_else(|e| fail(&format!("unable to build the model: {}", e)));
+  for name in training.metrics.clone().unwrap_or(Vec::new()) {
+    model.add_metric(metrics::get_metric(&name).unwrap_or_else(|_| fail(&format!("unknown metric {}", name))));
+  }
+  if let Some(ref dir) = training.checkpoint_dir {
+    model.set_checkpoint_dir(dir).unwrap_or_else(|e| fail(&format!("{}: {}", dir, e)));
+  }
+  model.info();
+
+  // the data is kept in host memory, batches are moved to the model device
+  manager.swap_device(cpu_device);
+  let source = load_data(&config.data, training.batch_size);
+  let loss = model.fit::<ArraySource, f32>(&source, cpu_device
+                                           , training.epochs, training.batch_size
+                                           , None, None, training.verbose.unwrap_or(false));
+
+  if let Some(ref path) = training.metrics_file {
+    let mut history = model.get_history().clone();
+    history.insert("batch_loss".to_string(), loss);
+    let encoded = json::encode(&history).unwrap();
+    File::create(path).and_then(|mut f| f.write_all(encoded.as_bytes()))
+      .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", path, e)));
+  }
+  if let Some(ref path) = training.model_file {
+    let checksum = hub::export(&model, path)
+      .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", path, e)));
+    println!("model:          {} [sha256 {}]", path, checksum);
+  }
+}
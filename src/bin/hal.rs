@@ -0,0 +1,186 @@
+//! hal: train, evaluate & predict without writing a driver
+//!
+//! Usage:
+//!
+//! ```text
+//! hal train <config.toml | config.json>
+//! hal eval <model.json> <data.csv | data.npy> [metric ...]
+//! hal predict <model.json> <input.npy | input.csv>
+//! hal report <min | max> <metric> <metrics.json[,config.toml]> ...
+//! ```
+//!
+//! `train` runs the config of hal-train [see its documentation], set
+//! `model_file` in the [training] section to save the trained model.
+//! Model files are hub files [see `hal::hub::export`].
+//!
+//! `eval` prints the mean loss & the metrics [eg: roc_auc] as json. The first
+//! `input_size` columns of the data are the inputs, the others the targets;
+//! a single label column of a multi-class model is one-hot encoded.
+//!
+//! `predict` writes the predicted probabilities [of the last time-step] as csv to stdout.
+//!
+//! `report` compares the metrics files of hal-train runs by the best value of a
+//! metric [the lowest or highest, eg: `hal report min val_loss a.json b.json`],
+//! with the hyperparameters that differ when the configs of the runs are provided.
+extern crate hal;
+extern crate arrayfire as af;
+extern crate rustc_serialize;
+
+mod common;
+
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use rustc_serialize::json;
+use af::{Array, Backend};
+
+use hal::Model;
+use hal::utils;
+use hal::loss;
+use hal::metrics;
+use hal::activations;
+use hal::hub;
+use hal::report::{Report, Run};
+use hal::config::ModelConfig;
+use hal::model::Sequential;
+use hal::device::{DeviceManagerFactory, Device};
+
+use common::fail;
+
+const USAGE: &'static str = "usage: hal train <config> | hal eval <model> <data> [metric ...] \
+                             | hal predict <model> <input> | hal report <min | max> <metric> <run> ...";
+const BATCH_SIZE: u64 = 256;
+
+/// Helper to read a data file by its extension [csv unless .npy or .idx]
+fn read_array(path: &str) -> Array {
+  let format = match path {
+    p if p.ends_with(".npy") => "npy",
+    p if p.ends_with(".idx") => "idx",
+    _                        => "csv",
+  };
+  common::read_format(path, format, false)
+}
+
+/// Loads the model on the default device, the data stays on the cpu
+fn load_model(path: &str) -> (Sequential, Device) {
+  let manager = DeviceManagerFactory::new();
+  let device = Device{ backend: Backend::DEFAULT, id: 0 };
+  let cpu_device = Device{ backend: Backend::CPU, id: 0 };
+  let model = hub::load_file(path, manager.clone(), device)
+    .unwrap_or_else(|e| fail(&format!("unable to load {}: {}", path, e)));
+  manager.swap_device(cpu_device);
+  (model, cpu_device)
+}
+
+/// Helper to get a size param of a layer config
+fn layer_size(model: &Sequential, layer: usize, param: &str) -> u64 {
+  let config = ModelConfig::from_model(model);
+  config.layers[layer].params.get(param).and_then(|s| s.parse::<u64>().ok())
+    .unwrap_or_else(|| fail(&format!("the model has no {} for layer {}", param, layer)))
+}
+
+fn eval(model_path: &str, data_path: &str, metric_names: &[String]) {
+  let (mut model, cpu_device) = load_model(model_path);
+  let data = read_array(data_path);
+  let input_size = layer_size(&model, 0, "input_size");
+  let output_size = layer_size(&model, model.get_layer_names().len() - 1, "output_size");
+  if data.dims()[1] <= input_size {
+    fail(&format!("{} has {} columns, the model needs {} inputs & the targets"
+                  , data_path, data.dims()[1], input_size));
+  }
+
+  let input = af::cols(&data, 0, input_size - 1);
+  let mut target = af::cols(&data, input_size, data.dims()[1] - 1);
+  if target.dims()[1] == 1 && output_size > 1 {
+    target = utils::one_hot(&target, output_size);
+  }
+
+  let mut metrics: Vec<Box<metrics::Metric>> = metric_names.iter().map(|name| {
+    metrics::get_metric(name).unwrap_or_else(|_| fail(&format!("unknown metric {}", name)))
+  }).collect();
+  let manager = model.get_manager();
+  let device = model.get_device();
+  let activation = loss::get_output_activation(model.get_loss());
+  let loss_name = model.get_loss().to_string();
+  let num_samples = data.dims()[0];
+  let mut loss_sum = 0f32;
+  let mut first = 0;
+  while first < num_samples {
+    let last = min(first + BATCH_SIZE, num_samples) - 1;
+    manager.swap_device(cpu_device);
+    let mut batch = manager.swap_arrays_backend::<f32>(&[&af::rows(&input, first, last), &af::rows(&target, first, last)]
+                                                      , cpu_device, device);
+    let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+    let output = model.infer::<f32>(&batch_input, device).pop().unwrap();
+    let batch_loss = loss::get_loss(&loss_name, &output, &batch_target)
+      .unwrap_or_else(|e| fail(&format!("unable to compute the {} loss: {}", loss_name, e)));
+    loss_sum += batch_loss * (last - first + 1) as f32;
+
+    let p = activations::get_activation(activation, &output).unwrap();
+    for metric in metrics.iter_mut() {
+      metric.update_with_inputs(&p, &batch_target, &batch_input);
+    }
+    first = last + 1;
+  }
+  manager.swap_device(cpu_device);
+
+  let mut values = BTreeMap::new();
+  values.insert("loss".to_string(), loss_sum / num_samples as f32);
+  values.insert("num_samples".to_string(), num_samples as f32);
+  for metric in metrics.iter() {
+    values.extend(metric.values());
+  }
+  println!("{}", json::encode(&values).unwrap());
+}
+
+fn predict(model_path: &str, input_path: &str) {
+  let (mut model, cpu_device) = load_model(model_path);
+  let input = read_array(input_path);
+  let manager = model.get_manager();
+  let num_samples = input.dims()[0];
+  let stdout = io::stdout();
+  let mut out = stdout.lock();
+  let mut first = 0;
+  while first < num_samples {
+    let last = min(first + BATCH_SIZE, num_samples) - 1;
+    manager.swap_device(cpu_device);
+    let p = model.predict_proba::<f32>(&af::rows(&input, first, last), cpu_device, cpu_device).pop().unwrap();
+    let (num_rows, num_cols) = (p.dims()[0] as usize, p.dims()[1] as usize);
+    let values = utils::array_to_vec(&p);
+    for row in 0..num_rows {
+      let line: Vec<String> = (0..num_cols).map(|c| values[c * num_rows + row].to_string()).collect();
+      writeln!(out, "{}", line.join(",")).unwrap_or_else(|e| fail(&format!("unable to write: {}", e)));
+    }
+    first = last + 1;
+  }
+}
+
+fn report(order: &str, metric: &str, runs: &[String]) {
+  let higher_is_better = match order {
+    "min" => false,
+    "max" => true,
+    _     => fail(&format!("unknown order {}, use min or max", order)),
+  };
+  let mut report = Report::new(metric, higher_is_better);
+  for run in runs {
+    let mut paths = run.splitn(2, ',');
+    let history = paths.next().unwrap();
+    let name = Path::new(history).file_stem().map_or(history.to_string(), |s| s.to_string_lossy().into_owned());
+    report.add(Run::load(&name, history, paths.next())
+               .unwrap_or_else(|e| fail(&format!("unable to load {}: {}", run, e))));
+  }
+  report.print();
+}
+
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  match args.get(1).map(|a| a.as_str()) {
+    Some("train") if args.len() == 3   => common::train(&common::read_config(&args[2])),
+    Some("eval") if args.len() >= 4    => eval(&args[2], &args[3], &args[4..]),
+    Some("predict") if args.len() == 4 => predict(&args[2], &args[3]),
+    Some("report") if args.len() >= 5  => report(&args[2], &args[3], &args[4..]),
+    _                                  => fail(USAGE),
+  }
+}
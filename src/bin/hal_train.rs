@@ -0,0 +1,44 @@
+//! hal-train: config driven training
+//!
+//! Usage: hal-train <config.toml | config.json>
+//!
+//! The config holds three sections [see `common::Config`]:
+//!
+//! ```toml
+//! [model]
+//! loss = "cross_entropy_softmax"
+//! optimizer = "adam"
+//!
+//! [[model.layers]]
+//! layer = "dense"
+//! params = { input_size = 784, output_size = 10, activation = "linear", w_init = "glorot_uniform", b_init = "zeros" }
+//!
+//! [data]
+//! format = "idx"
+//! inputs = "train-images-idx3-ubyte"
+//! targets = "train-labels-idx1-ubyte"
+//! one_hot = 10
+//! validation_fraction = 0.1
+//!
+//! [training]
+//! epochs = 10
+//! batch_size = 32
+//! checkpoint_dir = "checkpoints"
+//! metrics_file = "metrics.json"
+//! model_file = "model.json"
+//! ```
+extern crate hal;
+extern crate arrayfire as af;
+extern crate rustc_serialize;
+
+mod common;
+
+use std::env;
+
+fn main() {
+  let args: Vec<String> = env::args().collect();
+  if args.len() != 2 {
+    common::fail("usage: hal-train <config.toml | config.json>");
+  }
+  common::train(&common::read_config(&args[1]));
+}
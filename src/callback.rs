@@ -0,0 +1,133 @@
+use af::Array;
+use std::collections::HashMap;
+
+use optimizer::Optimizer;
+use params::ParamManager;
+#[cfg(feature = "progress")]
+use std::cmp::max;
+#[cfg(feature = "progress")]
+use std::io::{self, Write};
+
+/// The state of a training run after a minibatch [see `Callback::on_batch_end`]
+///
+/// # Parameters
+///
+/// - `epoch` & `iteration` are the (0 based) position of the minibatch
+/// - `num_epochs` & `num_iterations` are the number of epochs & of minibatches per epoch
+/// - `batch_loss` is the mean loss of the minibatch
+/// - `running_loss` is the mean loss of the epoch so far
+/// - `samples_per_sec` is the training throughput of the run so far
+/// - `elapsed` is the time since the start of `fit` [seconds]
+/// - `eta` is the estimated time until the end of `fit` [seconds]
+#[derive(Clone, Debug)]
+pub struct BatchProgress {
+  pub epoch: u64,
+  pub num_epochs: u64,
+  pub iteration: u64,
+  pub num_iterations: u64,
+  pub batch_loss: f32,
+  pub running_loss: f32,
+  pub samples_per_sec: f64,
+  pub elapsed: f64,
+  pub eta: f64,
+}
+
+/// Read-only view of a model after a minibatch [see `Callback::inspect_batch`]
+///
+/// # Parameters
+///
+/// - `optimizer` is the optimizer of the model [see `Optimizer::get_state`]
+/// - `param_manager` holds the parameters [& their deltas] of the model
+/// - `layer_deltas` are the derivatives of the loss w.r.t. the outputs of every layer
+///   [see `Sequential::get_layer_deltas`]
+pub struct TrainingState<'a> {
+  pub optimizer: &'a Optimizer,
+  pub param_manager: &'a ParamManager,
+  pub layer_deltas: &'a [Array],
+}
+
+/// Hooks that are called by `Model::fit` [see `Sequential::add_callback`]
+///
+/// All the hooks default to doing nothing.
+pub trait Callback: Send {
+  /// Called after the optimization step of every minibatch
+  fn on_batch_end(&mut self, _progress: &BatchProgress) {}
+
+  /// Called right after `on_batch_end` with read-only access to the model [see `TrainingState`]
+  ///
+  /// eg: dump the optimizer state & the layer deltas once the loss diverges
+  fn inspect_batch(&mut self, _state: &TrainingState, _progress: &BatchProgress) {}
+
+  /// Called at the end of every epoch with the epoch values
+  /// ["loss" & the validation values prefixed with "val_", see `Model::get_history`]
+  fn on_epoch_end(&mut self, _epoch: u64, _values: &HashMap<String, f32>) {}
+
+  /// Called once all the epochs are done
+  fn on_train_end(&mut self) {}
+}
+
+/// Formats seconds as hh:mm:ss
+pub fn format_duration(seconds: f64) -> String {
+  let seconds = seconds.max(0.0).round() as u64;
+  format!("{:02}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+}
+
+fn print_epoch_values(epoch: u64, values: &HashMap<String, f32>) {
+  let mut names: Vec<&String> = values.keys().filter(|name| name.starts_with("val_")).collect();
+  names.sort();
+  for name in names {
+    print!("\n[epoch: {}] {}: {}", epoch, name, values[name]);
+  }
+}
+
+/// Prints the loss of every minibatch & the validation values of every epoch
+///
+/// This is the output of `fit` with `verbose` set.
+pub struct PrintLogger;
+
+impl Callback for PrintLogger {
+  fn on_batch_end(&mut self, progress: &BatchProgress) {
+    print!("\n[epoch: {}][iter: {}] {} ", progress.epoch, progress.iteration, progress.batch_loss);
+  }
+
+  fn on_epoch_end(&mut self, epoch: u64, values: &HashMap<String, f32>) {
+    print_epoch_values(epoch, values);
+  }
+}
+
+/// Single line terminal progress bar with the running loss, throughput & ETA
+///
+/// eg: `epoch 2/10 [=========>          ] 48/100 loss: 0.1532 | 5120 samples/s | eta 00:01:07`
+#[cfg(feature = "progress")]
+pub struct ProgressBar {
+  pub width: usize,
+}
+
+#[cfg(feature = "progress")]
+impl ProgressBar {
+  pub fn new(width: usize) -> ProgressBar {
+    ProgressBar { width: width }
+  }
+}
+
+#[cfg(feature = "progress")]
+impl Callback for ProgressBar {
+  fn on_batch_end(&mut self, progress: &BatchProgress) {
+    let done = progress.iteration + 1;
+    let filled = (self.width as u64 * done / max(progress.num_iterations, 1)) as usize;
+    let bar: String = (0..self.width).map(|i| match i {
+      i if i + 1 < filled  => '=',
+      i if i + 1 == filled => '>',
+      _                    => ' ',
+    }).collect();
+    print!("\repoch {}/{} [{}] {}/{} loss: {:.4} | {:.0} samples/s | eta {}"
+           , progress.epoch + 1, progress.num_epochs, bar, done, progress.num_iterations
+           , progress.running_loss, progress.samples_per_sec, format_duration(progress.eta));
+    io::stdout().flush().unwrap();
+  }
+
+  fn on_epoch_end(&mut self, epoch: u64, values: &HashMap<String, f32>) {
+    print_epoch_values(epoch, values);
+    println!("");
+  }
+}
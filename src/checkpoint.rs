@@ -0,0 +1,241 @@
+use af;
+use af::{Array, Dim4, HasAfEnum};
+use num::Zero;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use rustc_serialize::json;
+
+use utils;
+use random;
+use data::DataSource;
+use device::{Device, DeviceManager};
+use error::HALError;
+use model::{Model, Sequential};
+use params::ParamManager;
+
+/// Extension of the checkpoint files
+pub const CHECKPOINT_EXTENSION: &'static str = "ckpt";
+
+/// Host copy of a single parameter array
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct ArrayRecord {
+  pub dims: Vec<u64>,
+  pub data: Vec<f64>,
+}
+
+/// The software & hardware that an artifact was produced with
+///
+/// # Parameters
+///
+/// - `hal_version` is the version of this crate
+/// - `arrayfire_version` is the version [and revision] of the ArrayFire library
+/// - `backend` is the backend of the device that the model computed on
+/// - `device_id` is the id of that device
+/// - `devices` are all the available devices [see `DeviceManagerFactory::get_devices`]
+/// - `generator` is the name of the random generator of the thread [see `random::set_generator`]
+/// - `seed` is the seed of that generator [None if it does not report one], the
+///   ArrayFire generators are reseeded from it before every draw
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct Environment {
+  pub hal_version: String,
+  pub arrayfire_version: String,
+  pub backend: String,
+  pub device_id: i32,
+  pub devices: Vec<String>,
+  pub generator: String,
+  pub seed: Option<u64>,
+}
+
+impl Environment {
+  /// Captures the environment of a model computing on the provided device
+  pub fn capture(manager: &DeviceManager, device: Device) -> Environment {
+    let (major, minor, patch) = af::get_version();
+    Environment {
+      hal_version: env!("CARGO_PKG_VERSION").to_string(),
+      arrayfire_version: format!("{}.{}.{} ({})", major, minor, patch, af::get_revision()),
+      backend: format!("{}", device.backend),
+      device_id: device.id,
+      devices: manager.get_devices().iter().map(|d| format!("{}:{}", d.backend, d.id)).collect(),
+      generator: random::generator_name(),
+      seed: random::generator_seed(),
+    }
+  }
+}
+
+/// Snapshot of all the trainable parameters of a model
+///
+/// The parameters are stored in the order of `ParamManager::get_all_arrays`
+/// [W0, b0, .. WN, bN] and can only be restored into a model of the same architecture.
+/// Checkpoints saved by a model also record the `Environment` of the run
+/// [older checkpoints load with None].
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Checkpoint {
+  pub epoch: u64,
+  pub params: Vec<ArrayRecord>,
+  pub environment: Option<Environment>,
+}
+
+impl Checkpoint {
+  /// Copies all the parameters of the manager to the host
+  pub fn from_params(manager: &ParamManager, epoch: u64) -> Checkpoint {
+    Checkpoint {
+      epoch: epoch,
+      params: manager.get_all_arrays().iter().map(|arr| ArrayRecord {
+        dims: arr.dims().get().to_vec(),
+        data: utils::array_to_vec(arr),
+      }).collect(),
+      environment: None,
+    }
+  }
+
+  /// Copies the parameters back into the manager (on the current device)
+  ///
+  /// Every parameter keeps the type it currently has in the manager
+  pub fn restore(&self, manager: &ParamManager) -> Result<(), HALError> {
+    let dims = manager.get_all_dims();
+    if dims.len() != self.params.len() {
+      warn!("checkpoint has {} parameters, the model {}", self.params.len(), dims.len());
+      return Err(HALError::CHECKPOINT_MISMATCH);
+    }
+    for (ind, (dim, record)) in dims.iter().zip(self.params.iter()).enumerate() {
+      if dim.get().to_vec() != record.dims {
+        warn!("checkpoint parameter {} is of dims {:?}, the model {:?}", ind, record.dims, dim.get());
+        return Err(HALError::CHECKPOINT_MISMATCH);
+      }
+    }
+
+    manager.with_mut_arrays_and_deltas(|ind, arr, _| {
+      let record = &self.params[ind];
+      let loaded = Array::new::<f64>(&record.data, Dim4::new(&[record.dims[0], record.dims[1]
+                                                              , record.dims[2], record.dims[3]]));
+      *arr = utils::cast(&loaded, arr.get_type());
+    });
+    Ok(())
+  }
+}
+
+/// Writes the checkpoint to the provided path as json
+pub fn save(checkpoint: &Checkpoint, path: &str) -> Result<(), HALError> {
+  let encoded = try!(json::encode(checkpoint).map_err(|_| HALError::CHECKPOINT_IO));
+  let mut file = try!(File::create(path).map_err(|e| {
+    warn!("unable to create checkpoint {}: {}", path, e);
+    HALError::CHECKPOINT_IO
+  }));
+  try!(file.write_all(encoded.as_bytes()).map_err(|e| {
+    warn!("unable to write checkpoint {}: {}", path, e);
+    HALError::CHECKPOINT_IO
+  }));
+  info!("wrote checkpoint {} [epoch {}]", path, checkpoint.epoch);
+  Ok(())
+}
+
+/// Reads a json checkpoint from the provided path
+pub fn load(path: &str) -> Result<Checkpoint, HALError> {
+  debug!("loading checkpoint {}", path);
+  let mut file = try!(File::open(path).map_err(|e| {
+    warn!("unable to open checkpoint {}: {}", path, e);
+    HALError::CHECKPOINT_IO
+  }));
+  let mut contents = String::new();
+  try!(file.read_to_string(&mut contents).map_err(|_| HALError::CHECKPOINT_IO));
+  json::decode(&contents).map_err(|_| HALError::CHECKPOINT_IO)
+}
+
+/// Returns the sorted paths of all the checkpoints in the directory
+pub fn list_checkpoints(dir: &str) -> Result<Vec<String>, HALError> {
+  let entries = try!(fs::read_dir(Path::new(dir)).map_err(|_| HALError::CHECKPOINT_IO));
+  let mut paths: Vec<String> = entries.filter_map(|entry| entry.ok())
+    .map(|entry| entry.path())
+    .filter(|path| path.extension().map_or(false, |ext| ext == CHECKPOINT_EXTENSION))
+    .map(|path| path.to_string_lossy().into_owned())
+    .collect();
+  paths.sort();
+  Ok(paths)
+}
+
+/// The evaluation results of a single checkpoint
+#[derive(Clone, Debug)]
+pub struct CheckpointScore {
+  pub path: String,
+  pub epoch: u64,
+  pub values: HashMap<String, f32>,
+}
+
+/// Evaluates every checkpoint of a directory and ranks them
+///
+/// The checkpoints are loaded one after the other into the same model,
+/// so the device & the datasource are reused for all the evaluations
+/// (see `Model::evaluate` for the reported values).
+///
+/// # Parameters
+///
+/// - `model` is a model with the same architecture as the checkpoints
+/// - `dir` is the directory containing the checkpoints
+/// - `source` is the datasource to evaluate on [validation data]
+/// - `src_device` is the source device of the data
+/// - `batch_size` is the minibatch size
+/// - `rank_by` is the value to rank by [eg: "loss" or a metric name]
+/// - `higher_is_better` sorts in descending order of `rank_by` when set
+///
+/// # Return Values
+///
+/// Vector of the checkpoint scores, best first
+pub fn evaluate_checkpoints<T, E>(model: &mut Sequential, dir: &str, source: &T, src_device: Device
+                                  , batch_size: u64, rank_by: &str, higher_is_better: bool)
+                                  -> Result<Vec<CheckpointScore>, HALError>
+  where T: DataSource, E: HasAfEnum + Zero + Clone
+{
+  let mut scores = Vec::new();
+  for path in try!(list_checkpoints(dir)) {
+    let epoch = try!(model.load_checkpoint(&path));
+    scores.push(CheckpointScore {
+      values: model.evaluate::<T, E>(source, src_device, batch_size),
+      path: path,
+      epoch: epoch,
+    });
+  }
+
+  scores.sort_by(|a, b| rank_order(a.values.get(rank_by), b.values.get(rank_by), higher_is_better));
+  Ok(scores)
+}
+
+/// Orders two (optional) ranking values, best first
+///
+/// Entries without the ranking value go last
+pub fn rank_order(a: Option<&f32>, b: Option<&f32>, higher_is_better: bool) -> Ordering {
+  match (a, b) {
+    (Some(va), Some(vb)) => {
+      let order = va.partial_cmp(vb).unwrap_or(Ordering::Equal);
+      if higher_is_better { order.reverse() } else { order }
+    },
+    (Some(_), None)      => Ordering::Less,
+    (None, Some(_))      => Ordering::Greater,
+    (None, None)         => Ordering::Equal,
+  }
+}
+
+/// Prints the ranked table of checkpoint scores
+pub fn print_ranking(scores: &Vec<CheckpointScore>) {
+  let mut names: Vec<&String> = scores.iter().flat_map(|s| s.values.keys()).collect();
+  names.sort();
+  names.dedup();
+
+  print!("{:<6}{:<8}", "rank", "epoch");
+  for name in names.iter() {
+    print!("{:<14}", name);
+  }
+  println!("path");
+  for (rank, score) in scores.iter().enumerate() {
+    print!("{:<6}{:<8}", rank + 1, score.epoch);
+    for name in names.iter() {
+      match score.values.get(*name) {
+        Some(value) => print!("{:<14.6}", value),
+        None        => print!("{:<14}", "-"),
+      }
+    }
+    println!("{}", score.path);
+  }
+}
@@ -0,0 +1,444 @@
+use std::collections::{BTreeMap, HashMap};
+use rustc_serialize::json;
+use rustc_serialize::json::Json;
+#[cfg(feature = "toml")]
+use toml;
+
+use utils;
+use error::HALError;
+use device::{Device, DeviceManager};
+use model::{Model, Sequential};
+use optimizer::{Optimizer, SGD, Adam, SGLD};
+
+/// Adds a layer of the registered type to the model
+pub type LayerBuilder = fn(&mut Sequential, &str, HashMap<&str, String>);
+
+/// Builds an optimizer from its params [or from its defaults when None]
+pub type OptimizerBuilder = fn(Option<&HashMap<&str, &str>>) -> Box<Optimizer>;
+
+fn add_builtin_layer(model: &mut Sequential, layer: &str, params: HashMap<&str, String>) {
+  model.add::<f32>(layer, params);
+}
+
+fn build_sgd(params: Option<&HashMap<&str, &str>>) -> Box<Optimizer> {
+  match params {
+    Some(p) => Box::new(SGD::new(p)),
+    None    => Box::new(SGD::default()),
+  }
+}
+
+fn build_adam(params: Option<&HashMap<&str, &str>>) -> Box<Optimizer> {
+  match params {
+    Some(p) => Box::new(Adam::new(p)),
+    None    => Box::new(Adam::default()),
+  }
+}
+
+fn build_sgld(params: Option<&HashMap<&str, &str>>) -> Box<Optimizer> {
+  match params {
+    Some(p) => Box::new(SGLD::new(p)),
+    None    => Box::new(SGLD::default()),
+  }
+}
+
+/// The kind of value that a config param needs to parse as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamKind {
+  Integer,
+  Float,
+  Boolean,
+  Text,
+  /// The precision of a layer ["f32" or "f64", see `utils::get_dtype`]
+  DType,
+}
+
+impl ParamKind {
+  /// Whether the value parses as this kind
+  pub fn accepts(&self, value: &str) -> bool {
+    match *self {
+      ParamKind::Integer => value.parse::<u64>().is_ok(),
+      ParamKind::Float   => value.parse::<f32>().is_ok(),
+      ParamKind::Boolean => value.parse::<bool>().is_ok(),
+      ParamKind::Text    => true,
+      ParamKind::DType   => utils::get_dtype(value).is_ok(),
+    }
+  }
+}
+
+/// A param that a layer or an optimizer accepts: (name, kind, whether it is required)
+pub type ParamSpec = (&'static str, ParamKind, bool);
+
+/// The params that every builtin layer accepts on top of its own [see `Model::add`]
+const COMMON_LAYER_PARAMS: [ParamSpec; 3] = [("name", ParamKind::Text, false)
+                                             , ("dtype", ParamKind::DType, false)
+                                             , ("grad_scale", ParamKind::Float, false)];
+
+/// The names that configs are allowed to refer to
+///
+/// This is the single place that lists the string dispatched layers,
+/// optimizers, losses & activations of HAL. Layers & optimizers map to
+/// their builders, new ones can be registered at runtime. The params of
+/// the layers & optimizers that registered them are checked by `ModelConfig::validate`
+/// [the params of the others are handed over unchecked].
+pub struct Registry {
+  pub layers: HashMap<String, LayerBuilder>,
+  pub optimizers: HashMap<String, OptimizerBuilder>,
+  pub losses: Vec<String>,
+  pub activations: Vec<String>,
+  pub layer_params: HashMap<String, Vec<ParamSpec>>,
+  pub optimizer_params: HashMap<String, Vec<ParamSpec>>,
+}
+
+impl Default for Registry {
+  fn default() -> Registry {
+    let mut registry = Registry {
+      layers: HashMap::new(),
+      optimizers: HashMap::new(),
+      losses: ["l2", "mse", "cross_entropy", "binary_cross_entropy"
+               , "cross_entropy_softmax", "ordinal_cross_entropy"]
+        .iter().map(|s| s.to_string()).collect(),
+      activations: ["softmax", "sigmoid", "relu", "lrelu", "tanh", "ones", "linear"]
+        .iter().map(|s| s.to_string()).collect(),
+      layer_params: HashMap::new(),
+      optimizer_params: HashMap::new(),
+    };
+    for layer in ["dense", "rnn", "unitary", "ordinal", "dropout"].iter() {
+      registry.register_layer(layer, add_builtin_layer);
+    }
+
+    use self::ParamKind::{Integer, Float, Boolean, Text};
+    let sizes: [ParamSpec; 2] = [("input_size", Integer, true), ("output_size", Integer, true)];
+    let inits: [ParamSpec; 2] = [("w_init", Text, true), ("b_init", Text, true)];
+    registry.register_layer_params("dense", &[&sizes[..], &inits[..], &[("activation", Text, true)][..]].concat());
+    registry.register_layer_params("rnn", &[&sizes[..], &inits[..]
+                                            , &[("hidden_size", Integer, true)
+                                                , ("inner_activation", Text, true)
+                                                , ("outer_activation", Text, true)][..]].concat());
+    registry.register_layer_params("ordinal", &[&sizes[..], &inits[..]].concat());
+    registry.register_layer_params("dropout", &[&sizes[..], &[("rate", Float, true)][..]].concat());
+    registry.register_layer_params("unitary", &[&sizes[..]
+                                                , &[("hidden_size", Integer, true)
+                                                    , ("o_activation", Text, true)
+                                                    , ("h_init", Text, true)
+                                                    , ("v_init", Text, true)
+                                                    , ("phase_init", Text, true)
+                                                    , ("householder_init", Text, true)
+                                                    , ("u_init", Text, true)
+                                                    , ("h_bias_init", Text, true)
+                                                    , ("o_bias_init", Text, true)
+                                                    , ("is_permut_const", Boolean, true)][..]].concat());
+
+    // the optimizer params that are left out keep their defaults
+    registry.register_optimizer("sgd", build_sgd);
+    registry.register_optimizer("adam", build_adam);
+    registry.register_optimizer("sgld", build_sgld);
+    registry.register_optimizer_params("sgd", &[("learning_rate", Float, false), ("momemtum", Float, false)
+                                                , ("decay", Float, false), ("nesterov", Boolean, false)
+                                                , ("clip_grad", Float, false)]);
+    registry.register_optimizer_params("adam", &[("learning_rate", Float, false), ("beta1", Float, false)
+                                                 , ("beta2", Float, false), ("eps", Float, false)
+                                                 , ("lambda", Float, false), ("clip_grad", Float, false)]);
+    registry.register_optimizer_params("sgld", &[("learning_rate", Float, false), ("num_train", Integer, false)
+                                                 , ("temperature", Float, false), ("prior_precision", Float, false)
+                                                 , ("preconditioned", Boolean, false), ("alpha", Float, false)
+                                                 , ("lambda", Float, false), ("clip_grad", Float, false)
+                                                 , ("burn_in", Integer, false), ("thinning", Integer, false)
+                                                 , ("max_samples", Integer, false)]);
+    registry
+  }
+}
+
+impl Registry {
+  pub fn register_layer(&mut self, name: &str, builder: LayerBuilder) {
+    self.layers.insert(name.to_lowercase(), builder);
+  }
+
+  pub fn register_optimizer(&mut self, name: &str, builder: OptimizerBuilder) {
+    self.optimizers.insert(name.to_lowercase(), builder);
+  }
+
+  /// Registers the params that the layer accepts [the common layer params are added]
+  pub fn register_layer_params(&mut self, name: &str, params: &[ParamSpec]) {
+    let params = params.iter().chain(COMMON_LAYER_PARAMS.iter()).cloned().collect();
+    self.layer_params.insert(name.to_lowercase(), params);
+  }
+
+  /// Registers the params that the optimizer accepts
+  pub fn register_optimizer_params(&mut self, name: &str, params: &[ParamSpec]) {
+    self.optimizer_params.insert(name.to_lowercase(), params.to_vec());
+  }
+
+  /// Whether the layer accepts the param [layers without registered params accept all]
+  pub fn layer_accepts(&self, layer: &str, param: &str) -> bool {
+    self.layer_params.get(&layer.to_lowercase())
+      .map_or(true, |params| params.iter().any(|&(name, _, _)| name == param))
+  }
+
+  /// Whether the optimizer accepts the param [optimizers without registered params accept all]
+  pub fn optimizer_accepts(&self, optimizer: &str, param: &str) -> bool {
+    self.optimizer_params.get(&optimizer.to_lowercase())
+      .map_or(true, |params| params.iter().any(|&(name, _, _)| name == param))
+  }
+
+  pub fn register_loss(&mut self, name: &str) {
+    self.losses.push(name.to_string());
+  }
+
+  pub fn register_activation(&mut self, name: &str) {
+    self.activations.push(name.to_string());
+  }
+}
+
+/// A layer type & the params that are handed to `Model::add`
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct LayerConfig {
+  pub layer: String,
+  pub params: BTreeMap<String, String>,
+}
+
+/// Declarative description of a sequential model
+///
+/// In json:
+///
+/// ```json
+/// { "loss": "cross_entropy_softmax", "optimizer": "adam",
+///   "layers": [{ "layer": "dense", "params": { "input_size": 784, "output_size": 10
+///                                            , "activation": "linear"
+///                                            , "w_init": "glorot_uniform", "b_init": "zeros" } }] }
+/// ```
+///
+/// The same keys are used in toml [`[[layers]]` tables]. Param values
+/// can be strings, numbers or booleans; `optimizer_params` can be left
+/// out to use the defaults of the optimizer.
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct ModelConfig {
+  pub loss: String,
+  pub optimizer: String,
+  pub optimizer_params: Option<BTreeMap<String, String>>,
+  pub layers: Vec<LayerConfig>,
+}
+
+/// Parses a json document
+pub fn parse_json(contents: &str) -> Result<Json, HALError> {
+  Json::from_str(contents).map_err(|e| {
+    warn!("unable to parse the config: {}", e);
+    HALError::CONFIG
+  })
+}
+
+/// Parses a toml document into the equivalent json tree
+#[cfg(feature = "toml")]
+pub fn parse_toml(contents: &str) -> Result<Json, HALError> {
+  fn convert(value: &toml::Value) -> Json {
+    match *value {
+      toml::Value::String(ref s)   => Json::String(s.clone()),
+      toml::Value::Integer(i)      => Json::I64(i),
+      toml::Value::Float(f)        => Json::F64(f),
+      toml::Value::Boolean(b)      => Json::Boolean(b),
+      toml::Value::Datetime(ref d) => Json::String(d.clone()),
+      toml::Value::Array(ref a)    => Json::Array(a.iter().map(convert).collect()),
+      toml::Value::Table(ref t)    => Json::Object(t.iter().map(|(k, v)| (k.clone(), convert(v))).collect()),
+    }
+  }
+
+  let mut parser = toml::Parser::new(contents);
+  match parser.parse() {
+    Some(table) => Ok(convert(&toml::Value::Table(table))),
+    None        => Err(HALError::CONFIG),
+  }
+}
+
+/// Helper to convert a scalar config value to the string that `Model::add` expects
+fn scalar_to_string(value: &Json) -> Result<String, HALError> {
+  match *value {
+    Json::String(ref s) => Ok(s.clone()),
+    Json::I64(i)        => Ok(i.to_string()),
+    Json::U64(u)        => Ok(u.to_string()),
+    Json::F64(f)        => Ok(f.to_string()),
+    Json::Boolean(b)    => Ok(b.to_string()),
+    _                   => Err(HALError::CONFIG),
+  }
+}
+
+fn to_string_map(value: &Json) -> Result<BTreeMap<String, String>, HALError> {
+  let object = try!(value.as_object().ok_or(HALError::CONFIG));
+  let mut map = BTreeMap::new();
+  for (key, value) in object.iter() {
+    map.insert(key.clone(), try!(scalar_to_string(value)));
+  }
+  Ok(map)
+}
+
+fn get_string(object: &json::Object, key: &str) -> Result<String, HALError> {
+  object.get(key).and_then(|v| v.as_string()).map(|s| s.to_string()).ok_or(HALError::CONFIG)
+}
+
+/// Sets the `input_size` of the layers that follow a layer with a new `output_size`
+///
+/// Dropout layers keep their sizes equal, so the size is passed through them
+/// up to the next other layer [see `Sequential::replace_layer` & `tuning::apply`].
+///
+/// # Parameters
+///
+/// - `following` are the (layer type, params) of the layers after the resized one
+/// - `output_size` is the new output size of the resized layer
+pub fn propagate_output_size<'a, M, I>(following: I, output_size: &str)
+  where M: 'a + Extend<(String, String)>, I: IntoIterator<Item = (&'a str, &'a mut M)>
+{
+  for (layer, params) in following {
+    params.extend(Some(("input_size".to_string(), output_size.to_string())));
+    if layer.to_lowercase() != "dropout" {
+      break;
+    }
+    params.extend(Some(("output_size".to_string(), output_size.to_string())));
+  }
+}
+
+/// Helper that verifies that the required params exist & that all the params parse
+fn check_params(owner: &str, specs: &[ParamSpec], params: &BTreeMap<String, String>)
+                -> Result<(), HALError>
+{
+  for &(name, kind, required) in specs.iter() {
+    match params.get(name) {
+      None if required                   => {
+        warn!("the {} param of {} is missing", name, owner);
+        return Err(HALError::CONFIG);
+      },
+      Some(value) if !kind.accepts(value) => {
+        warn!("the {} param of {} is not a valid {:?}: {}", name, owner, kind, value);
+        return Err(HALError::CONFIG);
+      },
+      _                                  => {},
+    }
+  }
+  Ok(())
+}
+
+impl ModelConfig {
+  /// Builds the config from a (json or toml) tree
+  pub fn from_value(value: &Json) -> Result<ModelConfig, HALError> {
+    let object = try!(value.as_object().ok_or(HALError::CONFIG));
+    let optimizer_params = match object.get("optimizer_params") {
+      Some(params) => Some(try!(to_string_map(params))),
+      None         => None,
+    };
+
+    let mut layers = Vec::new();
+    for layer in try!(object.get("layers").and_then(|l| l.as_array()).ok_or(HALError::CONFIG)) {
+      let layer = try!(layer.as_object().ok_or(HALError::CONFIG));
+      layers.push(LayerConfig {
+        layer: try!(get_string(layer, "layer")),
+        params: try!(to_string_map(try!(layer.get("params").ok_or(HALError::CONFIG)))),
+      });
+    }
+
+    Ok(ModelConfig {
+      loss: try!(get_string(object, "loss")),
+      optimizer: try!(get_string(object, "optimizer")),
+      optimizer_params: optimizer_params,
+      layers: layers,
+    })
+  }
+
+  pub fn from_json(contents: &str) -> Result<ModelConfig, HALError> {
+    ModelConfig::from_value(&try!(parse_json(contents)))
+  }
+
+  #[cfg(feature = "toml")]
+  pub fn from_toml(contents: &str) -> Result<ModelConfig, HALError> {
+    ModelConfig::from_value(&try!(parse_toml(contents)))
+  }
+
+  /// Describes an existing model [layers, loss & the current optimizer params]
+  pub fn from_model(model: &Sequential) -> ModelConfig {
+    let optimizer = model.get_optimizer();
+    ModelConfig {
+      loss: model.get_loss().to_string(),
+      optimizer: optimizer.get_name().to_lowercase(),
+      optimizer_params: Some(optimizer.get_params().into_iter().collect()),
+      layers: model.get_layer_configs().iter().map(|&(ref layer, ref params)| LayerConfig {
+        layer: layer.clone(),
+        params: params.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+      }).collect(),
+    }
+  }
+
+  pub fn to_json(&self) -> String {
+    json::as_pretty_json(self).to_string()
+  }
+
+  #[cfg(feature = "toml")]
+  pub fn to_toml(&self) -> String {
+    toml::encode_str(self)
+  }
+
+  /// Verifies that all the names of the config are registered & that the
+  /// params of the layers & of the optimizer exist & parse [see `Registry`]
+  pub fn validate(&self, registry: &Registry) -> Result<(), HALError> {
+    let activation_keys = ["activation", "inner_activation", "outer_activation", "o_activation"];
+    let known_layers = self.layers.iter().all(|l| {
+      registry.layers.contains_key(&l.layer.to_lowercase())
+        && activation_keys.iter().filter_map(|k| l.params.get(*k))
+             .all(|a| registry.activations.contains(a))
+    });
+    if !known_layers
+      || !registry.optimizers.contains_key(&self.optimizer.to_lowercase())
+      || !registry.losses.contains(&self.loss)
+    {
+      warn!("the config refers to unknown layers, activations, optimizers or losses");
+      return Err(HALError::CONFIG);
+    }
+
+    for (i, layer) in self.layers.iter().enumerate() {
+      if let Some(specs) = registry.layer_params.get(&layer.layer.to_lowercase()) {
+        try!(check_params(&format!("layer {} [{}]", i, layer.layer), specs, &layer.params));
+      }
+
+      // checked by `Model::add` with an assert
+      if layer.layer.to_lowercase() == "dropout" {
+        let rate = layer.params.get("rate").and_then(|r| r.parse::<f32>().ok()).unwrap_or(0.0);
+        if layer.params.get("input_size") != layer.params.get("output_size") || rate < 0.0 || rate >= 1.0 {
+          warn!("the dropout layer {} needs the same input & output_size and a rate in [0, 1)", i);
+          return Err(HALError::CONFIG);
+        }
+      }
+    }
+
+    match (registry.optimizer_params.get(&self.optimizer.to_lowercase()), self.optimizer_params.as_ref()) {
+      (Some(specs), Some(params)) => check_params(&format!("the {} optimizer", self.optimizer), specs, params),
+      _                           => Ok(()),
+    }
+  }
+
+  /// Builds the model with the default registry
+  pub fn build(&self, manager: DeviceManager, device: Device) -> Result<Sequential, HALError> {
+    self.build_with(&Registry::default(), manager, device)
+  }
+
+  /// Builds the model with the builders of the provided registry
+  pub fn build_with(&self, registry: &Registry, manager: DeviceManager, device: Device)
+                    -> Result<Sequential, HALError>
+  {
+    try!(self.validate(registry));
+    let optimizer_builder = registry.optimizers[&self.optimizer.to_lowercase()];
+    let optimizer = match self.optimizer_params {
+      Some(ref params) => {
+        // params that are not provided keep the defaults of the optimizer
+        let mut full_params = optimizer_builder(None).get_params();
+        full_params.extend(params.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let full_params: HashMap<&str, &str> = full_params.iter()
+          .map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        optimizer_builder(Some(&full_params))
+      },
+      None             => optimizer_builder(None),
+    };
+
+    let mut model = Sequential::new(manager, optimizer, &self.loss, device);
+    for layer in self.layers.iter() {
+      let layer_builder = registry.layers[&layer.layer.to_lowercase()];
+      let params = layer.params.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+      layer_builder(&mut model, &layer.layer, params);
+    }
+    Ok(model)
+  }
+}
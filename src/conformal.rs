@@ -0,0 +1,133 @@
+use af;
+use af::{Array, DType, HasAfEnum};
+use num::Zero;
+use std::f32;
+
+use utils;
+use model::Model;
+use device::Device;
+
+/// Returns the split conformal quantile of the calibration scores
+///
+/// The ceil((n + 1) * (1 - alpha)) smallest score, which guarantees that the
+/// score of a new exchangeable sample is below it with probability >= 1 - alpha.
+/// Returns infinity when the calibration set is too small for the requested coverage.
+pub fn conformal_quantile(scores: &Vec<f32>, alpha: f32) -> f32 {
+  assert!(alpha > 0.0 && alpha < 1.0, "alpha needs to be in (0, 1)");
+  let mut sorted = scores.clone();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  let n = sorted.len();
+  // tolerate the rounding of alpha [eg: 10 * 0.9 = 9.0000004 in f32]
+  let rank = ((n + 1) as f64 * (1.0 - alpha as f64) - 1e-6).ceil() as usize;
+  match rank {
+    0          => f32::NEG_INFINITY,
+    r if r > n => f32::INFINITY,
+    r          => sorted[r - 1],
+  }
+}
+
+/// Helper to copy the first time-step of the probabilities to the host
+fn host_proba<M, T>(model: &mut M, inputs: &Array, src_device: Device) -> Vec<f32>
+  where M: Model, T: HasAfEnum + Zero + Clone
+{
+  let proba = model.predict_proba::<T>(inputs, src_device, src_device).remove(0);
+  utils::array_to_vec(&proba).iter().map(|&x| x as f32).collect()
+}
+
+/// Split conformal prediction sets for classifiers
+///
+/// The non-conformity score of a calibration sample is 1 - p(true class)
+/// [see `Model::predict_proba`]. At inference a class is part of the prediction
+/// set when 1 - p(class) <= threshold, so that the set contains the true class
+/// with probability >= 1 - alpha.
+///
+/// # Parameters
+///
+/// - `alpha` is the allowed miscoverage rate
+/// - `threshold` is the calibrated score quantile
+pub struct ConformalClassifier {
+  pub alpha: f32,
+  pub threshold: f32,
+}
+
+impl ConformalClassifier {
+  /// Calibrates the threshold on a held out set
+  ///
+  /// # Parameters
+  ///
+  /// - `model` is the trained model
+  /// - `inputs` is the [num_samples, input_size] calibration input
+  /// - `targets` is the one-hot [num_samples, num_classes] calibration target
+  /// - `src_device` is the device of the calibration data
+  /// - `alpha` is the allowed miscoverage rate
+  pub fn calibrate<M, T>(model: &mut M, inputs: &Array, targets: &Array
+                         , src_device: Device, alpha: f32) -> ConformalClassifier
+    where M: Model, T: HasAfEnum + Zero + Clone
+  {
+    let proba = host_proba::<M, T>(model, inputs, src_device);
+    let targets = utils::array_to_vec(targets);
+    let num_samples = inputs.dims()[0] as usize;
+    let num_classes = proba.len() / num_samples;
+
+    // column major [num_samples, num_classes]
+    let scores = (0..num_samples).map(|s| {
+      let true_class = (0..num_classes).find(|&c| targets[c * num_samples + s] > 0.5)
+        .expect("calibration targets need to be one-hot");
+      1.0 - proba[true_class * num_samples + s]
+    }).collect();
+
+    ConformalClassifier {
+      alpha: alpha,
+      threshold: conformal_quantile(&scores, alpha),
+    }
+  }
+
+  /// Returns the [batch, num_classes] indicator of the classes in the prediction set
+  pub fn predict_sets<M, T>(&self, model: &mut M, inputs: &Array
+                            , src_device: Device, dest_device: Device) -> Array
+    where M: Model, T: HasAfEnum + Zero + Clone
+  {
+    let proba = model.predict_proba::<T>(inputs, src_device, dest_device).remove(0);
+    utils::cast(&af::ge(&proba, &(1.0 - self.threshold), false), DType::F32)
+  }
+}
+
+/// Split conformal prediction intervals for regression
+///
+/// The non-conformity score is the absolute residual |y - f(x)| of the calibration
+/// samples, the intervals f(x) +- width contain the target with probability >= 1 - alpha.
+///
+/// # Parameters
+///
+/// - `alpha` is the allowed miscoverage rate
+/// - `width` is the calibrated half width of the intervals
+pub struct ConformalRegressor {
+  pub alpha: f32,
+  pub width: f32,
+}
+
+impl ConformalRegressor {
+  /// Calibrates the interval width on a held out [num_samples, output_size] set
+  pub fn calibrate<M, T>(model: &mut M, inputs: &Array, targets: &Array
+                         , src_device: Device, alpha: f32) -> ConformalRegressor
+    where M: Model, T: HasAfEnum + Zero + Clone
+  {
+    let predictions = host_proba::<M, T>(model, inputs, src_device);
+    let scores = utils::array_to_vec(targets).iter().zip(predictions.iter())
+      .map(|(&y, &p)| (y as f32 - p).abs()).collect();
+
+    ConformalRegressor {
+      alpha: alpha,
+      width: conformal_quantile(&scores, alpha),
+    }
+  }
+
+  /// Returns the (lower, upper) bounds of the prediction intervals
+  pub fn predict_intervals<M, T>(&self, model: &mut M, inputs: &Array
+                                 , src_device: Device, dest_device: Device) -> (Array, Array)
+    where M: Model, T: HasAfEnum + Zero + Clone
+  {
+    let predictions = model.predict_proba::<T>(inputs, src_device, dest_device).remove(0);
+    (af::sub(&predictions, &self.width, false), af::add(&predictions, &self.width, false))
+  }
+}
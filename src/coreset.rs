@@ -0,0 +1,174 @@
+use af;
+use af::{Array, DType, HasAfEnum};
+use num::Zero;
+use std::cmp::{min, Ordering};
+
+use loss;
+use utils;
+use data::ArraySource;
+use device::{Device, DeviceManager};
+use model::{Model, Sequential};
+
+/// Helper to run `f` over the training range of the source in chunks of batch_size samples
+fn for_training_batches<F>(manager: DeviceManager, source: &ArraySource, src_device: Device
+                           , batch_size: u64, mut f: F)
+  where F: FnMut(&Array, &Array)
+{
+  assert!(batch_size > 0, "need a positive batch size");
+  let num_train = source.params.num_train;
+  let mut first = 0;
+  while first < num_train {
+    let last = min(first + batch_size, num_train) - 1;
+    manager.swap_device(src_device);
+    f(&af::rows(&source.input, first, last), &af::rows(&source.target, first, last));
+    first = last + 1;
+  }
+}
+
+/// Scores every training sample of the source by its loss under the model
+///
+/// The loss is summed over the time-steps [cost matrices are ignored].
+/// Hard samples have high scores.
+pub fn loss_scores<T>(model: &mut Sequential, source: &ArraySource, src_device: Device
+                      , batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  let loss_name = model.get_loss().to_string();
+  let (manager, device) = (model.get_manager(), model.get_device());
+  let mut scores = Vec::with_capacity(source.params.num_train as usize);
+  for_training_batches(manager.clone(), source, src_device, batch_size, |input, target| {
+    let outputs = model.infer::<T>(input, src_device);
+    let target = manager.swap_array_backend::<T>(target, src_device, device);
+    let mut batch_scores = vec![0.0f32; input.dims()[0] as usize];
+    for (t, output) in outputs.iter().enumerate() {
+      let tar = af::slice(&target, t as u64);
+      let losses = af::sum(&loss::get_loss_vec(&loss_name, output, &tar).unwrap(), 1);
+      for (score, loss) in batch_scores.iter_mut().zip(utils::array_to_vec(&losses)) {
+        *score += loss as f32;
+      }
+    }
+    scores.extend(batch_scores);
+  });
+  scores
+}
+
+/// Scores every training sample of the source by the l2 norm of its gradient [GraNd, Paul et al, 2021]
+///
+/// # Parameters
+///
+/// - `param_indices` are the parameters of the norm [None: all of them, see `Sequential::per_sample_gradients`]
+pub fn gradient_norm_scores<T>(model: &mut Sequential, source: &ArraySource, src_device: Device
+                               , param_indices: Option<&Vec<usize>>, batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  let mut scores = Vec::with_capacity(source.params.num_train as usize);
+  for_training_batches(model.get_manager(), source, src_device, batch_size, |input, target| {
+    let (_, gradients) = model.per_sample_gradients::<T>(input, target, src_device, param_indices);
+    let mut squared_norms = vec![0.0f64; input.dims()[0] as usize];
+    for gradient in &gradients {
+      let g = utils::cast(gradient, DType::F32);
+      let rows = utils::array_to_vec(&af::sum(&af::mul(&g, &g, false), 1));
+      for (norm, row) in squared_norms.iter_mut().zip(rows) {
+        *norm += row;
+      }
+    }
+    scores.extend(squared_norms.iter().map(|n| n.sqrt() as f32));
+  });
+  scores
+}
+
+/// Counts the forgetting events of every training sample [Toneva et al, 2019]
+///
+/// A forgetting event is a sample that was classified correctly in one
+/// `record` and incorrectly in the next one. Samples that are never learned
+/// are the hardest of all and score `u64::MAX` [see `scores`], samples that are
+/// learned once & never forgotten score 0 and are the safest to drop.
+/// Call `record` after every epoch.
+///
+/// # Parameters
+///
+/// - `correct` is whether each sample was classified correctly by the last record
+/// - `learned` is whether each sample was ever classified correctly
+/// - `events` are the number of forgetting events of each sample
+/// - `num_records` is the number of recorded epochs
+pub struct ForgettingEvents {
+  pub correct: Vec<bool>,
+  pub learned: Vec<bool>,
+  pub events: Vec<u64>,
+  pub num_records: u64,
+}
+
+impl ForgettingEvents {
+  pub fn new(num_samples: usize) -> ForgettingEvents {
+    ForgettingEvents {
+      correct: vec![false; num_samples],
+      learned: vec![false; num_samples],
+      events: vec![0; num_samples],
+      num_records: 0,
+    }
+  }
+
+  /// Updates the events with the classification outcome of every sample
+  pub fn update(&mut self, correct: &Vec<bool>) {
+    assert!(correct.len() == self.correct.len(), "need an outcome per sample");
+    for (i, &now) in correct.iter().enumerate() {
+      if self.correct[i] && !now {
+        self.events[i] += 1;
+      }
+      self.learned[i] = self.learned[i] || now;
+      self.correct[i] = now;
+    }
+    self.num_records += 1;
+  }
+
+  /// Classifies the training range of the source [one-hot targets] & updates the events
+  pub fn record<T>(&mut self, model: &mut Sequential, source: &ArraySource, src_device: Device
+                   , batch_size: u64)
+    where T: HasAfEnum + Zero + Clone
+  {
+    let mut correct = Vec::with_capacity(self.correct.len());
+    for_training_batches(model.get_manager(), source, src_device, batch_size, |input, target| {
+      let classes = model.predict_classes::<T>(input, src_device, src_device, None);
+      let last = classes.len() - 1;
+      let tar = af::slice(target, last as u64);
+      let tar = utils::cast(&af::eq(&tar, &af::max(&tar, 1), true), DType::F32);
+      let hits = af::sum(&af::mul(&utils::cast(&classes[last], DType::F32), &tar, false), 1);
+      correct.extend(utils::array_to_vec(&hits).iter().map(|&h| h > 0.5));
+    });
+    self.update(&correct);
+  }
+
+  /// Returns the number of forgetting events per sample [u64::MAX for never learned samples]
+  pub fn scores(&self) -> Vec<u64> {
+    self.events.iter().zip(self.learned.iter())
+      .map(|(&e, &learned)| if learned { e } else { ::std::u64::MAX })
+      .collect()
+  }
+}
+
+/// Returns the indices of the `fraction` of the samples with the highest scores [in increasing order]
+///
+/// Ties are broken by keeping the earlier sample.
+pub fn select<S>(scores: &Vec<S>, fraction: f32) -> Vec<u32>
+  where S: PartialOrd
+{
+  assert!(fraction > 0.0 && fraction <= 1.0, "the kept fraction needs to be in (0, 1]");
+  let num_kept = min((fraction * scores.len() as f32).ceil() as usize, scores.len());
+  let mut order: Vec<usize> = (0..scores.len()).collect();
+  order.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal));
+  let mut kept: Vec<u32> = order[..num_kept].iter().map(|&i| i as u32).collect();
+  kept.sort();
+  kept
+}
+
+/// Returns a source with the highest scoring `fraction` of the training samples
+/// [the test & validation samples are kept, see `ArraySource::subset`]
+///
+/// eg: keep the hardest half of the data
+/// `coreset::prune(&source, &coreset::loss_scores::<f32>(&mut model, &source, device, 64), 0.5, 32)`
+pub fn prune<S>(source: &ArraySource, scores: &Vec<S>, fraction: f32, batch_size: u64) -> ArraySource
+  where S: PartialOrd
+{
+  assert!(scores.len() as u64 == source.params.num_train, "need a score per training sample");
+  source.subset(&select(scores, fraction), batch_size)
+}
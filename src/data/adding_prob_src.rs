@@ -6,6 +6,7 @@ use std::cell::{RefCell, Cell};
 
 use initializations::uniform;
 use utils;
+use random;
 
 use data::{Data, DataSource, DataParams, Normalize, Shuffle};
 
@@ -50,8 +51,8 @@ impl AddingProblemSource {
 
     let between1 = Range::new(0, bptt_unroll/4);
     let between2 = Range::new(bptt_unroll/4, bptt_unroll/2);
-    let mut rng1 = rand::thread_rng();
-    let mut rng2 = rand::thread_rng();
+    let mut rng1 = random::rng();
+    let mut rng2 = random::rng();
 
     let mut vec_total = Vec::with_capacity((batch_size*bptt_unroll) as usize);
     let vec_zeros = vec!(0f32; (bptt_unroll/2) as usize);
@@ -0,0 +1,158 @@
+use af;
+use af::{Array, Dim4};
+use rand::Rng;
+use std::cell::{RefCell, Cell};
+
+use utils;
+use random;
+use data::{Data, DataSource, DataParams};
+
+/// In memory datasource over a [num_samples, features] input & target array
+///
+/// The samples are split (in order) into contiguous train, test & validation
+/// ranges. Batches are read sequentially from every range [wrapping around]
+/// or are drawn at random from the range when shuffling is enabled.
+///
+/// # Parameters
+///
+/// - `params` are the data parameters
+/// - `input` is the [num_samples, input_size] input array
+/// - `target` is the [num_samples, target_size] target array
+/// - `cursors` are the next sample of the train, test & validation ranges
+pub struct ArraySource {
+  pub params: DataParams,
+  pub input: Array,
+  pub target: Array,
+  cursors: [Cell<u64>; 3],
+}
+
+impl ArraySource {
+  pub fn new(input: Array, target: Array, batch_size: u64
+             , test_fraction: f32, validation_fraction: f32
+             , is_shuffled: bool) -> ArraySource
+  {
+    assert!(test_fraction + validation_fraction < 1.0
+            , "need some samples left for training");
+    let num_samples = input.dims()[0];
+    let num_test = (test_fraction * num_samples as f32) as u64;
+    let num_validation = (validation_fraction * num_samples as f32) as u64;
+    ArraySource::with_splits(input, target, batch_size, num_test, num_validation, is_shuffled)
+  }
+
+  /// Same as `new` with the number of test & validation samples [the last samples of the arrays]
+  pub fn with_splits(input: Array, target: Array, batch_size: u64
+                     , num_test: u64, num_validation: u64
+                     , is_shuffled: bool) -> ArraySource
+  {
+    let idims = input.dims();
+    let tdims = target.dims();
+    assert!(idims[0] == tdims[0]
+            , "inputs and targets need the same number of samples");
+    assert!(num_test + num_validation < idims[0]
+            , "need some samples left for training");
+
+    let num_train = idims[0] - num_test - num_validation;
+    assert!(num_train >= batch_size, "need at least one training batch");
+
+    ArraySource {
+      params: DataParams {
+        input_dims: Dim4::new(&[batch_size, idims[1], 1, 1]),
+        target_dims: Dim4::new(&[batch_size, tdims[1], 1, 1]),
+        shuffle: is_shuffled,
+        normalize: false,
+        current_epoch: Cell::new(0),
+        dtype: input.get_type(),
+        num_samples: num_train,
+        num_train: num_train,
+        num_test: num_test,
+        num_validation: match num_validation {
+          0 => None,
+          n => Some(n),
+        },
+      },
+      input: input,
+      target: target,
+      cursors: [Cell::new(0), Cell::new(0), Cell::new(0)],
+    }
+  }
+
+  /// Returns the (first sample, number of samples) of the train [0], test [1] & validation [2] range
+  fn range(&self, split: usize) -> (u64, u64) {
+    let num_validation = self.params.num_validation.unwrap_or(0);
+    match split {
+      0 => (0, self.params.num_train),
+      1 => (self.params.num_train, self.params.num_test),
+      _ => (self.params.num_train + self.params.num_test, num_validation),
+    }
+  }
+
+  /// Returns the batch with the provided rows
+  pub fn get_rows(&self, indices: &Vec<u32>) -> Data {
+    let idx = utils::vec_to_array::<u32>(indices.clone(), Dim4::new(&[indices.len() as u64, 1, 1, 1]));
+    Data {
+      input: RefCell::new(Box::new(af::lookup(&self.input, &idx, 0))),
+      target: RefCell::new(Box::new(af::lookup(&self.target, &idx, 0))),
+    }
+  }
+
+  /// Returns a source over the provided training samples & all the test & validation samples
+  ///
+  /// # Parameters
+  ///
+  /// - `train_indices` are the training samples to keep [indices of the training range]
+  /// - `batch_size` is the batch size of the new source
+  pub fn subset(&self, train_indices: &Vec<u32>, batch_size: u64) -> ArraySource {
+    assert!(train_indices.iter().all(|&i| (i as u64) < self.params.num_train)
+            , "indices need to be in the training range");
+    let (first, _) = self.range(1);
+    let num_held_out = self.params.num_test + self.params.num_validation.unwrap_or(0);
+    let indices: Vec<u32> = train_indices.iter().cloned()
+      .chain((first..first + num_held_out).map(|i| i as u32)).collect();
+    let kept = self.get_rows(&indices);
+    ArraySource::with_splits(*kept.input.into_inner(), *kept.target.into_inner(), batch_size
+                             , self.params.num_test, self.params.num_validation.unwrap_or(0)
+                             , self.params.shuffle)
+  }
+
+  fn get_batch(&self, split: usize, num_batch: u64) -> Option<Data> {
+    let (first, count) = self.range(split);
+    if count == 0 {
+      return None;
+    }
+
+    let cursor = self.cursors[split].get();
+    self.cursors[split].set((cursor + num_batch) % count);
+    let indices: Vec<u32> = match self.params.shuffle {
+      true  => {
+        let mut rng = random::rng();
+        (0..num_batch).map(|_| (first + rng.gen_range(0, count)) as u32).collect()
+      },
+      false => (0..num_batch).map(|i| (first + (cursor + i) % count) as u32).collect(),
+    };
+
+    // track the epochs of the training data [the cursor wrapped around]
+    if split == 0 && self.cursors[0].get() < num_batch {
+      self.params.current_epoch.set(self.params.current_epoch.get() + 1);
+    }
+    Some(self.get_rows(&indices))
+  }
+}
+
+impl DataSource for ArraySource
+{
+  fn info(&self) -> DataParams {
+    self.params.clone()
+  }
+
+  fn get_train_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(0, num_batch).unwrap()
+  }
+
+  fn get_test_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(1, num_batch).expect("no test samples available")
+  }
+
+  fn get_validation_iter(&self, num_batch: u64) -> Option<Data> {
+    self.get_batch(2, num_batch)
+  }
+}
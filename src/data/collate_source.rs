@@ -0,0 +1,170 @@
+use af;
+use af::Array;
+use rand::Rng;
+use std::cell::{RefCell, Cell};
+
+use utils;
+use random;
+use data::{Data, DataSource, DataParams};
+
+/// Assembles a batch of raw samples into the (input, target) arrays of the model
+pub type Collate<S> = Box<Fn(&[&S]) -> (Array, Array)>;
+
+/// Datasource of raw samples that are batched by a user collate closure
+///
+/// The samples can be of any type [eg: a struct of an image, a text & tabular
+/// features], the collate closure turns a slice of them into the [batch, feature, time]
+/// input & target arrays of the model. The parts of heterogeneous samples are
+/// usually encoded separately & joined along the features [see `concat_features`],
+/// variable length parts can be padded with `RaggedBatch::to_padded`.
+///
+/// The samples are split (in order) into contiguous train, test & validation
+/// ranges. The dims of the batches are probed by collating the first batch.
+/// Shuffled ranges are visited in a new random order every epoch [every
+/// sample once per epoch].
+///
+/// eg: `CollateSource::new(samples, Box::new(|batch: &[&Sample]| (encode(batch), labels(batch))), 32, 0.1, 0.1, true)`
+///
+/// # Parameters
+///
+/// - `params` are the data parameters
+/// - `samples` are the raw samples
+/// - `collate` assembles the batches of samples [see `Collate`]
+/// - `cursors` are the next position in the orders of the train, test & validation ranges
+/// - `orders` are the (shuffled) offsets of the samples of every range
+pub struct CollateSource<S> {
+  pub params: DataParams,
+  pub samples: Vec<S>,
+  collate: Collate<S>,
+  cursors: [Cell<u64>; 3],
+  orders: [RefCell<Vec<u64>>; 3],
+}
+
+impl<S> CollateSource<S> {
+  pub fn new(samples: Vec<S>, collate: Collate<S>, batch_size: u64
+             , test_fraction: f32, validation_fraction: f32
+             , is_shuffled: bool) -> CollateSource<S>
+  {
+    assert!(test_fraction + validation_fraction < 1.0
+            , "need some samples left for training");
+    let num_samples = samples.len() as u64;
+    let num_test = (test_fraction * num_samples as f32) as u64;
+    let num_validation = (validation_fraction * num_samples as f32) as u64;
+    let num_train = num_samples - num_test - num_validation;
+    assert!(batch_size > 0 && num_train >= batch_size, "need at least one training batch");
+
+    // probe the dims of the batches
+    let (input, target) = {
+      let first: Vec<&S> = samples.iter().take(batch_size as usize).collect();
+      collate(&first)
+    };
+    assert!(input.dims()[0] == batch_size && target.dims()[0] == batch_size
+            , "the collate needs to return a row per sample");
+
+    CollateSource {
+      params: DataParams {
+        input_dims: input.dims(),
+        target_dims: target.dims(),
+        shuffle: is_shuffled,
+        normalize: false,
+        current_epoch: Cell::new(0),
+        dtype: input.get_type(),
+        num_samples: num_train,
+        num_train: num_train,
+        num_test: num_test,
+        num_validation: match num_validation {
+          0 => None,
+          n => Some(n),
+        },
+      },
+      samples: samples,
+      collate: collate,
+      cursors: [Cell::new(0), Cell::new(0), Cell::new(0)],
+      orders: [RefCell::new((0..num_train).collect())
+               , RefCell::new((0..num_test).collect())
+               , RefCell::new((0..num_validation).collect())],
+    }
+  }
+
+  /// Returns the (first sample, number of samples) of the train [0], test [1] & validation [2] range
+  fn range(&self, split: usize) -> (u64, u64) {
+    let num_validation = self.params.num_validation.unwrap_or(0);
+    match split {
+      0 => (0, self.params.num_train),
+      1 => (self.params.num_train, self.params.num_test),
+      _ => (self.params.num_train + self.params.num_test, num_validation),
+    }
+  }
+
+  /// Returns the collated batch of the provided samples
+  pub fn get_samples(&self, indices: &[u64]) -> Data {
+    let batch: Vec<&S> = indices.iter().map(|&i| &self.samples[i as usize]).collect();
+    let (input, target) = (self.collate)(&batch);
+    assert!(input.dims()[0] == indices.len() as u64 && target.dims()[0] == indices.len() as u64
+            , "the collate needs to return a row per sample");
+    Data {
+      input: RefCell::new(Box::new(input)),
+      target: RefCell::new(Box::new(target)),
+    }
+  }
+
+  fn get_batch(&self, split: usize, num_batch: u64) -> Option<Data> {
+    let (first, count) = self.range(split);
+    if count == 0 {
+      return None;
+    }
+
+    // a new permutation is drawn whenever a pass over the range starts
+    let cursor = self.cursors[split].get();
+    self.cursors[split].set((cursor + num_batch) % count);
+    let mut order = self.orders[split].borrow_mut();
+    let indices: Vec<u64> = (0..num_batch).map(|i| {
+      let position = ((cursor + i) % count) as usize;
+      if position == 0 && self.params.shuffle {
+        random::rng().shuffle(&mut order[..]);
+      }
+      first + order[position]
+    }).collect();
+
+    // track the epochs of the training data [the cursor wrapped around]
+    if split == 0 && self.cursors[0].get() < num_batch {
+      self.params.current_epoch.set(self.params.current_epoch.get() + 1);
+    }
+    Some(self.get_samples(&indices))
+  }
+}
+
+impl<S> DataSource for CollateSource<S>
+{
+  fn info(&self) -> DataParams {
+    self.params.clone()
+  }
+
+  fn get_train_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(0, num_batch).unwrap()
+  }
+
+  fn get_test_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(1, num_batch).expect("no test samples available")
+  }
+
+  fn get_validation_iter(&self, num_batch: u64) -> Option<Data> {
+    self.get_batch(2, num_batch)
+  }
+}
+
+/// Stacks per sample [1, feature, time] rows into a [batch, feature, time] array
+pub fn stack_rows(rows: &[Array]) -> Array {
+  assert!(rows.len() > 0, "need at least one row");
+  rows[1..].iter().fold(rows[0].clone(), |acc, row| af::join(0, &acc, row))
+}
+
+/// Joins the [batch, feature_i, time] encodings of the parts of heterogeneous samples
+/// along the features [cast to the type of the first part]
+///
+/// eg: the flattened pixels of an image, the bag of words of a text & tabular features
+pub fn concat_features(parts: &[Array]) -> Array {
+  assert!(parts.len() > 0, "need at least one part");
+  let dtype = parts[0].get_type();
+  parts[1..].iter().fold(parts[0].clone(), |acc, part| af::join(1, &acc, &utils::cast(part, dtype)))
+}
@@ -5,6 +5,7 @@ use af::{Array, Dim4, DType};
 use std::cell::{RefCell, Cell};
 
 use utils;
+use random;
 use data::{Data, DataSource, DataParams, Normalize, Shuffle};
 
 pub struct CopyingProblemSource {
@@ -49,7 +50,7 @@ impl CopyingProblemSource {
   fn generate_input(&self, batch_size: u64, input_size: u64, bptt_unroll: u64, seq_size: u64) -> Array {
 
     let between = Range::new(0,input_size-2);
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
 
     let mut vec_total = Vec::with_capacity((batch_size*input_size*bptt_unroll) as usize);
     let vec_zeros = vec!(0f32; input_size as usize);
@@ -10,6 +10,21 @@ mod adding_prob_src;
 pub use self::xor::XORSource;
 mod xor;
 
+pub use self::array_source::ArraySource;
+mod array_source;
+
+pub use self::timeseries_source::TimeSeriesSource;
+mod timeseries_source;
+
+pub use self::ragged::RaggedBatch;
+mod ragged;
+
+pub use self::collate_source::{CollateSource, Collate, stack_rows, concat_features};
+mod collate_source;
+
+pub use self::readers::{read_csv_array, read_idx, read_npy, write_npy};
+mod readers;
+
 unsafe impl Send for SinSource {}
 unsafe impl Sync for SinSource {}
 
@@ -17,6 +32,7 @@ unsafe impl Sync for SinSource {}
 // unsafe impl Send for XORSource {}
 // unsafe impl Sync for XORSource {}
 
+use af;
 use af::{Dim4, Array, DType};
 use std::cell::{RefCell, Cell};
 use std::sync::{Arc, Mutex};
@@ -328,3 +344,86 @@ impl Normalize for Data {
     self.target = RefCell::new(Box::new(normalized_target));
   }
 }
+
+/// Running per feature statistics of a stream of batches
+///
+/// Batches are merged into the running mean & sum of squared deviations
+/// with Chan's parallel update, so the statistics of all the data seen
+/// so far are kept without keeping the data. Sequential inputs
+/// [batch, feature, time] are treated as batch * time samples.
+///
+/// # Parameters
+///
+/// - `count` is the number of samples seen so far
+/// - `mean` is the [1, num_features] running mean
+/// - `m2` is the [1, num_features] running sum of squared deviations from the mean
+pub struct FeatureStatistics {
+  pub count: f64,
+  pub mean: Array,
+  pub m2: Array,
+}
+
+impl FeatureStatistics {
+  pub fn new(num_features: u64) -> FeatureStatistics {
+    let dims = Dim4::new(&[1, num_features, 1, 1]);
+    FeatureStatistics {
+      count: 0.0,
+      mean: utils::constant(dims, DType::F32, 0.0f32),
+      m2: utils::constant(dims, DType::F32, 0.0f32),
+    }
+  }
+
+  /// Helper to flatten [batch, feature, time] into [batch * time, feature]
+  fn flatten(batch: &Array) -> Array {
+    let dims = batch.dims();
+    match dims[2] > 1 {
+      true  => af::moddims(&af::reorder(batch, Dim4::new(&[0, 2, 1, 3]))
+                           , Dim4::new(&[dims[0] * dims[2], dims[1], 1, 1])),
+      false => batch.clone(),
+    }
+  }
+
+  /// Merges the statistics of the provided batch into the running statistics
+  pub fn update(&mut self, batch: &Array) {
+    let x = utils::cast(&FeatureStatistics::flatten(batch), DType::F32);
+    let batch_count = x.dims()[0] as f64;
+    let batch_mean = af::mean(&x, 0);
+    let centered = af::sub(&x, &batch_mean, true);
+    let batch_m2 = af::sum(&af::mul(&centered, &centered, false), 0);
+
+    let delta = af::sub(&batch_mean, &self.mean, false);
+    let count = self.count + batch_count;
+    self.mean = af::add(&self.mean, &af::mul(&delta, &((batch_count / count) as f32), false), false);
+    self.m2 = af::add(&af::add(&self.m2, &batch_m2, false)
+                      , &af::mul(&af::mul(&delta, &delta, false)
+                                 , &((self.count * batch_count / count) as f32), false)
+                      , false);
+    self.count = count;
+    self.mean.eval();
+    self.m2.eval();
+  }
+
+  /// Returns the [1, num_features] population variance
+  pub fn variance(&self) -> Array {
+    af::div(&self.m2, &(self.count.max(1.0) as f32), false)
+  }
+
+  /// Returns the [1, num_features] standard deviation
+  pub fn std_dev(&self) -> Array {
+    af::sqrt(&self.variance())
+  }
+
+  /// Normalizes the input by the running mean & num_std deviations
+  /// (see `utils::normalize_array`). Inputs are returned as is until
+  /// the first update.
+  pub fn normalize(&self, input: &Array, num_std: f32) -> Array {
+    if self.count == 0.0 {
+      return input.clone();
+    }
+
+    let dtype = input.get_type();
+    let std_dev = af::add(&af::mul(&self.std_dev(), &num_std, false), &1e-9f32, false);
+    af::div(&af::sub(input, &utils::cast(&self.mean, dtype), true)
+            , &utils::cast(&std_dev, dtype), true)
+  }
+}
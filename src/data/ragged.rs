@@ -0,0 +1,174 @@
+use af;
+use af::{Array, Dim4};
+use std::cmp::{max, min};
+
+use utils;
+
+/// A batch of variable length sequences stored contiguously
+///
+/// The steps of all the sequences are the rows of a single [total_steps, num_features]
+/// array, sequence b is the rows offsets[b]..offsets[b + 1]. Only the batches
+/// that are fed to the model are padded [to the longest sequence of the batch,
+/// see `to_padded`], so a whole dataset with a skewed length distribution
+/// never pays for the padding of its longest sequence. Batching sequences of
+/// similar lengths together keeps the padding of every batch small [see `length_batches`].
+///
+/// eg: train a recurrent model batch by batch
+/// `for batch in inputs.length_batches(32) { model.partial_fit_ragged::<f32>(&inputs.select(&batch), &targets.select(&batch), device); }`
+///
+/// # Parameters
+///
+/// - `values` are the [total_steps, num_features] steps of all the sequences
+/// - `offsets` are the num_sequences + 1 increasing row offsets of the sequences
+#[derive(Clone)]
+pub struct RaggedBatch {
+  pub values: Array,
+  pub offsets: Vec<u64>,
+}
+
+impl RaggedBatch {
+  pub fn new(values: Array, offsets: Vec<u64>) -> RaggedBatch {
+    assert!(offsets.len() > 1 && offsets[0] == 0, "the offsets need to start at 0 with one sequence or more");
+    assert!(offsets.windows(2).all(|w| w[0] < w[1]), "every sequence needs at least one step");
+    assert!(*offsets.last().unwrap() == values.dims()[0]
+            , "the last offset needs to be the number of steps");
+    RaggedBatch {
+      values: values,
+      offsets: offsets,
+    }
+  }
+
+  /// Concatenates the [length, num_features] sequences
+  pub fn from_sequences(sequences: &[Array]) -> RaggedBatch {
+    assert!(sequences.len() > 0, "need at least one sequence");
+    let mut offsets = vec![0];
+    let mut values = sequences[0].clone();
+    for (i, sequence) in sequences.iter().enumerate() {
+      assert!(sequence.dims()[1] == sequences[0].dims()[1], "the sequences need the same number of features");
+      if i > 0 {
+        values = af::join(0, &values, sequence);
+      }
+      let last = offsets[i];
+      offsets.push(last + sequence.dims()[0]);
+    }
+    RaggedBatch::new(values, offsets)
+  }
+
+  /// Returns the ragged batch of a padded [batch, num_features, time] array
+  ///
+  /// eg: the outputs of a model for the padded inputs
+  pub fn from_padded(padded: &Array, lengths: &[u64]) -> RaggedBatch {
+    let dims = padded.dims();
+    let (num_sequences, num_features, max_length) = (dims[0], dims[1], dims[2]);
+    assert!(lengths.len() as u64 == num_sequences, "need a length per sequence");
+    assert!(lengths.iter().all(|&l| l > 0 && l <= max_length), "the lengths need to be in [1, time]");
+
+    // [b, f, t] --> [b + num_sequences * t, f]
+    let rows = af::moddims(&af::reorder(padded, Dim4::new(&[0, 2, 1, 3]))
+                           , Dim4::new(&[num_sequences * max_length, num_features, 1, 1]));
+    let mut steps = Vec::new();
+    let mut offsets = vec![0];
+    for (b, &length) in lengths.iter().enumerate() {
+      steps.extend((0..length).map(|t| (b as u64 + num_sequences * t) as u32));
+      let last = offsets[b];
+      offsets.push(last + length);
+    }
+    let idx = utils::vec_to_array::<u32>(steps, Dim4::new(&[*offsets.last().unwrap(), 1, 1, 1]));
+    RaggedBatch::new(af::lookup(&rows, &idx, 0), offsets)
+  }
+
+  pub fn num_sequences(&self) -> usize {
+    self.offsets.len() - 1
+  }
+
+  pub fn num_features(&self) -> u64 {
+    self.values.dims()[1]
+  }
+
+  pub fn lengths(&self) -> Vec<u64> {
+    self.offsets.windows(2).map(|w| w[1] - w[0]).collect()
+  }
+
+  pub fn max_length(&self) -> u64 {
+    self.lengths().iter().fold(0, |m, &l| max(m, l))
+  }
+
+  /// Returns the [length, num_features] steps of a sequence
+  pub fn sequence(&self, index: usize) -> Array {
+    af::rows(&self.values, self.offsets[index], self.offsets[index + 1] - 1)
+  }
+
+  /// Returns the [num_sequences, num_features] last step of every sequence
+  pub fn last_steps(&self) -> Array {
+    let last: Vec<u32> = self.offsets[1..].iter().map(|&o| o as u32 - 1).collect();
+    let idx = utils::vec_to_array::<u32>(last, Dim4::new(&[self.num_sequences() as u64, 1, 1, 1]));
+    af::lookup(&self.values, &idx, 0)
+  }
+
+  /// Returns the ragged batch of the provided sequences [in the order of the indices]
+  pub fn select(&self, indices: &[u32]) -> RaggedBatch {
+    let mut steps = Vec::new();
+    let mut offsets = vec![0];
+    for (i, &index) in indices.iter().enumerate() {
+      let (first, end) = (self.offsets[index as usize], self.offsets[index as usize + 1]);
+      steps.extend((first..end).map(|s| s as u32));
+      let last = offsets[i];
+      offsets.push(last + end - first);
+    }
+    let idx = utils::vec_to_array::<u32>(steps, Dim4::new(&[*offsets.last().unwrap(), 1, 1, 1]));
+    RaggedBatch::new(af::lookup(&self.values, &idx, 0), offsets)
+  }
+
+  /// Returns the [num_sequences, 1, max_length] mask of the steps of every sequence
+  ///
+  /// eg: `af::slice(&mask, t)` are the sequences that are still running at step t
+  pub fn mask(&self) -> Array {
+    let (num_sequences, max_length) = (self.num_sequences() as u64, self.max_length());
+    let lengths = self.lengths();
+    let mask: Vec<f32> = (0..max_length)
+      .flat_map(|t| lengths.iter().map(move |&l| if t < l { 1.0 } else { 0.0 }))
+      .collect();
+    let mask = utils::vec_to_array::<f32>(mask, Dim4::new(&[num_sequences, 1, max_length, 1]));
+    utils::cast(&mask, self.values.get_type())
+  }
+
+  /// Returns the [num_sequences, num_features, max_length] padded batch [the layout of the model inputs]
+  ///
+  /// The steps after the end of a sequence are set to `value`.
+  pub fn to_padded(&self, value: f32) -> Array {
+    let (num_sequences, max_length) = (self.num_sequences() as u64, self.max_length());
+    let num_features = self.num_features();
+    let offsets = &self.offsets;
+    let steps: Vec<u32> = (0..max_length)
+      .flat_map(|t| (0..num_sequences as usize).map(move |b| {
+        min(offsets[b] + t, offsets[b + 1] - 1) as u32
+      }))
+      .collect();
+    let idx = utils::vec_to_array::<u32>(steps, Dim4::new(&[num_sequences * max_length, 1, 1, 1]));
+    let rows = af::lookup(&self.values, &idx, 0);
+
+    // [b + num_sequences * t, f] --> [b, f, t]
+    let padded = af::reorder(&af::moddims(&rows, Dim4::new(&[num_sequences, max_length, num_features, 1]))
+                             , Dim4::new(&[0, 2, 1, 3]));
+    let mask = af::tile(&self.mask(), Dim4::new(&[1, num_features, 1, 1]));
+    let fill = af::mul(&af::sub(&1.0f32, &mask, false), &value, false);
+    af::add(&af::mul(&padded, &mask, false), &utils::cast(&fill, padded.get_type()), false)
+  }
+
+  /// Returns the ratio of the padded to the stored number of steps [1.0 without padding]
+  pub fn padding_ratio(&self) -> f32 {
+    (self.num_sequences() as u64 * self.max_length()) as f32 / *self.offsets.last().unwrap() as f32
+  }
+
+  /// Splits the sequences into batches of at most `batch_size` sequences of similar lengths
+  ///
+  /// The sequences are sorted by length [ties keep their order], so the
+  /// padding of every batch is bounded by the length range of the batch.
+  pub fn length_batches(&self, batch_size: usize) -> Vec<Vec<u32>> {
+    assert!(batch_size > 0, "need a positive batch size");
+    let lengths = self.lengths();
+    let mut order: Vec<u32> = (0..self.num_sequences() as u32).collect();
+    order.sort_by_key(|&i| lengths[i as usize]);
+    order.chunks(batch_size).map(|c| c.to_vec()).collect()
+  }
+}
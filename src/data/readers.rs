@@ -0,0 +1,238 @@
+use af;
+use af::{Array, Dim4};
+use csv;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use utils;
+use error::HALError;
+
+/// Reads a numeric csv file into a [rows, cols] f32 array
+///
+/// # Parameters
+///
+/// - `filename` is the path of the csv file
+/// - `has_header` skips the first row when set
+pub fn read_csv_array(filename: &str, has_header: bool) -> Result<Array, HALError> {
+  let reader = try!(csv::Reader::from_file(Path::new(filename)).map_err(|e| {
+    warn!("unable to open {}: {}", filename, e);
+    HALError::DATA_IO
+  }));
+  let mut reader = reader.has_headers(has_header);
+
+  let mut values: Vec<f32> = Vec::new();
+  let mut num_rows: u64 = 0;
+  let mut num_cols: Option<u64> = None;
+  for row in reader.records() {
+    let row = try!(row.map_err(|_| HALError::DATA_IO));
+    match num_cols {
+      Some(cols) if cols != row.len() as u64 => return Err(HALError::DATA_IO),
+      _                                      => num_cols = Some(row.len() as u64),
+    };
+    for value in row {
+      values.push(try!(value.trim().parse::<f32>().map_err(|_| HALError::DATA_IO)));
+    }
+    num_rows += 1;
+  }
+
+  // the csv is row major while arrays are column major
+  let num_cols = try!(num_cols.ok_or(HALError::DATA_IO));
+  let transposed = utils::vec_to_array::<f32>(values, Dim4::new(&[num_cols, num_rows, 1, 1]));
+  Ok(af::transpose(&transposed, false))
+}
+
+/// Reads an IDX file [eg: MNIST] into a [num_items, item_size] f32 array
+///
+/// The IDX format is a big endian header of two zero bytes, the element type,
+/// the number of dimensions & the size of every dimension followed by the
+/// row major data. Every item [first dimension] is flattened into a row.
+pub fn read_idx(filename: &str) -> Result<Array, HALError> {
+  let mut file = try!(File::open(Path::new(filename)).map_err(|e| {
+    warn!("unable to open {}: {}", filename, e);
+    HALError::DATA_IO
+  }));
+  let mut bytes = Vec::new();
+  try!(file.read_to_end(&mut bytes).map_err(|_| HALError::DATA_IO));
+  if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+    return Err(HALError::DATA_IO);
+  }
+
+  let be_u32 = |b: &[u8]| ((b[0] as u32) << 24) | ((b[1] as u32) << 16) | ((b[2] as u32) << 8) | b[3] as u32;
+  let element_size = match bytes[2] {
+    0x08 | 0x09 => 1,
+    0x0B        => 2,
+    0x0C | 0x0D => 4,
+    0x0E        => 8,
+    _           => return Err(HALError::DATA_IO),
+  };
+  let num_dims = bytes[3] as usize;
+  let offset = 4 + 4 * num_dims;
+  if num_dims == 0 || bytes.len() < offset {
+    return Err(HALError::DATA_IO);
+  }
+
+  let dims: Vec<u64> = (0..num_dims).map(|d| be_u32(&bytes[4 + 4*d..8 + 4*d]) as u64).collect();
+  let num_items = dims[0];
+  let item_size = dims.iter().skip(1).fold(1, |acc, d| acc * d);
+  let num_elements = (num_items * item_size) as usize;
+  if bytes.len() < offset + num_elements * element_size {
+    return Err(HALError::DATA_IO);
+  }
+
+  let data = &bytes[offset..];
+  let values: Vec<f32> = (0..num_elements).map(|i| {
+    let b = &data[i * element_size..(i + 1) * element_size];
+    match bytes[2] {
+      0x08 => b[0] as f32,
+      0x09 => b[0] as i8 as f32,
+      0x0B => (((b[0] as u16) << 8) | b[1] as u16) as i16 as f32,
+      0x0C => be_u32(b) as i32 as f32,
+      0x0D => f32::from_bits(be_u32(b)),
+      _    => f64::from_bits(((be_u32(&b[0..4]) as u64) << 32) | be_u32(&b[4..8]) as u64) as f32,
+    }
+  }).collect();
+
+  // row major items --> [item_size, num_items] --> [num_items, item_size]
+  let transposed = utils::vec_to_array::<f32>(values, Dim4::new(&[item_size, num_items, 1, 1]));
+  Ok(af::transpose(&transposed, false))
+}
+
+/// Helper that returns the header text following the key of a npy header dict
+fn npy_field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+  let pattern = format!("'{}':", key);
+  header.find(&pattern).map(|start| header[start + pattern.len()..].trim())
+}
+
+/// Reads a numpy .npy file of up to 4 dimensions into an f32 array of the same shape
+///
+/// The little endian f4, f8, i1, i2, i4, i8 & u1 element types are supported, in C or
+/// fortran order. One dimensional arrays are read as a [length, 1] column.
+pub fn read_npy(filename: &str) -> Result<Array, HALError> {
+  let mut file = try!(File::open(Path::new(filename)).map_err(|e| {
+    warn!("unable to open {}: {}", filename, e);
+    HALError::DATA_IO
+  }));
+  let mut bytes = Vec::new();
+  try!(file.read_to_end(&mut bytes).map_err(|_| HALError::DATA_IO));
+  if bytes.len() < 10 || &bytes[0..6] != b"\x93NUMPY" {
+    return Err(HALError::DATA_IO);
+  }
+
+  // version 1 has a 2 byte header length, later versions 4 bytes
+  let le = |b: &[u8]| b.iter().rev().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+  let (header_start, header_len) = match bytes[6] {
+    1 => (10, le(&bytes[8..10]) as usize),
+    _ => (12, le(&bytes[8..12]) as usize),
+  };
+  if bytes.len() < header_start + header_len {
+    return Err(HALError::DATA_IO);
+  }
+  let header = try!(::std::str::from_utf8(&bytes[header_start..header_start + header_len])
+                    .map_err(|_| HALError::DATA_IO));
+
+  let descr = try!(npy_field(header, "descr").and_then(|d| d.split('\'').nth(1)).ok_or(HALError::DATA_IO));
+  let fortran_order = try!(npy_field(header, "fortran_order").ok_or(HALError::DATA_IO)).starts_with("True");
+  let shape = try!(npy_field(header, "shape").and_then(|s| s.find(')').map(|end| &s[1..end]))
+                   .ok_or(HALError::DATA_IO));
+  let mut shape: Vec<u64> = try!(shape.split(',').map(|d| d.trim()).filter(|d| d.len() > 0)
+                                 .map(|d| d.parse::<u64>().map_err(|_| HALError::DATA_IO)).collect());
+  if shape.len() == 0 || shape.len() > 4 {
+    return Err(HALError::SHAPE);
+  }
+  if shape.len() == 1 {
+    shape.push(1);
+  }
+
+  if descr.len() < 2 || descr.starts_with('>') {
+    return Err(HALError::DATA_IO);
+  }
+  let element_size = match &descr[1..] {
+    "f4" | "i4" => 4,
+    "f8" | "i8" => 8,
+    "i2"        => 2,
+    "i1" | "u1" => 1,
+    _           => return Err(HALError::DATA_IO),
+  };
+  let num_elements = shape.iter().fold(1, |acc, d| acc * d) as usize;
+  let data = &bytes[header_start + header_len..];
+  if data.len() < num_elements * element_size {
+    return Err(HALError::DATA_IO);
+  }
+
+  // column major strides of the dims
+  let mut strides = vec![1u64; shape.len()];
+  for d in 1..shape.len() {
+    strides[d] = strides[d - 1] * shape[d - 1];
+  }
+
+  let mut values = vec![0f32; num_elements];
+  for i in 0..num_elements {
+    let b = &data[i * element_size..(i + 1) * element_size];
+    let value = match &descr[1..] {
+      "f4" => f32::from_bits(le(b) as u32),
+      "f8" => f64::from_bits(le(b)) as f32,
+      "i8" => le(b) as i64 as f32,
+      "i4" => le(b) as u32 as i32 as f32,
+      "i2" => le(b) as u16 as i16 as f32,
+      "i1" => b[0] as i8 as f32,
+      _    => b[0] as f32,
+    };
+
+    // the C order index i --> the column major index of the same element
+    let index = match fortran_order {
+      true  => i,
+      false => {
+        let (mut rest, mut index) = (i as u64, 0);
+        for d in (0..shape.len()).rev() {
+          index += (rest % shape[d]) * strides[d];
+          rest /= shape[d];
+        }
+        index as usize
+      },
+    };
+    values[index] = value;
+  }
+
+  let mut dims = [1u64; 4];
+  for (d, &s) in dims.iter_mut().zip(shape.iter()) {
+    *d = s;
+  }
+  Ok(utils::vec_to_array::<f32>(values, Dim4::new(&dims)))
+}
+
+/// Writes the array to a numpy .npy file [little endian f4, fortran order]
+///
+/// The trailing unit dims past the second are dropped, so the file reads back
+/// with the same dims [see `read_npy`].
+pub fn write_npy(filename: &str, input: &Array) -> Result<(), HALError> {
+  let dims = input.dims();
+  let rank = (2..4).rev().find(|&d| dims[d] > 1).map(|d| d + 1).unwrap_or(2);
+  let shape: Vec<String> = (0..rank).map(|d| dims[d].to_string()).collect();
+  let mut header = format!("{{'descr': '<f4', 'fortran_order': True, 'shape': ({},), }}", shape.join(", "));
+
+  // the data starts at a multiple of 64 bytes [magic, version & header length take 10]
+  while (10 + header.len() + 1) % 64 != 0 {
+    header.push(' ');
+  }
+  header.push('\n');
+
+  let mut bytes = Vec::with_capacity(10 + header.len() + 4 * dims.elements() as usize);
+  bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+  bytes.push((header.len() & 0xFF) as u8);
+  bytes.push((header.len() >> 8) as u8);
+  bytes.extend_from_slice(header.as_bytes());
+  for value in utils::array_to_vec(input) {
+    let bits = (value as f32).to_bits();
+    bytes.extend((0..4).map(|b| (bits >> (8 * b)) as u8));
+  }
+
+  let mut file = try!(File::create(Path::new(filename)).map_err(|e| {
+    warn!("unable to create {}: {}", filename, e);
+    HALError::DATA_IO
+  }));
+  file.write_all(&bytes).map_err(|e| {
+    warn!("unable to write {}: {}", filename, e);
+    HALError::DATA_IO
+  })
+}
@@ -0,0 +1,200 @@
+use af;
+use af::{Array, Dim4};
+use rand::Rng;
+use std::cell::{RefCell, Cell};
+
+use utils;
+use random;
+use data::{Data, DataSource, DataParams};
+
+/// Sliding window datasource over a long [num_steps, num_features] series
+///
+/// Every sample is a window of `lookback` consecutive steps [the input], every
+/// step of the window is followed by the next `horizon` steps of the target
+/// columns [the target]. The target of the last step is the forecast of the window.
+/// Windows start every `stride` steps and are split (in order) into contiguous
+/// train, test & validation ranges, so the evaluated windows come after the
+/// trained ones [windows at the split boundaries overlap in time].
+///
+/// Batches are [batch_size, num_features, lookback] inputs & [batch_size,
+/// horizon * num_targets, lookback] targets [the column h * num_targets + c is
+/// the step h + 1 of the target column c]: time is the third dimension, as
+/// expected by the recurrent layers & `Model::fit`. Only the forecast of the
+/// window is trained with `loss_indices` [see `forecast_indices`].
+///
+/// With `normalize` set every window is normalized by its own per feature
+/// mean & standard deviation [over the lookback], the targets are scaled with
+/// the statistics of their input column. This makes the model learn the shape
+/// of the series rather than its level.
+///
+/// # Parameters
+///
+/// - `params` are the data parameters
+/// - `series` is the [num_steps, num_features] series
+/// - `target_columns` are the features that are forecasted
+/// - `lookback` is the number of input steps of a window
+/// - `horizon` is the number of forecasted steps of a window
+/// - `stride` is the number of steps between the start of two windows
+/// - `cursors` are the next window of the train, test & validation ranges
+pub struct TimeSeriesSource {
+  pub params: DataParams,
+  pub series: Array,
+  pub target_columns: Vec<u32>,
+  pub lookback: u64,
+  pub horizon: u64,
+  pub stride: u64,
+  cursors: [Cell<u64>; 3],
+}
+
+impl TimeSeriesSource {
+  pub fn new(series: Array, target_columns: Vec<u32>, lookback: u64, horizon: u64
+             , stride: u64, batch_size: u64, test_fraction: f32
+             , validation_fraction: f32, normalize: bool
+             , is_shuffled: bool) -> TimeSeriesSource
+  {
+    let dims = series.dims();
+    assert!(lookback > 0 && horizon > 0 && stride > 0
+            , "lookback, horizon & stride need to be positive");
+    assert!(dims[0] >= lookback + horizon, "the series is shorter than a single window");
+    assert!(target_columns.len() > 0, "need at least one target column");
+    assert!(target_columns.iter().all(|&c| (c as u64) < dims[1])
+            , "target columns need to be features of the series");
+    assert!(test_fraction + validation_fraction < 1.0
+            , "need some windows left for training");
+
+    let num_windows = (dims[0] - lookback - horizon) / stride + 1;
+    let num_test = (test_fraction * num_windows as f32) as u64;
+    let num_validation = (validation_fraction * num_windows as f32) as u64;
+    let num_train = num_windows - num_test - num_validation;
+    assert!(num_train >= batch_size, "need at least one training batch");
+
+    TimeSeriesSource {
+      params: DataParams {
+        input_dims: Dim4::new(&[batch_size, dims[1], lookback, 1]),
+        target_dims: Dim4::new(&[batch_size, horizon * target_columns.len() as u64, lookback, 1]),
+        shuffle: is_shuffled,
+        normalize: normalize,
+        current_epoch: Cell::new(0),
+        dtype: series.get_type(),
+        num_samples: num_train,
+        num_train: num_train,
+        num_test: num_test,
+        num_validation: match num_validation {
+          0 => None,
+          n => Some(n),
+        },
+      },
+      series: series,
+      target_columns: target_columns,
+      lookback: lookback,
+      horizon: horizon,
+      stride: stride,
+      cursors: [Cell::new(0), Cell::new(0), Cell::new(0)],
+    }
+  }
+
+  /// Returns the (first window, number of windows) of the train [0], test [1] & validation [2] range
+  fn range(&self, split: usize) -> (u64, u64) {
+    let num_validation = self.params.num_validation.unwrap_or(0);
+    match split {
+      0 => (0, self.params.num_train),
+      1 => (self.params.num_train, self.params.num_test),
+      _ => (self.params.num_train + self.params.num_test, num_validation),
+    }
+  }
+
+  /// Gathers the [num_windows, num_columns, length] steps offset..offset + length of every window
+  fn gather(&self, windows: &Vec<u64>, offset: u64, length: u64, columns: Option<&Array>) -> Array {
+    let num_windows = windows.len() as u64;
+    let steps: Vec<u32> = (0..length)
+      .flat_map(|t| windows.iter().map(move |w| (w * self.stride + offset + t) as u32))
+      .collect();
+    let idx = utils::vec_to_array::<u32>(steps, Dim4::new(&[num_windows * length, 1, 1, 1]));
+    let rows = af::lookup(&self.series, &idx, 0);
+    let rows = match columns {
+      Some(columns) => af::lookup(&rows, columns, 1),
+      None          => rows,
+    };
+
+    // [window + num_windows * t, column] --> [window, column, t]
+    let num_columns = rows.dims()[1];
+    af::reorder(&af::moddims(&rows, Dim4::new(&[num_windows, length, num_columns, 1]))
+                , Dim4::new(&[0, 2, 1, 3]))
+  }
+
+  /// Returns the `loss_indices` that only train the forecast of the windows [the last step]
+  ///
+  /// eg: `model.fit::<TimeSeriesSource, f32>(&source, device, epochs, batch_size, None, Some(&source.forecast_indices()), false)`
+  pub fn forecast_indices(&self) -> Vec<bool> {
+    (0..self.lookback).map(|t| t == self.lookback - 1).collect()
+  }
+
+  /// Returns the batch of the provided windows
+  pub fn get_windows(&self, windows: &Vec<u64>) -> Data {
+    let columns = utils::vec_to_array::<u32>(self.target_columns.clone()
+                                             , Dim4::new(&[self.target_columns.len() as u64, 1, 1, 1]));
+    let mut input = self.gather(windows, 0, self.lookback, None);
+    // the step h + 1 after every input step, for all the steps of the horizon
+    let mut target = (1..self.horizon).fold(self.gather(windows, 1, self.lookback, Some(&columns)), |acc, h| {
+      af::join(1, &acc, &self.gather(windows, h + 1, self.lookback, Some(&columns)))
+    });
+
+    if self.params.normalize {
+      let mean = af::mean(&input, 2);
+      let centered = af::sub(&input, &mean, true);
+      let std_dev = af::add(&af::sqrt(&af::mean(&af::mul(&centered, &centered, false), 2))
+                            , &1e-9f32, false); // to not divide by zero
+      let repeat = Dim4::new(&[1, self.horizon, 1, 1]);
+      target = af::div(&af::sub(&target, &af::tile(&af::lookup(&mean, &columns, 1), repeat), true)
+                       , &af::tile(&af::lookup(&std_dev, &columns, 1), repeat), true);
+      input = af::div(&centered, &std_dev, true);
+    }
+
+    Data {
+      input: RefCell::new(Box::new(input)),
+      target: RefCell::new(Box::new(target)),
+    }
+  }
+
+  fn get_batch(&self, split: usize, num_batch: u64) -> Option<Data> {
+    let (first, count) = self.range(split);
+    if count == 0 {
+      return None;
+    }
+
+    let cursor = self.cursors[split].get();
+    self.cursors[split].set((cursor + num_batch) % count);
+    let windows: Vec<u64> = match self.params.shuffle {
+      true  => {
+        let mut rng = random::rng();
+        (0..num_batch).map(|_| first + rng.gen_range(0, count)).collect()
+      },
+      false => (0..num_batch).map(|i| first + (cursor + i) % count).collect(),
+    };
+
+    // track the epochs of the training data [the cursor wrapped around]
+    if split == 0 && self.cursors[0].get() < num_batch {
+      self.params.current_epoch.set(self.params.current_epoch.get() + 1);
+    }
+    Some(self.get_windows(&windows))
+  }
+}
+
+impl DataSource for TimeSeriesSource
+{
+  fn info(&self) -> DataParams {
+    self.params.clone()
+  }
+
+  fn get_train_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(0, num_batch).unwrap()
+  }
+
+  fn get_test_iter(&self, num_batch: u64) -> Data {
+    self.get_batch(1, num_batch).expect("no test windows available")
+  }
+
+  fn get_validation_iter(&self, num_batch: u64) -> Option<Data> {
+    self.get_batch(2, num_batch)
+  }
+}
@@ -6,6 +6,7 @@ use std::sync::{Arc, Mutex};
 use std::cell::{RefCell, Cell};
 
 use utils;
+use random;
 use data::{Data, DataSource, DataParams, Normalize, Shuffle};
 
 pub struct XORSource {
@@ -53,6 +54,7 @@ impl XORSource {
 
     // generate the random type
     //let x_t = utils::constant(self.params.input_dims, self.params.dtype, 0.0f32);
+    random::seed_arrayfire();
     let x_t = af::randu::<bool>(dims);
     let y_t = af::bitxor(&x_t, &lastex);
 
@@ -0,0 +1,129 @@
+use af;
+use af::{Array, Dim4, DType};
+use std::cmp::min;
+
+use utils;
+use random;
+
+/// Draws one class per row among the k most probable classes [renormalized]
+///
+/// `probabilities` are [batch, num_classes] rows [see `Model::predict_proba`], the
+/// draws never leave the device. The draws follow the generator of the current
+/// thread, `random::set_seed` makes them reproducible.
+///
+/// # Return Values
+///
+/// The [batch, 1] u32 drawn classes
+pub fn top_k_sample(probabilities: &Array, k: u64) -> Array {
+  let (values, indices) = utils::top_k(probabilities, k);
+  sample_sorted(&values, &indices)
+}
+
+/// Nucleus [top-p] sampling [Holtzman et al, 2019]
+///
+/// Draws one class per row among the smallest set of most probable classes
+/// whose total probability reaches `p` [the most probable class is always kept].
+///
+/// # Return Values
+///
+/// The [batch, 1] u32 drawn classes
+pub fn nucleus_sample(probabilities: &Array, p: f32) -> Array {
+  assert!(p > 0.0 && p <= 1.0, "p needs to be in (0, 1]");
+  let (values, indices) = utils::argsort(probabilities, 1, false);
+  let preceding = af::sub(&af::accum(&values, 1), &values, false);
+  let kept = utils::cast(&af::lt(&preceding, &p, false), values.get_type());
+  sample_sorted(&af::mul(&values, &kept, false), &indices)
+}
+
+/// Helper that draws a column per row proportionally to the [batch, n] weights & returns its index
+fn sample_sorted(weights: &Array, indices: &Array) -> Array {
+  let (batch_size, num_cols) = (weights.dims()[0], weights.dims()[1]);
+  if num_cols == 1 {
+    return indices.clone();
+  }
+  let cumulative = af::accum(weights, 1);
+  random::seed_arrayfire();
+  let draw = utils::cast(&af::randu::<f32>(Dim4::new(&[batch_size, 1, 1, 1])), weights.get_type());
+  let draw = af::mul(&draw, &af::col(&cumulative, num_cols - 1), false);
+
+  // the drawn column is the number of cumulative weights below the draw [the last one excluded]
+  let below = af::lt(&af::cols(&cumulative, 0, num_cols - 2), &draw, true);
+  let position = af::sum(&utils::cast(&below, DType::U32), 1);
+  utils::gather_cols(indices, &position)
+}
+
+/// One step of beam search: extends every beam by every token & keeps the `beam_width` best
+///
+/// # Parameters
+///
+/// - `scores` are the [num_beams, 1] cumulative log probabilities of the beams
+/// - `log_probs` are the [num_beams, vocabulary] log probabilities of the next token of every beam
+/// - `beam_width` is the number of kept extensions [at most num_beams * vocabulary]
+///
+/// # Return Values
+///
+/// The ([beam_width, 1] scores, [beam_width, 1] u32 parent beams, [beam_width, 1] u32 tokens)
+/// of the kept extensions, best first
+pub fn beam_search_step(scores: &Array, log_probs: &Array, beam_width: u64) -> (Array, Array, Array) {
+  let (num_beams, vocabulary) = (log_probs.dims()[0], log_probs.dims()[1]);
+  assert!(scores.dims()[0] == num_beams, "need a score per beam");
+  let total = af::add(log_probs, &utils::cast(scores, log_probs.get_type()), true);
+
+  // candidate beam + num_beams * token of a single row
+  let candidates = af::moddims(&total, Dim4::new(&[1, num_beams * vocabulary, 1, 1]));
+  let (best, linear) = utils::top_k(&candidates, beam_width);
+  let linear = af::transpose(&linear, false);
+  (af::transpose(&best, false)
+   , af::rem(&linear, &(num_beams as u32), false)
+   , af::div(&linear, &(num_beams as u32), false))
+}
+
+/// Beam search over `length` tokens
+///
+/// The index arrays stay on the device during the search, only the final
+/// sequences are moved to the host.
+///
+/// # Parameters
+///
+/// - `log_probs` are the [1, vocabulary] log probabilities of the first token
+/// - `next` maps the [num_beams, 1] u32 parent beams & tokens of the last step to the
+///   [num_beams, vocabulary] log probabilities of the next tokens [the caller
+///   reorders its recurrent state by the parents]
+///
+/// # Return Values
+///
+/// The token sequences & their log probabilities, best first
+pub fn beam_search<F>(log_probs: &Array, beam_width: u64, length: u64, mut next: F) -> Vec<(Vec<u32>, f32)>
+  where F: FnMut(&Array, &Array) -> Array
+{
+  assert!(length > 0, "need to decode at least one token");
+  let zero = utils::constant(Dim4::new(&[1, 1, 1, 1]), log_probs.get_type(), 0.0);
+  let width = min(beam_width, log_probs.dims()[1]);
+  let (mut scores, parents, tokens) = beam_search_step(&zero, log_probs, width);
+  let mut steps = vec![(parents, tokens)];
+  for _ in 1..length {
+    let next_log_probs = {
+      let &(ref parents, ref tokens) = steps.last().unwrap();
+      next(parents, tokens)
+    };
+    let width = min(beam_width, next_log_probs.dims()[0] * next_log_probs.dims()[1]);
+    let (s, parents, tokens) = beam_search_step(&scores, &next_log_probs, width);
+    scores = s;
+    steps.push((parents, tokens));
+  }
+
+  // follow the parents back from the last step
+  let host: Vec<(Vec<f64>, Vec<f64>)> = steps.iter()
+    .map(|&(ref p, ref t)| (utils::array_to_vec(p), utils::array_to_vec(t))).collect();
+  let final_scores = utils::array_to_vec(&scores);
+  (0..final_scores.len()).map(|beam| {
+    let mut sequence = Vec::with_capacity(host.len());
+    let mut current = beam;
+    for &(ref parents, ref tokens) in host.iter().rev() {
+      sequence.push(tokens[current] as u32);
+      current = parents[current] as usize;
+    }
+    sequence.reverse();
+    (sequence, final_scores[beam] as f32)
+  }).collect()
+}
@@ -1,8 +1,12 @@
 use af;
 use af::{Backend, Array, HasAfEnum};
 use num::Zero;
-//use std::cell::Cell;
-use std::sync::{Arc, Mutex};
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::sync::Arc;
+
+use transfer;
+use transfer::HostBuffer;
 
 pub type DeviceManager = Arc<DeviceManagerFactory>;
 
@@ -12,14 +16,23 @@ pub struct Device {
   pub id: i32,
 }
 
+/// Swaps the ArrayFire backend & device of the calling thread
+///
+/// ArrayFire keeps the active device per thread, so does the manager: a
+/// thread that trains on another device only needs to `swap_device` to it.
+/// The host staging memory of the transfers is also kept per thread and
+/// reused by all of them [see `swap_arrays_backend`].
 pub struct DeviceManagerFactory {
   devices: Vec<Device>,
-  current: Arc<Mutex<Device>>,
 }
 
-// toggle the backend and device
+thread_local!(static CURRENT: Cell<Option<Device>> = Cell::new(None));
+thread_local!(static STAGING: RefCell<HostBuffer<u8>> = RefCell::new(HostBuffer::new()));
+
+// toggle the backend and device [of the calling thread]
 fn set_device(device: Device) {
   af::set_backend(device.backend);
+  af::set_device(device.id);
 }
 
 fn create_devices(backend: Backend) -> Vec<Device> {
@@ -42,34 +55,39 @@ impl DeviceManagerFactory {
     }
 
     assert!(devices.len() > 0);
+    info!("available devices: {:?}", devices);
     devices.push(Device{ backend: Backend::DEFAULT, id:0 });
     let current = devices.last().unwrap().clone();
     set_device(current);
+    CURRENT.with(|c| c.set(Some(current)));
 
     Arc::new(DeviceManagerFactory {
       devices: devices,
-      current: Arc::new(Mutex::new(current)),
     })
   }
 
   pub fn swap_device(&self, device: Device)
   {
-    let mut c = self.current.lock().unwrap();
-    if c.backend != device.backend || c.id != device.id
+    let current = CURRENT.with(|c| c.get());
+    if current != Some(device)
     {
       assert!(self.devices.contains(&device)
               , "device backend = {} | available = {:?}"
               , device.backend, self.devices);
-      // println!("Swapping {}/{} to {}/{}", c.backend, c.id
-      //          , device.backend, device.id);
+      trace!("swapping device {:?} to {}/{}", current, device.backend, device.id);
       set_device(device);
-      *c = device;
+      CURRENT.with(|c| c.set(Some(device)));
     }
   }
 
+  /// Returns all the available devices [the last one is the default device]
+  pub fn get_devices(&self) -> &Vec<Device> {
+    &self.devices
+  }
+
+  /// Returns the device of the calling thread [the default device before its first swap]
   pub fn current_device(&self) -> Device {
-    let c = self.current.lock().unwrap();
-    c.clone()
+    CURRENT.with(|c| c.get()).unwrap_or(*self.devices.last().unwrap())
   }
 
   pub fn swap_array_backend<T>(&self, input: &Array
@@ -77,32 +95,28 @@ impl DeviceManagerFactory {
                                , target_device: Device) -> Array
     where T: HasAfEnum + Zero + Clone
   {
-    // return if the devices match
-    if input_device.id == target_device.id
-        && input_device.backend == target_device.backend
-    {
-      return input.clone() // increases the ref count
-    }
-
-    // we have done something bad if the following triggers
-    let ib = input.get_backend();
-    if input_device.backend != Backend::DEFAULT {
-      assert!(ib == input_device.backend
-              , "provide src was {:?}, but actually {:?}"
-              , input_device.backend
-              , ib);
-    }
-
-    // ensure we are on the old device
-    self.swap_device(input_device);
+    self.swap_arrays_backend::<T>(&[input], input_device, target_device).pop().unwrap()
+  }
 
-    // copy data to the host
-    let dims = input.dims();
-    let mut buffer: Vec<T> = vec![T::zero(); dims.elements() as usize];
-    input.host(&mut buffer);
+  /// Moves several arrays with a single device swap each way [see `transfer::swap_arrays`]
+  ///
+  /// The arrays are staged in the host memory of the calling thread, which
+  /// only grows, so repeated transfers of batches do not allocate.
+  pub fn swap_arrays_backend<T>(&self, inputs: &[&Array]
+                                , input_device: Device
+                                , target_device: Device) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    STAGING.with(|staging| {
+      let mut buffer = mem::replace(&mut *staging.borrow_mut(), HostBuffer::new()).retype::<T>();
+      let outputs = transfer::swap_arrays::<T>(self, &mut buffer, inputs, input_device, target_device);
+      *staging.borrow_mut() = buffer.retype::<u8>();
+      outputs
+    })
+  }
 
-    // swap to the new device
-    self.swap_device(target_device);
-    Array::new::<T>(&buffer, dims)
+  /// Returns the number of bytes of the host staging memory of the calling thread
+  pub fn staging_capacity(&self) -> usize {
+    STAGING.with(|staging| staging.borrow().capacity())
   }
 }
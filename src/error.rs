@@ -18,6 +18,46 @@ pub enum HALError {
   ///
   UNKNOWN_LOSS       =   2,
   ///
+  /// No valid CTC alignment exists for the labels
+  ///
+  CTC_ALIGNMENT      =   3,
+  ///
+  /// Unable to read or write a checkpoint
+  ///
+  CHECKPOINT_IO      =   4,
+  ///
+  /// Checkpoint does not match the model parameters
+  ///
+  CHECKPOINT_MISMATCH =  5,
+  ///
+  /// Unable to read or parse a data file
+  ///
+  DATA_IO            =   6,
+  ///
+  /// Invalid model configuration
+  ///
+  CONFIG             =   7,
+  ///
+  /// The model contains layers that can not be quantized
+  ///
+  QUANTIZATION       =   8,
+  ///
+  /// The model contains layers that can not be explained
+  ///
+  EXPLANATION        =   9,
+  ///
+  /// The shape can not be represented by a 4 dimensional array
+  ///
+  SHAPE              =  10,
+  ///
+  /// Unable to download a file
+  ///
+  DOWNLOAD           =  11,
+  ///
+  /// The checksum of a file does not match the expected one
+  ///
+  CHECKSUM           =  12,
+  ///
   /// Unknown Error
   ///
   UNKNOWN            =   999
@@ -35,6 +75,16 @@ impl Error for HALError {
       HALError::SUCCESS        => "Function returned successfully",
       HALError::GRADIENT_ERROR => "Gradient check error",
       HALError::UNKNOWN_LOSS   => "Unknown loss requested",
+      HALError::CTC_ALIGNMENT  => "No valid CTC alignment for the provided labels",
+      HALError::CHECKPOINT_IO  => "Unable to read or write the checkpoint",
+      HALError::CHECKPOINT_MISMATCH => "Checkpoint does not match the model parameters",
+      HALError::DATA_IO        => "Unable to read or parse the data file",
+      HALError::CONFIG         => "Invalid model configuration",
+      HALError::QUANTIZATION   => "Only dense layers can be quantized",
+      HALError::EXPLANATION    => "Only dense layers can be explained",
+      HALError::SHAPE          => "Only non-empty arrays of up to 4 dimensions are supported",
+      HALError::DOWNLOAD       => "Unable to download the file",
+      HALError::CHECKSUM       => "The checksum of the file does not match",
       HALError::UNKNOWN        => "Unkown Error",
     }
   }
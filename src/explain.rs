@@ -0,0 +1,352 @@
+use af;
+use af::{Array, Dim4, DType, HasAfEnum, MatProp};
+use num::Zero;
+
+use utils;
+use layer;
+use device::Device;
+use error::HALError;
+use model::{Model, Sequential};
+
+/// Stabilizer of the alpha-beta denominators
+const ALPHA_BETA_EPSILON: f32 = 1e-9;
+
+/// Rule that redistributes the relevance of a layer output onto its inputs
+///
+/// With z_j = sum_i a_i w_ij + b_j:
+///
+/// - `Epsilon(eps)`: R_i = sum_j a_i w_ij / (z_j + eps * sign(z_j)) * R_j
+///   [eps = 0 is the plain z-rule, which conserves the relevance when there are no biases]
+/// - `AlphaBeta(alpha, beta)`: positive & negative contributions are redistributed
+///   separately, weighted by alpha & beta [alpha - beta = 1, `AlphaBeta(1, 0)` is the z+ rule]
+#[derive(Clone, Copy, Debug)]
+pub enum LRPRule {
+  Epsilon(f32),
+  AlphaBeta(f32, f32),
+}
+
+fn positive(x: &Array) -> Array {
+  af::mul(x, &utils::cast(&af::gt(x, &0.0f32, false), x.get_type()), false)
+}
+
+fn negative(x: &Array) -> Array {
+  af::sub(x, &positive(x), false)
+}
+
+/// z + eps * sign(z) [sign(0) = 1]
+fn stabilize(z: &Array, eps: f32) -> Array {
+  let sign = af::sub(&af::mul(&utils::cast(&af::ge(z, &0.0f32, false), z.get_type()), &2.0f32, false)
+                     , &1.0f32, false);
+  af::add(z, &af::mul(&sign, &eps, false), false)
+}
+
+/// sign(x) * max(|x| - threshold, 0) [the proximal step of the L1 norm]
+fn soft_threshold(x: &Array, threshold: f32) -> Array {
+  af::add(&positive(&af::sub(x, &threshold, false)), &negative(&af::add(x, &threshold, false)), false)
+}
+
+/// Returns the index of the largest column of every row
+fn argmax_rows(outputs: &Array) -> Vec<usize> {
+  let (batch_size, num_classes) = (outputs.dims()[0] as usize, outputs.dims()[1] as usize);
+  let host = utils::array_to_vec(outputs);
+  (0..batch_size).map(|b| (0..num_classes).fold(0, |best, c| {
+    if host[c * batch_size + b] > host[best * batch_size + b] { c } else { best }
+  })).collect()
+}
+
+/// Returns the [num_rows, num_classes] one-hot rows of the provided classes
+fn one_hot_rows(classes: &Vec<usize>, num_classes: usize) -> Array {
+  let mut values = vec![0.0f32; classes.len() * num_classes];
+  for (b, &class) in classes.iter().enumerate() {
+    assert!(class < num_classes, "class {} of {} classes", class, num_classes);
+    values[class * classes.len() + b] = 1.0;
+  }
+  utils::vec_to_array::<f32>(values, Dim4::new(&[classes.len() as u64, num_classes as u64, 1, 1]))
+}
+
+/// Redistributes the relevance of the outputs of a dense layer onto its inputs
+///
+/// # Parameters
+///
+/// - `input` is the [batch_size, input_size] input of the layer
+/// - `weight` is the [input_size, output_size] weight matrix
+/// - `bias` is the bias of the layer
+/// - `relevance` is the [batch_size, output_size] relevance of the outputs
+/// - `rule` is the redistribution rule
+pub fn dense_relevance(input: &Array, weight: &Array, bias: &Array
+                       , relevance: &Array, rule: LRPRule) -> Array
+{
+  let backward = |s: &Array, w: &Array| af::matmul(s, w, MatProp::NONE, MatProp::TRANS);
+  match rule {
+    LRPRule::Epsilon(eps)          => {
+      let z = layer::linear(input, weight, Some(bias), "linear");
+      let s = af::div(relevance, &stabilize(&z, eps), false);
+      af::mul(input, &backward(&s, weight), false)
+    },
+    LRPRule::AlphaBeta(alpha, beta) => {
+      let (ap, an) = (positive(input), negative(input));
+      let (wp, wn) = (positive(weight), negative(weight));
+      let matmul = |a: &Array, w: &Array| af::matmul(a, w, MatProp::NONE, MatProp::NONE);
+
+      // positive & negative contributions a_i * w_ij
+      let zp = af::add(&matmul(&ap, &wp), &matmul(&an, &wn), false);
+      let zn = af::add(&matmul(&ap, &wn), &matmul(&an, &wp), false);
+      let sp = af::div(relevance, &af::add(&zp, &ALPHA_BETA_EPSILON, false), false);
+      let sn = af::div(relevance, &af::sub(&zn, &ALPHA_BETA_EPSILON, false), false);
+
+      let rp = af::add(&af::mul(&ap, &backward(&sp, &wp), false)
+                       , &af::mul(&an, &backward(&sp, &wn), false), false);
+      let rn = af::add(&af::mul(&ap, &backward(&sn, &wn), false)
+                       , &af::mul(&an, &backward(&sn, &wp), false), false);
+      af::sub(&af::mul(&rp, &alpha, false), &af::mul(&rn, &beta, false), false)
+    },
+  }
+}
+
+/// Layer-wise relevance propagation [Bach et al, 2015] of a dense model
+///
+/// The relevance of the explained output [the output of the last layer for
+/// the target class] is propagated back to the inputs with the provided rule.
+/// Elementwise activations pass the relevance through unchanged.
+///
+/// # Parameters
+///
+/// - `model` is the model to explain
+/// - `inputs` is a [batch_size, input_size] array of samples
+/// - `src_device` is the device of the inputs [& of the returned relevances]
+/// - `target` is the class to explain [None: the highest scoring class of every sample]
+/// - `rule` is the redistribution rule of every layer
+///
+/// # Return Values
+///
+/// [batch_size, input_size] relevance of every input feature. With online
+/// normalization [see `Sequential::set_online_normalization`] the relevance
+/// is that of the normalized inputs.
+pub fn lrp<T>(model: &mut Sequential, inputs: &Array, src_device: Device
+              , target: Option<usize>, rule: LRPRule) -> Result<Array, HALError>
+  where T: HasAfEnum + Zero + Clone
+{
+  if model.get_layer_configs().iter().any(|&(ref layer, _)| layer.to_lowercase() != "dense") {
+    return Err(HALError::EXPLANATION);
+  }
+  assert!(inputs.dims()[2] <= 1, "relevances can only be propagated for a single time-step");
+
+  let device = model.get_device();
+  let manager = model.get_manager();
+  model.forward_all_activations::<T>(inputs, src_device, device);
+  let params = model.get_param_manager();
+  let last = params.num_layers() - 1;
+
+  // keep the output of the explained class only
+  let outputs = params.get_output(last, 0);
+  let classes = match target {
+    Some(class) => vec![class; outputs.dims()[0] as usize],
+    None        => argmax_rows(&outputs),
+  };
+  let mask = one_hot_rows(&classes, outputs.dims()[1] as usize);
+  let mut relevance = af::mul(&outputs, &utils::cast(&mask, outputs.get_type()), false);
+
+  for layer in (0..last + 1).rev() {
+    let dtype = params.get_dtype(layer);
+    relevance = dense_relevance(&params.get_input(layer, 0), &params.get_weight(layer, 0)
+                                , &params.get_bias(layer, 0), &utils::cast(&relevance, dtype), rule);
+  }
+  params.reset_all_unrolls();
+
+  let relevance = utils::cast(&relevance, T::get_af_dtype());
+  Ok(manager.swap_array_backend::<T>(&relevance, device, src_device))
+}
+
+/// The outcome of a counterfactual search [see `counterfactual`]
+///
+/// # Parameters
+///
+/// - `input` is the counterfactual input
+/// - `perturbation` is the change of the original input [input - original]
+/// - `found` is set when every sample is predicted as the desired class
+/// - `iterations` is the number of gradient steps that were taken
+/// - `l1_distance` is the L1 norm of the perturbation
+pub struct Counterfactual {
+  pub input: Array,
+  pub perturbation: Array,
+  pub found: bool,
+  pub iterations: u64,
+  pub l1_distance: f32,
+}
+
+/// Searches a minimal perturbation of the input that changes the prediction to the desired class
+///
+/// Minimizes loss(model(x'), desired) + l1_penalty * |x' - x|_1 with proximal
+/// gradient steps on the input, which keeps the perturbation sparse. The search
+/// stops as soon as every sample is predicted as the desired class.
+///
+/// The backward passes accumulate parameter gradients, these are zeroed after
+/// every step, so this should not be called in between `backward` & `step`.
+///
+/// # Parameters
+///
+/// - `model` is the model to debug
+/// - `input` is a [batch_size, input_size] array of samples
+/// - `src_device` is the device of the input [& of the counterfactual]
+/// - `desired_class` is the prediction to achieve
+/// - `l1_penalty` is the weight of the L1 distance to the original input
+/// - `learning_rate` is the step size of the search
+/// - `max_iterations` is the maximum number of gradient steps
+pub fn counterfactual<T>(model: &mut Sequential, input: &Array, src_device: Device
+                         , desired_class: usize, l1_penalty: f32, learning_rate: f32
+                         , max_iterations: u64) -> Counterfactual
+  where T: HasAfEnum + Zero + Clone
+{
+  let device = model.get_device();
+  let manager = model.get_manager();
+  let original = manager.swap_array_backend::<T>(input, src_device, device);
+  let mut current = original.copy();
+  let mut target = None;
+  let mut found = false;
+  let mut iterations = 0;
+
+  while iterations < max_iterations {
+    // the stopping test uses the deterministic prediction [dropout disabled]
+    let inferred = model.infer::<T>(&current, device);
+    let classes = argmax_rows(&inferred[0]);
+    if classes.iter().all(|&class| class == desired_class) {
+      found = true;
+      break;
+    }
+
+    if target.is_none() {
+      let desired = vec![desired_class; classes.len()];
+      target = Some(utils::cast(&one_hot_rows(&desired, inferred[0].dims()[1] as usize)
+                                , inferred[0].get_type()));
+    }
+    let predictions = model.forward::<T>(&current, device, device);
+    let (_, input_deltas) = model.backward_inputs(&predictions, target.as_ref().unwrap(), None);
+    model.get_param_manager().zero_all_deltas();
+
+    // gradient step on the loss followed by the proximal step of the L1 penalty
+    let gradient = utils::cast(&input_deltas[0], current.get_type());
+    let moved = af::sub(&af::sub(&current, &af::mul(&gradient, &learning_rate, false), false)
+                        , &original, false);
+    current = af::add(&original, &soft_threshold(&moved, learning_rate * l1_penalty), false);
+    current.eval();
+    iterations += 1;
+  }
+
+  let perturbation = af::sub(&current, &original, false);
+  let l1_distance = af::sum_all(&af::abs(&perturbation)).0 as f32;
+  Counterfactual {
+    input: manager.swap_array_backend::<T>(&current, device, src_device),
+    perturbation: manager.swap_array_backend::<T>(&perturbation, device, src_device),
+    found: found,
+    iterations: iterations,
+    l1_distance: l1_distance,
+  }
+}
+
+/// Stacks the per sample gradients of all the parameters into a [num_samples, num_params] matrix
+fn gradient_matrix<T>(model: &mut Sequential, input: &Array, target: &Array, src_device: Device
+                      , param_indices: Option<&Vec<usize>>, batch_size: u64) -> Array
+  where T: HasAfEnum + Zero + Clone
+{
+  let device = model.get_device();
+  let mut samples = model.get_manager().swap_arrays_backend::<T>(&[input, target], src_device, device);
+  let (target, input) = (samples.pop().unwrap(), samples.pop().unwrap());
+  let num_samples = input.dims()[0];
+  let mut matrix: Option<Array> = None;
+  let mut first = 0;
+  while first < num_samples {
+    let last = ::std::cmp::min(first + batch_size, num_samples) - 1;
+    let (_, gradients) = model.per_sample_gradients::<T>(&af::rows(&input, first, last)
+                                                         , &af::rows(&target, first, last)
+                                                         , device, param_indices);
+    let rows = gradients.iter().skip(1).fold(utils::cast(&gradients[0], DType::F32), |acc, g| {
+      af::join(1, &acc, &utils::cast(g, DType::F32))
+    });
+    matrix = Some(match matrix {
+      None             => {
+        let dims = Dim4::new(&[num_samples, rows.dims()[1], 1, 1]);
+        utils::set_row_planes(&utils::constant(dims, DType::F32, 0.0f32), &rows, first, last)
+      },
+      Some(ref matrix) => utils::set_row_planes(matrix, &rows, first, last),
+    });
+    first = last + 1;
+  }
+  matrix.unwrap()
+}
+
+fn dot(a: &Array, b: &Array) -> f32 {
+  af::sum_all(&af::mul(a, b, false)).0 as f32
+}
+
+/// Estimates the influence of every training sample on the loss of the test samples
+///
+/// Influence functions [Koh & Liang, 2017] approximate the change of the test
+/// loss when a training sample is upweighted: -grad_test^T H^-1 grad_i. The
+/// Hessian is approximated by the damped empirical Fisher of the training
+/// gradients [G^T G / n + damping * I] and the inverse Hessian-vector product
+/// is solved with conjugate gradients, so only per sample gradients are needed
+/// [see `Sequential::per_sample_gradients`].
+///
+/// Positive scores mark harmful samples [upweighting them increases the test
+/// loss], mislabeled samples typically have the largest scores. The gradients
+/// of all the training samples are kept on the device: restricting the
+/// parameters [eg: to the last layer] keeps this tractable for larger models.
+///
+/// # Parameters
+///
+/// - `model` is the trained model
+/// - `train_input` & `train_target` are the training samples to score
+/// - `test_input` & `test_target` are the predictions to explain
+/// - `src_device` is the device of the samples
+/// - `param_indices` are the parameters to use [None: all of them, see `Sequential::per_sample_gradients`]
+/// - `damping` is added to the diagonal of the Hessian approximation
+/// - `cg_iterations` is the maximum number of conjugate gradient steps
+/// - `batch_size` is the number of samples whose gradients are computed at once
+///
+/// # Return Values
+///
+/// The influence of every training sample [in the order of the rows of `train_input`]
+pub fn influence<T>(model: &mut Sequential, train_input: &Array, train_target: &Array
+                    , test_input: &Array, test_target: &Array, src_device: Device
+                    , param_indices: Option<&Vec<usize>>, damping: f32
+                    , cg_iterations: u64, batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  assert!(damping > 0.0, "the damping needs to be positive");
+  let train = gradient_matrix::<T>(model, train_input, train_target, src_device, param_indices, batch_size);
+  let test = gradient_matrix::<T>(model, test_input, test_target, src_device, param_indices, batch_size);
+  let num_train = train.dims()[0] as f32;
+
+  // (G^T G / n + damping * I) v
+  let hvp = |v: &Array| {
+    let gv = af::matmul(&train, v, MatProp::NONE, MatProp::NONE);
+    af::add(&af::div(&af::matmul(&train, &gv, MatProp::TRANS, MatProp::NONE), &num_train, false)
+            , &af::mul(v, &damping, false), false)
+  };
+
+  // conjugate gradients for H s = grad_test [summed over the test samples]
+  let b = af::transpose(&af::sum(&test, 0), false);
+  let mut s = utils::constant(b.dims(), DType::F32, 0.0f32);
+  let mut r = b.copy();
+  let mut p = b.copy();
+  let mut rs = dot(&r, &r);
+  let tolerance = 1e-10 * rs;
+  for _ in 0..cg_iterations {
+    if rs <= tolerance {
+      break;
+    }
+    let hp = hvp(&p);
+    let alpha = rs / dot(&p, &hp);
+    s = af::add(&s, &af::mul(&p, &alpha, false), false);
+    r = af::sub(&r, &af::mul(&hp, &alpha, false), false);
+    let rs_next = dot(&r, &r);
+    p = af::add(&r, &af::mul(&p, &(rs_next / rs), false), false);
+    rs = rs_next;
+    s.eval();
+    r.eval();
+    p.eval();
+  }
+
+  let scores = af::mul(&af::matmul(&train, &s, MatProp::NONE, MatProp::NONE), &-1.0f32, false);
+  utils::array_to_vec(&scores).iter().map(|&v| v as f32).collect()
+}
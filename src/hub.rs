@@ -0,0 +1,235 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::collections::BTreeMap;
+use rustc_serialize::json;
+use hyper::Client;
+use hyper::header::Connection;
+use hyper::status::StatusCode;
+use sha2::{Digest, Sha256};
+
+use checkpoint::Checkpoint;
+use config::ModelConfig;
+use device::{Device, DeviceManager};
+use error::HALError;
+use model::Sequential;
+
+/// A pretrained model: the architecture & the trained parameters
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct Pretrained {
+  pub config: ModelConfig,
+  pub checkpoint: Checkpoint,
+}
+
+/// A named pretrained model of the hub
+///
+/// # Parameters
+///
+/// - `name` is the name that the model is fetched by
+/// - `url` is the location of the model file [http:// or file:// for local mirrors]
+/// - `sha256` is the hex digest of the model file [64 hex characters]
+///
+/// hyper is built without its ssl feature, https:// urls are rejected when the
+/// entry is registered. The checksum still verifies the files served over http.
+/// - `description` describes the model & the data it was trained on
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug, PartialEq)]
+pub struct HubEntry {
+  pub name: String,
+  pub url: String,
+  pub sha256: String,
+  pub description: String,
+}
+
+/// Registry of named pretrained models with a local cache
+///
+/// Models are downloaded once into `cache_dir` & verified against their
+/// checksum every time they are fetched, corrupted or outdated cache files
+/// are downloaded again.
+///
+/// eg: start the fine-tuning of an example from pretrained weights
+/// `let mut model = try!(hub.load("mnist_mlp", manager, device));`
+pub struct ModelHub {
+  pub cache_dir: String,
+  entries: BTreeMap<String, HubEntry>,
+}
+
+impl ModelHub {
+  pub fn new(cache_dir: &str) -> ModelHub {
+    ModelHub {
+      cache_dir: cache_dir.to_string(),
+      entries: BTreeMap::new(),
+    }
+  }
+
+  /// Creates a hub with the entries of a json index [a list of `HubEntry`]
+  pub fn from_index(cache_dir: &str, index: &str) -> Result<ModelHub, HALError> {
+    let entries: Vec<HubEntry> = try!(json::decode(index).map_err(|e| {
+      warn!("unable to parse the hub index: {}", e);
+      HALError::CONFIG
+    }));
+    let mut hub = ModelHub::new(cache_dir);
+    for entry in entries {
+      try!(hub.register(entry));
+    }
+    Ok(hub)
+  }
+
+  /// Adds a model to the hub [replaces the entry of the same name]
+  ///
+  /// Entries with an unsupported url scheme or a malformed digest are rejected [see `HubEntry`].
+  pub fn register(&mut self, entry: HubEntry) -> Result<(), HALError> {
+    if !is_sha256(&entry.sha256) {
+      warn!("invalid sha256 digest {} of the hub model {}", entry.sha256, entry.name);
+      return Err(HALError::CONFIG);
+    }
+    if !is_supported_url(&entry.url) {
+      warn!("unsupported url {} of the hub model {}, only http:// & file:// are supported"
+            , entry.url, entry.name);
+      return Err(HALError::CONFIG);
+    }
+    self.entries.insert(entry.name.clone(), entry);
+    Ok(())
+  }
+
+  /// Returns all the entries of the hub [sorted by name]
+  pub fn list(&self) -> Vec<&HubEntry> {
+    self.entries.values().collect()
+  }
+
+  /// Returns the cache path of the named model [the file may not exist yet]
+  ///
+  /// Names with path separators [or a parent directory] & digests that are
+  /// not 64 hex characters are rejected, the cache files always stay inside `cache_dir`.
+  pub fn cache_path(&self, name: &str) -> Result<String, HALError> {
+    let entry = try!(self.entries.get(name).ok_or(HALError::CONFIG));
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.contains("..") {
+      warn!("invalid hub model name {}", name);
+      return Err(HALError::CONFIG);
+    }
+    if !is_sha256(&entry.sha256) {
+      warn!("invalid sha256 digest {} of the hub model {}", entry.sha256, name);
+      return Err(HALError::CONFIG);
+    }
+    let short: String = entry.sha256.to_lowercase().chars().take(12).collect();
+    Ok(format!("{}/{}.{}.json", self.cache_dir, name, short))
+  }
+
+  /// Returns the path of the verified local copy of the named model, downloading it if needed
+  pub fn fetch(&self, name: &str) -> Result<String, HALError> {
+    let entry = try!(self.entries.get(name).ok_or(HALError::CONFIG));
+    let path = try!(self.cache_path(name));
+    if Path::new(&path).is_file() {
+      if let Ok(bytes) = read_bytes(&path) {
+        if sha256(&bytes) == entry.sha256.to_lowercase() {
+          debug!("using the cached {}", path);
+          return Ok(path);
+        }
+      }
+      warn!("the cached {} is corrupted, downloading it again", path);
+    }
+
+    let bytes = try!(download(&entry.url));
+    let digest = sha256(&bytes);
+    if digest != entry.sha256.to_lowercase() {
+      warn!("{} has the checksum {}, the hub expects {}", entry.url, digest, entry.sha256);
+      return Err(HALError::CHECKSUM);
+    }
+
+    // write to a temporary file first so that an interrupted write is never cached
+    try!(fs::create_dir_all(&self.cache_dir).map_err(|_| HALError::CHECKPOINT_IO));
+    let partial = format!("{}.partial", path);
+    try!(File::create(&partial).and_then(|mut f| f.write_all(&bytes))
+         .map_err(|_| HALError::CHECKPOINT_IO));
+    try!(fs::rename(&partial, &path).map_err(|_| HALError::CHECKPOINT_IO));
+    info!("cached {} [{} bytes]", path, bytes.len());
+    Ok(path)
+  }
+
+  /// Builds the named model with its pretrained parameters, ready for fine-tuning
+  ///
+  /// The model gets the optimizer the model was saved with [its state is not saved]
+  pub fn load(&self, name: &str, manager: DeviceManager, device: Device) -> Result<Sequential, HALError> {
+    let path = try!(self.fetch(name));
+    load_file(&path, manager, device)
+  }
+}
+
+/// Builds the model of a hub file [see `export`]
+pub fn load_file(path: &str, manager: DeviceManager, device: Device) -> Result<Sequential, HALError> {
+  let contents = try!(read_bytes(path));
+  let contents = try!(String::from_utf8(contents).map_err(|_| HALError::CHECKPOINT_IO));
+  let pretrained: Pretrained = try!(json::decode(&contents).map_err(|_| HALError::CHECKPOINT_IO));
+  let model = try!(pretrained.config.build(manager.clone(), device));
+  manager.swap_device(device);
+  try!(pretrained.checkpoint.restore(model.get_param_manager()));
+  Ok(model)
+}
+
+/// Writes the model as a hub file & returns its checksum [see `HubEntry`]
+pub fn export(model: &Sequential, path: &str) -> Result<String, HALError> {
+  model.get_manager().swap_device(model.get_device());
+  let pretrained = Pretrained {
+    config: ModelConfig::from_model(model),
+    checkpoint: Checkpoint::from_params(model.get_param_manager(), 0),
+  };
+  let encoded = try!(json::encode(&pretrained).map_err(|_| HALError::CHECKPOINT_IO));
+  try!(File::create(path).and_then(|mut f| f.write_all(encoded.as_bytes())).map_err(|e| {
+    warn!("unable to write {}: {}", path, e);
+    HALError::CHECKPOINT_IO
+  }));
+  Ok(sha256(encoded.as_bytes()))
+}
+
+/// Returns the lower case hex sha256 digest of the bytes
+pub fn sha256(bytes: &[u8]) -> String {
+  let mut hasher = Sha256::new();
+  hasher.input(bytes);
+  hasher.result().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Helper to check that a digest is 64 hex characters [see `sha256`]
+fn is_sha256(digest: &str) -> bool {
+  digest.len() == 64 && digest.chars().all(|c| c.is_digit(16))
+}
+
+/// Helper to check that the url can be downloaded [hyper is built without ssl]
+fn is_supported_url(url: &str) -> bool {
+  url.starts_with("http://") || url.starts_with("file://")
+}
+
+/// Helper to read a whole file
+fn read_bytes(path: &str) -> Result<Vec<u8>, HALError> {
+  let mut bytes = Vec::new();
+  try!(File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)).map_err(|e| {
+    warn!("unable to read {}: {}", path, e);
+    HALError::CHECKPOINT_IO
+  }));
+  Ok(bytes)
+}
+
+/// Helper to download a url [file:// urls are read from the local file system]
+///
+/// Only http:// & file:// urls are supported, hyper is built without ssl.
+fn download(url: &str) -> Result<Vec<u8>, HALError> {
+  if url.starts_with("file://") {
+    return read_bytes(&url["file://".len()..]).map_err(|_| HALError::DOWNLOAD);
+  }
+  if !is_supported_url(url) {
+    warn!("unable to download {}: only http:// & file:// urls are supported [no ssl]", url);
+    return Err(HALError::DOWNLOAD);
+  }
+
+  info!("downloading {}", url);
+  let client = Client::new();
+  let mut res = try!(client.get(url).header(Connection::close()).send().map_err(|e| {
+    warn!("unable to download {}: {}", url, e);
+    HALError::DOWNLOAD
+  }));
+  if res.status != StatusCode::Ok {
+    warn!("unable to download {}: {}", url, res.status);
+    return Err(HALError::DOWNLOAD);
+  }
+  let mut body = Vec::new();
+  try!(res.read_to_end(&mut body).map_err(|_| HALError::DOWNLOAD));
+  Ok(body)
+}
@@ -1,9 +1,8 @@
 use af;
-use rand;
-use rand::Rng;
 use af::{Dim4, Array, HasAfEnum};
 
 use utils;
+use random;
 use error::HALError;
 
 /// A helper to provide the scaling for uniform and normal
@@ -23,8 +22,7 @@ pub fn get_fans(dims: Dim4) -> (f32, f32){
 /// A helper to return a normal shape with the provided scale
 pub fn normal<T: HasAfEnum>(dims: Dim4, scale: f32) -> Array {
   // seed device
-  let mut rng = rand::thread_rng();
-  af::set_seed(rng.gen::<u64>());
+  random::seed_arrayfire();
 
   let src_type = T::get_af_dtype();
   let scale_vec = utils::constant(dims, src_type, scale);
@@ -39,8 +37,7 @@ pub fn normal<T: HasAfEnum>(dims: Dim4, scale: f32) -> Array {
 /// A helper to provide a uniform shape with the provided scale
 pub fn uniform<T: HasAfEnum>(dims: Dim4, min: f32, max: f32) -> Array{
   // seed device
-  let mut rng = rand::thread_rng();
-  af::set_seed(rng.gen::<u64>());
+  random::seed_arrayfire();
 
   let src_type = T::get_af_dtype();
   let range = max - min;
@@ -0,0 +1,84 @@
+use af::{Array, Dim4, DType};
+use ndarray::{ArrayD, IxDyn, ShapeBuilder};
+use std::cmp::max;
+use std::convert::TryFrom;
+
+use utils;
+use error::HALError;
+
+/// An `af::Array` that converts to & from `ndarray::ArrayD<f32>`
+///
+/// ArrayFire is column major & limited to 4 dimensions, ndarrays of up to 4
+/// axes keep their logical shape [eg: a [batch_size, features] ndarray is a
+/// [batch_size, features] input of a model]. Arrays come back as ndarrays
+/// with at least 2 axes [trailing unit axes are dropped] & are cast to f32.
+///
+/// eg: `let input: Array = Tensor::try_from(&features)?.into();`
+pub struct Tensor(pub Array);
+
+impl From<Array> for Tensor {
+  fn from(array: Array) -> Tensor {
+    Tensor(array)
+  }
+}
+
+impl From<Tensor> for Array {
+  fn from(tensor: Tensor) -> Array {
+    tensor.0
+  }
+}
+
+impl<'a> TryFrom<&'a ArrayD<f32>> for Tensor {
+  type Error = HALError;
+
+  fn try_from(array: &'a ArrayD<f32>) -> Result<Tensor, HALError> {
+    let shape = array.shape();
+    if shape.len() > 4 || array.len() == 0 {
+      return Err(HALError::SHAPE);
+    }
+
+    let mut dims = [1u64; 4];
+    for (d, &s) in dims.iter_mut().zip(shape.iter()) {
+      *d = s as u64;
+    }
+
+    // iterating the reversed axes visits the elements in column major order
+    let values: Vec<f32> = array.view().reversed_axes().iter().cloned().collect();
+    Ok(Tensor(utils::vec_to_array::<f32>(values, Dim4::new(&dims))))
+  }
+}
+
+impl TryFrom<ArrayD<f32>> for Tensor {
+  type Error = HALError;
+
+  fn try_from(array: ArrayD<f32>) -> Result<Tensor, HALError> {
+    Tensor::try_from(&array)
+  }
+}
+
+impl<'a> From<&'a Tensor> for ArrayD<f32> {
+  fn from(tensor: &'a Tensor) -> ArrayD<f32> {
+    let dims = tensor.0.dims();
+    let num_axes = max(dims.ndims(), 2);
+    let shape: Vec<usize> = (0..num_axes).map(|i| dims[i] as usize).collect();
+
+    let array = utils::cast(&tensor.0, DType::F32);
+    let mut values = vec![0.0f32; dims.elements() as usize];
+    array.host(&mut values);
+    ArrayD::from_shape_vec(IxDyn(&shape).f(), values)
+      .expect("the number of elements matches the dimensions")
+  }
+}
+
+impl From<Tensor> for ArrayD<f32> {
+  fn from(tensor: Tensor) -> ArrayD<f32> {
+    ArrayD::from(&tensor)
+  }
+}
+
+impl Tensor {
+  /// Returns the wrapped array
+  pub fn array(&self) -> &Array {
+    &self.0
+  }
+}
@@ -0,0 +1,71 @@
+use af;
+use af::{Array};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use utils;
+use random;
+use layer::{Layer};
+use params::Params;
+
+/// Inverted dropout: zeroes every input with probability `rate` and scales
+/// the kept ones by 1 / (1 - rate), so no rescaling is needed at inference
+///
+/// The masks are only drawn while `active` is set: the model sets it during
+/// training and clears it for inference [see `Sequential::infer`], unless
+/// Monte-Carlo dropout is enabled [see `Sequential::set_mc_dropout`].
+/// The mask of every unroll is kept in the `optional` params for the backward pass.
+pub struct Dropout {
+  pub size: usize,
+  pub rate: f32,
+  pub active: Arc<AtomicBool>,
+}
+
+impl Layer for Dropout
+{
+  fn forward(&self, params: Arc<Mutex<Params>>, inputs: &Array, _state: Option<&Vec<Array>>) -> (Array, Option<Vec<Array>>)
+  {
+    // get a handle to the underlying params
+    let mut ltex = params.lock().unwrap();
+
+    let mask = match self.active.load(Ordering::SeqCst) && self.rate > 0.0 {
+      true  => {
+        random::seed_arrayfire();
+        let kept = af::ge(&af::randu::<f32>(inputs.dims()), &self.rate, false);
+        utils::cast(&af::div(&utils::cast(&kept, af::DType::F32), &(1.0 - self.rate), false)
+                    , inputs.get_type())
+      },
+      false => utils::constant(inputs.dims(), inputs.get_type(), 1.0f32),
+    };
+    let a_t = af::mul(inputs, &mask, false);
+
+    // parameter manager keeps the output, inputs & masks
+    let current_unroll = ltex.current_unroll;
+    if ltex.inputs.len() > current_unroll { // store in existing
+      ltex.inputs[current_unroll] = inputs.clone();
+      ltex.outputs[current_unroll] = a_t.clone();
+      ltex.optional[current_unroll] = mask;
+    }else{                                  // add new
+      ltex.inputs.push(inputs.clone());
+      ltex.outputs.push(a_t.clone());
+      ltex.optional.push(mask);
+    }
+
+    // update location in vector
+    ltex.current_unroll += 1;
+
+    (a_t, None)
+  }
+
+  fn backward(&self, params: Arc<Mutex<Params>>, delta: &Array) -> Array
+  {
+    // get a handle to the underlying params
+    let mut ltex = params.lock().unwrap();
+    let current_unroll = ltex.current_unroll;
+    assert!(current_unroll > 0
+            , "Cannot call backward pass without at least 1 forward pass");
+
+    ltex.current_unroll -= 1;
+    af::mul(delta, &ltex.optional[current_unroll - 1], false)
+  }
+}
@@ -6,6 +6,12 @@ mod unitary;
 pub use self::rnn::RNN;
 mod rnn;
 
+pub use self::ordinal::Ordinal;
+mod ordinal;
+
+pub use self::dropout::Dropout;
+mod dropout;
+
 // pub use self::lstm::LSTM;
 // mod lstm;
 
@@ -16,7 +22,7 @@ use std::sync::{Arc, Mutex};
 
 use activations;
 
-pub trait Layer {
+pub trait Layer: Send {
   fn forward(&self, params: Arc<Mutex<Params>>, inputs: &Array, state: Option<&Vec<Array>>) -> (Array, Option<Vec<Array>>);
   fn backward(&self, params: Arc<Mutex<Params>>, delta: &Array) -> Array;
 }
@@ -0,0 +1,70 @@
+use af;
+use af::{Array};
+use std::sync::{Arc, Mutex};
+
+use layer::{Layer};
+use params::Params;
+
+/// Cumulative link (CORAL) output layer for ordinal targets
+///
+/// A single weight vector is shared across all K-1 rank thresholds and
+/// each threshold gets its own bias. This produces K-1 logits per sample
+/// (one for each P(y > k)) which are rank consistent by construction.
+/// The logits are expected to be paired with the `ordinal_cross_entropy` loss.
+pub struct Ordinal {
+  pub input_size: usize,
+  pub output_size: usize, // number of classes - 1
+}
+
+impl Layer for Ordinal
+{
+  fn forward(&self, params: Arc<Mutex<Params>>, inputs: &Array, state: Option<&Vec<Array>>) -> (Array, Option<Vec<Array>>)
+  {
+    // get a handle to the underlying params
+    let mut ltex = params.lock().unwrap();
+
+    // w_x = xw             [batch, 1]
+    // z_t = w_x + b^T      [batch, K-1] (the shared projection is broadcast over thresholds)
+    let wx = af::matmul(inputs, &ltex.weights[0], af::MatProp::NONE, af::MatProp::NONE);
+    let a_t = af::add(&wx, &af::transpose(&ltex.biases[0], false), true);
+
+    // parameter manager keeps the output & inputs
+    let current_unroll = ltex.current_unroll;
+    if ltex.inputs.len() > current_unroll { // store in existing
+      ltex.inputs[current_unroll] = inputs.clone();
+      ltex.outputs[current_unroll] = a_t.clone();
+    }else{                                  // add new
+      ltex.inputs.push(inputs.clone());
+      ltex.outputs.push(a_t.clone());
+    }
+
+    // update location in vector
+    ltex.current_unroll += 1;
+
+    (a_t.clone(), None) // clone just increases the ref count
+  }
+
+  fn backward(&self, params: Arc<Mutex<Params>>, delta: &Array) -> Array
+  {
+    // get a handle to the underlying params
+    let mut ltex = params.lock().unwrap();
+    let current_unroll = ltex.current_unroll;
+    assert!(current_unroll > 0
+            , "Cannot call backward pass without at least 1 forward pass");
+
+    // the logits are linear, so the only coupling is the shared weight:
+    // delta_s = sum_{thresholds} delta     [batch, 1]
+    // dw      = x^T * delta_s              [input, 1]
+    // db      = sum_{batch} delta          [K-1, 1]
+    let delta_s = af::sum(delta, 1);
+    let dw = af::matmul(&ltex.inputs[current_unroll - 1], &delta_s
+                        , af::MatProp::TRANS, af::MatProp::NONE);
+    let db = af::transpose(&af::sum(delta, 0), false);
+    ltex.deltas[0] = af::add(&ltex.deltas[0], &dw, false);
+    ltex.deltas[1] = af::add(&ltex.deltas[1], &db, false);
+
+    ltex.current_unroll -= 1;
+
+    af::matmul(&delta_s, &ltex.weights[0], af::MatProp::NONE, af::MatProp::TRANS)
+  }
+}
@@ -10,6 +10,12 @@ extern crate hyper;
 extern crate spmc;
 extern crate statistical;
 extern crate rustc_serialize;
+extern crate sha2;
+#[macro_use] extern crate log;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
 
 pub use layer::{Layer};
 pub mod layer;
@@ -26,8 +32,30 @@ pub mod data;
 pub mod params;
 pub mod error;
 pub mod loss;
+pub mod metrics;
+pub mod checkpoint;
+pub mod config;
+pub mod hub;
+pub mod quantize;
+pub mod conformal;
+pub mod prune;
+pub mod monitor;
+pub mod tuning;
+pub mod report;
+pub mod random;
+pub mod testing;
+pub mod explain;
+pub mod callback;
+pub mod privacy;
+pub mod coreset;
+pub mod active;
+pub mod semisupervised;
+pub mod decode;
 pub mod activations;
 pub mod initializations;
 pub mod plot;
 pub mod utils;
 pub mod device;
+pub mod transfer;
+#[cfg(feature = "ndarray")]
+pub mod interop;
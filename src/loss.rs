@@ -1,5 +1,5 @@
 use af;
-use af::Array;
+use af::{Array, Dim4, DType};
 
 use utils;
 use activations;
@@ -50,6 +50,15 @@ pub fn cross_entropy_softmax_vec(pred: &Array, target: &Array) -> Array {
   cross_entropy_vec(&activations::softmax(pred), target)
 }
 
+/// Returns the vector form of the ordinal (CORAL) cross entropy
+/// sum_k -y_k ln s(x_k) - [1-y_k]ln[1-s(x_k)]
+///
+/// `pred` are the K-1 threshold logits and `target` are the
+/// extended binary rank levels (see `ordinal_levels`)
+pub fn ordinal_cross_entropy_vec(pred: &Array, target: &Array) -> Array {
+  binary_cross_entropy_vec(pred, target)
+}
+
 /// Provide a reduced form the L2 loss (single scalar)
 pub fn l2(pred: &Array, target: &Array) -> f32 {
   af::sum_all(&l2_vec(pred, target)).0 as f32
@@ -81,6 +90,11 @@ pub fn cross_entropy_softmax(pred: &Array, target: &Array) -> f32 {
   af::sum_all(&cross_entropy_softmax_vec(pred, target)).0 as f32
 }
 
+/// Provide a reduced form the ordinal cross-entropy loss (single scalar)
+pub fn ordinal_cross_entropy(pred: &Array, target: &Array) -> f32 {
+  af::sum_all(&ordinal_cross_entropy_vec(pred, target)).0 as f32
+}
+
 /// Provides the vector derivative of the mean squared error
 pub fn mse_derivative(pred: &Array, target: &Array) -> Array {
   af::sub(pred, target, false)
@@ -110,6 +124,447 @@ pub fn binary_cross_entropy_derivative(pred: &Array, target: &Array) -> Array {
   mse_derivative(&activations::sigmoid(pred), target)
 }
 
+/// Provides the vector derivative of the ordinal cross-entropy error
+/// Note: Assumes threshold logits [the sigmoid is applied here]
+pub fn ordinal_cross_entropy_derivative(pred: &Array, target: &Array) -> Array {
+  binary_cross_entropy_derivative(pred, target)
+}
+
+/// Encodes integer ranks into the extended binary levels used by the ordinal loss
+/// level_k = 1 if rank > k else 0
+///
+/// # Parameters
+/// - `ranks` is a [batch, 1] array of ranks in [0, num_classes)
+/// - `num_classes` is the number of ordered categories
+pub fn ordinal_levels(ranks: &Array, num_classes: u64) -> Array {
+  let num_thresholds = num_classes - 1;
+  let batch_size = ranks.dims()[0];
+  let thresholds = af::range::<f32>(Dim4::new(&[batch_size, num_thresholds, 1, 1]), 1);
+  let tiled = af::tile(&utils::cast(ranks, thresholds.get_type())
+                       , Dim4::new(&[1, num_thresholds, 1, 1]));
+  utils::cast(&af::gt(&tiled, &thresholds, false), ranks.get_type())
+}
+
+/// Decodes ordinal threshold logits into the predicted rank
+/// rank = sum_k [s(x_k) > 0.5]
+pub fn ordinal_rank(pred: &Array) -> Array {
+  let above = af::gt(&activations::sigmoid(pred), &0.5f32, false);
+  utils::cast(&af::sum(&utils::cast(&above, pred.get_type()), 1), pred.get_type())
+}
+
+///
+/// Cost sensitive losses
+///
+/// A [num_classes, num_classes] cost matrix C where C[i, j] is the cost of
+/// predicting class j when the true class is i. The loss of every sample is
+/// reweighted by the total cost of misclassifying its true class.
+///
+
+/// Returns the [batch, 1] sample weights implied by a cost matrix
+/// w = target * sum_j C[:, j]   [target is one-hot]
+pub fn cost_weights(target: &Array, cost: &Array) -> Array {
+  let cost = utils::cast(cost, target.get_type());
+  af::matmul(target, &af::sum(&cost, 1), af::MatProp::NONE, af::MatProp::NONE)
+}
+
+/// Helper to provide a per sample weighted loss from a string
+/// sum(w * loss_vec)
+pub fn get_weighted_loss(name: &str, pred: &Array, target: &Array
+                         , weights: &Array) -> Result<f32, HALError>
+{
+  get_loss_vec(name, pred, target)
+    .map(|l| af::sum_all(&af::mul(&l, weights, true)).0 as f32)
+}
+
+/// Helper to provide a per sample weighted loss derivative from a string
+pub fn get_weighted_loss_derivative(name: &str, pred: &Array, target: &Array
+                                    , weights: &Array) -> Result<Array, HALError>
+{
+  get_loss_derivative(name, pred, target).map(|d| af::mul(&d, weights, true))
+}
+
+///
+/// Masked losses
+///
+/// A [batch, 1] mask of ones & zeros selects the samples of a time-step that
+/// count [eg: the sequences that are still running, see `data::RaggedBatch::mask`].
+///
+
+/// Helper to provide the loss of the masked samples from a string
+/// [the same reduction as `get_loss` on the selected rows, 0 without any]
+pub fn get_masked_loss(name: &str, pred: &Array, target: &Array
+                       , mask: &Array) -> Result<f32, HALError>
+{
+  let valid: Vec<u32> = utils::array_to_vec(mask).iter().enumerate()
+    .filter(|&(_, &m)| m > 0.0).map(|(i, _)| i as u32).collect();
+  if valid.len() == 0 {
+    // still validate the name of the loss
+    return get_loss_derivative(name, pred, target).map(|_| 0.0);
+  }
+  let idx = utils::vec_to_array::<u32>(valid.clone(), Dim4::new(&[valid.len() as u64, 1, 1, 1]));
+  get_loss(name, &af::lookup(pred, &idx, 0), &af::lookup(target, &idx, 0))
+}
+
+/// Helper to provide the loss derivative of the masked samples from a string
+/// [zero for the other samples]
+pub fn get_masked_loss_derivative(name: &str, pred: &Array, target: &Array
+                                  , mask: &Array) -> Result<Array, HALError>
+{
+  get_weighted_loss_derivative(name, pred, target, &utils::cast(mask, pred.get_type()))
+}
+
+/// Returns the one-hot minimum expected cost (Bayes risk) decisions
+/// argmin_j sum_i p_i C[i, j]
+pub fn minimum_risk_classes(probabilities: &Array, cost: &Array) -> Array {
+  let dtype = probabilities.get_type();
+  let expected_cost = af::matmul(probabilities, &utils::cast(cost, dtype)
+                                 , af::MatProp::NONE, af::MatProp::NONE);
+  utils::cast(&af::eq(&expected_cost, &af::min(&expected_cost, 1), true), dtype)
+}
+
+///
+/// Metric learning losses
+///
+/// These operate on embeddings instead of a single prediction/target pair.
+/// The derivatives are returned per input so that they can be pushed back
+/// through the model that produced each embedding (see `Model::backward_deltas`)
+///
+
+/// Returns the [batch, 1] squared euclidean distance between rows
+pub fn squared_distance(left: &Array, right: &Array) -> Array {
+  let diff = af::sub(left, right, false);
+  af::sum(&af::mul(&diff, &diff, false), 1)
+}
+
+/// Returns the vector form of the contrastive loss
+/// 0.5 * [y d^2 + (1 - y) max(0, margin - d)^2]
+///
+/// # Parameters
+/// - `left` & `right` are the [batch, embedding] pairs
+/// - `similar` is a [batch, 1] array that is 1 for similar pairs and 0 otherwise
+/// - `margin` is the distance beyond which dissimilar pairs are not penalized
+pub fn contrastive_vec(left: &Array, right: &Array, similar: &Array, margin: f32) -> Array {
+  let d2 = squared_distance(left, right);
+  let hinge = activations::relu(&af::sub(&margin, &af::sqrt(&d2), false));
+  let pos = af::mul(similar, &d2, false);
+  let neg = af::mul(&af::sub(&1.0f32, similar, false), &af::mul(&hinge, &hinge, false), false);
+  af::mul(&af::add(&pos, &neg, false), &0.5f32, false)
+}
+
+/// Provide a reduced form the contrastive loss (single scalar)
+pub fn contrastive(left: &Array, right: &Array, similar: &Array, margin: f32) -> f32 {
+  af::sum_all(&contrastive_vec(left, right, similar, margin)).0 as f32
+}
+
+/// Provides the derivatives of the contrastive loss w.r.t. (left, right)
+/// dL/dleft = [y - (1 - y) max(0, margin - d) / d] * (left - right)
+pub fn contrastive_derivative(left: &Array, right: &Array
+                              , similar: &Array, margin: f32) -> (Array, Array)
+{
+  let eps = 1e-10f32; // numerical stability for identical pairs
+  let diff = af::sub(left, right, false);
+  let d = af::sqrt(&af::add(&squared_distance(left, right), &eps, false));
+  let hinge = activations::relu(&af::sub(&margin, &d, false));
+  let coeff = af::sub(similar
+                      , &af::div(&af::mul(&af::sub(&1.0f32, similar, false), &hinge, false)
+                                 , &d, false)
+                      , false);
+  let dleft = af::mul(&diff, &coeff, true);
+  let dright = af::mul(&dleft, &-1.0f32, false);
+  (dleft, dright)
+}
+
+/// Returns the vector form of the triplet loss
+/// max(0, |a - p|^2 - |a - n|^2 + margin)
+pub fn triplet_vec(anchor: &Array, positive: &Array, negative: &Array, margin: f32) -> Array {
+  let dp = squared_distance(anchor, positive);
+  let dn = squared_distance(anchor, negative);
+  activations::relu(&af::add(&af::sub(&dp, &dn, false), &margin, false))
+}
+
+/// Provide a reduced form the triplet loss (single scalar)
+pub fn triplet(anchor: &Array, positive: &Array, negative: &Array, margin: f32) -> f32 {
+  af::sum_all(&triplet_vec(anchor, positive, negative, margin)).0 as f32
+}
+
+/// Provides the derivatives of the triplet loss w.r.t. (anchor, positive, negative)
+/// dL/da = 2(n - p) | dL/dp = -2(a - p) | dL/dn = 2(a - n)  [for active triplets]
+pub fn triplet_derivative(anchor: &Array, positive: &Array
+                          , negative: &Array, margin: f32) -> (Array, Array, Array)
+{
+  let active = utils::cast(&af::gt(&triplet_vec(anchor, positive, negative, margin)
+                                   , &0.0f32, false), anchor.get_type());
+  let scaled = af::mul(&active, &2.0f32, false);
+  let danchor = af::mul(&af::sub(negative, positive, false), &scaled, true);
+  let dpositive = af::mul(&af::sub(positive, anchor, false), &scaled, true);
+  let dnegative = af::mul(&af::sub(anchor, negative, false), &scaled, true);
+  (danchor, dpositive, dnegative)
+}
+
+/// Helper for batch hard mining
+///
+/// Returns the one-hot [batch, batch] selection of the hardest positive and
+/// the hardest negative of every anchor based on the squared distances and the
+/// [batch, 1] mask of the anchors that have both [the others form no triplet]
+fn batch_hard_selection(embeddings: &Array, labels: &Array) -> (Array, Array, Array) {
+  let batch_size = embeddings.dims()[0];
+  let dtype = embeddings.get_type();
+  let square_dims = Dim4::new(&[batch_size, batch_size, 1, 1]);
+  let tile_dims = Dim4::new(&[1, batch_size, 1, 1]);
+
+  // pairwise distances: |x_i|^2 + |x_j|^2 - 2 x_i x_j
+  let sq = af::tile(&af::sum(&af::mul(embeddings, embeddings, false), 1), tile_dims);
+  let gram = af::matmul(embeddings, embeddings, af::MatProp::NONE, af::MatProp::TRANS);
+  let distances = activations::relu(&af::sub(&af::add(&sq, &af::transpose(&sq, false), false)
+                                             , &af::mul(&gram, &2.0f32, false), false));
+
+  // masks of same labels [excluding the anchor itself] & different labels
+  let tiled_labels = af::tile(labels, tile_dims);
+  let same = utils::cast(&af::eq(&tiled_labels, &af::transpose(&tiled_labels, false), false), dtype);
+  let eye = utils::cast(&af::identity::<f32>(square_dims), dtype);
+  let positives = af::sub(&same, &eye, false);
+
+  // hardest positive is the furthest same label sample
+  // hardest negative is the closest differently labeled sample
+  // [the other samples are pushed below 0 so that coincident positives still win]
+  let offset = af::max_all(&distances).0 as f32 + 1.0;
+  let (_, pos_idx) = af::imax(&af::sub(&af::mul(&distances, &positives, false)
+                                       , &af::sub(&1.0f32, &positives, false), false), 1);
+  let (_, neg_idx) = af::imin(&af::add(&distances, &af::mul(&same, &offset, false), false), 1);
+
+  // anchors without a positive [a singleton label] or without a negative
+  let num_positives = af::sum(&positives, 1);
+  let num_negatives = af::sum(&af::sub(&1.0f32, &same, false), 1);
+  let valid = af::mul(&utils::cast(&af::gt(&num_positives, &0.0f32, false), dtype)
+                      , &utils::cast(&af::gt(&num_negatives, &0.0f32, false), dtype), false);
+
+  let columns = af::range::<f32>(square_dims, 1);
+  let one_hot = |idx: &Array| {
+    utils::cast(&af::eq(&af::tile(&utils::cast(idx, DType::F32), tile_dims), &columns, false), dtype)
+  };
+  (one_hot(&pos_idx), one_hot(&neg_idx), valid)
+}
+
+/// Provide a reduced form the batch hard triplet loss (single scalar)
+///
+/// Every sample in the batch is used as an anchor and is paired with its
+/// hardest positive and hardest negative in the same batch. Anchors without a
+/// positive or without a negative in the batch do not contribute.
+///
+/// # Parameters
+/// - `embeddings` is the [batch, embedding] model output
+/// - `labels` is the [batch, 1] array of class identifiers
+/// - `margin` is the triplet margin
+pub fn batch_hard_triplet(embeddings: &Array, labels: &Array, margin: f32) -> f32 {
+  let (pos_sel, neg_sel, valid) = batch_hard_selection(embeddings, labels);
+  let positive = af::matmul(&pos_sel, embeddings, af::MatProp::NONE, af::MatProp::NONE);
+  let negative = af::matmul(&neg_sel, embeddings, af::MatProp::NONE, af::MatProp::NONE);
+  let losses = triplet_vec(embeddings, &positive, &negative, margin);
+  af::sum_all(&af::mul(&losses, &valid, false)).0 as f32
+}
+
+/// Provides the derivative of the batch hard triplet loss w.r.t. the embeddings
+///
+/// Since positives & negatives are rows of the same batch their gradients
+/// are scattered back onto the rows that were selected [the triplets of the
+/// masked anchors are zeroed before that].
+pub fn batch_hard_triplet_derivative(embeddings: &Array, labels: &Array, margin: f32) -> Array {
+  let (pos_sel, neg_sel, valid) = batch_hard_selection(embeddings, labels);
+  let positive = af::matmul(&pos_sel, embeddings, af::MatProp::NONE, af::MatProp::NONE);
+  let negative = af::matmul(&neg_sel, embeddings, af::MatProp::NONE, af::MatProp::NONE);
+  let (danchor, dpositive, dnegative) = triplet_derivative(embeddings, &positive, &negative, margin);
+  let danchor = af::mul(&danchor, &valid, true);
+  let dpositive = af::mul(&dpositive, &valid, true);
+  let dnegative = af::mul(&dnegative, &valid, true);
+  let scattered_pos = af::matmul(&pos_sel, &dpositive, af::MatProp::TRANS, af::MatProp::NONE);
+  let scattered_neg = af::matmul(&neg_sel, &dnegative, af::MatProp::TRANS, af::MatProp::NONE);
+  af::add(&danchor, &af::add(&scattered_pos, &scattered_neg, false), false)
+}
+
+///
+/// Connectionist Temporal Classification
+///
+/// The CTC loss scores unaligned label sequences against the per time-step
+/// logits of a recurrent model (`Model::forward` outputs). The forward-backward
+/// recursions are run in log space on the host for every sample of the batch.
+/// The derivatives are w.r.t. the logits [the softmax is applied here] and can be
+/// pushed through the model with `Model::backward_deltas`.
+///
+
+/// Numerically stable ln(e^a + e^b)
+fn log_sum_exp(a: f64, b: f64) -> f64 {
+  if a == ::std::f64::NEG_INFINITY { return b; }
+  if b == ::std::f64::NEG_INFINITY { return a; }
+  let max = a.max(b);
+  max + ((a - max).exp() + (b - max).exp()).ln()
+}
+
+/// Runs the CTC forward-backward algorithm
+///
+/// Returns the negative log likelihood of every sample and the
+/// derivatives w.r.t. the logits of every time-step
+fn ctc_forward_backward(predictions: &Vec<Array>, labels: &Vec<Vec<u32>>
+                        , blank: u32) -> Result<(Vec<f32>, Vec<Array>), HALError>
+{
+  let neg_inf = ::std::f64::NEG_INFINITY;
+  let time_steps = predictions.len();
+  assert!(time_steps > 0, "ctc requires at least one time-step");
+  let dims = predictions[0].dims();
+  let dtype = predictions[0].get_type();
+  let batch_size = dims[0] as usize;
+  let num_classes = dims[1] as usize;
+  assert!(labels.len() == batch_size
+          , "ctc requires one label sequence per sample");
+
+  // host copies of the per time-step probabilities [column major: b + batch * k]
+  let probs: Vec<Vec<f64>> = predictions.iter()
+    .map(|p| utils::array_to_vec(&activations::softmax(p)))
+    .collect();
+  let mut grads: Vec<Vec<f64>> = probs.clone();
+  let mut losses = Vec::with_capacity(batch_size);
+
+  for b in 0..batch_size {
+    let prob = |t: usize, k: u32| probs[t][b + batch_size * k as usize];
+    let log_prob = |t: usize, k: u32| prob(t, k).max(1e-300).ln();
+
+    // extended label sequence: [blank, l1, blank, l2, ..., blank]
+    let mut ext = vec![blank];
+    for l in &labels[b] {
+      assert!((*l as usize) < num_classes && *l != blank
+              , "ctc labels must be non-blank classes");
+      ext.push(*l);
+      ext.push(blank);
+    }
+    let s_len = ext.len();
+
+    // alpha[t][s]: log probability of all prefixes ending at s at time t
+    let mut alpha = vec![vec![neg_inf; s_len]; time_steps];
+    alpha[0][0] = log_prob(0, ext[0]);
+    if s_len > 1 { alpha[0][1] = log_prob(0, ext[1]); }
+    for t in 1..time_steps {
+      for s in 0..s_len {
+        let mut a = alpha[t - 1][s];
+        if s > 0 { a = log_sum_exp(a, alpha[t - 1][s - 1]); }
+        if s > 1 && ext[s] != blank && ext[s] != ext[s - 2] {
+          a = log_sum_exp(a, alpha[t - 1][s - 2]);
+        }
+        alpha[t][s] = a + log_prob(t, ext[s]);
+      }
+    }
+
+    // beta[t][s]: log probability of all suffixes starting at s at time t
+    let last = time_steps - 1;
+    let mut beta = vec![vec![neg_inf; s_len]; time_steps];
+    beta[last][s_len - 1] = log_prob(last, ext[s_len - 1]);
+    if s_len > 1 { beta[last][s_len - 2] = log_prob(last, ext[s_len - 2]); }
+    for t in (0..last).rev() {
+      for s in 0..s_len {
+        let mut bt = beta[t + 1][s];
+        if s + 1 < s_len { bt = log_sum_exp(bt, beta[t + 1][s + 1]); }
+        if s + 2 < s_len && ext[s] != blank && ext[s] != ext[s + 2] {
+          bt = log_sum_exp(bt, beta[t + 1][s + 2]);
+        }
+        beta[t][s] = bt + log_prob(t, ext[s]);
+      }
+    }
+
+    let mut log_likelihood = alpha[last][s_len - 1];
+    if s_len > 1 { log_likelihood = log_sum_exp(log_likelihood, alpha[last][s_len - 2]); }
+    if log_likelihood == neg_inf {
+      return Err(HALError::CTC_ALIGNMENT);
+    }
+    losses.push(-log_likelihood as f32);
+
+    // dL/dz_tk = y_tk - 1/(p * y_tk) * sum_{s: ext[s] = k} alpha_t(s) beta_t(s)
+    for t in 0..time_steps {
+      let mut occupancy = vec![neg_inf; num_classes];
+      for s in 0..s_len {
+        let k = ext[s] as usize;
+        occupancy[k] = log_sum_exp(occupancy[k], alpha[t][s] + beta[t][s]);
+      }
+      for k in 0..num_classes {
+        let index = b + batch_size * k;
+        let posterior = match occupancy[k] == neg_inf {
+          true  => 0.0,
+          false => (occupancy[k] - log_prob(t, k as u32) - log_likelihood).exp(),
+        };
+        grads[t][index] = probs[t][index] - posterior;
+      }
+    }
+  }
+
+  let grad_arrays = grads.into_iter()
+    .map(|g| utils::cast(&utils::vec_to_array::<f64>(g, dims), dtype))
+    .collect();
+  Ok((losses, grad_arrays))
+}
+
+/// Returns the CTC negative log likelihood of every sample
+///
+/// # Parameters
+/// - `predictions` are the per time-step [batch, num_classes] logits
+/// - `labels` are the (unaligned) label sequences of every sample
+/// - `blank` is the class index reserved for the blank symbol
+pub fn ctc_vec(predictions: &Vec<Array>, labels: &Vec<Vec<u32>>
+               , blank: u32) -> Result<Vec<f32>, HALError>
+{
+  ctc_forward_backward(predictions, labels, blank).map(|(losses, _)| losses)
+}
+
+/// Provide a reduced form the CTC loss (single scalar)
+pub fn ctc(predictions: &Vec<Array>, labels: &Vec<Vec<u32>>
+           , blank: u32) -> Result<f32, HALError>
+{
+  ctc_vec(predictions, labels, blank).map(|l| l.iter().fold(0f32, |sum, val| sum + val))
+}
+
+/// Provides the derivatives of the CTC loss w.r.t. the per time-step logits
+pub fn ctc_derivative(predictions: &Vec<Array>, labels: &Vec<Vec<u32>>
+                      , blank: u32) -> Result<Vec<Array>, HALError>
+{
+  ctc_forward_backward(predictions, labels, blank).map(|(_, grads)| grads)
+}
+
+/// Returns the vector form of the distillation loss
+/// T^2 * sum_k p_k [ln p_k - ln q_k], p = softmax(teacher / T), q = softmax(student / T)
+///
+/// The T^2 factor keeps the gradient magnitudes independent of the temperature
+///
+/// # Parameters
+/// - `student` & `teacher` are the [batch, num_classes] logits
+/// - `temperature` is the softening temperature T
+pub fn distillation_vec(student: &Array, teacher: &Array, temperature: f32) -> Array {
+  let eps = 1e-10f32; // numerical stability for vanishing probabilities
+  let p = activations::softmax(&af::div(teacher, &temperature, false));
+  let q = activations::softmax(&af::div(student, &temperature, false));
+  let log_ratio = af::sub(&af::log(&af::add(&p, &eps, false))
+                          , &af::log(&af::add(&q, &eps, false)), false);
+  af::mul(&af::sum(&af::mul(&p, &log_ratio, false), 1), &(temperature * temperature), false)
+}
+
+/// Provide a reduced form the distillation loss (single scalar)
+pub fn distillation(student: &Array, teacher: &Array, temperature: f32) -> f32 {
+  af::sum_all(&distillation_vec(student, teacher, temperature)).0 as f32
+}
+
+/// Provides the derivative of the distillation loss w.r.t. the student logits
+/// T * [softmax(student / T) - softmax(teacher / T)]
+pub fn distillation_derivative(student: &Array, teacher: &Array, temperature: f32) -> Array {
+  let p = activations::softmax(&af::div(teacher, &temperature, false));
+  let q = activations::softmax(&af::div(student, &temperature, false));
+  af::mul(&af::sub(&q, &p, false), &temperature, false)
+}
+
+/// Helper to provide the activation that maps a loss' logits to probabilities
+pub fn get_output_activation(name: &str) -> &'static str {
+  match name {
+    "cross_entropy_softmax" => "softmax",
+    "binary_cross_entropy"  => "sigmoid",
+    "ordinal_cross_entropy" => "sigmoid",
+    _                       => "ones",
+  }
+}
+
 
 /// Helper to provide a loss from a string
 pub fn get_loss(name: &str, pred: &Array, target: &Array) -> Result<f32, HALError> {
@@ -119,6 +574,7 @@ pub fn get_loss(name: &str, pred: &Array, target: &Array) -> Result<f32, HALErro
     "cross_entropy"         => Ok(cross_entropy(pred, target)),
     "binary_cross_entropy"  => Ok(binary_cross_entropy(pred, target)),
     "cross_entropy_softmax" => Ok(cross_entropy_softmax(pred, target)),
+    "ordinal_cross_entropy" => Ok(ordinal_cross_entropy(pred, target)),
     _                       => Err(HALError::UNKNOWN_LOSS),
   }
 }
@@ -131,6 +587,7 @@ pub fn get_loss_vec(name: &str, pred: &Array, target: &Array) -> Result<Array, H
     "cross_entropy"         => Ok(cross_entropy_vec(pred, target)),
     "binary_cross_entropy"  => Ok(binary_cross_entropy_vec(pred, target)),
     "cross_entropy_softmax" => Ok(cross_entropy_softmax_vec(pred, target)),
+    "ordinal_cross_entropy" => Ok(ordinal_cross_entropy_vec(pred, target)),
     _                       => Err(HALError::UNKNOWN_LOSS),
   }
 }
@@ -143,6 +600,7 @@ pub fn get_loss_derivative(name: &str, pred: &Array, target: &Array) -> Result<A
     "cross_entropy"         => Ok(cross_entropy_derivative(pred, target)),
     "binary_cross_entropy"  => Ok(binary_cross_entropy_derivative(pred, target)),
     "cross_entropy_softmax" => Ok(cross_entropy_softmax_derivative(pred, target)),
+    "ordinal_cross_entropy" => Ok(ordinal_cross_entropy_derivative(pred, target)),
     _                       => Err(HALError::UNKNOWN_LOSS),
   }
 }
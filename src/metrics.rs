@@ -0,0 +1,436 @@
+use af;
+use af::Array;
+use std::collections::BTreeMap;
+
+use utils;
+use error::HALError;
+
+/// Returns the fraction of labels that are incorrectly predicted
+///
+/// Both arrays are [batch, num_labels] indicator arrays (see `Model::predict_classes`)
+pub fn hamming_loss(pred: &Array, target: &Array) -> f32 {
+  let mismatch = af::neq(pred, &utils::cast(target, pred.get_type()), false);
+  af::mean_all(&utils::cast(&mismatch, pred.get_type())).0 as f32
+}
+
+/// Returns the (true positive, false positive, false negative) counts per label
+///
+/// Both arrays are [batch, num_labels] indicator arrays
+pub fn label_counts(pred: &Array, target: &Array) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+  let target = utils::cast(target, pred.get_type());
+  let tp = af::sum(&af::mul(pred, &target, false), 0);
+  let fp = af::sum(&af::mul(pred, &af::sub(&1.0f32, &target, false), false), 0);
+  let fneg = af::sum(&af::mul(&af::sub(&1.0f32, pred, false), &target, false), 0);
+  (utils::array_to_vec(&tp), utils::array_to_vec(&fp), utils::array_to_vec(&fneg))
+}
+
+/// Returns the F1 score of every label
+/// f1 = 2tp / (2tp + fp + fn)
+///
+/// Labels that are never predicted nor present are given a score of 0
+pub fn per_label_f1(pred: &Array, target: &Array) -> Vec<f32> {
+  let (tp, fp, fneg) = label_counts(pred, target);
+  tp.iter().zip(fp.iter()).zip(fneg.iter()).map(|((tp, fp), fneg)| {
+    let denominator = 2.0 * tp + fp + fneg;
+    match denominator > 0.0 {
+      true  => (2.0 * tp / denominator) as f32,
+      false => 0.0,
+    }
+  }).collect()
+}
+
+/// Returns the unweighted mean of the per label F1 scores
+pub fn macro_f1(pred: &Array, target: &Array) -> f32 {
+  let f1 = per_label_f1(pred, target);
+  f1.iter().fold(0f32, |sum, val| sum + val) / f1.len() as f32
+}
+
+/// Returns the fraction of correctly classified samples
+///
+/// `pred` are the [batch, num_classes] output probabilities and `target` the
+/// one-hot targets; single output models are thresholded at 0.5
+pub fn accuracy(pred: &Array, target: &Array) -> f32 {
+  let target = utils::cast(target, pred.get_type());
+  let correct = match pred.dims()[1] {
+    1 => af::eq(&af::ge(pred, &0.5f32, false), &af::ge(&target, &0.5f32, false), false),
+    _ => af::eq(&af::imax(pred, 1).1, &af::imax(&target, 1).1, false),
+  };
+  af::mean_all(&utils::cast(&correct, pred.get_type())).0 as f32
+}
+
+/// Returns the number of samples whose target class is among the k most probable classes
+///
+/// `pred` are the [batch, num_classes] output probabilities and `target` the
+/// one-hot targets, only the count is moved to the host
+pub fn top_k_correct(pred: &Array, target: &Array, k: u64) -> f32 {
+  let (_, top) = utils::top_k(pred, k);
+  let (_, class) = af::imax(target, 1);
+  let hits = af::eq(&top, &utils::cast(&class, top.get_type()), true);
+  af::sum_all(&hits).0 as f32
+}
+
+/// Returns the fraction of samples whose target class is among the k most probable classes
+pub fn top_k_accuracy(pred: &Array, target: &Array, k: u64) -> f32 {
+  top_k_correct(pred, target, k) / pred.dims()[0] as f32
+}
+
+/// Trait that describes a metric that is accumulated over many minibatches
+///
+/// `update` is called with the output probabilities of the model
+/// [see `Model::predict_proba`] and the matching targets of every batch.
+pub trait Metric: Send {
+  fn name(&self) -> String;
+  fn update(&mut self, pred: &Array, target: &Array);
+  fn value(&self) -> f32;
+  fn reset(&mut self);
+
+  /// Same as `update`, with the (unnormalized) inputs of the batch
+  ///
+  /// This is what `Model::evaluate` calls, metrics that depend on input
+  /// features [eg: `GroupFairness`] override it.
+  fn update_with_inputs(&mut self, pred: &Array, target: &Array, _inputs: &Array) {
+    self.update(pred, target);
+  }
+
+  /// Returns all the named values of the metric [defaults to (name, value)]
+  fn values(&self) -> Vec<(String, f32)> {
+    vec![(self.name(), self.value())]
+  }
+}
+
+/// Streaming ROC / precision-recall curve of a binary classifier
+///
+/// Scores are accumulated into `num_bins` equally spaced bins over [0, 1],
+/// so memory is constant w.r.t. the number of samples and the areas are
+/// exact as long as no two samples of different classes share a bin.
+///
+/// # Parameters
+///
+/// - `kind` is the area reported as the metric value ["roc_auc" or "pr_auc"]
+/// - `column` is the column of the predictions & targets holding the positive class
+/// - `positives` are the per bin counts of positive samples
+/// - `negatives` are the per bin counts of negative samples
+pub struct BinaryCurve {
+  pub kind: String,
+  pub column: usize,
+  positives: Vec<f64>,
+  negatives: Vec<f64>,
+}
+
+impl BinaryCurve {
+  pub fn new(kind: &str, num_bins: usize, column: usize) -> BinaryCurve {
+    assert!(kind == "roc_auc" || kind == "pr_auc"
+            , "unknown binary curve {}, expected roc_auc or pr_auc", kind);
+    assert!(num_bins > 0, "need at least one bin");
+    BinaryCurve {
+      kind: kind.to_string(),
+      column: column,
+      positives: vec![0.0; num_bins],
+      negatives: vec![0.0; num_bins],
+    }
+  }
+
+  /// Returns the cumulative (threshold, true positive, false positive) counts
+  /// from the highest threshold [nothing predicted positive] to zero [everything positive]
+  fn cumulative_counts(&self) -> Vec<(f32, f64, f64)> {
+    let num_bins = self.positives.len();
+    let mut counts = vec![(1.0f32, 0.0, 0.0)];
+    let (mut tp, mut fp) = (0.0, 0.0);
+    for bin in (0..num_bins).rev() {
+      tp += self.positives[bin];
+      fp += self.negatives[bin];
+      counts.push((bin as f32 / num_bins as f32, tp, fp));
+    }
+    counts
+  }
+
+  /// Returns the (threshold, false positive rate, true positive rate) points of the ROC curve
+  pub fn roc(&self) -> Vec<(f32, f32, f32)> {
+    let counts = self.cumulative_counts();
+    let (_, num_pos, num_neg) = *counts.last().unwrap();
+    counts.iter().map(|&(threshold, tp, fp)| {
+      let tpr = if num_pos > 0.0 { tp / num_pos } else { 0.0 };
+      let fpr = if num_neg > 0.0 { fp / num_neg } else { 0.0 };
+      (threshold, fpr as f32, tpr as f32)
+    }).collect()
+  }
+
+  /// Returns the (threshold, recall, precision) points of the precision-recall curve
+  ///
+  /// Thresholds with no positive predictions are skipped
+  pub fn pr(&self) -> Vec<(f32, f32, f32)> {
+    let counts = self.cumulative_counts();
+    let (_, num_pos, _) = *counts.last().unwrap();
+    counts.iter().filter(|&&(_, tp, fp)| tp + fp > 0.0).map(|&(threshold, tp, fp)| {
+      let recall = if num_pos > 0.0 { tp / num_pos } else { 0.0 };
+      (threshold, recall as f32, (tp / (tp + fp)) as f32)
+    }).collect()
+  }
+
+  /// Area under the ROC curve [trapezoidal rule]
+  pub fn roc_auc(&self) -> f32 {
+    let roc = self.roc();
+    roc.windows(2).fold(0f32, |area, w| {
+      area + (w[1].1 - w[0].1) * (w[1].2 + w[0].2) / 2.0
+    })
+  }
+
+  /// Area under the precision-recall curve [average precision]
+  pub fn pr_auc(&self) -> f32 {
+    let mut previous_recall = 0f32;
+    self.pr().iter().fold(0f32, |area, &(_, recall, precision)| {
+      let step = recall - previous_recall;
+      previous_recall = recall;
+      area + step * precision
+    })
+  }
+}
+
+impl Metric for BinaryCurve {
+  fn name(&self) -> String {
+    self.kind.clone()
+  }
+
+  fn update(&mut self, pred: &Array, target: &Array) {
+    // single column outputs [sigmoid] hold the positive class directly
+    let column = if pred.dims()[1] > 1 { self.column as u64 } else { 0 };
+    let scores = utils::array_to_vec(&af::col(pred, column));
+    let labels = utils::array_to_vec(&af::col(target, column));
+    let num_bins = self.positives.len();
+    for (score, label) in scores.iter().zip(labels.iter()) {
+      let bin = ((score.max(0.0) * num_bins as f64) as usize).min(num_bins - 1);
+      match *label > 0.5 {
+        true  => self.positives[bin] += 1.0,
+        false => self.negatives[bin] += 1.0,
+      };
+    }
+  }
+
+  fn value(&self) -> f32 {
+    match self.kind.as_str() {
+      "pr_auc" => self.pr_auc(),
+      _        => self.roc_auc(),
+    }
+  }
+
+  fn reset(&mut self) {
+    for bin in 0..self.positives.len() {
+      self.positives[bin] = 0.0;
+      self.negatives[bin] = 0.0;
+    }
+  }
+}
+
+/// Confusion counts of a protected group
+#[derive(Clone, Copy, Debug, Default)]
+struct GroupCounts {
+  tp: f64,
+  fp: f64,
+  tn: f64,
+  fneg: f64,
+}
+
+impl GroupCounts {
+  fn rate(num: f64, den: f64) -> f32 {
+    if den > 0.0 { (num / den) as f32 } else { 0.0 }
+  }
+
+  fn accuracy(&self) -> f32 {
+    GroupCounts::rate(self.tp + self.tn, self.tp + self.fp + self.tn + self.fneg)
+  }
+
+  fn tpr(&self) -> f32 {
+    GroupCounts::rate(self.tp, self.tp + self.fneg)
+  }
+
+  fn fpr(&self) -> f32 {
+    GroupCounts::rate(self.fp, self.fp + self.tn)
+  }
+
+  fn positive_rate(&self) -> f32 {
+    GroupCounts::rate(self.tp + self.fp, self.tp + self.fp + self.tn + self.fneg)
+  }
+}
+
+/// Returns max - min of the values [0 for less than two values]
+fn gap(values: Vec<f32>) -> f32 {
+  match values.len() {
+    0 | 1 => 0.0,
+    _     => values.iter().fold(::std::f32::MIN, |m, &v| m.max(v))
+           - values.iter().fold(::std::f32::MAX, |m, &v| m.min(v)),
+  }
+}
+
+/// Group fairness of a binary classifier w.r.t. a protected attribute
+///
+/// The protected attribute is a column of the inputs that the datasource
+/// provides [its (rounded) value is the group of the sample]. Predictions are
+/// positive when the probability of the positive class is at least 0.5.
+///
+/// Besides its value [the gap of `kind`], the metric reports per group
+/// `accuracy[g]`, `tpr[g]` & `fpr[g]` as well as `demographic_parity_gap`
+/// [max - min positive prediction rate] & `equalized_odds_gap` [max of the
+/// tpr & fpr gaps, see `Metric::values`].
+///
+/// # Parameters
+///
+/// - `kind` is the value of the metric ["demographic_parity" or "equalized_odds"]
+/// - `attribute_column` is the column of the inputs holding the protected attribute
+/// - `column` is the column of the predictions & targets holding the positive class
+pub struct GroupFairness {
+  pub kind: String,
+  pub attribute_column: usize,
+  pub column: usize,
+  groups: BTreeMap<i64, GroupCounts>,
+  num_skipped: u64,
+}
+
+impl GroupFairness {
+  pub fn new(kind: &str, attribute_column: usize, column: usize) -> GroupFairness {
+    assert!(kind == "demographic_parity" || kind == "equalized_odds"
+            , "unknown fairness criterion {}, expected demographic_parity or equalized_odds", kind);
+    GroupFairness {
+      kind: kind.to_string(),
+      attribute_column: attribute_column,
+      column: column,
+      groups: BTreeMap::new(),
+      num_skipped: 0,
+    }
+  }
+
+  /// Returns the number of samples that were skipped because they came without inputs [see `Metric::update`]
+  pub fn num_skipped(&self) -> u64 {
+    self.num_skipped
+  }
+
+  /// Returns the groups that were seen so far
+  pub fn groups(&self) -> Vec<i64> {
+    self.groups.keys().cloned().collect()
+  }
+
+  pub fn demographic_parity_gap(&self) -> f32 {
+    gap(self.groups.values().map(|c| c.positive_rate()).collect())
+  }
+
+  pub fn equalized_odds_gap(&self) -> f32 {
+    let tpr_gap = gap(self.groups.values().map(|c| c.tpr()).collect());
+    let fpr_gap = gap(self.groups.values().map(|c| c.fpr()).collect());
+    tpr_gap.max(fpr_gap)
+  }
+}
+
+impl Metric for GroupFairness {
+  fn name(&self) -> String {
+    self.kind.clone()
+  }
+
+  /// Samples without inputs can not be attributed to a group, they are skipped
+  fn update(&mut self, pred: &Array, _target: &Array) {
+    if self.num_skipped == 0 {
+      warn!("{} needs the inputs of the batch [see `Metric::update_with_inputs`], skipping the samples", self.kind);
+    }
+    self.num_skipped += pred.dims()[0];
+  }
+
+  fn update_with_inputs(&mut self, pred: &Array, target: &Array, inputs: &Array) {
+    // single column outputs [sigmoid] hold the positive class directly
+    let column = if pred.dims()[1] > 1 { self.column as u64 } else { 0 };
+    let scores = utils::array_to_vec(&af::col(pred, column));
+    let labels = utils::array_to_vec(&af::col(target, column));
+    let attributes = utils::array_to_vec(&af::col(inputs, self.attribute_column as u64));
+    for ((score, label), attribute) in scores.iter().zip(labels.iter()).zip(attributes.iter()) {
+      let counts = self.groups.entry(attribute.round() as i64).or_insert(GroupCounts::default());
+      match (*score >= 0.5, *label > 0.5) {
+        (true, true)   => counts.tp += 1.0,
+        (true, false)  => counts.fp += 1.0,
+        (false, false) => counts.tn += 1.0,
+        (false, true)  => counts.fneg += 1.0,
+      };
+    }
+  }
+
+  fn value(&self) -> f32 {
+    match self.kind.as_str() {
+      "equalized_odds" => self.equalized_odds_gap(),
+      _                => self.demographic_parity_gap(),
+    }
+  }
+
+  fn reset(&mut self) {
+    self.groups.clear();
+    self.num_skipped = 0;
+  }
+
+  fn values(&self) -> Vec<(String, f32)> {
+    let mut values = vec![(self.name(), self.value())
+                          , ("demographic_parity_gap".to_string(), self.demographic_parity_gap())
+                          , ("equalized_odds_gap".to_string(), self.equalized_odds_gap())];
+    for (group, counts) in self.groups.iter() {
+      values.push((format!("accuracy[{}]", group), counts.accuracy()));
+      values.push((format!("tpr[{}]", group), counts.tpr()));
+      values.push((format!("fpr[{}]", group), counts.fpr()));
+    }
+    values
+  }
+}
+
+/// Streaming top-k accuracy of a classifier [see `top_k_accuracy`]
+pub struct TopKAccuracy {
+  pub k: u64,
+  correct: f64,
+  total: f64,
+}
+
+impl TopKAccuracy {
+  pub fn new(k: u64) -> TopKAccuracy {
+    assert!(k > 0, "k needs to be positive");
+    TopKAccuracy {
+      k: k,
+      correct: 0.0,
+      total: 0.0,
+    }
+  }
+}
+
+impl Metric for TopKAccuracy {
+  fn name(&self) -> String {
+    format!("top_{}_accuracy", self.k)
+  }
+
+  fn update(&mut self, pred: &Array, target: &Array) {
+    self.correct += top_k_correct(pred, target, self.k) as f64;
+    self.total += pred.dims()[0] as f64;
+  }
+
+  fn value(&self) -> f32 {
+    match self.total > 0.0 {
+      true  => (self.correct / self.total) as f32,
+      false => 0.0,
+    }
+  }
+
+  fn reset(&mut self) {
+    self.correct = 0.0;
+    self.total = 0.0;
+  }
+}
+
+/// Helper to return a metric based on a string
+///
+/// The binary curves use 1000 bins & the second column as the positive
+/// class [the only column is used for single output models], "top_{k}_accuracy"
+/// is the top-k accuracy [eg: "top_5_accuracy"]
+pub fn get_metric(name: &str) -> Result<Box<Metric>, HALError> {
+  let name = name.to_lowercase();
+  match name.as_str() {
+    "roc_auc" => Ok(Box::new(BinaryCurve::new("roc_auc", 1000, 1))),
+    "pr_auc"  => Ok(Box::new(BinaryCurve::new("pr_auc", 1000, 1))),
+    n if n.len() > "top__accuracy".len() && n.starts_with("top_") && n.ends_with("_accuracy") => {
+      match n["top_".len()..n.len() - "_accuracy".len()].parse::<u64>() {
+        Ok(k) if k > 0 => Ok(Box::new(TopKAccuracy::new(k))),
+        _              => Err(HALError::UNKNOWN),
+      }
+    },
+    _         => Err(HALError::UNKNOWN),
+  }
+}
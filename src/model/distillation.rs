@@ -0,0 +1,132 @@
+use af;
+use af::{Array, HasAfEnum};
+use num::Zero;
+use std::cmp::max;
+
+use loss;
+use utils;
+use data::{DataSource};
+use device::{Device, DeviceManager};
+use model::{Model, Sequential};
+
+/// Trains a student model to match a frozen teacher [Hinton et al, 2015]
+///
+/// Every batch runs the forward pass of the teacher (its parameters are never
+/// updated) alongside the student and minimizes
+///
+/// alpha * hard_loss(student, target) + (1 - alpha) * distillation(student, teacher, T)
+///
+/// where the hard loss is the loss of the student [eg: cross_entropy_softmax]
+/// and the soft term is the temperature scaled KL divergence of the softened
+/// class distributions [see `loss::distillation`]. Both models need to output
+/// logits [a linear last layer].
+///
+/// # Parameters
+///
+/// - `student` is the model that is trained
+/// - `teacher` is the frozen model that provides the soft targets
+/// - `temperature` is the softening temperature T
+/// - `alpha` is the weight of the hard label loss
+pub struct Distillation {
+  pub student: Sequential,
+  pub teacher: Sequential,
+  pub temperature: f32,
+  pub alpha: f32,
+  manager: DeviceManager,
+}
+
+impl Distillation {
+  pub fn new(student: Sequential, teacher: Sequential, temperature: f32, alpha: f32) -> Distillation {
+    assert!(temperature > 0.0, "temperature needs to be positive");
+    assert!(alpha >= 0.0 && alpha <= 1.0, "alpha needs to be in [0, 1]");
+    Distillation {
+      manager: student.get_manager(),
+      student: student,
+      teacher: teacher,
+      temperature: temperature,
+      alpha: alpha,
+    }
+  }
+
+  /// Runs a single distillation step on the provided minibatch
+  ///
+  /// # Parameters
+  ///
+  /// - `batch_input` is the [batch, feature, time] input
+  /// - `batch_target` are the hard labels of the batch
+  /// - `src_device` is the device of the minibatch
+  ///
+  /// # Return Values
+  ///
+  /// Vector of (hard, soft) losses [the weighted sum is optimized] per time-step
+  pub fn partial_fit<E>(&mut self, batch_input: &Array, batch_target: &Array
+                        , src_device: Device) -> Vec<(f32, f32)>
+    where E: HasAfEnum + Zero + Clone
+  {
+    let device = self.student.get_device();
+    let seq_len = max(batch_input.dims()[2], 1) as usize;
+
+    // frozen teacher: inference only [no dropout], no backward pass is expected
+    let teacher_device = self.teacher.get_device();
+    let teacher_logits: Vec<Array> = self.teacher.infer::<E>(batch_input, src_device).iter()
+      .map(|logits| self.manager.swap_array_backend::<E>(logits, teacher_device, device)).collect();
+
+    let mut student_logits = self.student.forward::<E>(batch_input, src_device, device);
+    student_logits.truncate(seq_len);
+    self.manager.swap_device(src_device);
+    let batch_target = self.manager.swap_array_backend::<E>(batch_target, src_device, device);
+
+    let hard_loss = self.student.get_loss().to_string();
+    let (temperature, alpha) = (self.temperature, self.alpha);
+    let mut losses = Vec::with_capacity(seq_len);
+    let deltas: Vec<Array> = student_logits.iter().zip(teacher_logits.iter()).enumerate()
+      .map(|(t, (student, teacher))| {
+        let teacher = utils::cast(teacher, student.get_type());
+        let target = af::slice(&batch_target, t as u64);
+        losses.push((loss::get_loss(&hard_loss, student, &target).unwrap()
+                     , loss::distillation(student, &teacher, temperature)));
+        af::add(&af::mul(&alpha, &loss::get_loss_derivative(&hard_loss, student, &target).unwrap(), false)
+                , &af::mul(&(1.0 - alpha), &loss::distillation_derivative(student, &teacher, temperature), false)
+                , false)
+      }).collect();
+
+    self.student.backward_deltas(&deltas);
+    self.student.step(batch_input.dims()[0]);
+    losses
+  }
+
+  /// Fit's the student to the teacher & the hard labels of the provided data
+  ///
+  /// # Parameters
+  ///
+  /// - `source` is the datasource
+  /// - `src_device` is the source device of the data
+  /// - `epochs` is the number of epochs to run the training loop for
+  /// - `batch_size` is the minibatch size
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the weighted losses (one per minibatch)
+  pub fn fit<T, E>(&mut self, source: &T, src_device: Device
+                   , epochs: u64, batch_size: u64) -> Vec<f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    let iters = source.info().num_samples as u64 / batch_size as u64;
+
+    let mut lossvec = Vec::<f32>::new();
+    for _ in 0..epochs {
+      for _ in 0..iters {
+        self.manager.swap_device(src_device);
+        let minibatch = source.get_train_iter(batch_size);
+        let losses = self.partial_fit::<E>(&minibatch.input.into_inner()
+                                           , &minibatch.target.into_inner(), src_device);
+        lossvec.push(losses.iter().fold(0f32, |sum, &(hard, soft)| {
+          sum + self.alpha * hard + (1.0 - self.alpha) * soft
+        }) / losses.len() as f32);
+      }
+    }
+
+    self.manager.swap_device(src_device);
+    lossvec
+  }
+}
@@ -0,0 +1,177 @@
+use af;
+use af::{Array, HasAfEnum};
+use num::Zero;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use config::ModelConfig;
+use checkpoint;
+use data::DataSource;
+use device::{Device, DeviceManager};
+use error::HALError;
+use model::{Model, Sequential};
+
+/// Name of the config file of an ensemble artifact
+const ENSEMBLE_CONFIG: &'static str = "config.json";
+
+/// Deep ensemble of independently initialized & trained models
+///
+/// All members share the same architecture [`config`] but are built with their
+/// own random initialization. Members are assigned to the provided devices in
+/// a round robin fashion, which allows training them in parallel.
+///
+/// # Parameters
+///
+/// - `members` are the models of the ensemble
+/// - `config` is the description of the architecture shared by all members
+/// - `manager` is the device manager shared by all members
+pub struct Ensemble {
+  pub members: Vec<Sequential>,
+  pub config: ModelConfig,
+  manager: DeviceManager,
+}
+
+impl Ensemble {
+  /// Builds `num_members` independently initialized models
+  pub fn new(config: &ModelConfig, manager: DeviceManager, devices: &Vec<Device>
+             , num_members: usize) -> Result<Ensemble, HALError>
+  {
+    assert!(devices.len() > 0, "need at least one device");
+    let mut members = Vec::with_capacity(num_members);
+    for i in 0..num_members {
+      let device = devices[i % devices.len()];
+      manager.swap_device(device);
+      members.push(try!(config.build(manager.clone(), device)));
+    }
+
+    Ok(Ensemble {
+      members: members,
+      config: config.clone(),
+      manager: manager,
+    })
+  }
+
+  /// Fit's every member on the provided data, one after the other
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the losses of every member
+  pub fn fit<T, E>(&mut self, source: &T, src_device: Device
+                   , epochs: u64, batch_size: u64, verbose: bool) -> Vec<Vec<f32>>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    self.members.iter_mut().map(|member| {
+      member.fit::<T, E>(source, src_device, epochs, batch_size, None, None, verbose)
+    }).collect()
+  }
+
+  /// Fit's every member on its own thread [and on its own device]
+  ///
+  /// Every thread selects the device of its member & reads from its own
+  /// source, built by `make_source` on the thread [eg: a shuffled source, so
+  /// that every member also sees the data in a different order].
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the losses of every member
+  pub fn fit_parallel<T, E, F>(&mut self, make_source: Arc<F>, src_device: Device
+                               , epochs: u64, batch_size: u64) -> Vec<Vec<f32>>
+    where T: DataSource + 'static, E: HasAfEnum + Zero + Clone + 'static
+    , F: Fn() -> T + Send + Sync + 'static
+  {
+    let handles: Vec<_> = self.members.drain(..).map(|mut member| {
+      let make_source = make_source.clone();
+      thread::spawn(move || {
+        member.get_manager().swap_device(member.get_device());
+        let source = make_source();
+        let loss = member.fit::<T, E>(&source, src_device, epochs, batch_size, None, None, false);
+        (member, loss)
+      })
+    }).collect();
+
+    let mut losses = Vec::with_capacity(handles.len());
+    for handle in handles {
+      let (member, loss) = handle.join().unwrap();
+      self.members.push(member);
+      losses.push(loss);
+    }
+    losses
+  }
+
+  /// Calculate the mean & variance of the member probabilities [see `Model::predict_proba`]
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  /// - `dest_device` is the destination device that the statistics should go to
+  ///
+  /// # Return Values
+  ///
+  /// Vector of (mean, variance) per time-step
+  pub fn predict_mean_variance<T>(&mut self, inputs: &Array, src_device: Device
+                                  , dest_device: Device) -> Vec<(Array, Array)>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let predictions: Vec<Vec<Array>> = self.members.iter_mut()
+      .map(|member| member.predict_proba::<T>(inputs, src_device, dest_device)).collect();
+    self.manager.swap_device(dest_device);
+
+    let num_members = predictions.len() as f32;
+    (0..predictions[0].len()).map(|t| {
+      let mut sum = predictions[0][t].clone();
+      let mut sum_squares = af::mul(&predictions[0][t], &predictions[0][t], false);
+      for member_predictions in predictions.iter().skip(1) {
+        let p = &member_predictions[t];
+        sum = af::add(&sum, p, false);
+        sum_squares = af::add(&sum_squares, &af::mul(p, p, false), false);
+      }
+
+      // var = E[p^2] - E[p]^2
+      let mean = af::div(&sum, &num_members, false);
+      let variance = af::sub(&af::div(&sum_squares, &num_members, false)
+                             , &af::mul(&mean, &mean, false), false);
+      (mean, variance)
+    }).collect()
+  }
+
+  /// Saves the ensemble as `dir/config.json` + one checkpoint per member
+  pub fn save(&self, dir: &str) -> Result<(), HALError> {
+    try!(fs::create_dir_all(dir).map_err(|_| HALError::CHECKPOINT_IO));
+    let mut file = try!(File::create(format!("{}/{}", dir, ENSEMBLE_CONFIG))
+                        .map_err(|_| HALError::CHECKPOINT_IO));
+    try!(file.write_all(self.config.to_json().as_bytes()).map_err(|_| HALError::CHECKPOINT_IO));
+    for (i, member) in self.members.iter().enumerate() {
+      try!(member.save_checkpoint(&Ensemble::member_path(dir, i), 0));
+    }
+    Ok(())
+  }
+
+  /// Loads an ensemble that was saved with `save`
+  pub fn load(dir: &str, manager: DeviceManager, devices: &Vec<Device>) -> Result<Ensemble, HALError> {
+    let mut contents = String::new();
+    try!(File::open(format!("{}/{}", dir, ENSEMBLE_CONFIG))
+         .and_then(|mut f| f.read_to_string(&mut contents))
+         .map_err(|_| HALError::CHECKPOINT_IO));
+    let config = try!(ModelConfig::from_json(&contents));
+
+    // other checkpoints in the directory [eg: of `set_checkpoint_dir`] are not members
+    let num_members = (0..).take_while(|&i| Path::new(&Ensemble::member_path(dir, i)).is_file()).count();
+    if num_members == 0 {
+      warn!("no member checkpoints in {}", dir);
+      return Err(HALError::CHECKPOINT_IO);
+    }
+    let mut ensemble = try!(Ensemble::new(&config, manager, devices, num_members));
+    for (i, member) in ensemble.members.iter_mut().enumerate() {
+      try!(member.load_checkpoint(&Ensemble::member_path(dir, i)));
+    }
+    Ok(ensemble)
+  }
+
+  fn member_path(dir: &str, index: usize) -> String {
+    format!("{}/member_{:04}.{}", dir, index, checkpoint::CHECKPOINT_EXTENSION)
+  }
+}
@@ -1,13 +1,24 @@
 pub use self::sequential::Sequential;
 mod sequential;
 
+pub use self::multihead::MultiHead;
+mod multihead;
+
+pub use self::ensemble::Ensemble;
+mod ensemble;
+
+pub use self::distillation::Distillation;
+mod distillation;
+
 use num::Zero;
-use af::{Array, HasAfEnum};
+use af::{Array, Dim4, HasAfEnum};
 use std::collections::HashMap;
 
 use device::{Device, DeviceManager};
 use data::{DataSource};
 use optimizer::Optimizer;
+use metrics::Metric;
+use error::HALError;
 
 pub trait Model {
   fn new(manager: DeviceManager
@@ -20,13 +31,45 @@ pub trait Model {
                , loss_indices: Option<&Vec<bool>>, verbose: bool) -> Vec<f32>
     where T: DataSource, E: HasAfEnum + Zero + Clone;
 
+  fn partial_fit<E>(&mut self, batch_input: &Array, batch_target: &Array, src_device: Device
+                    , bptt_interval: Option<u64>, loss_indices: Option<&Vec<bool>>) -> Vec<f32>
+    where E: HasAfEnum + Zero + Clone;
+
   fn forward<T>(&mut self, inputs: &Array
                 , src_device: Device
                 , dest_device: Device) -> Vec<Array>
     where T: HasAfEnum + Zero + Clone;
 
+  fn forward_to<T>(&mut self, layer_name: &str, inputs: &Array
+                   , src_device: Device
+                   , dest_device: Device) -> Result<Vec<Array>, HALError>
+    where T: HasAfEnum + Zero + Clone;
+
+  fn predict_proba<T>(&mut self, inputs: &Array
+                      , src_device: Device
+                      , dest_device: Device) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone;
+
+  fn predict_classes<T>(&mut self, inputs: &Array
+                        , src_device: Device
+                        , dest_device: Device
+                        , threshold: Option<f32>) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone;
+
   fn backward(&mut self, predictions: &Vec<Array>, targets: &Array, loss_indices: Option<&Vec<bool>>) -> Vec<f32>;
+  fn backward_deltas(&mut self, deltas: &Vec<Array>) -> Vec<Array>;
+
+  fn set_cost_matrix<T>(&mut self, cost: &Array, src_device: Device, use_for_decision: bool)
+    where T: HasAfEnum + Zero + Clone;
+
+  fn add_metric(&mut self, metric: Box<Metric>);
+  fn evaluate<T, E>(&mut self, source: &T, src_device: Device, batch_size: u64) -> HashMap<String, f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone;
+  fn get_history(&self) -> &HashMap<String, Vec<f32>>;
 
   fn add<T: HasAfEnum>(&mut self, layer: &str, params: HashMap<&str, String>);
   fn info(&self);
+
+  fn warmup<T>(&mut self, batch_shape: Dim4, iterations: u64) -> Vec<f64>
+    where T: HasAfEnum + Zero + Clone;
 }
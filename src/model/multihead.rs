@@ -0,0 +1,351 @@
+use af;
+use af::{Array, HasAfEnum};
+use num::Zero;
+use std::collections::HashMap;
+
+use loss;
+use utils;
+use activations;
+use data::{DataSource};
+use device::{Device, DeviceManager};
+use metrics::Metric;
+use model::{Model, Sequential};
+use params::LossScaler;
+
+/// A shared trunk feeding several heads, each with its own loss & optimizer
+///
+/// The trunk forward pass is executed exactly once per batch and its
+/// activations are handed to every head. On the backward pass the
+/// derivatives w.r.t. the trunk outputs of all the heads are summed
+/// before a single backward pass through the trunk.
+///
+/// The objective is the weighted sum of the head losses [see
+/// `set_loss_weights`], eg: a classification & a regression head sharing a
+/// trunk. Every head tracks its own metrics [see `add_metric`].
+///
+/// # Parameters
+///
+/// - `trunk` is the shared model, its outputs are the inputs of all the heads
+/// - `heads` are the task specific models
+/// - `loss_weights` are the weights of the head losses in the objective
+/// - `metrics` are the metrics of every head
+/// - `history` are the per epoch values recorded by `fit` [see `get_history`]
+/// - `manager` is the device manager of the trunk
+/// - `device` is the device that the trunk & heads compute on
+pub struct MultiHead {
+  pub trunk: Sequential,
+  pub heads: Vec<Sequential>,
+  pub loss_weights: Vec<f32>,
+  metrics: Vec<Vec<Box<Metric>>>,
+  history: HashMap<String, Vec<f32>>,
+  manager: DeviceManager,
+  device: Device,
+}
+
+/// Helper to stack per time-step [batch, feature] arrays into a [batch, feature, time] array
+fn stack_time_steps(steps: &Vec<Array>) -> Array {
+  let mut stacked = steps[0].clone();
+  for step in steps.iter().skip(1) {
+    stacked = af::join(2, &stacked, step);
+  }
+  stacked
+}
+
+impl MultiHead {
+  pub fn new(trunk: Sequential, heads: Vec<Sequential>) -> MultiHead {
+    assert!(heads.len() > 0, "need at least one head");
+    let device = trunk.get_device();
+    for head in heads.iter() {
+      assert!(head.get_device() == device
+              , "the trunk & all the heads need to be on the same device");
+    }
+
+    MultiHead {
+      manager: trunk.get_manager(),
+      trunk: trunk,
+      loss_weights: vec![1.0; heads.len()],
+      metrics: heads.iter().map(|_| Vec::new()).collect(),
+      heads: heads,
+      history: HashMap::new(),
+      device: device,
+    }
+  }
+
+  /// Sets the weight of the loss of every head in the objective [all 1 by default]
+  pub fn set_loss_weights(&mut self, loss_weights: Vec<f32>) {
+    assert!(loss_weights.len() == self.heads.len(), "need a loss weight per head");
+    assert!(loss_weights.iter().all(|&w| w >= 0.0), "loss weights can not be negative");
+    self.loss_weights = loss_weights;
+  }
+
+  /// Adds a metric of the provided head that is evaluated on the validation data
+  /// after every epoch of `fit` [see `evaluate`]
+  pub fn add_metric(&mut self, head: usize, metric: Box<Metric>) {
+    assert!(head < self.heads.len(), "there are only {} heads", self.heads.len());
+    self.metrics[head].push(metric);
+  }
+
+  /// Returns the per epoch history of the training losses ["loss" & "head{i}_loss"]
+  /// and of the validation values [prefixed with "val_", see `evaluate`] recorded by `fit`
+  pub fn get_history(&self) -> &HashMap<String, Vec<f32>> {
+    &self.history
+  }
+
+  /// Weighted sum of the (time-step averaged) losses of the heads
+  fn objective(&self, losses: &Vec<Vec<f32>>) -> f32 {
+    losses.iter().zip(self.loss_weights.iter()).fold(0f32, |sum, (head_losses, w)| {
+      sum + w * head_losses.iter().fold(0f32, |s, l| s + l) / head_losses.len() as f32
+    })
+  }
+
+  /// Calculate the forward pass of the trunk [once] and of all the heads
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  /// - `dest_device` is the destination device that the data should go to
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the outputs of every head (one per time-step)
+  pub fn forward<T>(&mut self, inputs: &Array
+                    , src_device: Device
+                    , dest_device: Device) -> Vec<Vec<Array>>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let device = self.device;
+    let seq_len = inputs.dims()[2] as usize;
+    let mut trunk_outputs = self.trunk.forward::<T>(inputs, src_device, device);
+    trunk_outputs.truncate(seq_len);
+    let shared = stack_time_steps(&trunk_outputs);
+    self.heads.iter_mut().map(|head| {
+      let mut outputs = head.forward::<T>(&shared, device, dest_device);
+      outputs.truncate(seq_len);
+      outputs
+    }).collect()
+  }
+
+  /// Inference pass of the trunk [once] and of all the heads [dropout disabled, see `Sequential::infer`]
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the outputs of every head (one per time-step) on the device of the model
+  pub fn infer<T>(&mut self, inputs: &Array, src_device: Device) -> Vec<Vec<Array>>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let device = self.device;
+    let shared = stack_time_steps(&self.trunk.infer::<T>(inputs, src_device));
+    self.heads.iter_mut().map(|head| head.infer::<T>(&shared, device)).collect()
+  }
+
+  /// Calculate the gradients of all the heads and of the shared trunk
+  ///
+  /// The gradients of every head are scaled by its loss weight
+  /// # Parameters
+  ///
+  /// - `predictions` are the outputs of every head [see `forward`]
+  /// - `targets` are the true targets of every head
+  /// - `loss_indices` are the optional indices of losses to use while computing the gradient
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the [unweighted] losses of every head
+  pub fn backward(&mut self, predictions: &Vec<Vec<Array>>, targets: &Vec<Array>
+                  , loss_indices: Option<&Vec<bool>>) -> Vec<Vec<f32>>
+  {
+    assert!(predictions.len() == self.heads.len() && targets.len() == self.heads.len()
+            , "need predictions & targets for every head");
+
+    let mut losses = Vec::with_capacity(self.heads.len());
+    let mut trunk_deltas: Vec<Array> = Vec::new();
+    for ((head, &weight), (pred, target)) in self.heads.iter_mut().zip(self.loss_weights.iter())
+      .zip(predictions.iter().zip(targets.iter()))
+    {
+      let (loss, input_deltas) = head.backward_inputs_weighted(pred, target, loss_indices, weight);
+      trunk_deltas = match trunk_deltas.len() {
+        0 => input_deltas,
+        _ => trunk_deltas.iter().zip(input_deltas.iter())
+          .map(|(acc, d)| af::add(acc, &utils::cast(d, acc.get_type()), false)).collect(),
+      };
+      losses.push(loss);
+    }
+
+    self.trunk.backward_deltas(&trunk_deltas);
+    losses
+  }
+
+  /// Shares a loss scaler between the trunk & all the heads [see `LossScaler`], None disables it
+  pub fn set_loss_scaler(&mut self, scaler: Option<LossScaler>) {
+    self.trunk.set_loss_scaler(scaler.clone());
+    for head in self.heads.iter_mut() {
+      head.set_loss_scaler(scaler.clone());
+    }
+  }
+
+  /// Applies the optimizers of the trunk & of all the heads
+  ///
+  /// The step is skipped by all of them when the gradients of any overflow [see `Sequential::step`].
+  pub fn step(&mut self, batch_size: u64) {
+    let mut finite = self.trunk.get_param_manager().unscale_all_deltas();
+    for head in self.heads.iter() {
+      finite = head.get_param_manager().unscale_all_deltas() && finite;
+    }
+    if let Some(scaler) = self.trunk.get_param_manager().get_loss_scaler() {
+      scaler.update(finite);
+    }
+    let models = Some(&mut self.trunk).into_iter().chain(self.heads.iter_mut());
+    for model in models {
+      match finite {
+        true  => model.apply_gradients(batch_size),
+        false => model.get_param_manager().zero_all_deltas(),
+      }
+    }
+  }
+
+  /// Evaluates the losses & the metrics of every head on the validation data
+  ///
+  /// # Parameters
+  ///
+  /// - `source` is the datasource
+  /// - `src_device` is the source device of the data
+  /// - `batch_size` is the minibatch size
+  /// - `target_columns` are the [first, last] target columns of every head
+  ///
+  /// # Return Values
+  ///
+  /// HashMap of the weighted validation objective ["loss"], of the mean loss of
+  /// every head ["head{i}_loss"] & of the metric values of every head ["head{i}_{name}"]
+  pub fn evaluate<T, E>(&mut self, source: &T, src_device: Device, batch_size: u64
+                        , target_columns: &Vec<(u64, u64)>) -> HashMap<String, f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    assert!(target_columns.len() == self.heads.len()
+            , "need the target columns of every head");
+    let iters = ::std::cmp::max(source.info().num_validation.unwrap_or(0) / batch_size, 1);
+    let device = self.device;
+    for metric in self.metrics.iter_mut().flat_map(|m| m.iter_mut()) {
+      metric.reset();
+    }
+
+    let mut loss_sums = vec![0f32; self.heads.len()];
+    let mut loss_count = 0;
+    for _ in 0..iters {
+      self.manager.swap_device(src_device);
+      let minibatch = match source.get_validation_iter(batch_size) {
+        Some(minibatch) => minibatch,
+        None            => break,
+      };
+      let (input, target) = (minibatch.input.into_inner(), minibatch.target.into_inner());
+      let mut batch = self.manager.swap_arrays_backend::<E>(&[&input, &target], src_device, device);
+      let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+
+      let predictions = self.infer::<E>(&batch_input, device);
+      for (i, outputs) in predictions.iter().enumerate() {
+        let (first, last) = target_columns[i];
+        let loss_name = self.heads[i].get_loss().to_string();
+        let activation = loss::get_output_activation(&loss_name);
+        let mut head_loss = 0f32;
+        for (t, output) in outputs.iter().enumerate() {
+          let tar = af::cols(&af::slice(&batch_target, t as u64), first, last);
+          head_loss += loss::get_loss(&loss_name, output, &tar).unwrap();
+          let p = activations::get_activation(activation, output).unwrap();
+          let inp = af::slice(&batch_input, t as u64);
+          for metric in self.metrics[i].iter_mut() {
+            metric.update_with_inputs(&p, &tar, &inp);
+          }
+        }
+        loss_sums[i] += head_loss / outputs.len() as f32;
+      }
+      loss_count += 1;
+    }
+    self.manager.swap_device(src_device);
+
+    let mut values = HashMap::new();
+    if loss_count > 0 {
+      let losses: Vec<Vec<f32>> = loss_sums.iter().map(|l| vec![l / loss_count as f32]).collect();
+      values.insert("loss".to_string(), self.objective(&losses));
+      for (i, loss) in losses.iter().enumerate() {
+        values.insert(format!("head{}_loss", i), loss[0]);
+        for metric in self.metrics[i].iter() {
+          values.extend(metric.values().into_iter().map(|(name, v)| (format!("head{}_{}", i, name), v)));
+        }
+      }
+    }
+    values
+  }
+
+  /// Fit's the trunk & all the heads to the provided data
+  ///
+  /// When metrics were added [see `add_metric`] the heads are evaluated on the
+  /// validation data at the end of every epoch and the results are recorded
+  /// in the history [see `get_history`].
+  ///
+  /// # Parameters
+  ///
+  /// - `source` is the datasource
+  /// - `src_device` is the source device of the data
+  /// - `epochs` is the number of epochs to run the training loop for
+  /// - `batch_size` is the minibatch size
+  /// - `target_columns` are the [first, last] target columns of every head
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the weighted objective [see `set_loss_weights`] of every minibatch
+  pub fn fit<T, E>(&mut self, source: &T, src_device: Device
+                   , epochs: u64, batch_size: u64
+                   , target_columns: &Vec<(u64, u64)>) -> Vec<f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    assert!(target_columns.len() == self.heads.len()
+            , "need the target columns of every head");
+    let iters = source.info().num_samples as u64 / batch_size as u64;
+    assert!(iters > 0, "need at least one minibatch of {} samples per epoch", batch_size);
+    let device = self.device;
+    let has_metrics = self.metrics.iter().any(|m| m.len() > 0);
+
+    let mut lossvec = Vec::<f32>::new();
+    for _ in 0..epochs {
+      let mut head_sums = vec![0f32; self.heads.len()];
+      for _ in 0..iters {
+        self.manager.swap_device(src_device);
+        let minibatch = source.get_train_iter(batch_size);
+        let (input, target) = (minibatch.input.into_inner(), minibatch.target.into_inner());
+        let mut batch = self.manager.swap_arrays_backend::<E>(&[&input, &target], src_device, device);
+        let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+        let targets: Vec<Array> = target_columns.iter()
+          .map(|&(first, last)| af::cols(&batch_target, first, last)).collect();
+
+        let predictions = self.forward::<E>(&batch_input, device, device);
+        let losses = self.backward(&predictions, &targets, None);
+        self.step(batch_size);
+
+        for (sum, head_losses) in head_sums.iter_mut().zip(losses.iter()) {
+          *sum += head_losses.iter().fold(0f32, |s, l| s + l) / head_losses.len() as f32;
+        }
+        lossvec.push(self.objective(&losses));
+      }
+
+      // record the epoch
+      let epoch_losses: Vec<Vec<f32>> = head_sums.iter().map(|s| vec![s / iters as f32]).collect();
+      let objective = self.objective(&epoch_losses);
+      self.history.entry("loss".to_string()).or_insert(Vec::new()).push(objective);
+      for (i, loss) in epoch_losses.iter().enumerate() {
+        self.history.entry(format!("head{}_loss", i)).or_insert(Vec::new()).push(loss[0]);
+      }
+      if has_metrics && source.info().num_validation.is_some() {
+        for (name, value) in self.evaluate::<T, E>(source, src_device, batch_size, target_columns) {
+          self.history.entry(format!("val_{}", name)).or_insert(Vec::new()).push(value);
+        }
+      }
+    }
+
+    self.manager.swap_device(src_device);
+    lossvec
+  }
+}
@@ -1,19 +1,34 @@
 use af;
-use af::{Array, Backend, HasAfEnum};
-use std::cmp::max;
+use af::{Array, Backend, Dim4, DType, HasAfEnum};
+use std::cmp::{max, min};
 use num::Zero;
 use itertools::Zip;
 use std::default::Default;
+use std::fs;
+use std::io::{Read, Write};
 use std::collections::HashMap;
+use std::time::Instant;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use loss;
+use callback::{BatchProgress, Callback, PrintLogger, TrainingState};
+use checkpoint;
+use config;
+use hub;
+use prune;
 use utils;
-use layer::{Layer, Dense, RNN, Unitary};//, LSTM};
-use data::{DataSource};
+use activations;
+use layer::{Layer, Dense, RNN, Unitary, Ordinal, Dropout};//, LSTM};
+use data::{self, DataSource, FeatureStatistics, RaggedBatch, ArraySource};
 use device::{Device, DeviceManager, DeviceManagerFactory};
 use model::Model;
-use optimizer::{Optimizer, SGD};
-use params::{ParamManager, DenseGenerator, LSTMGenerator, RNNGenerator, UnitaryGenerator};
+use metrics::Metric;
+use semisupervised::FixMatch;
+use error::HALError;
+use optimizer::{self, Optimizer, SGD, WeightAverage};
+use params::{ParamManager, LossScaler, DenseGenerator, LSTMGenerator, RNNGenerator, UnitaryGenerator, OrdinalGenerator, DropoutGenerator};
 
 pub struct Sequential {
   layers: Vec<Box<Layer>>,
@@ -22,6 +37,24 @@ pub struct Sequential {
   manager: DeviceManager,
   loss: String,
   device: Device,
+  cost_matrix: Option<Array>,
+  cost_decision: bool,
+  metrics: Vec<Box<Metric>>,
+  history: HashMap<String, Vec<f32>>,
+  online_normalization: Option<(FeatureStatistics, f32)>,
+  checkpoint_dir: Option<String>,
+  layer_configs: Vec<(String, HashMap<String, String>)>,
+  pruning_masks: Option<Vec<Array>>,
+  environment: Option<checkpoint::Environment>,
+  weight_averages: Vec<WeightAverage>,
+  checkpoint_segments: Vec<(usize, usize)>,
+  callbacks: Vec<Box<Callback>>,
+  dropout_active: Arc<AtomicBool>,
+  mc_dropout: bool,
+  consistency: Option<FixMatch>,
+  layer_deltas: Vec<Array>,
+  frozen_layers: usize,
+  cached_prefix: bool,
 }
 
 impl Default for Sequential {
@@ -33,6 +66,24 @@ impl Default for Sequential {
       manager: DeviceManagerFactory::new(),
       loss: "mse".to_string(),
       device: Device{ backend: Backend::DEFAULT, id: 0 },
+      cost_matrix: None,
+      cost_decision: false,
+      metrics: Vec::new(),
+      history: HashMap::new(),
+      online_normalization: None,
+      checkpoint_dir: None,
+      layer_configs: Vec::new(),
+      pruning_masks: None,
+      environment: None,
+      weight_averages: Vec::new(),
+      checkpoint_segments: Vec::new(),
+      callbacks: Vec::new(),
+      dropout_active: Arc::new(AtomicBool::new(true)),
+      mc_dropout: false,
+      consistency: None,
+      layer_deltas: Vec::new(),
+      frozen_layers: 0,
+      cached_prefix: false,
     }
   }
 }
@@ -43,6 +94,1023 @@ impl Drop for Sequential {
   }
 }
 
+impl Sequential {
+  /// Inference only forward pass
+  ///
+  /// Runs the forward pass, keeps only the outputs of the provided sequence
+  /// and rewinds the layers so that no backward pass is expected.
+  /// The outputs are left on the model device.
+  /// Dropout is disabled unless Monte-Carlo dropout is set [see `set_mc_dropout`].
+  pub fn infer<T>(&mut self, inputs: &Array, src_device: Device) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let compute_device = self.device;
+    let seq_len = max(inputs.dims()[2], 1) as usize;
+    self.dropout_active.store(self.mc_dropout, Ordering::SeqCst);
+    let mut outputs = self.forward_all_activations::<T>(inputs, src_device, compute_device);
+    self.dropout_active.store(true, Ordering::SeqCst);
+    outputs.truncate(seq_len);
+    self.param_manager.reset_all_unrolls();
+    outputs
+  }
+
+  /// Keeps the dropout layers active during inference [Monte-Carlo dropout, Gal & Ghahramani, 2016]
+  ///
+  /// Repeated `predict_proba` calls then sample from an approximate posterior
+  /// predictive [see `active::mc_dropout_scores`].
+  pub fn set_mc_dropout(&mut self, enabled: bool) {
+    self.mc_dropout = enabled;
+  }
+
+  /// Adds a consistency loss on unlabeled data to every training step of `fit`
+  ///
+  /// The fraction of confident pseudo-labels of every epoch is recorded in
+  /// the history as "mask_rate". None removes it.
+  pub fn set_consistency(&mut self, consistency: Option<FixMatch>) {
+    self.consistency = consistency;
+  }
+
+  /// Returns the (layer type, params) that every layer was added with
+  pub fn get_layer_configs(&self) -> &Vec<(String, HashMap<String, String>)> {
+    &self.layer_configs
+  }
+
+  /// Returns the name of the loss
+  pub fn get_loss(&self) -> &str {
+    &self.loss
+  }
+
+  /// Returns the optimizer of the model
+  pub fn get_optimizer(&self) -> &Box<Optimizer> {
+    &self.optimizer
+  }
+
+  /// Returns the deltas of the last backward pass: the derivatives of the loss
+  /// w.r.t. the outputs of every layer [of the first time-step, in the layer precision]
+  ///
+  /// eg: find the layer that blows up when the training diverges [see `Callback::inspect_batch`]
+  pub fn get_layer_deltas(&self) -> &Vec<Array> {
+    &self.layer_deltas
+  }
+
+  /// Overwrites all the parameters [in the order of `ParamManager::get_all_arrays`]
+  ///
+  /// eg: to evaluate the posterior samples of a sampling optimizer [see `Optimizer::get_samples`]
+  pub fn set_params(&mut self, arrays: &Vec<Array>) {
+    assert!(arrays.len() == self.param_manager.get_all_dims().len()
+            , "need one array per parameter");
+    for (ind, arr) in arrays.iter().enumerate() {
+      self.param_manager.set_array_from_index(arr.clone(), ind);
+    }
+  }
+
+  /// Returns the device that the model computes on
+  pub fn get_device(&self) -> Device {
+    self.device
+  }
+
+  /// Returns the device manager of the model
+  pub fn get_manager(&self) -> DeviceManager {
+    self.manager.clone()
+  }
+
+  /// Returns the parameter manager of the model
+  pub fn get_param_manager(&self) -> &ParamManager {
+    &self.param_manager
+  }
+
+  /// Enables the normalization of the model inputs by running statistics
+  ///
+  /// The per feature statistics are updated by every training batch
+  /// [see `Model::partial_fit`] and persist across calls, so that online
+  /// learning sees the same normalization as inference does.
+  ///
+  /// # Parameters
+  ///
+  /// - `num_features` is the number of input features
+  /// - `num_std` is the number of standard deviations to scale by
+  pub fn set_online_normalization(&mut self, num_features: u64, num_std: f32) {
+    self.manager.swap_device(self.device);
+    self.online_normalization = Some((FeatureStatistics::new(num_features), num_std));
+  }
+
+  /// Returns the running input statistics (if online normalization is enabled)
+  pub fn get_input_statistics(&self) -> Option<&FeatureStatistics> {
+    self.online_normalization.as_ref().map(|&(ref stats, _)| stats)
+  }
+
+  /// Makes `fit` save a checkpoint of the parameters at the end of every epoch
+  ///
+  /// The checkpoints are written to `dir/epoch_XXXX.ckpt`, the directory is created if needed
+  /// (see `checkpoint::evaluate_checkpoints` to pick the best epoch afterwards).
+  /// The history records "checkpoint_failed" every epoch [1 when the checkpoint could not be saved].
+  pub fn set_checkpoint_dir(&mut self, dir: &str) -> Result<(), HALError> {
+    try!(fs::create_dir_all(dir).map_err(|_| HALError::CHECKPOINT_IO));
+    self.checkpoint_dir = Some(dir.to_string());
+    Ok(())
+  }
+
+  /// Saves all the parameters of the model & the current environment to the provided path
+  pub fn save_checkpoint(&self, path: &str, epoch: u64) -> Result<(), HALError> {
+    self.manager.swap_device(self.device);
+    let mut saved = checkpoint::Checkpoint::from_params(&self.param_manager, epoch);
+    saved.environment = Some(checkpoint::Environment::capture(&self.manager, self.device));
+    checkpoint::save(&saved, path)
+  }
+
+  /// Loads the parameters of a checkpoint with the same architecture into the model
+  ///
+  /// The environment that the checkpoint was saved in is kept [see `get_environment`]
+  ///
+  /// # Return Values
+  ///
+  /// The epoch that the checkpoint was saved at
+  pub fn load_checkpoint(&mut self, path: &str) -> Result<u64, HALError> {
+    let loaded = try!(checkpoint::load(path));
+    self.manager.swap_device(self.device);
+    try!(loaded.restore(&self.param_manager));
+    self.environment = loaded.environment;
+    Ok(loaded.epoch)
+  }
+
+  /// Returns the environment of the last loaded checkpoint [None for older checkpoints]
+  pub fn get_environment(&self) -> Option<&checkpoint::Environment> {
+    self.environment.as_ref()
+  }
+
+  /// Applies the optimizer to the gradients accumulated since the last step
+  ///
+  /// The gradients are unscaled first [see `set_loss_scaler` & the `grad_scale`
+  /// layer param], a step whose gradients overflow is skipped.
+  pub fn step(&mut self, batch_size: u64) {
+    let finite = self.param_manager.unscale_all_deltas();
+    let finite = match self.param_manager.get_loss_scaler() {
+      Some(scaler) => scaler.update(finite),
+      None         => finite,
+    };
+    match finite {
+      true  => self.apply_gradients(batch_size),
+      false => self.param_manager.zero_all_deltas(),
+    }
+  }
+
+  /// Applies the optimizer to the accumulated gradients as they are [without unscaling them]
+  pub fn apply_gradients(&mut self, batch_size: u64) {
+    self.optimizer.update(&mut self.param_manager, batch_size);
+    if let Some(ref masks) = self.pruning_masks {
+      prune::apply_masks(&self.param_manager, masks);
+    }
+    for average in self.weight_averages.iter_mut() {
+      average.update(&self.param_manager);
+    }
+  }
+
+  /// Scales the derivatives of the loss of every backward pass [see `LossScaler`], None disables it
+  ///
+  /// Loss scaling keeps the small gradients of low precision layers [see the
+  /// `dtype` layer param] from underflowing. The deltas of the model inputs &
+  /// of the layers [see `get_layer_deltas`] are returned unscaled.
+  pub fn set_loss_scaler(&mut self, scaler: Option<LossScaler>) {
+    self.param_manager.set_loss_scaler(scaler);
+  }
+
+  /// Maintains an averaged copy of the parameters during training [see `WeightAverage`]
+  pub fn add_weight_average(&mut self, average: WeightAverage) {
+    self.weight_averages.push(average);
+  }
+
+  /// Returns the weight average of the provided kind ["ema" or "swa"]
+  pub fn get_weight_average(&self, kind: &str) -> Option<&WeightAverage> {
+    self.weight_averages.iter().find(|a| a.kind == kind)
+  }
+
+  /// Exchanges the parameters with the weights of the average of the provided kind
+  ///
+  /// eg: swap the averaged weights in, evaluate or `save_checkpoint`,
+  /// then swap again to continue training with the original weights.
+  ///
+  /// # Return Values
+  ///
+  /// false when there is no such average or nothing was averaged yet
+  pub fn swap_averaged_weights(&mut self, kind: &str) -> bool {
+    self.manager.swap_device(self.device);
+    match self.weight_averages.iter_mut().find(|a| a.kind == kind) {
+      Some(average) => average.swap(&self.param_manager),
+      None          => false,
+    }
+  }
+
+  /// Adds a callback that is notified of the progress of `fit` [see `Callback`]
+  pub fn add_callback(&mut self, callback: Box<Callback>) {
+    self.callbacks.push(callback);
+  }
+
+  /// Enables gradient checkpointing for the provided (first, last) layer segments
+  ///
+  /// The intermediate activations of a segment [everything but the input of
+  /// `first` & the output of `last`] are released after the forward pass and
+  /// recomputed from the segment input during the backward pass. This trades
+  /// one extra forward pass of the segment for its activation memory.
+  ///
+  /// Only dense layers can be checkpointed, the segments need to span at
+  /// least two layers & can not overlap. An empty vector disables checkpointing.
+  /// While the forward passes start from the cached prefix [see `set_cached_prefix`]
+  /// the segments can not straddle the last frozen layer.
+  pub fn set_gradient_checkpoints(&mut self, segments: Vec<(usize, usize)>) -> Result<(), HALError> {
+    let mut sorted = segments.clone();
+    sorted.sort();
+    for (i, &(first, last)) in sorted.iter().enumerate() {
+      if first >= last || last >= self.layers.len()
+        || (i > 0 && sorted[i - 1].1 >= first)
+        || self.layer_configs[first..last + 1].iter().any(|&(ref layer, _)| layer.to_lowercase() != "dense")
+      {
+        return Err(HALError::CONFIG);
+      }
+    }
+    if self.cached_prefix && Sequential::straddles(&sorted, self.frozen_layers) {
+      warn!("the checkpoint segments can not straddle the {} cached frozen layers", self.frozen_layers);
+      return Err(HALError::CONFIG);
+    }
+    self.checkpoint_segments = sorted;
+    Ok(())
+  }
+
+  /// Whether a (first, last) segment starts in the first `num_layers` layers & ends after them
+  ///
+  /// The inputs of such a segment are never computed when starting from the cached
+  /// prefix, so it can not be recomputed in the backward pass.
+  fn straddles(segments: &[(usize, usize)], num_layers: usize) -> bool {
+    segments.iter().any(|&(first, last)| first < num_layers && last >= num_layers)
+  }
+
+  /// Returns the checkpointed (first, last) layer segments [see `set_gradient_checkpoints`]
+  pub fn get_gradient_checkpoints(&self) -> &Vec<(usize, usize)> {
+    &self.checkpoint_segments
+  }
+
+  /// Same as `Model::forward` but keeps the activations of the gradient checkpoints
+  ///
+  /// The inputs & outputs of every layer are left in the param manager [see
+  /// `set_gradient_checkpoints`], eg: to read them in an inference pass
+  /// [see `explain::lrp` & `quantize::calibrate`].
+  pub fn forward_all_activations<T>(&mut self, inputs: &Array, src_device: Device
+                                    , dest_device: Device) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    self.forward_with::<T>(inputs, src_device, dest_device, false)
+  }
+
+  /// Forward pass that optionally releases the activations of the checkpointed segments
+  fn forward_with<T>(&mut self, inputs: &Array, src_device: Device
+                     , dest_device: Device, release_checkpoints: bool) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    // check & swap if the backend matches to runtime one (if not already)
+    let mut activ = self.manager.swap_array_backend::<T>(&inputs, src_device, self.device);
+    if let Some((ref stats, num_std)) = self.online_normalization {
+      if !self.cached_prefix {
+        activ = stats.normalize(&activ, num_std);
+      }
+    }
+
+    // the cached inputs are the outputs of the frozen layers [see `set_cached_prefix`]
+    let first_layer = if self.cached_prefix { self.frozen_layers } else { 0 };
+
+    // if dim[3] > 1 we assume we have an RNN
+    // we will need to unwind at least once for non RNNs
+    let bptt_unroll = max(activ.dims()[2], 1);
+    let mut activate;
+
+    for t in 0..bptt_unroll {
+      activate = af::slice(&activ, t);
+      for i in first_layer..self.layers.len() {
+        // cast to the precision of the layer (no-op if they match)
+        activate = utils::cast(&activate, self.param_manager.get_dtype(i));
+        let (a, _) = self.layers[i].forward(self.param_manager.get_params(i)
+                                            , &activate, None);
+        activate = a;
+      }
+
+      if release_checkpoints {
+        for &(first, last) in self.checkpoint_segments.iter().filter(|&&(first, _)| first >= first_layer) {
+          let unroll = self.param_manager.get_current_unroll(first) - 1;
+          self.release_segment(first, last, unroll);
+        }
+      }
+    }
+
+    // TODO: Parameterize
+    // zero the states
+    // self.param_manager.zero_all_states(None);
+
+    // return the collected outputs of the last layer
+    let last_index = self.layers.len() - 1;
+    let mut outputs = self.param_manager.get_outputs(last_index);
+
+    // return to the dest device
+    // outputs are in the precision of the last layer, the host copy needs T
+    for i in 0..outputs.len() {
+      if dest_device != self.device {
+        outputs[i] = utils::cast(&outputs[i], T::get_af_dtype());
+      }
+      outputs[i] = self.manager.swap_array_backend::<T>(&outputs[i], self.device, dest_device);
+    }
+
+    outputs
+  }
+
+
+  /// Replaces the intermediate activations of a segment at the unroll with placeholders
+  fn release_segment(&self, first: usize, last: usize, unroll: usize) {
+    for i in first..last {
+      let dtype = self.param_manager.get_dtype(i);
+      let placeholder = utils::constant(Dim4::new(&[1, 1, 1, 1]), dtype, 0.0f32);
+      self.param_manager.set_output(i, unroll, placeholder.clone());
+      self.param_manager.set_input(i + 1, unroll, placeholder);
+    }
+  }
+
+  /// Re-runs the forward pass of a segment for its last stored unroll
+  fn recompute_segment(&self, first: usize, last: usize) {
+    let unroll = self.param_manager.get_current_unroll(first) - 1;
+    let mut activate = self.param_manager.get_input(first, unroll);
+    for i in first..last {
+      self.param_manager.set_current_unroll(i, unroll);
+      let (a, _) = self.layers[i].forward(self.param_manager.get_params(i), &activate, None);
+      activate = utils::cast(&a, self.param_manager.get_dtype(i + 1));
+    }
+    self.param_manager.set_input(last, unroll, activate);
+  }
+
+  /// Sets the masks that are applied to the parameters after every optimizer step
+  ///
+  /// The masks are applied right away, so pruned weights stay zero while
+  /// fine-tuning [see `prune::prune`]. None removes the masks.
+  ///
+  /// # Parameters
+  ///
+  /// - `masks` has one mask per array [in the order of `ParamManager::get_all_arrays`]
+  pub fn set_pruning_masks(&mut self, masks: Option<Vec<Array>>) {
+    if let Some(ref masks) = masks {
+      assert!(masks.len() == self.param_manager.get_all_dims().len()
+              , "need one mask per parameter");
+      self.manager.swap_device(self.device);
+      prune::apply_masks(&self.param_manager, masks);
+    }
+    self.pruning_masks = masks;
+  }
+
+  /// Returns the pruning masks of the model
+  pub fn get_pruning_masks(&self) -> Option<&Vec<Array>> {
+    self.pruning_masks.as_ref()
+  }
+
+  /// Collects the outputs of several layers in a single inference pass [activation taps]
+  ///
+  /// Same as `Model::forward_to`, the layers are run up to the last tapped one.
+  ///
+  /// # Return Values
+  ///
+  /// The outputs of every tapped layer (one per time-step) by layer name
+  pub fn forward_taps<T>(&mut self, layer_names: &[&str], inputs: &Array
+                         , src_device: Device, dest_device: Device)
+                         -> Result<HashMap<String, Vec<Array>>, HALError>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let names = self.get_layer_names();
+    let mut indices = Vec::with_capacity(layer_names.len());
+    for name in layer_names {
+      match names.iter().position(|n| n == name) {
+        Some(index) => indices.push(index),
+        None        => return Err(HALError::CONFIG),
+      }
+    }
+    let last = match indices.iter().max() {
+      Some(&last) => last,
+      None        => return Ok(HashMap::new()),
+    };
+
+    let mut activ = self.manager.swap_array_backend::<T>(&inputs, src_device, self.device);
+    if let Some((ref stats, num_std)) = self.online_normalization {
+      activ = stats.normalize(&activ, num_std);
+    }
+
+    let mut taps: Vec<Vec<Array>> = vec![Vec::new(); indices.len()];
+    self.dropout_active.store(self.mc_dropout, Ordering::SeqCst);
+    for t in 0..max(activ.dims()[2], 1) {
+      let mut activate = af::slice(&activ, t);
+      for i in 0..last + 1 {
+        activate = utils::cast(&activate, self.param_manager.get_dtype(i));
+        let (a, _) = self.layers[i].forward(self.param_manager.get_params(i)
+                                            , &activate, None);
+        for (tap, _) in taps.iter_mut().zip(indices.iter()).filter(|&(_, &index)| index == i) {
+          tap.push(a.clone());
+        }
+        activate = a;
+      }
+    }
+    self.dropout_active.store(true, Ordering::SeqCst);
+    self.param_manager.reset_all_unrolls();
+
+    // return to the dest device [in the precision of T, see `forward`]
+    let mut outputs = HashMap::new();
+    for (name, tap) in layer_names.iter().zip(taps) {
+      let tap = tap.iter().map(|a| {
+        let a = match dest_device != self.device {
+          true  => utils::cast(a, T::get_af_dtype()),
+          false => a.clone(),
+        };
+        self.manager.swap_array_backend::<T>(&a, self.device, dest_device)
+      }).collect();
+      outputs.insert(name.to_string(), tap);
+    }
+    Ok(outputs)
+  }
+
+  /// Returns the name of every layer
+  ///
+  /// Layers are named by their optional `name` param, the others are
+  /// named by their type & position [eg: "dense_2"].
+  pub fn get_layer_names(&self) -> Vec<String> {
+    self.layer_configs.iter().enumerate().map(|(i, &(ref layer, ref params))| {
+      match params.get("name") {
+        Some(name) => name.clone(),
+        None       => format!("{}_{}", layer, i),
+      }
+    }).collect()
+  }
+
+  /// Rebuilds the layers from the provided configs
+  ///
+  /// `sources` holds the index of the old layer that every new layer comes from:
+  /// their weights & biases are kept wherever the dimensions still match,
+  /// the others are freshly initialized. Pruning masks, weight averages &
+  /// gradient checkpoints refer to the old layers and are removed, the
+  /// optimizer state is reset. The leading frozen layers that are unchanged
+  /// stay frozen [see `freeze`], the cached prefix is disabled when they change.
+  /// The loss scaler & the gradient scales of the kept layers are carried over.
+  fn rebuild<T: HasAfEnum>(&mut self, configs: Vec<(String, HashMap<String, String>)>
+                           , sources: Vec<Option<usize>>)
+  {
+    assert!(configs.len() == sources.len(), "need a source per layer");
+    assert!(configs.len() > 0, "need at least one layer");
+    let old_arrays: Vec<(Vec<Array>, Vec<Array>)> = (0..self.layers.len())
+      .map(|i| (self.param_manager.get_weights(i), self.param_manager.get_biases(i))).collect();
+    let frozen = (0..min(self.frozen_layers, configs.len() - 1))
+      .take_while(|&j| sources[j] == Some(j) && configs[j] == self.layer_configs[j]).count();
+    let gradient_scales: Vec<f32> = (0..self.layers.len())
+      .map(|i| self.param_manager.get_gradient_scale(i)).collect();
+    let loss_scaler = self.param_manager.get_loss_scaler().cloned();
+
+    self.layers = Vec::new();
+    self.param_manager = ParamManager::default();
+    self.param_manager.set_loss_scaler(loss_scaler);
+    self.layer_configs = Vec::new();
+    for &(ref layer, ref params) in configs.iter() {
+      self.add::<T>(layer, params.iter().map(|(k, v)| (k.as_str(), v.clone())).collect());
+    }
+
+    // the gradient scales follow their layers unless the new config sets one
+    for (j, source) in sources.iter().enumerate() {
+      if let Some(i) = *source {
+        if !configs[j].1.contains_key("grad_scale") {
+          self.param_manager.set_gradient_scale(j, gradient_scales[i]);
+        }
+      }
+    }
+
+    let compatible = |old: &Array, new: &Array| old.dims() == new.dims() && old.get_type() == new.get_type();
+    for (j, source) in sources.iter().enumerate() {
+      if let Some(i) = *source {
+        let (ref weights, ref biases) = old_arrays[i];
+        for (k, (w, new_w)) in weights.iter().zip(self.param_manager.get_weights(j)).enumerate() {
+          if compatible(w, &new_w) {
+            self.param_manager.set_weight(j, k, w.clone());
+          }
+        }
+        for (k, (b, new_b)) in biases.iter().zip(self.param_manager.get_biases(j)).enumerate() {
+          if compatible(b, &new_b) {
+            self.param_manager.set_bias(j, k, b.clone());
+          }
+        }
+      }
+    }
+
+    self.pruning_masks = None;
+    self.weight_averages = Vec::new();
+    self.checkpoint_segments = Vec::new();
+    if self.cached_prefix && frozen != self.frozen_layers {
+      warn!("the frozen layers changed, the forward passes no longer start from their cache");
+      self.cached_prefix = false;
+    }
+    self.freeze(frozen);
+    let params = self.optimizer.get_params();
+    let params: HashMap<&str, &str> = params.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+    match optimizer::get_optimizer(&self.optimizer.get_name(), &params) {
+      Ok(fresh) => self.optimizer = fresh,
+      Err(_)    => warn!("could not reset the state of the {} optimizer", self.optimizer.get_name()),
+    }
+  }
+
+  /// Inserts a layer at the provided position [see `Model::add` for the params]
+  ///
+  /// The weights of all the other layers are kept, the optimizer state is reset.
+  pub fn insert_layer<T: HasAfEnum>(&mut self, index: usize, layer: &str
+                                    , params: HashMap<&str, String>)
+  {
+    assert!(index <= self.layers.len(), "cannot insert past the last layer");
+    let mut configs = self.layer_configs.clone();
+    let mut sources: Vec<Option<usize>> = (0..configs.len()).map(|i| Some(i)).collect();
+    configs.insert(index, (layer.to_string(), params.iter()
+                           .map(|(k, v)| (k.to_string(), v.clone())).collect()));
+    sources.insert(index, None);
+    self.rebuild::<T>(configs, sources);
+  }
+
+  /// Removes the layer at the provided position
+  pub fn remove_layer<T: HasAfEnum>(&mut self, index: usize) {
+    assert!(index < self.layers.len() && self.layers.len() > 1
+            , "can only remove an existing layer & not the last one");
+    let mut configs = self.layer_configs.clone();
+    let mut sources: Vec<Option<usize>> = (0..configs.len()).map(|i| Some(i)).collect();
+    configs.remove(index);
+    sources.remove(index);
+    self.rebuild::<T>(configs, sources);
+  }
+
+  /// Replaces the layer at the provided position
+  ///
+  /// The weights of the replaced layer are kept wherever their dimensions match the new layer.
+  /// A new `output_size` is propagated to the `input_size` of the next layer [& through
+  /// the dropout layers in between], which is freshly initialized when its dims change.
+  ///
+  /// # Return Values
+  ///
+  /// HALError::CONFIG when the `input_size` does not match the outputs of the previous layer
+  pub fn replace_layer<T: HasAfEnum>(&mut self, index: usize, layer: &str
+                                     , params: HashMap<&str, String>) -> Result<(), HALError>
+  {
+    assert!(index < self.layers.len(), "can only replace an existing layer");
+    let mut configs = self.layer_configs.clone();
+    let sources: Vec<Option<usize>> = (0..configs.len()).map(|i| Some(i)).collect();
+    if index > 0 && params.get("input_size") != configs[index - 1].1.get("output_size") {
+      warn!("the input_size of layer {} needs to match the {:?} outputs of the previous layer"
+            , index, configs[index - 1].1.get("output_size"));
+      return Err(HALError::CONFIG);
+    }
+    configs[index] = (layer.to_string(), params.iter()
+                      .map(|(k, v)| (k.to_string(), v.clone())).collect());
+
+    if let Some(size) = params.get("output_size") {
+      config::propagate_output_size(configs[index + 1..].iter_mut()
+                                    .map(|&mut (ref next, ref mut next_params)| (next.as_str(), next_params))
+                                    , size);
+    }
+    self.rebuild::<T>(configs, sources);
+    Ok(())
+  }
+
+  /// Replaces the final dense layer by one with `output_size` outputs [eg: a new number of classes]
+  ///
+  /// The new layer keeps the activation & initializers of the old one.
+  /// The cost matrix is removed when the number of outputs changes.
+  pub fn replace_output<T: HasAfEnum>(&mut self, output_size: usize) -> Result<(), HALError> {
+    let last = self.layers.len() - 1;
+    let (layer, mut params) = self.layer_configs[last].clone();
+    if layer != "dense" {
+      return Err(HALError::CONFIG);
+    }
+    if params["output_size"] != output_size.to_string() {
+      self.cost_matrix = None;
+    }
+    params.insert("output_size".to_string(), output_size.to_string());
+    self.replace_layer::<T>(last, &layer, params.iter().map(|(k, v)| (k.as_str(), v.clone())).collect())
+  }
+
+  /// Inserts a dropout layer after every hidden dense layer [the output layer is left as is]
+  ///
+  /// Dense layers that are already followed by a dropout layer are skipped.
+  pub fn insert_dropout<T: HasAfEnum>(&mut self, rate: f32) {
+    let mut configs = Vec::new();
+    let mut sources = Vec::new();
+    let num_layers = self.layer_configs.len();
+    for (i, config) in self.layer_configs.iter().enumerate() {
+      configs.push(config.clone());
+      sources.push(Some(i));
+      let followed_by_dropout = i + 1 < num_layers && self.layer_configs[i + 1].0 == "dropout";
+      if config.0 == "dense" && i + 1 < num_layers && !followed_by_dropout {
+        let size = config.1["output_size"].clone();
+        let mut params = HashMap::new();
+        params.insert("input_size".to_string(), size.clone());
+        params.insert("output_size".to_string(), size);
+        params.insert("rate".to_string(), rate.to_string());
+        configs.push(("dropout".to_string(), params));
+        sources.push(None);
+      }
+    }
+    self.rebuild::<T>(configs, sources);
+  }
+
+  /// Removes all the layers after the named layer [see `get_layer_names`]
+  ///
+  /// eg: keep the trunk of a classifier to use its embeddings as features
+  pub fn truncate<T: HasAfEnum>(&mut self, name: &str) -> Result<(), HALError> {
+    let index = match self.get_layer_names().iter().position(|n| n == name) {
+      Some(index) => index,
+      None        => return Err(HALError::CONFIG),
+    };
+    let configs = self.layer_configs[..index + 1].to_vec();
+    let sources = (0..index + 1).map(|i| Some(i)).collect();
+    self.rebuild::<T>(configs, sources);
+    Ok(())
+  }
+
+  /// Same as `Model::backward` but also returns the derivatives w.r.t. the model inputs
+  /// (one per time-step), which is what is needed to chain this model onto another
+  pub fn backward_inputs(&mut self, predictions: &Vec<Array>, targets: &Array
+                         , loss_indices: Option<&Vec<bool>>) -> (Vec<f32>, Vec<Array>)
+  {
+    self.backward_inputs_weighted(predictions, targets, loss_indices, 1.0)
+  }
+
+  /// Same as `backward_inputs` for the objective `loss_weight * loss`
+  ///
+  /// All the gradients [of the parameters & of the inputs] are scaled by the
+  /// weight, the returned losses are not.
+  pub fn backward_inputs_weighted(&mut self, predictions: &Vec<Array>, targets: &Array
+                                  , loss_indices: Option<&Vec<bool>>, loss_weight: f32)
+                                  -> (Vec<f32>, Vec<Array>)
+  {
+    let mut loss_vec = Vec::with_capacity(predictions.len());
+    let mut deltas = Vec::with_capacity(predictions.len());
+
+    for (pred, ind) in Zip::new((predictions.iter().rev(), (0..predictions.len()).rev()))
+    {
+      let tar = af::slice(&targets, ind as u64);
+
+      // handle loss indices that are not to be allowed
+      let delta = match loss_indices {
+        Some(li) => {
+          assert!(li.len() == predictions.len()
+                  , "loss indices need to be of the same size as the predictions");
+          match li[ind] {
+            false => utils::constant(tar.dims(), tar.get_type(), 0.0f32),
+            true  => {
+              let (l, d) = self.loss_and_derivative(pred, &tar);
+              loss_vec.push(l);
+              d
+            },
+          }
+        },
+        None     => {
+          let (l, d) = self.loss_and_derivative(pred, &tar);
+          loss_vec.push(l);
+          d
+        },
+      };
+      deltas.push(if loss_weight == 1.0 { delta } else { af::mul(&delta, &loss_weight, false) });
+    }
+
+    // deltas were gathered from the last time-step to the first
+    deltas.reverse();
+    let input_deltas = self.backward_deltas(&deltas);
+    (loss_vec, input_deltas)
+  }
+
+  /// Runs a single optimization step on a batch of ragged sequences [see `RaggedBatch`]
+  ///
+  /// The sequences are padded to the longest one of the batch, the losses & the
+  /// deltas of the padded steps are masked out [see `loss::get_masked_loss`].
+  /// The recurrent layers are causal, so the padding never changes the outputs
+  /// of the real steps. The cost matrix is not applied to masked losses.
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` are the [length, feature] input sequences
+  /// - `targets` are either sequences of the lengths of the inputs [a target per step]
+  ///   or a single step per sequence [the target of the last step of every sequence]
+  /// - `src_device` is the device of the sequences
+  ///
+  /// # Return Values
+  ///
+  /// Vector of losses (one per time-step of the longest sequence that has a target)
+  pub fn partial_fit_ragged<E>(&mut self, inputs: &RaggedBatch, targets: &RaggedBatch
+                               , src_device: Device) -> Vec<f32>
+    where E: HasAfEnum + Zero + Clone
+  {
+    assert!(inputs.num_sequences() == targets.num_sequences()
+            , "need a target sequence per input sequence");
+    let per_step = targets.lengths() == inputs.lengths();
+    assert!(per_step || targets.max_length() == 1
+            , "the targets need the lengths of the inputs or a single step");
+    let compute_device = self.device;
+    self.manager.swap_device(src_device);
+    let padded_target = match per_step {
+      true  => targets.to_padded(0.0),
+      false => targets.values.clone(),
+    };
+    let mut batch = self.manager.swap_arrays_backend::<E>(&[&inputs.to_padded(0.0), &padded_target
+                                                           , &inputs.mask(), &inputs.values]
+                                                         , src_device, compute_device);
+    let (steps, mask) = (batch.pop().unwrap(), batch.pop().unwrap());
+    let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+
+    // only the real steps update the running statistics
+    if let Some((ref mut stats, _)) = self.online_normalization {
+      stats.update(&steps);
+    }
+
+    let predictions = self.forward::<E>(&batch_input, compute_device, compute_device);
+    let max_length = predictions.len() as u64;
+    let mut loss_vec = Vec::new();
+    let mut deltas = Vec::with_capacity(predictions.len());
+    for (t, pred) in predictions.iter().enumerate() {
+      let t = t as u64;
+      // the sequences that run at t, or that end at t for the last step targets
+      let (tar, step_mask) = match per_step {
+        true  => (af::slice(&batch_target, t), af::slice(&mask, t)),
+        false => (batch_target.clone(), match t + 1 < max_length {
+          true  => af::sub(&af::slice(&mask, t), &af::slice(&mask, t + 1), false),
+          false => af::slice(&mask, t),
+        }),
+      };
+      if af::sum_all(&step_mask).0 > 0.0 {
+        loss_vec.push(loss::get_masked_loss(&self.loss, pred, &tar, &step_mask).unwrap());
+      }
+      deltas.push(loss::get_masked_loss_derivative(&self.loss, pred, &tar, &step_mask).unwrap());
+    }
+    self.backward_deltas(&deltas);
+    self.step(inputs.num_sequences() as u64);
+    loss_vec
+  }
+
+  /// Calculate the predicted probabilities of every step of ragged sequences
+  ///
+  /// # Return Values
+  ///
+  /// The [length, output] probabilities of every sequence on `dest_device`
+  /// [see `RaggedBatch::last_steps` for sequence classifiers]
+  pub fn predict_proba_ragged<T>(&mut self, inputs: &RaggedBatch, src_device: Device
+                                 , dest_device: Device) -> RaggedBatch
+    where T: HasAfEnum + Zero + Clone
+  {
+    self.manager.swap_device(src_device);
+    let padded = inputs.to_padded(0.0);
+    let probabilities = self.predict_proba::<T>(&padded, src_device, dest_device);
+    self.manager.swap_device(dest_device);
+    let joined = probabilities[1..].iter().fold(probabilities[0].clone(), |acc, p| af::join(2, &acc, p));
+    RaggedBatch::from_padded(&joined, &inputs.lengths())
+  }
+
+  /// Computes the gradient of the loss of every sample w.r.t. the selected parameters
+  ///
+  /// The gradients are not reduced over the batch, they are computed with one
+  /// forward & backward pass per sample. The gradients accumulated before the
+  /// call are restored afterwards, so this can be called in between `backward`
+  /// & `step`. Like in `step` the gradients are divided by the loss scale &
+  /// multiplied by the gradient scale of their layer.
+  ///
+  /// # Parameters
+  ///
+  /// - `batch_input` & `batch_target` are the samples
+  /// - `src_device` is the device of the samples
+  /// - `param_indices` are the parameters to differentiate [indices in the order
+  ///   of `ParamManager::get_all_arrays`, None: all of them]
+  ///
+  /// # Return Values
+  ///
+  /// (losses of every time-step averaged over the samples, one [batch_size, num_elements]
+  /// array per selected parameter whose row b is the flattened gradient of sample b)
+  /// [the gradients are on the device of the model]
+  pub fn per_sample_gradients<E>(&mut self, batch_input: &Array, batch_target: &Array
+                                 , src_device: Device, param_indices: Option<&Vec<usize>>)
+                                 -> (Vec<f32>, Vec<Array>)
+    where E: HasAfEnum + Zero + Clone
+  {
+    let device = self.device;
+    let mut batch = self.manager.swap_arrays_backend::<E>(&[batch_input, batch_target]
+                                                       , src_device, device);
+    let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+    let batch_size = batch_input.dims()[0];
+
+    let accumulated = self.param_manager.get_all_deltas();
+    let selected: Vec<usize> = match param_indices {
+      Some(indices) => {
+        assert!(indices.iter().all(|&i| i < accumulated.len())
+                , "the model only has {} parameters", accumulated.len());
+        indices.clone()
+      },
+      None          => (0..accumulated.len()).collect(),
+    };
+    self.param_manager.zero_all_deltas();
+
+    // unscale the deltas like `step` [see `ParamManager::unscale_all_deltas`]
+    let loss_scale = self.param_manager.get_loss_scale();
+    let factors: Vec<f32> = (0..self.param_manager.num_layers()).flat_map(|layer| {
+      let factor = self.param_manager.get_gradient_scale(layer) / loss_scale;
+      vec![factor; self.param_manager.num_arrays(layer)]
+    }).collect();
+
+    let mut gradients: Vec<Array> = selected.iter().map(|&ind| {
+      let dims = Dim4::new(&[batch_size, accumulated[ind].dims().elements(), 1, 1]);
+      utils::constant(dims, accumulated[ind].get_type(), 0.0f32)
+    }).collect();
+    let mut loss_sum: Vec<f32> = Vec::new();
+    for b in 0..batch_size {
+      let predictions = self.forward::<E>(&af::rows(&batch_input, b, b), device, device);
+      let losses = self.backward(&predictions, &af::rows(&batch_target, b, b), None);
+      if loss_sum.len() == 0 {
+        loss_sum = vec![0.0; losses.len()];
+      }
+      for (sum, loss) in loss_sum.iter_mut().zip(losses.iter()) {
+        *sum += *loss;
+      }
+
+      let deltas = self.param_manager.get_all_deltas();
+      for (gradient, &ind) in gradients.iter_mut().zip(selected.iter()) {
+        let delta = if factors[ind] == 1.0 { deltas[ind].clone() } else {
+          utils::cast(&af::mul(&deltas[ind], &factors[ind], false), deltas[ind].get_type())
+        };
+        let row = af::moddims(&af::flat(&delta), Dim4::new(&[1, delta.dims().elements(), 1, 1]));
+        *gradient = utils::set_row_plane(gradient, &row, b);
+      }
+      self.param_manager.zero_all_deltas();
+    }
+
+    self.param_manager.with_mut_arrays_and_deltas(|ind, _, delta| {
+      *delta = accumulated[ind].clone();
+    });
+    (loss_sum.iter().map(|l| l / batch_size as f32).collect(), gradients)
+  }
+
+  /// Freezes the first `num_layers` layers [0 unfreezes all the layers]
+  ///
+  /// The optimizer skips the parameters of the frozen layers [see
+  /// `ParamManager::set_frozen`], so neither their weights nor their optimizer state change.
+  pub fn freeze(&mut self, num_layers: usize) {
+    assert!(num_layers < self.layers.len(), "need at least one trainable layer");
+    assert!(!self.cached_prefix || num_layers == 0
+            || !Sequential::straddles(&self.checkpoint_segments, num_layers)
+            , "the checkpoint segments can not straddle the cached frozen layers");
+    for i in 0..self.layers.len() {
+      self.param_manager.set_frozen(i, i < num_layers);
+    }
+    self.frozen_layers = num_layers;
+    if num_layers == 0 {
+      self.cached_prefix = false;
+    }
+  }
+
+  /// Returns the number of frozen layers [see `freeze`]
+  pub fn get_frozen_layers(&self) -> usize {
+    self.frozen_layers
+  }
+
+  /// Starts the forward passes from the cached outputs of the frozen layers [see `cache_frozen_outputs`]
+  ///
+  /// While set, the inputs of training & inference are the [batch, feature, time]
+  /// outputs of the last frozen layer: the frozen layers are neither run forward
+  /// nor backward, the input normalization is skipped & the input deltas are
+  /// w.r.t. the cached outputs.
+  ///
+  /// Gradient checkpoint segments can not straddle the last frozen layer [see
+  /// `set_gradient_checkpoints`].
+  pub fn set_cached_prefix(&mut self, enabled: bool) {
+    assert!(!enabled || self.frozen_layers > 0, "need frozen layers to start from their cache");
+    assert!(!enabled || !Sequential::straddles(&self.checkpoint_segments, self.frozen_layers)
+            , "the checkpoint segments can not straddle the cached frozen layers");
+    self.cached_prefix = enabled;
+  }
+
+  /// Computes the outputs of the frozen layers for every sample of the source once
+  ///
+  /// Fine-tuning the trainable layers on the returned source [with `set_cached_prefix`]
+  /// runs the frozen backbone once instead of once per epoch. The cached outputs
+  /// stay on the device, or are also written to a npy file & read back by later
+  /// runs. The file is keyed by a digest of the frozen parameters [kept in
+  /// `{cache_file}.sha256`], it is recomputed when the frozen layers change.
+  ///
+  /// eg: `let cached = try!(model.cache_frozen_outputs::<f32>(&source, 256, device, Some("features.npy")));`
+  /// `model.set_cached_prefix(true); model.fit::<ArraySource, f32>(&cached, device, ..);`
+  ///
+  /// # Parameters
+  ///
+  /// - `source` are the [num_samples, feature, time] raw samples
+  /// - `batch_size` is the number of samples of every forward pass of the frozen layers
+  /// - `src_device` is the device of the arrays of the source
+  /// - `cache_file` is the optional npy file of the cached outputs
+  ///
+  /// # Return Values
+  ///
+  /// The source of the cached outputs & the targets [with the splits & the batch size of `source`]
+  /// on the model device, `HALError::DATA_IO` when the cache file can not be read or written
+  pub fn cache_frozen_outputs<T>(&mut self, source: &ArraySource, batch_size: u64
+                                 , src_device: Device, cache_file: Option<&str>)
+                                 -> Result<ArraySource, HALError>
+    where T: HasAfEnum + Zero + Clone
+  {
+    assert!(self.frozen_layers > 0, "need frozen layers to cache [see `freeze`]");
+    assert!(batch_size > 0, "need a positive batch size");
+    let num_samples = source.input.dims()[0];
+    let device = self.device;
+    self.manager.swap_device(device);
+
+    let digest = self.frozen_digest();
+    let cached = match cache_file {
+      Some(path) if fs::metadata(path).is_ok() => {
+        let stored = fs::File::open(format!("{}.sha256", path)).and_then(|mut file| {
+          let mut stored = String::new();
+          file.read_to_string(&mut stored).map(|_| stored)
+        });
+        match stored {
+          Ok(ref stored) if stored.trim() == digest => {
+            let loaded = try!(data::read_npy(path));
+            match loaded.dims()[0] == num_samples {
+              true  => Some(utils::cast(&loaded, T::get_af_dtype())),
+              false => {
+                warn!("the cache {} has {} samples, the source {}, recomputing it", path, loaded.dims()[0], num_samples);
+                None
+              },
+            }
+          },
+          _                                          => {
+            warn!("the cache {} was computed by other frozen layers, recomputing it", path);
+            None
+          },
+        }
+      },
+      _                                        => None,
+    };
+
+    let features = match cached {
+      Some(features) => features,
+      None           => {
+        let name = self.get_layer_names()[self.frozen_layers - 1].clone();
+        let mut features: Option<Array> = None;
+        for b in 0..(num_samples + batch_size - 1) / batch_size {
+          let first = b * batch_size;
+          let batch = af::rows(&source.input, first, min(first + batch_size, num_samples) - 1);
+          let mut taps = try!(self.forward_taps::<T>(&[name.as_str()], &batch, src_device, device));
+          let steps = taps.remove(&name).unwrap();
+          let outputs = steps[1..].iter().fold(steps[0].clone(), |acc, step| af::join(2, &acc, step));
+          let outputs = utils::cast(&outputs, T::get_af_dtype());
+          features = Some(match features {
+            Some(f) => af::join(0, &f, &outputs),
+            None    => outputs,
+          });
+        }
+        let features = features.unwrap();
+        if let Some(path) = cache_file {
+          try!(data::write_npy(path, &features));
+          let digest_file = format!("{}.sha256", path);
+          try!(fs::File::create(&digest_file).and_then(|mut file| file.write_all(digest.as_bytes()))
+               .map_err(|e| {
+                 warn!("unable to write {}: {}", digest_file, e);
+                 HALError::DATA_IO
+               }));
+        }
+        features
+      },
+    };
+
+    let target = self.manager.swap_array_backend::<T>(&source.target, src_device, device);
+    let params = source.info();
+    Ok(ArraySource::with_splits(features, target, params.input_dims[0]
+                                , params.num_test, params.num_validation.unwrap_or(0)
+                                , params.shuffle))
+  }
+
+  /// Returns the sha256 of the dims & values of the parameters of the frozen layers
+  fn frozen_digest(&self) -> String {
+    let mut bytes: Vec<u8> = Vec::new();
+    for i in 0..self.frozen_layers {
+      for array in self.param_manager.get_weights(i).iter().chain(self.param_manager.get_biases(i).iter()) {
+        for &dim in array.dims().get().iter() {
+          bytes.extend((0..8).map(|b| (dim >> (8 * b)) as u8));
+        }
+        for value in utils::array_to_vec(array) {
+          let bits = value.to_bits();
+          bytes.extend((0..8).map(|b| (bits >> (8 * b)) as u8));
+        }
+      }
+    }
+    hub::sha256(&bytes)
+  }
+
+  /// Helper to compute the (optionally cost weighted) loss and its derivative
+  fn loss_and_derivative(&self, pred: &Array, target: &Array) -> (f32, Array) {
+    match self.cost_matrix {
+      Some(ref cost) => {
+        let weights = loss::cost_weights(target, cost);
+        (loss::get_weighted_loss(&self.loss, pred, target, &weights).unwrap()
+         , loss::get_weighted_loss_derivative(&self.loss, pred, target, &weights).unwrap())
+      },
+      None           => (loss::get_loss(&self.loss, pred, target).unwrap()
+                         , loss::get_loss_derivative(&self.loss, pred, target).unwrap()),
+    }
+  }
+}
+
 impl Model for Sequential {
   fn new(manager: DeviceManager
          , optimizer: Box<Optimizer>
@@ -55,6 +1123,24 @@ impl Model for Sequential {
       loss: loss.to_string(),
       optimizer: optimizer,
       device: device,
+      cost_matrix: None,
+      cost_decision: false,
+      metrics: Vec::new(),
+      history: HashMap::new(),
+      online_normalization: None,
+      checkpoint_dir: None,
+      layer_configs: Vec::new(),
+      pruning_masks: None,
+      environment: None,
+      weight_averages: Vec::new(),
+      checkpoint_segments: Vec::new(),
+      callbacks: Vec::new(),
+      dropout_active: Arc::new(AtomicBool::new(true)),
+      mc_dropout: false,
+      consistency: None,
+      layer_deltas: Vec::new(),
+      frozen_layers: 0,
+      cached_prefix: false,
     }
   }
 
@@ -67,9 +1153,29 @@ impl Model for Sequential {
   ///
   /// - `layer` is the type of layer to add
   /// - `params` is a hashmap of params for the provided layer
+  ///
+  /// An optional `dtype` param ["f32" or "f64"] overrides the precision of this
+  /// layer only. Activations & deltas are cast at the layer boundaries.
+  /// An optional `grad_scale` param multiplies the gradients of this layer
+  /// [see `ParamManager::set_gradient_scale`].
   fn add<T: HasAfEnum>(&mut self, layer: &str
-                       , params: HashMap<&str, String>)
+                       , mut params: HashMap<&str, String>)
   {
+    // re-dispatch on the overriden precision
+    if let Some(dtype) = params.remove("dtype") {
+      match utils::get_dtype(&dtype) {
+        Ok(DType::F32) => self.add::<f32>(layer, params),
+        Ok(DType::F64) => self.add::<f64>(layer, params),
+        _              => panic!("unsupported layer dtype {}", dtype),
+      }
+      self.layer_configs.last_mut().unwrap().1.insert("dtype".to_string(), dtype);
+      return;
+    }
+
+    // keep the layer description to be able to rebuild the model (see `config`)
+    self.layer_configs.push((layer.to_string(), params.iter()
+                             .map(|(k, v)| (k.to_string(), v.clone())).collect()));
+
     //TODO: Error handling for hashmap
     let input_size = params.get("input_size").unwrap().parse::<u64>().unwrap() as usize;
     let output_size = params.get("output_size").unwrap().parse::<u64>().unwrap() as usize;
@@ -95,6 +1201,24 @@ impl Model for Sequential {
                                       , hidden_size: hidden_size
                                       , output_size: output_size}));
       }
+      "ordinal" => {
+        // output_size is the number of rank thresholds [num_classes - 1]
+        self.param_manager.add_ordinal::<T>(self.manager.clone(), self.device
+                                            , input_size, output_size
+                                            , params.get("w_init").unwrap()
+                                            , params.get("b_init").unwrap());
+        self.layers.push(Box::new(Ordinal{input_size: input_size
+                                          , output_size: output_size}));
+      },
+      "dropout" => {
+        assert!(input_size == output_size, "dropout keeps the size of its input");
+        let rate = params.get("rate").unwrap().parse::<f32>().unwrap();
+        assert!(rate >= 0.0 && rate < 1.0, "the dropout rate needs to be in [0, 1)");
+        self.param_manager.add_dropout::<T>(self.manager.clone(), self.device);
+        self.layers.push(Box::new(Dropout{size: input_size
+                                          , rate: rate
+                                          , active: self.dropout_active.clone()}));
+      },
       // "lstm"  => {
       //   self.param_manager.add_lstm::<T>(self.manager.clone(), self.device
       //                               , input_size, output_size
@@ -140,6 +1264,105 @@ impl Model for Sequential {
 
       _  => panic!("Error unknown layer type"),
     }
+
+    if let Some(scale) = params.get("grad_scale") {
+      let layer_index = self.param_manager.num_layers() - 1;
+      self.param_manager.set_gradient_scale(layer_index, scale.parse::<f32>().unwrap());
+    }
+  }
+
+  /// Enables cost sensitive learning
+  ///
+  /// The loss of every sample is reweighted by the total cost of
+  /// misclassifying its true class (see `loss::cost_weights`)
+  ///
+  /// # Parameters
+  ///
+  /// - `cost` is the [num_classes, num_classes] matrix, cost[i, j] is the cost of predicting j for i
+  /// - `src_device` is the device that the cost matrix resides on
+  /// - `use_for_decision` makes `predict_classes` pick the minimum expected cost class
+  fn set_cost_matrix<T>(&mut self, cost: &Array, src_device: Device, use_for_decision: bool)
+    where T: HasAfEnum + Zero + Clone
+  {
+    let dims = cost.dims();
+    assert!(dims[0] == dims[1], "the cost matrix needs to be square");
+    self.cost_matrix = Some(self.manager.swap_array_backend::<T>(cost, src_device, self.device));
+    self.cost_decision = use_for_decision;
+  }
+
+  /// Adds a metric that is evaluated on the validation data after every epoch of `fit`
+  fn add_metric(&mut self, metric: Box<Metric>) {
+    self.metrics.push(metric);
+  }
+
+  /// Evaluates the loss & all the metrics on the validation data
+  ///
+  /// The metrics are reset and then streamed over `num_validation / batch_size`
+  /// minibatches, so areas like ROC-AUC are computed over the entire validation set.
+  ///
+  /// # Parameters
+  ///
+  /// - `source` is the datasource
+  /// - `src_device` is the source device of the data
+  /// - `batch_size` is the minibatch size
+  ///
+  /// # Return Values
+  ///
+  /// HashMap of the mean validation loss ["loss"] and the values of every metric [see `Metric::values`]
+  fn evaluate<T, E>(&mut self, source: &T, src_device: Device, batch_size: u64) -> HashMap<String, f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    let data_params = source.info();
+    let iters = max(data_params.num_validation.unwrap_or(0) / batch_size, 1);
+    let activation = loss::get_output_activation(&self.loss);
+    let compute_device = self.device;
+    for metric in self.metrics.iter_mut() {
+      metric.reset();
+    }
+
+    let mut loss_sum = 0f32;
+    let mut loss_count = 0;
+    for _ in 0..iters {
+      self.manager.swap_device(src_device);
+      let minibatch = match source.get_validation_iter(batch_size) {
+        Some(minibatch) => minibatch,
+        None            => break,
+      };
+      let (input, target) = (minibatch.input.into_inner(), minibatch.target.into_inner());
+      let mut batch = self.manager.swap_arrays_backend::<E>(&[&input, &target]
+                                                           , src_device
+                                                           , compute_device);
+      let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+      let outputs = self.infer::<E>(&batch_input, compute_device);
+      for (t, output) in outputs.iter().enumerate() {
+        let tar = af::slice(&batch_target, t as u64);
+        loss_sum += self.loss_and_derivative(output, &tar).0;
+        loss_count += 1;
+
+        let p = activations::get_activation(activation, output).unwrap();
+        let inp = af::slice(&batch_input, t as u64);
+        for metric in self.metrics.iter_mut() {
+          metric.update_with_inputs(&p, &tar, &inp);
+        }
+      }
+    }
+    self.manager.swap_device(src_device);
+
+    let mut values = HashMap::new();
+    if loss_count > 0 {
+      values.insert("loss".to_string(), loss_sum / loss_count as f32);
+      for metric in self.metrics.iter() {
+        values.extend(metric.values());
+      }
+    }
+    values
+  }
+
+  /// Returns the per epoch history of the training loss ["loss"], of the
+  /// validation loss & metrics [prefixed with "val_"] and of the wall clock
+  /// seconds of every epoch ["epoch_time", including the validation] recorded by `fit`
+  fn get_history(&self) -> &HashMap<String, Vec<f32>> {
+    &self.history
   }
 
   //TODO: convert to log crate w/ hashmap
@@ -147,6 +1370,61 @@ impl Model for Sequential {
     println!("");
     self.optimizer.info();
     println!("loss:           {}\nnum_layers:     {}", self.loss, self.layers.len());
+    if self.pruning_masks.is_some() {
+      self.manager.swap_device(self.device);
+      for (i, sparsity) in prune::layer_sparsity(&self.param_manager).iter().enumerate() {
+        println!("sparsity[{}]:    {:.2}%", i, 100.0 * sparsity);
+      }
+    }
+  }
+
+  /// Runs dummy forward & backward passes to compile the kernels & prime the allocator
+  ///
+  /// ArrayFire compiles its JIT kernels & grows its memory pools on the first
+  /// pass of every shape, run this before timing-sensitive serving or
+  /// benchmarking. The parameters, the accumulated gradients, the optimizer &
+  /// the running input statistics are left untouched [the optimizer state is
+  /// allocated if it was not].
+  ///
+  /// # Parameters
+  ///
+  /// - `batch_shape` is the [batch, feature, time] shape of the served batches
+  /// - `iterations` is the number of (forward, backward & inference) passes
+  ///
+  /// # Return Values
+  ///
+  /// The wall clock seconds of every iteration [the first one includes the compilation]
+  fn warmup<T>(&mut self, batch_shape: Dim4, iterations: u64) -> Vec<f64>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let input_size = self.layer_configs.first().and_then(|&(_, ref params)| params.get("input_size"))
+      .and_then(|s| s.parse::<u64>().ok());
+    assert!(input_size.map_or(true, |size| size == batch_shape[1])
+            , "the model expects {:?} input features", input_size);
+    let device = self.device;
+    self.manager.swap_device(device);
+    let dims = Dim4::new(&[batch_shape[0], batch_shape[1], max(batch_shape[2], 1), 1]);
+    let input = utils::cast(&af::randu::<f32>(dims), T::get_af_dtype());
+
+    let accumulated = self.param_manager.get_all_deltas();
+    let layer_deltas = mem::replace(&mut self.layer_deltas, Vec::new());
+    let mut latencies = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+      let start = Instant::now();
+      let predictions = self.forward::<T>(&input, device, device);
+      let targets = predictions[1..].iter().fold(predictions[0].clone(), |acc, p| af::join(2, &acc, p));
+      self.backward(&predictions, &targets, None);
+      self.infer::<T>(&input, device);
+      af::sync(device.id);
+      let elapsed = start.elapsed();
+      latencies.push(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9);
+    }
+
+    self.param_manager.with_mut_arrays_and_deltas(|ind, _, delta| {
+      *delta = accumulated[ind].clone();
+    });
+    self.layer_deltas = layer_deltas;
+    latencies
   }
 
   /// Calculate the forward pass of all the layers
@@ -168,37 +1446,106 @@ impl Model for Sequential {
                 , dest_device: Device) -> Vec<Array>
     where T: HasAfEnum + Zero + Clone
   {
-    // check & swap if the backend matches to runtime one (if not already)
-    let activ = self.manager.swap_array_backend::<T>(&inputs, src_device, self.device);
-
-    // if dim[3] > 1 we assume we have an RNN
-    // we will need to unwind at least once for non RNNs
-    let bptt_unroll = max(activ.dims()[2], 1);
-    let mut activate;
-
-    for t in 0..bptt_unroll {
-      activate = af::slice(&activ, t);
-      for i in 0..self.layers.len() {
-        let (a, _) = self.layers[i].forward(self.param_manager.get_params(i)
-                                            , &activate, None);
-        activate = a;
-      }
-    }
-
-    // TODO: Parameterize
-    // zero the states
-    // self.param_manager.zero_all_states(None);
+    self.forward_with::<T>(inputs, src_device, dest_device, true)
+  }
 
-    // return the collected outputs of the last layer
-    let last_index = self.layers.len() - 1;
-    let mut outputs = self.param_manager.get_outputs(last_index);
+  /// Calculate the outputs of an intermediate layer at inference time
+  ///
+  /// Only the layers up to the named one are run [see `Sequential::get_layer_names`],
+  /// eg: to harvest the embeddings of a hidden layer.
+  ///
+  /// # Parameters
+  ///
+  /// - `layer_name` is the name of the layer whose outputs are returned
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  /// - `dest_device` is the destination device that the data should go to
+  ///
+  /// # Return Values
+  ///
+  /// Vector of outputs of the layer (one per time-step) or `HALError::CONFIG` for unknown layers
+  fn forward_to<T>(&mut self, layer_name: &str, inputs: &Array
+                   , src_device: Device
+                   , dest_device: Device) -> Result<Vec<Array>, HALError>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let mut taps = try!(self.forward_taps::<T>(&[layer_name], inputs, src_device, dest_device));
+    Ok(taps.remove(layer_name).unwrap())
+  }
 
-    // return to the dest device
-    for i in 0..outputs.len() {
-      outputs[i] = self.manager.swap_array_backend::<T>(&outputs[i], self.device, dest_device);
-    }
+  /// Calculate the output probabilities of the model
+  ///
+  /// Runs an inference forward pass and maps the logits through the
+  /// activation that is implied by the loss (eg: softmax for cross_entropy_softmax,
+  /// per label sigmoids for binary_cross_entropy)
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  /// - `dest_device` is the destination device that the data should go to
+  ///
+  /// # Return Values
+  ///
+  /// Vector of probabilities (one per time-step)
+  fn predict_proba<T>(&mut self, inputs: &Array
+                      , src_device: Device
+                      , dest_device: Device) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let activation = loss::get_output_activation(&self.loss);
+    let outputs = self.infer::<T>(inputs, src_device);
+    outputs.iter().map(|o| {
+      let p = activations::get_activation(activation, o).unwrap();
+      self.manager.swap_array_backend::<T>(&p, self.device, dest_device)
+    }).collect()
+  }
 
-    outputs
+  /// Calculate the predicted classes of the model
+  ///
+  /// Single label models return one-hot rows of the most probable class
+  /// [or of the minimum expected cost class, see `set_cost_matrix`].
+  /// Multi-label models [binary_cross_entropy] return an indicator per
+  /// label whose probability exceeds the threshold [default: 0.5].
+  /// Providing a threshold forces the multi-label decision rule.
+  /// Ordinal models [ordinal_cross_entropy] return the [batch, 1] rank.
+  ///
+  /// # Parameters
+  ///
+  /// - `inputs` is an array of activations [batch, feature, time]
+  /// - `src_device` is the source device that the data is coming from
+  /// - `dest_device` is the destination device that the data should go to
+  /// - `threshold` is the optional per label probability threshold
+  ///
+  /// # Return Values
+  ///
+  /// Vector of class predictions (one per time-step)
+  fn predict_classes<T>(&mut self, inputs: &Array
+                        , src_device: Device
+                        , dest_device: Device
+                        , threshold: Option<f32>) -> Vec<Array>
+    where T: HasAfEnum + Zero + Clone
+  {
+    let compute_device = self.device;
+    let probabilities = self.predict_proba::<T>(inputs, src_device, compute_device);
+    let loss_name = self.loss.to_lowercase();
+    probabilities.iter().map(|p| {
+      let dtype = p.get_type();
+      let classes = match (loss_name.as_str(), threshold) {
+        ("ordinal_cross_entropy", t) => {
+          let above = af::gt(p, &t.unwrap_or(0.5f32), false);
+          af::sum(&utils::cast(&above, dtype), 1)
+        },
+        ("binary_cross_entropy", t) | (_, t @ Some(_)) => {
+          utils::cast(&af::gt(p, &t.unwrap_or(0.5f32), false), dtype)
+        },
+        _ => match (self.cost_decision, &self.cost_matrix) {
+          (true, &Some(ref cost)) => loss::minimum_risk_classes(p, cost),
+          _                       => utils::cast(&af::eq(p, &af::max(p, 1), true), dtype),
+        },
+      };
+      self.manager.swap_array_backend::<T>(&classes, compute_device, dest_device)
+    }).collect()
   }
 
   /// Fit's model to provided data
@@ -216,6 +1563,12 @@ impl Model for Sequential {
   /// - `loss_indices` are the indices to utilize when doing backward pass (useful for RNN long term tasks)
   /// - `verbose` specifies whether or not to print verbose details during training
   ///
+  /// When metrics were added [see `add_metric`] the model is evaluated on the
+  /// validation data at the end of every epoch and the results are recorded
+  /// in the history [see `get_history`].
+  /// With consistency regularization [see `set_consistency`] every step also
+  /// trains on a batch of unlabeled data, the returned losses are the supervised ones.
+  ///
   /// # Return Values
   ///
   /// Vector of losses
@@ -229,9 +1582,9 @@ impl Model for Sequential {
     let idims = data_params.input_dims;
     let tdims = data_params.target_dims;
     let iters =  data_params.num_samples as u64 / batch_size as u64;
-    println!("\ntrain samples: {:?} | target samples: {:?} | batch size: {}"
-             , idims, tdims, batch_size);
-    println!("epochs: {} | iterations[per epoch]: {}", epochs, iters);
+    info!("train samples: {:?} | target samples: {:?} | batch size: {}"
+          , idims, tdims, batch_size);
+    info!("epochs: {} | iterations[per epoch]: {}", epochs, iters);
     assert!(idims[0] == tdims[0]
             , "batch sizes for inputs and targets much be equal");
     assert!(idims[2] == tdims[2]
@@ -240,9 +1593,10 @@ impl Model for Sequential {
             , "Need at least one layer to fit!");
 
     // verify that last layer is of logits type when using
-    // softmax_crossentropy or binary_crossentropy
+    // softmax_crossentropy, binary_crossentropy or ordinal_cross_entropy
     if self.loss.to_lowercase() == "cross_entropy_softmax"
       || self.loss.to_lowercase() == "binary_cross_entropy"
+      || self.loss.to_lowercase() == "ordinal_cross_entropy"
     {
       let last_layer_index = self.layers.len() - 1;
       let last_layer_activations = self.param_manager.get_activations(last_layer_index);
@@ -258,16 +1612,19 @@ impl Model for Sequential {
     let mut lossvec = Vec::<f32>::new();
     let compute_device = self.device.clone();
 
+    // verbose runs print through a logger for the duration of the fit
+    let num_callbacks = self.callbacks.len();
+    if verbose {
+      self.callbacks.push(Box::new(PrintLogger));
+    }
+    let start = Instant::now();
+
     // iterate epoch times over the number of batch iterations
     for epoch in 0..epochs {
+      let epoch_start = lossvec.len();
+      let epoch_timer = Instant::now();
+      let mut mask_rates = Vec::new();
       for iter in 0..iters {
-        // ensure we are on the original device device
-        self.manager.swap_device(src_device);
-
-        if verbose {
-          print!("\n[epoch: {}][iter: {}] ", epoch, iter);
-        }
-
         // extract part of the array onto the GPU
         self.manager.swap_device(src_device);
         let minibatch = source.get_train_iter(batch_size);
@@ -275,51 +1632,169 @@ impl Model for Sequential {
                 , "Ensure that input dims are of batch rows");
         assert!(minibatch.target.borrow().dims()[0] == batch_size
                 , "Ensure that target dims are of batch rows");
-        let batch_input = self.manager.swap_array_backend::<E>(&minibatch.input.into_inner()
-                                                          , src_device
-                                                          , compute_device);
-        let batch_target = self.manager.swap_array_backend::<E>(&minibatch.target.into_inner()
-                                                           , src_device
-                                                           , compute_device);
+        let (input, target) = (minibatch.input.into_inner(), minibatch.target.into_inner());
+        let mut batch = self.manager.swap_arrays_backend::<E>(&[&input, &target]
+                                                             , src_device
+                                                             , compute_device);
+        let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+
+        // the consistency gradients are applied by the supervised step
+        if let Some(consistency) = self.consistency.take() {
+          mask_rates.push(consistency.backward::<E>(self, src_device, batch_size));
+          self.consistency = Some(consistency);
+        }
+        let current_loss_vec = self.partial_fit::<E>(&batch_input, &batch_target, compute_device
+                                                      , bptt_interval, loss_indices);
 
-        // if bptt_interval is specified we slice our minibatch
-        // into bptt_interval number of slices and then forward pass on it
-        let mut current_loss_vec = Vec::new();
-        if let Some(bptt_interval) = bptt_interval {
-          let num_seqs = idims[2]/bptt_interval;
-          let start: Vec<_>  = (0..num_seqs).map(|x| x * bptt_interval).collect();
-          let finish: Vec<_> = (1..num_seqs+1).map(|x| x * bptt_interval).collect();
-          for (begin, end) in Zip::new((start, finish)) //TODO: fix when .step_by() becomes stable
-          {
-            let bptt_input_slice = af::slices(&batch_input, begin, end-1);
-            let bptt_target_slice = af::slices(&batch_target, begin, end-1);
-            let a_t = self.forward::<E>(&bptt_input_slice, compute_device, compute_device);
-            current_loss_vec = self.backward(&a_t, &bptt_target_slice, loss_indices);
+        // cache the loss and notify the callbacks
+        let loss_sum = current_loss_vec.iter().fold(0f32, |sum, val| sum + val);
+        let batch_loss = loss_sum / current_loss_vec.len() as f32;
+        lossvec.extend(current_loss_vec);
+        if self.callbacks.len() > 0 {
+          let epoch_losses = &lossvec[epoch_start..];
+          let elapsed = start.elapsed();
+          let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+          let num_done = epoch * iters + iter + 1;
+          let progress = BatchProgress {
+            epoch: epoch,
+            num_epochs: epochs,
+            iteration: iter,
+            num_iterations: iters,
+            batch_loss: batch_loss,
+            running_loss: epoch_losses.iter().fold(0f32, |sum, val| sum + val) / epoch_losses.len() as f32,
+            samples_per_sec: (num_done * batch_size) as f64 / elapsed,
+            elapsed: elapsed,
+            eta: elapsed / num_done as f64 * (epochs * iters - num_done) as f64,
+          };
+          let state = TrainingState {
+            optimizer: &*self.optimizer,
+            param_manager: &self.param_manager,
+            layer_deltas: &self.layer_deltas,
+          };
+          for callback in self.callbacks.iter_mut() {
+            callback.on_batch_end(&progress);
+            callback.inspect_batch(&state, &progress);
           }
-        }else{
-          let a_t = self.forward::<E>(&batch_input, compute_device, compute_device);
-          current_loss_vec = self.backward(&a_t, &batch_target, loss_indices);
         }
+      }
 
-        self.optimizer.update(&mut self.param_manager, batch_size as u64);
+      // record the mean training loss of the epoch
+      let mut epoch_values = HashMap::new();
+      let epoch_losses = &lossvec[epoch_start..];
+      if epoch_losses.len() > 0 {
+        let epoch_loss = epoch_losses.iter().fold(0f32, |sum, val| sum + val) / epoch_losses.len() as f32;
+        self.history.entry("loss".to_string()).or_insert(Vec::new()).push(epoch_loss);
+        epoch_values.insert("loss".to_string(), epoch_loss);
+      }
+      if mask_rates.len() > 0 {
+        let mask_rate = mask_rates.iter().fold(0f32, |sum, val| sum + val) / mask_rates.len() as f32;
+        self.history.entry("mask_rate".to_string()).or_insert(Vec::new()).push(mask_rate);
+        epoch_values.insert("mask_rate".to_string(), mask_rate);
+      }
 
+      // snapshot the parameters of the epoch
+      if let Some(dir) = self.checkpoint_dir.clone() {
+        let path = format!("{}/epoch_{:04}.{}", dir, epoch, checkpoint::CHECKPOINT_EXTENSION);
+        let failed = match self.save_checkpoint(&path, epoch) {
+          Ok(_)  => 0.0,
+          Err(e) => {
+            error!("unable to snapshot epoch {}: {}", epoch, e);
+            1.0
+          },
+        };
+        self.history.entry("checkpoint_failed".to_string()).or_insert(Vec::new()).push(failed);
+        epoch_values.insert("checkpoint_failed".to_string(), failed);
+      }
 
-        // cache and print loss (if verbose)
-        if verbose {
-          let loss_sum = current_loss_vec.iter().fold(0f32, |sum, val| sum + val);
-          let avg_loss = loss_sum / current_loss_vec.len() as f32 ;
-          print!("{} ", avg_loss);
+      // stream the validation data through the metrics
+      if self.metrics.len() > 0 {
+        let values = self.evaluate::<T, E>(source, src_device, batch_size);
+        for (name, value) in values {
+          epoch_values.insert(format!("val_{}", name), value);
+          self.history.entry(format!("val_{}", name)).or_insert(Vec::new()).push(value);
         }
-        lossvec.extend(current_loss_vec);
+      }
+      let epoch_time = epoch_timer.elapsed();
+      let epoch_time = epoch_time.as_secs() as f32 + epoch_time.subsec_nanos() as f32 * 1e-9;
+      self.history.entry("epoch_time".to_string()).or_insert(Vec::new()).push(epoch_time);
+
+      let mut summary: Vec<String> = epoch_values.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+      summary.sort();
+      info!("[epoch: {}/{}] {}", epoch + 1, epochs, summary.join(" | "));
+      for callback in self.callbacks.iter_mut() {
+        callback.on_epoch_end(epoch, &epoch_values);
       }
     }
 
+    for callback in self.callbacks.iter_mut() {
+      callback.on_train_end();
+    }
+    self.callbacks.truncate(num_callbacks);
+
     //utils::write_csv::<f32>("loss.csv", &lossvec);
     self.manager.swap_device(src_device); // return to src device
     lossvec
   }
 
 
+  /// Runs a single optimization step on the provided minibatch
+  ///
+  /// The optimizer state [eg: momemtum, moments] and the running input statistics
+  /// [see `Sequential::set_online_normalization`] persist across calls, which allows
+  /// for online learning where the data arrives as a stream. `fit` is a loop over this.
+  ///
+  /// # Parameters
+  ///
+  /// - `batch_input` is the minibatch of inputs [batch, feature, time]
+  /// - `batch_target` is the minibatch of targets [batch, feature, time]
+  /// - `src_device` is the source device of the data
+  /// - `bptt_interval` is the optional parameter for truncated backprop through time (RNN's only)
+  /// - `loss_indices` are the indices to utilize when doing backward pass (useful for RNN long term tasks)
+  ///
+  /// # Return Values
+  ///
+  /// Vector of losses (one per utilized time-step)
+  fn partial_fit<E>(&mut self, batch_input: &Array, batch_target: &Array, src_device: Device
+                    , bptt_interval: Option<u64>, loss_indices: Option<&Vec<bool>>) -> Vec<f32>
+    where E: HasAfEnum + Zero + Clone
+  {
+    let compute_device = self.device;
+    let mut batch = self.manager.swap_arrays_backend::<E>(&[batch_input, batch_target]
+                                                       , src_device, compute_device);
+    let (batch_target, batch_input) = (batch.pop().unwrap(), batch.pop().unwrap());
+    let idims = batch_input.dims();
+    assert!(idims[0] == batch_target.dims()[0]
+            , "batch sizes for inputs and targets much be equal");
+
+    if let Some((ref mut stats, _)) = self.online_normalization {
+      if !self.cached_prefix {
+        stats.update(&batch_input);
+      }
+    }
+
+    // if bptt_interval is specified we slice our minibatch
+    // into bptt_interval number of slices and then forward pass on it
+    let mut current_loss_vec = Vec::new();
+    if let Some(bptt_interval) = bptt_interval {
+      let num_seqs = idims[2]/bptt_interval;
+      let start: Vec<_>  = (0..num_seqs).map(|x| x * bptt_interval).collect();
+      let finish: Vec<_> = (1..num_seqs+1).map(|x| x * bptt_interval).collect();
+      for (begin, end) in Zip::new((start, finish)) //TODO: fix when .step_by() becomes stable
+      {
+        let bptt_input_slice = af::slices(&batch_input, begin, end-1);
+        let bptt_target_slice = af::slices(&batch_target, begin, end-1);
+        let a_t = self.forward::<E>(&bptt_input_slice, compute_device, compute_device);
+        current_loss_vec = self.backward(&a_t, &bptt_target_slice, loss_indices);
+      }
+    }else{
+      let a_t = self.forward::<E>(&batch_input, compute_device, compute_device);
+      current_loss_vec = self.backward(&a_t, &batch_target, loss_indices);
+    }
+
+    self.step(idims[0]);
+    current_loss_vec
+  }
+
   /// Calculate the layer gradients and return the loss vector
   ///
   /// Given predictions and output data, this function computes all the gradients for all
@@ -335,39 +1810,65 @@ impl Model for Sequential {
   ///
   /// Vector of losses
   fn backward(&mut self, predictions: &Vec<Array>, targets: &Array, loss_indices: Option<&Vec<bool>>) -> Vec<f32> {
+    self.backward_inputs(predictions, targets, loss_indices).0
+  }
+
+  /// Calculate the layer gradients from externally computed output derivatives
+  ///
+  /// This is the backward pass for objectives that can not be expressed as a
+  /// single prediction/target pair (eg: contrastive or triplet embeddings).
+  /// Embeddings that were produced by separate forward passes can be pushed back
+  /// by calling this in the reverse order of the forward passes.
+  ///
+  /// # Parameters
+  ///
+  /// - `deltas` are the derivatives of the objective w.r.t. the model outputs (one per time-step)
+  ///
+  /// # Return Values
+  ///
+  /// Vector of derivatives w.r.t. the model inputs (one per time-step)
+  fn backward_deltas(&mut self, deltas: &Vec<Array>) -> Vec<Array> {
     // setup the optimizer parameters (if not already setup)
     self.optimizer.setup(self.param_manager.get_all_dims());
-    let mut loss_vec = Vec::with_capacity(predictions.len());
+    let mut input_deltas = Vec::with_capacity(deltas.len());
+    let last_index = self.layers.len();
+    let mut layer_deltas = Vec::with_capacity(last_index);
+    let loss_scale = self.param_manager.get_loss_scale();
+    let unscale = |d: Array| if loss_scale == 1.0 { d } else {
+      utils::cast(&af::div(&d, &loss_scale, false), d.get_type())
+    };
 
-    for (pred, ind) in Zip::new((predictions.iter().rev(), (0..predictions.len()).rev()))
-    {
-      let tar = af::slice(&targets, ind as u64);
-      let last_index = self.layers.len();
+    let first_layer = if self.cached_prefix { self.frozen_layers } else { 0 };
 
-      // handle loss indices that are not to be allowed
-      let mut delta = match loss_indices {
-        Some(li) => {
-          assert!(li.len() == predictions.len()
-                  , "loss indices need to be of the same size as the predictions");
-          match li[ind] {
-            false => utils::constant(tar.dims(), tar.get_type(), 0.0f32),
-            true  => {
-              loss_vec.push(loss::get_loss(&self.loss, pred, &tar).unwrap());
-              loss::get_loss_derivative(&self.loss, pred, &tar).unwrap()
-            },
-          }
-        },
-        None     => {
-          loss_vec.push(loss::get_loss(&self.loss, pred, &tar).unwrap());
-          loss::get_loss_derivative(&self.loss, pred, &tar).unwrap()
-        },
+    for ind in (0..deltas.len()).rev() {
+      let mut delta = if loss_scale == 1.0 { deltas[ind].clone() } else {
+        af::mul(&deltas[ind], &loss_scale, false)
       };
+      layer_deltas.clear();
+      for i in (first_layer..last_index).rev() {
+        // bring back the released activations of a checkpointed segment
+        let segment = self.checkpoint_segments.iter().find(|&&(_, last)| last == i).cloned();
+        if let Some((first, last)) = segment {
+          self.recompute_segment(first, last);
+        }
 
-      for i in (0..last_index).rev() {
+        delta = utils::cast(&delta, self.param_manager.get_dtype(i));
+        layer_deltas.push(delta.clone());
         delta = self.layers[i].backward(self.param_manager.get_params(i), &delta);
+
+        let segment = self.checkpoint_segments.iter().find(|&&(first, _)| first == i).cloned();
+        if let Some((first, last)) = segment {
+          let unroll = self.param_manager.get_current_unroll(first);
+          self.release_segment(first, last, unroll);
+        }
       }
+      input_deltas.push(delta);
     }
 
-    loss_vec
+    // deltas were gathered from the last layer to the first
+    layer_deltas.reverse();
+    self.layer_deltas = layer_deltas.into_iter().map(&unscale).collect();
+    input_deltas.reverse();
+    input_deltas.into_iter().map(&unscale).collect()
   }
 }
@@ -0,0 +1,368 @@
+use af::{Array, HasAfEnum};
+use num::Zero;
+use std::collections::{HashMap, VecDeque};
+
+use loss;
+use utils;
+use metrics;
+use activations;
+use data::{DataSource, FeatureStatistics};
+use device::Device;
+use model::Sequential;
+
+/// Sum of the (total, count) pairs of the last `size` batches
+pub struct RollingWindow {
+  pub size: usize,
+  entries: VecDeque<(f64, f64)>,
+  total: f64,
+  count: f64,
+}
+
+impl RollingWindow {
+  pub fn new(size: usize) -> RollingWindow {
+    assert!(size > 0, "the window needs at least one entry");
+    RollingWindow {
+      size: size,
+      entries: VecDeque::with_capacity(size),
+      total: 0.0,
+      count: 0.0,
+    }
+  }
+
+  /// Adds the total of a batch of `count` samples, dropping the oldest batch if full
+  pub fn push(&mut self, total: f64, count: f64) {
+    if self.entries.len() == self.size {
+      let (old_total, old_count) = self.entries.pop_front().unwrap();
+      self.total -= old_total;
+      self.count -= old_count;
+    }
+    self.entries.push_back((total, count));
+    self.total += total;
+    self.count += count;
+  }
+
+  /// Returns the per sample mean over the window
+  pub fn mean(&self) -> f32 {
+    match self.count > 0.0 {
+      true  => (self.total / self.count) as f32,
+      false => 0.0,
+    }
+  }
+}
+
+/// The state of a streaming evaluation that is handed to the callbacks
+#[derive(Clone, Debug)]
+pub struct StreamReport {
+  pub num_batches: u64,
+  pub num_samples: u64,
+  pub values: HashMap<String, f32>,
+}
+
+/// Called with every report, returning false stops the evaluation
+pub type ReportCallback = Box<FnMut(&StreamReport) -> bool>;
+
+/// Evaluates a model against an unbounded stream of labeled batches
+///
+/// The per sample "loss" & "accuracy" are kept over a rolling window of the
+/// last `window_size` batches [see `RollingWindow`]. Every `report_every` batches
+/// a `StreamReport` is handed to all the callbacks, eg: to monitor the accuracy
+/// drift of a deployed model on labeled production traffic.
+///
+/// # Parameters
+///
+/// - `window_size` is the number of batches of the rolling window
+/// - `report_every` is the number of batches between two reports
+pub struct StreamingEvaluator {
+  pub window_size: usize,
+  pub report_every: u64,
+  windows: Vec<(String, RollingWindow)>,
+  callbacks: Vec<ReportCallback>,
+  num_batches: u64,
+  num_samples: u64,
+}
+
+impl StreamingEvaluator {
+  pub fn new(window_size: usize, report_every: u64) -> StreamingEvaluator {
+    assert!(report_every > 0, "need to report at least every batch");
+    StreamingEvaluator {
+      window_size: window_size,
+      report_every: report_every,
+      windows: vec![("loss".to_string(), RollingWindow::new(window_size))
+                    , ("accuracy".to_string(), RollingWindow::new(window_size))],
+      callbacks: Vec::new(),
+      num_batches: 0,
+      num_samples: 0,
+    }
+  }
+
+  pub fn add_callback(&mut self, callback: ReportCallback) {
+    self.callbacks.push(callback);
+  }
+
+  /// Returns the current state of the rolling windows
+  pub fn report(&self) -> StreamReport {
+    StreamReport {
+      num_batches: self.num_batches,
+      num_samples: self.num_samples,
+      values: self.windows.iter().map(|&(ref name, ref w)| (name.clone(), w.mean())).collect(),
+    }
+  }
+
+  /// Evaluates a single labeled batch [non recurrent models]
+  ///
+  /// # Return Values
+  ///
+  /// false when a callback requested to stop
+  pub fn update<T>(&mut self, model: &mut Sequential, input: &Array, target: &Array
+                   , src_device: Device) -> bool
+    where T: HasAfEnum + Zero + Clone
+  {
+    let device = model.get_device();
+    let logits = model.infer::<T>(input, src_device).remove(0);
+    let manager = model.get_manager();
+    let target = manager.swap_array_backend::<T>(target, src_device, device);
+
+    let batch_size = input.dims()[0] as f64;
+    let batch_loss = loss::get_loss(model.get_loss(), &logits, &target).unwrap() as f64;
+    let proba = activations::get_activation(loss::get_output_activation(model.get_loss())
+                                            , &logits).unwrap();
+    let batch_accuracy = metrics::accuracy(&proba, &target) as f64;
+    manager.swap_device(src_device);
+
+    // mse is the mean over the batch, the other losses are sums over it
+    let batch_total = match model.get_loss() {
+      "mse" => batch_loss * batch_size,
+      _     => batch_loss,
+    };
+    self.windows[0].1.push(batch_total, batch_size);
+    self.windows[1].1.push(batch_accuracy * batch_size, batch_size);
+    self.num_batches += 1;
+    self.num_samples += batch_size as u64;
+
+    let mut proceed = true;
+    if self.num_batches % self.report_every == 0 {
+      let report = self.report();
+      for callback in self.callbacks.iter_mut() {
+        proceed = callback(&report) && proceed;
+      }
+    }
+    proceed
+  }
+
+  /// Consumes batches of the (unbounded) training iterator of the source
+  ///
+  /// # Parameters
+  ///
+  /// - `model` is the model to evaluate
+  /// - `source` is the live datasource
+  /// - `src_device` is the source device of the data
+  /// - `batch_size` is the minibatch size
+  /// - `max_batches` optionally bounds the number of batches
+  ///
+  /// # Return Values
+  ///
+  /// The final report, the evaluation stops when a callback returns false
+  pub fn run<T, E>(&mut self, model: &mut Sequential, source: &T, src_device: Device
+                   , batch_size: u64, max_batches: Option<u64>) -> StreamReport
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    let mut remaining = max_batches;
+    while remaining != Some(0) {
+      model.get_manager().swap_device(src_device);
+      let minibatch = source.get_train_iter(batch_size);
+      let proceed = self.update::<E>(model, &minibatch.input.into_inner()
+                                     , &minibatch.target.into_inner(), src_device);
+      remaining = remaining.map(|r| r - 1);
+      if !proceed {
+        break;
+      }
+    }
+    self.report()
+  }
+}
+
+/// z-scores of the deciles of the standard normal [bin edges of the gaussian PSI]
+const NORMAL_DECILES: [f64; 9] = [-1.2816, -0.8416, -0.5244, -0.2533, 0.0
+                                  , 0.2533, 0.5244, 0.8416, 1.2816];
+
+/// Standard normal CDF [Abramowitz & Stegun 7.1.26, |error| < 1.5e-7]
+fn normal_cdf(z: f64) -> f64 {
+  let x = z.abs() / 2f64.sqrt();
+  let t = 1.0 / (1.0 + 0.3275911 * x);
+  let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741
+                                     + t * (-1.453152027 + t * 1.061405429))));
+  let erf = 1.0 - poly * (-x * x).exp();
+  match z >= 0.0 {
+    true  => 0.5 * (1.0 + erf),
+    false => 0.5 * (1.0 - erf),
+  }
+}
+
+/// The training-set distribution of a single feature
+#[derive(Clone, Debug)]
+pub enum ReferenceDistribution {
+  /// Gaussian with the stored mean & standard deviation [see `FeatureStatistics`]
+  Gaussian(f64, f64),
+  /// Sorted training samples
+  Empirical(Vec<f64>),
+}
+
+impl ReferenceDistribution {
+  pub fn cdf(&self, x: f64) -> f64 {
+    match *self {
+      ReferenceDistribution::Gaussian(mean, std) => match std > 0.0 {
+        true  => normal_cdf((x - mean) / std),
+        false => if x >= mean { 1.0 } else { 0.0 },
+      },
+      ReferenceDistribution::Empirical(ref sorted) => {
+        let num_below = sorted.iter().take_while(|&&v| v <= x).count();
+        num_below as f64 / sorted.len() as f64
+      },
+    }
+  }
+
+  /// Returns the inner edges of the 10 equiprobable bins of the reference
+  pub fn decile_edges(&self) -> Vec<f64> {
+    match *self {
+      ReferenceDistribution::Gaussian(mean, std) => {
+        NORMAL_DECILES.iter().map(|z| mean + std * z).collect()
+      },
+      ReferenceDistribution::Empirical(ref sorted) => (1..10).map(|k| {
+        sorted[(k * (sorted.len() - 1)) / 10]
+      }).collect(),
+    }
+  }
+}
+
+/// Kolmogorov-Smirnov statistic of the values against the reference: max |F_values(x) - F_reference(x)|
+pub fn ks_statistic(reference: &ReferenceDistribution, values: &Vec<f64>) -> f64 {
+  let mut sorted = values.clone();
+  sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  let n = sorted.len() as f64;
+  sorted.iter().enumerate().fold(0.0, |ks, (i, &x)| {
+    let f = reference.cdf(x);
+    // the empirical CDF jumps from i / n to (i + 1) / n at x
+    ks.max((f - i as f64 / n).abs()).max(((i + 1) as f64 / n - f).abs())
+  })
+}
+
+/// Population stability index over the deciles of the reference
+/// sum_k (a_k - e_k) ln(a_k / e_k), e_k = 0.1
+pub fn population_stability_index(reference: &ReferenceDistribution, values: &Vec<f64>) -> f64 {
+  let eps = 1e-4; // empty bins
+  let edges = reference.decile_edges();
+  let mut counts = vec![0.0; edges.len() + 1];
+  for x in values.iter() {
+    counts[edges.iter().take_while(|&e| x > e).count()] += 1.0;
+  }
+  counts.iter().fold(0.0, |psi, &c| {
+    let actual = (c / values.len() as f64).max(eps);
+    psi + (actual - 0.1) * (actual / 0.1).ln()
+  })
+}
+
+/// The drift statistics of a single feature
+#[derive(Clone, Debug)]
+pub struct DriftReport {
+  pub feature: usize,
+  pub ks: f32,
+  pub psi: f32,
+}
+
+/// Called with the reports of the features that drifted
+pub type DriftCallback = Box<FnMut(&Vec<DriftReport>)>;
+
+/// Compares incoming batches against the training distribution of every feature
+///
+/// A feature drifts when its KS statistic exceeds `ks_threshold` or its PSI
+/// exceeds `psi_threshold` [0.1: moderate, 0.25: significant shift].
+///
+/// Usage:
+///
+/// ```ignore
+/// let stats = model.get_input_statistics().unwrap();
+/// let mut detector = DriftDetector::from_statistics(stats, 0.1, 0.25);
+/// detector.add_callback(Box::new(|drifted| println!("drift: {:?}", drifted)));
+/// detector.check(&batch);
+/// ```
+pub struct DriftDetector {
+  pub references: Vec<ReferenceDistribution>,
+  pub ks_threshold: f32,
+  pub psi_threshold: f32,
+  callbacks: Vec<DriftCallback>,
+}
+
+impl DriftDetector {
+  pub fn new(references: Vec<ReferenceDistribution>, ks_threshold: f32, psi_threshold: f32) -> DriftDetector {
+    DriftDetector {
+      references: references,
+      ks_threshold: ks_threshold,
+      psi_threshold: psi_threshold,
+      callbacks: Vec::new(),
+    }
+  }
+
+  /// Gaussian references from the running statistics of the preprocessing [see `Sequential::get_input_statistics`]
+  pub fn from_statistics(stats: &FeatureStatistics, ks_threshold: f32, psi_threshold: f32) -> DriftDetector {
+    let means = utils::array_to_vec(&stats.mean);
+    let stds = utils::array_to_vec(&stats.std_dev());
+    let references = means.into_iter().zip(stds.into_iter())
+      .map(|(mean, std)| ReferenceDistribution::Gaussian(mean, std)).collect();
+    DriftDetector::new(references, ks_threshold, psi_threshold)
+  }
+
+  /// Empirical references from [num_samples, num_features] training samples
+  pub fn from_samples(samples: &Array, ks_threshold: f32, psi_threshold: f32) -> DriftDetector {
+    let references = feature_values(samples).into_iter().map(|mut values| {
+      values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+      ReferenceDistribution::Empirical(values)
+    }).collect();
+    DriftDetector::new(references, ks_threshold, psi_threshold)
+  }
+
+  pub fn add_callback(&mut self, callback: DriftCallback) {
+    self.callbacks.push(callback);
+  }
+
+  /// Computes the drift statistics of every feature of the [batch, feature, time] batch
+  ///
+  /// The callbacks are called with the drifted features (if any)
+  ///
+  /// # Return Values
+  ///
+  /// The reports of all the features
+  pub fn check(&mut self, batch: &Array) -> Vec<DriftReport> {
+    let values = feature_values(batch);
+    assert!(values.len() == self.references.len()
+            , "need a reference for every feature");
+
+    let reports: Vec<DriftReport> = self.references.iter().zip(values.iter()).enumerate()
+      .map(|(feature, (reference, v))| DriftReport {
+        feature: feature,
+        ks: ks_statistic(reference, v) as f32,
+        psi: population_stability_index(reference, v) as f32,
+      }).collect();
+
+    let drifted: Vec<DriftReport> = reports.iter()
+      .filter(|r| r.ks > self.ks_threshold || r.psi > self.psi_threshold)
+      .cloned().collect();
+    if drifted.len() > 0 {
+      for callback in self.callbacks.iter_mut() {
+        callback(&drifted);
+      }
+    }
+    reports
+  }
+}
+
+/// Helper to copy the values of every feature of a [batch, feature, time] array to the host
+fn feature_values(input: &Array) -> Vec<Vec<f64>> {
+  let dims = input.dims();
+  let (batch_size, num_features) = (dims[0] as usize, dims[1] as usize);
+  let host = utils::array_to_vec(input);
+  (0..num_features).map(|f| {
+    host.chunks(batch_size * num_features)
+      .flat_map(|step| step[f * batch_size..(f + 1) * batch_size].iter().cloned())
+      .collect()
+  }).collect()
+}
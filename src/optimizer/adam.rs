@@ -1,12 +1,10 @@
 use af;
 use af::{Array, Dim4};
-use itertools::Zip;
 use std::collections::HashMap;
 use std::default::Default;
 
 use optimizer;
 use params::ParamManager;
-use initializations;
 use optimizer::Optimizer;
 
 #[allow(non_snake_case)]
@@ -19,6 +17,7 @@ pub struct Adam {
   pub lambda: f32,
   pub clip_grad: f32,
   pub iter: u64,
+  beta1_t: f32, // beta1 decayed by lambda every step [beta1 stays the configured value]
   mt: Vec<Array>,
   vt: Vec<Array>,
 }
@@ -34,6 +33,7 @@ impl Default for Adam {
       lambda: 1.0 - 1e-8,
       clip_grad: 5.0,
       iter: 0,
+      beta1_t: 0.9,
       mt: Vec::new(),
       vt: Vec::new(),
     }
@@ -51,18 +51,15 @@ impl Optimizer for Adam {
       lambda: params.get("lambda").unwrap().parse::<f32>().unwrap(),
       clip_grad: params.get("clip_grad").unwrap().parse::<f32>().unwrap(),
       iter: 0,
+      beta1_t: params.get("beta1").unwrap().parse::<f32>().unwrap(),
       mt: Vec::new(),
       vt: Vec::new(),
     }
   }
 
   fn setup(&mut self, dims: Vec<Dim4>) {
-    if self.mt.len() == 0 {
-      for dim in dims {
-        self.vt.push(initializations::zeros::<f32>(dim));
-        self.mt.push(initializations::zeros::<f32>(dim));
-      }
-    }
+    optimizer::fit_state(&mut self.mt, &dims);
+    optimizer::fit_state(&mut self.vt, &dims);
   }
 
   fn update(&mut self, parameter_manager: &mut ParamManager, batch_size: u64)
@@ -70,41 +67,69 @@ impl Optimizer for Adam {
     self.iter += 1;
     // let lr = self.learning_rate * (1.0 / (1.0 + self.decay * (self.iter as f32)));
     // let alpha = lr / batch_size as f32;
-    self.beta1 = self.beta1 * self.lambda;
-
-    // all arrays are returned as [W0, b0, .. WN, bN, ..] (note this is per layer)
-    // deltas are returned in the same way
-    let num_params = self.vt.len();
-    for (arr, delta, vt_i, mt_i, ind) in Zip::new((parameter_manager.get_all_arrays().iter()   // weights + biases
-                                                   , parameter_manager.get_all_deltas().iter() // deltas of above
-                                                   , self.vt.iter_mut()                        // vt
-                                                   , self.mt.iter_mut()                        // mt
-                                                   , 0..num_params))                           // current index
-    {
-      let grad_update = match self.clip_grad > 0.0 {
+    self.beta1_t = self.beta1_t * self.lambda;
+
+    // params & deltas are visited as [W0, b0, .. WN, bN, ..] (note this is per layer)
+    // and are updated in place [frozen layers are skipped], the deltas are zeroed in the same pass
+    let (beta1, beta2, eps) = (self.beta1_t, self.beta2, self.eps);
+    let (learning_rate, clip_grad) = (self.learning_rate, self.clip_grad);
+    let (mt, vt) = (&mut self.mt, &mut self.vt);
+    parameter_manager.with_mut_trainable_arrays_and_deltas(|ind, arr, delta| {
+      let grad_update = match clip_grad > 0.0 {
         false => delta.clone(),
-        true  => optimizer::clip_grads(&delta, self.clip_grad),
+        true  => optimizer::clip_grads(&delta, clip_grad),
       };
 
-      *mt_i = af::add(&af::mul(&self.beta1, mt_i, false)
-                      , &af::mul(&(1.0 - self.beta1), &grad_update, false)
-                      , false);
-      *vt_i = af::add(&af::mul(&self.beta2, vt_i, false)
-                      , &af::mul(&(1.0 - self.beta2), &af::mul(&grad_update, &grad_update, false), false)
-                      , false);
-      let mhat_i = af::div(mt_i, &(1.0 - self.beta1), false);
-      let vhat_i = af::div(vt_i, &(1.0 - self.beta2), false);
-      let update = af::mul(&self.learning_rate, &af::div(&mhat_i, &af::add(&af::sqrt(&vhat_i), &self.eps, false), false)
+      mt[ind] = af::add(&af::mul(&beta1, &mt[ind], false)
+                        , &af::mul(&(1.0 - beta1), &grad_update, false)
+                        , false);
+      vt[ind] = af::add(&af::mul(&beta2, &vt[ind], false)
+                        , &af::mul(&(1.0 - beta2), &af::mul(&grad_update, &grad_update, false), false)
+                        , false);
+      let mhat_i = af::div(&mt[ind], &(1.0 - beta1), false);
+      let vhat_i = af::div(&vt[ind], &(1.0 - beta2), false);
+      let update = af::mul(&learning_rate, &af::div(&mhat_i, &af::add(&af::sqrt(&vhat_i), &eps, false), false)
                            , false);
 
-      parameter_manager.set_array_from_index(af::sub(arr, &update, false), ind);
-    }
+      *arr = af::sub(&*arr, &update, false);
 
-    // zero out the deltas
-    parameter_manager.zero_all_deltas();
+      // materialize to keep the JIT trees from growing across iterations
+      mt[ind].eval();
+      vt[ind].eval();
+      arr.eval();
+    });
+
+    // zero out the state derivatives
     parameter_manager.zero_all_state_derivatives();
   }
 
+  fn get_step(&self) -> u64 {
+    self.iter
+  }
+
+  fn get_state(&self) -> HashMap<&str, &[Array]> {
+    let mut state = HashMap::new();
+    state.insert("mt", &self.mt[..]);
+    state.insert("vt", &self.vt[..]);
+    state
+  }
+
+  fn get_name(&self) -> String {
+    self.name.clone()
+  }
+
+  /// Returns the params that `new` expects
+  fn get_params(&self) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    params.insert("learning_rate".to_string(), self.learning_rate.to_string());
+    params.insert("beta1".to_string(), self.beta1.to_string());
+    params.insert("beta2".to_string(), self.beta2.to_string());
+    params.insert("eps".to_string(), self.eps.to_string());
+    params.insert("lambda".to_string(), self.lambda.to_string());
+    params.insert("clip_grad".to_string(), self.clip_grad.to_string());
+    params
+  }
+
   fn info(&self){
     println!("optimizer_name: {}", self.name);
     println!("learning_rate:  {}", self.learning_rate);
@@ -0,0 +1,122 @@
+use af;
+use af::Array;
+use std::mem;
+
+use params::ParamManager;
+
+/// Averaged copy of the parameters, maintained after every optimizer step
+///
+/// - `ema` keeps exponential moving average shadow weights:
+///   avg = decay * avg + (1 - decay) * w
+/// - `swa` [stochastic weight averaging, Izmailov et al, 2018] keeps the equal
+///   weighted mean of the parameters of every `frequency` steps after `start_step`:
+///   avg = avg + (w - avg) / (n + 1)
+///
+/// The averaged weights can be swapped into the model for evaluation or
+/// export [see `Sequential::swap_averaged_weights`].
+///
+/// # Parameters
+///
+/// - `kind` is "ema" or "swa"
+/// - `decay` is the decay of the ema
+/// - `start_step` is the first step that is averaged by swa
+/// - `frequency` is the number of steps between two swa snapshots
+/// - `num_averaged` is the number of snapshots in the swa mean
+/// - `step` is the number of optimizer steps seen so far
+pub struct WeightAverage {
+  pub kind: String,
+  pub decay: f32,
+  pub start_step: u64,
+  pub frequency: u64,
+  pub num_averaged: u64,
+  pub step: u64,
+  averaged: Vec<Array>,
+}
+
+impl WeightAverage {
+  pub fn ema(decay: f32) -> WeightAverage {
+    assert!(decay >= 0.0 && decay < 1.0, "decay needs to be in [0, 1)");
+    WeightAverage {
+      kind: "ema".to_string(),
+      decay: decay,
+      start_step: 0,
+      frequency: 1,
+      num_averaged: 0,
+      step: 0,
+      averaged: Vec::new(),
+    }
+  }
+
+  pub fn swa(start_step: u64, frequency: u64) -> WeightAverage {
+    assert!(frequency > 0, "need to average at least every step");
+    WeightAverage {
+      kind: "swa".to_string(),
+      decay: 0.0,
+      start_step: start_step,
+      frequency: frequency,
+      num_averaged: 0,
+      step: 0,
+      averaged: Vec::new(),
+    }
+  }
+
+  /// Folds the current parameters into the average [called after every optimizer step]
+  pub fn update(&mut self, parameter_manager: &ParamManager) {
+    self.step += 1;
+    let averaged = &mut self.averaged;
+    match self.kind.as_str() {
+      "ema" => {
+        let decay = self.decay;
+        parameter_manager.with_mut_arrays_and_deltas(|ind, arr, _| {
+          if averaged.len() <= ind {
+            averaged.push(arr.copy());
+          } else {
+            averaged[ind] = af::add(&af::mul(&decay, &averaged[ind], false)
+                                    , &af::mul(&(1.0 - decay), &*arr, false), false);
+            averaged[ind].eval();
+          }
+        });
+      },
+      _     => {
+        if self.step < self.start_step || (self.step - self.start_step) % self.frequency != 0 {
+          return;
+        }
+        let weight = 1.0 / (self.num_averaged + 1) as f32;
+        parameter_manager.with_mut_arrays_and_deltas(|ind, arr, _| {
+          if averaged.len() <= ind {
+            averaged.push(arr.copy());
+          } else {
+            averaged[ind] = af::add(&averaged[ind]
+                                    , &af::mul(&weight, &af::sub(&*arr, &averaged[ind], false), false)
+                                    , false);
+            averaged[ind].eval();
+          }
+        });
+        self.num_averaged += 1;
+      },
+    }
+  }
+
+  /// Returns the averaged parameters [in the order of `ParamManager::get_all_arrays`]
+  pub fn get_arrays(&self) -> &Vec<Array> {
+    &self.averaged
+  }
+
+  /// Exchanges the parameters of the manager with the averaged ones
+  ///
+  /// Swapping twice restores the original parameters
+  ///
+  /// # Return Values
+  ///
+  /// false when nothing was averaged yet
+  pub fn swap(&mut self, parameter_manager: &ParamManager) -> bool {
+    if self.averaged.len() == 0 {
+      return false;
+    }
+    let averaged = &mut self.averaged;
+    parameter_manager.with_mut_arrays_and_deltas(|ind, arr, _| {
+      mem::swap(arr, &mut averaged[ind]);
+    });
+    true
+  }
+}
@@ -4,19 +4,47 @@ mod sgd;
 pub use self::adam::Adam;
 mod adam;
 
+pub use self::sgld::SGLD;
+mod sgld;
+
+pub use self::averaging::WeightAverage;
+mod averaging;
+
 use af;
 use af::{Array, Dim4, NormType};
 use std::collections::HashMap;
 
 use utils;
+use initializations;
 use error::HALError;
 use params::ParamManager;
 
-pub trait Optimizer {
+pub trait Optimizer: Send {
   fn new(params: &HashMap<&str, &str>) -> Self where Self: Sized;
   //fn setup(&mut self, w_dim: Vec<Dim4>, b_dim: Vec<Dim4>);
   fn setup(&mut self, dims: Vec<Dim4>);
   fn update(&mut self, parameter_manager: &mut ParamManager, batch_size: u64);
+  fn get_name(&self) -> String;
+  fn get_params(&self) -> HashMap<String, String>;
+
+  /// Number of optimization steps taken so far [0 for optimizers that do not count them]
+  fn get_step(&self) -> u64 {
+    0
+  }
+
+  /// Read-only view of the per-parameter state by name [eg: the "mt" & "vt" moments of `Adam`]
+  ///
+  /// Every entry holds one array per parameter [in the order of `ParamManager::get_all_arrays`]
+  /// and is empty until the first step.
+  fn get_state(&self) -> HashMap<&str, &[Array]> {
+    HashMap::new()
+  }
+
+  /// Posterior weight samples collected by sampling optimizers [see `SGLD`]
+  fn get_samples(&self) -> &[Vec<Array>] {
+    &[]
+  }
+
   fn info(&self);
 }
 
@@ -24,6 +52,7 @@ pub fn get_optimizer(name: &str, params: &HashMap<&str, &str>) -> Result<Box<Opt
   match name.to_lowercase().as_str() {
     "sgd"  => Ok(Box::new(SGD::new(params))),
     "adam" => Ok(Box::new(Adam::new(params))),
+    "sgld" => Ok(Box::new(SGLD::new(params))),
     _     => Err(HALError::UNKNOWN),
   }
 }
@@ -32,6 +61,7 @@ pub fn get_optimizer_with_defaults(name: &str) -> Result<Box<Optimizer>, HALErro
   match name.to_lowercase().as_str() {
     "sgd" =>  Ok(Box::new(SGD::default())),
     "adam" => Ok(Box::new(Adam::default())),
+    "sgld" => Ok(Box::new(SGLD::default())),
     _     => Err(HALError::UNKNOWN),
   }
 }
@@ -42,3 +72,19 @@ pub fn clip_grads(input: &Array, rescale: f32) -> Array {
   utils::cast(&af::mul(input, &scale, false), input.get_type())
   //utils::clip_by_value(input, -5.0f32, 5.0f32)
 }
+
+/// Matches the per parameter state of an optimizer to the dims of the parameters
+///
+/// Parameters that were added after the first step [eg: a layer added after
+/// `setup`] get a zero state, the state of parameters whose dims changed is
+/// reset & the state of removed parameters is dropped.
+pub fn fit_state(state: &mut Vec<Array>, dims: &[Dim4]) {
+  state.truncate(dims.len());
+  for (s, &dim) in state.iter_mut().zip(dims.iter()) {
+    if s.dims() != dim {
+      *s = initializations::zeros::<f32>(dim);
+    }
+  }
+  let num_kept = state.len();
+  state.extend(dims[num_kept..].iter().map(|&dim| initializations::zeros::<f32>(dim)));
+}
@@ -1,11 +1,9 @@
 use af;
 use af::{Array, Dim4, DType};
-use itertools::Zip;
 use std::collections::HashMap;
 use std::default::Default;
 
 use params::ParamManager;
-use initializations;
 use optimizer;
 use optimizer::Optimizer;
 
@@ -51,11 +49,7 @@ impl Optimizer for SGD {
   }
 
   fn setup(&mut self, dims: Vec<Dim4>) {  //, w_dims: Vec<Dim4>, b_dims: Vec<Dim4>){
-    if self.velocity.len() == 0 {
-      for dim in dims {
-        self.velocity.push(initializations::zeros::<f32>(dim));
-      }
-    }
+    optimizer::fit_state(&mut self.velocity, &dims);
   }
 
   fn update(&mut self, parameter_manager: &mut ParamManager, batch_size: u64)
@@ -64,32 +58,58 @@ impl Optimizer for SGD {
     let lr = self.learning_rate * (1.0 / (1.0 + self.decay * (self.iter as f32)));
     let alpha = lr / batch_size as f32;
 
-    // all arrays are returned as [W0, b0, .. WN, bN, ..] (note this is per layer)
-    // deltas are returned in the same way
-    let num_params = self.velocity.len();
-    for (arr, delta, velocity, ind) in Zip::new((parameter_manager.get_all_arrays().iter()   // weights + biases
-                                                 , parameter_manager.get_all_deltas().iter() // deltas of above
-                                                 , self.velocity.iter_mut()                  // velocity of above
-                                                 , 0..num_params))                           // current index
-    {
-      let grad_update = match self.clip_grad > 0.0 {
+    // params & deltas are visited as [W0, b0, .. WN, bN, ..] (note this is per layer)
+    // and are updated in place [frozen layers are skipped], the deltas are zeroed in the same pass
+    let momemtum = self.momemtum;
+    let clip_grad = self.clip_grad;
+    let velocity = &mut self.velocity;
+    parameter_manager.with_mut_trainable_arrays_and_deltas(|ind, arr, delta| {
+      let grad_update = match clip_grad > 0.0 {
         false => delta.clone(),
-        true  => optimizer::clip_grads(&delta, self.clip_grad),
+        true  => optimizer::clip_grads(&delta, clip_grad),
       };
 
       // v   = momemtum * v + learning_rate * d_w (or d_b)
       // p   = p - v
-      *velocity = af::add(&af::mul(&self.momemtum, velocity, false),
-                          &af::mul(&alpha, &grad_update, false), false);
-      assert!(velocity.dims().get() == arr.dims().get());
-      parameter_manager.set_array_from_index(af::sub(arr, velocity, false), ind);
-    }
+      velocity[ind] = af::add(&af::mul(&momemtum, &velocity[ind], false),
+                              &af::mul(&alpha, &grad_update, false), false);
+      assert!(velocity[ind].dims().get() == arr.dims().get());
+      *arr = af::sub(&*arr, &velocity[ind], false);
+
+      // materialize to keep the JIT trees from growing across iterations
+      velocity[ind].eval();
+      arr.eval();
+    });
 
-    // zero out the deltas
-    parameter_manager.zero_all_deltas();
+    // zero out the state derivatives
     parameter_manager.zero_all_state_derivatives();
   }
 
+  fn get_step(&self) -> u64 {
+    self.iter
+  }
+
+  fn get_state(&self) -> HashMap<&str, &[Array]> {
+    let mut state = HashMap::new();
+    state.insert("velocity", &self.velocity[..]);
+    state
+  }
+
+  fn get_name(&self) -> String {
+    self.name.clone()
+  }
+
+  /// Returns the params that `new` expects
+  fn get_params(&self) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    params.insert("learning_rate".to_string(), self.learning_rate.to_string());
+    params.insert("momemtum".to_string(), self.momemtum.to_string());
+    params.insert("decay".to_string(), self.decay.to_string());
+    params.insert("nesterov".to_string(), self.nesterov.to_string());
+    params.insert("clip_grad".to_string(), self.clip_grad.to_string());
+    params
+  }
+
   fn info(&self){
     println!("optimizer_name: {}", self.name);
     println!("learning_rate:  {}", self.learning_rate);
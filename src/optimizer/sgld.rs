@@ -0,0 +1,194 @@
+use af;
+use af::{Array, Dim4};
+use std::collections::HashMap;
+use std::default::Default;
+
+use utils;
+use random;
+use params::ParamManager;
+use optimizer;
+use optimizer::Optimizer;
+
+/// Stochastic gradient Langevin dynamics [Welling & Teh, 2011]
+///
+/// Every step follows the (minibatch estimated) gradient of the posterior
+/// potential U = -log p(data | w) - log p(w) (with the likelihood of all `num_train`
+/// samples & a N(0, 1/prior_precision) prior) and injects gaussian noise of
+/// variance `learning_rate * temperature`:
+///
+/// w = w - lr/2 * G * (N * mean(grad) + prior_precision * w) + N(0, lr * temperature * G)
+///
+/// With `preconditioned` the RMSprop preconditioner G = 1 / (lambda + sqrt(V))
+/// of pSGLD [Li et al, 2016] is used, otherwise G = 1. After `burn_in` iterations
+/// a copy of the parameters is collected every `thinning` iterations (keeping at
+/// most `max_samples`) as samples of the posterior [see `get_samples`].
+#[allow(non_snake_case)]
+pub struct SGLD {
+  pub name: String,
+  pub learning_rate: f32,
+  pub num_train: u64,
+  pub temperature: f32,
+  pub prior_precision: f32,
+  pub preconditioned: bool,
+  pub alpha: f32,
+  pub lambda: f32,
+  pub clip_grad: f32,
+  pub burn_in: u64,
+  pub thinning: u64,
+  pub max_samples: usize,
+  pub iter: u64,
+  vt: Vec<Array>,
+  samples: Vec<Vec<Array>>,
+}
+
+impl Default for SGLD {
+  fn default() -> SGLD {
+    SGLD {
+      name: "SGLD".to_string(),
+      learning_rate: 1e-4,
+      num_train: 1,
+      temperature: 1.0,
+      prior_precision: 0.0,
+      preconditioned: false,
+      alpha: 0.99,
+      lambda: 1e-5,
+      clip_grad: 0.0,
+      burn_in: 1000,
+      thinning: 100,
+      max_samples: 100,
+      iter: 0,
+      vt: Vec::new(),
+      samples: Vec::new(),
+    }
+  }
+}
+
+impl Optimizer for SGLD {
+  fn new(params: &HashMap<&str, &str>) -> SGLD {
+    SGLD{
+      name: "SGLD".to_string(),
+      learning_rate: params.get("learning_rate").unwrap().parse::<f32>().unwrap(),
+      num_train: params.get("num_train").unwrap().parse::<u64>().unwrap(),
+      temperature: params.get("temperature").unwrap().parse::<f32>().unwrap(),
+      prior_precision: params.get("prior_precision").unwrap().parse::<f32>().unwrap(),
+      preconditioned: params.get("preconditioned").unwrap().parse::<bool>().unwrap(),
+      alpha: params.get("alpha").unwrap().parse::<f32>().unwrap(),
+      lambda: params.get("lambda").unwrap().parse::<f32>().unwrap(),
+      clip_grad: params.get("clip_grad").unwrap().parse::<f32>().unwrap(),
+      burn_in: params.get("burn_in").unwrap().parse::<u64>().unwrap(),
+      thinning: params.get("thinning").unwrap().parse::<u64>().unwrap(),
+      max_samples: params.get("max_samples").unwrap().parse::<usize>().unwrap(),
+      iter: 0,
+      vt: Vec::new(),
+      samples: Vec::new(),
+    }
+  }
+
+  fn setup(&mut self, dims: Vec<Dim4>) {
+    optimizer::fit_state(&mut self.vt, &dims);
+  }
+
+  fn update(&mut self, parameter_manager: &mut ParamManager, batch_size: u64)
+  {
+    self.iter += 1;
+
+    // gradient of the potential of the full dataset from the minibatch mean
+    let grad_scale = self.num_train as f32 / batch_size as f32;
+    let (lr, temperature, clip_grad) = (self.learning_rate, self.temperature, self.clip_grad);
+    let prior_precision = self.prior_precision;
+    let (preconditioned, alpha, lambda) = (self.preconditioned, self.alpha, self.lambda);
+    let vt = &mut self.vt;
+    random::seed_arrayfire();
+    parameter_manager.with_mut_trainable_arrays_and_deltas(|ind, arr, delta| {
+      let mut grad = af::mul(&(*delta), &grad_scale, false);
+      if clip_grad > 0.0 {
+        grad = optimizer::clip_grads(&grad, clip_grad);
+      }
+      if prior_precision > 0.0 {
+        grad = af::add(&grad, &af::mul(&prior_precision, &(*arr), false), false);
+      }
+
+      // G = 1 / (lambda + sqrt(V)), V = alpha * V + (1 - alpha) * g^2
+      let preconditioner = match preconditioned {
+        true  => {
+          vt[ind] = af::add(&af::mul(&alpha, &vt[ind], false)
+                            , &af::mul(&(1.0 - alpha), &af::mul(&grad, &grad, false), false)
+                            , false);
+          vt[ind].eval();
+          af::div(&1.0f32, &af::add(&af::sqrt(&vt[ind]), &lambda, false), false)
+        },
+        false => utils::constant(arr.dims(), arr.get_type(), 1.0f32),
+      };
+
+      // noise ~ N(0, lr * temperature * G)
+      let noise = af::mul(&af::randn::<f32>(arr.dims())
+                          , &af::sqrt(&af::mul(&(lr * temperature), &preconditioner, false))
+                          , false);
+      let drift = af::mul(&(lr / 2.0), &af::mul(&preconditioner, &grad, false), false);
+      *arr = utils::cast(&af::add(&af::sub(&*arr, &drift, false), &noise, false), arr.get_type());
+      arr.eval();
+    });
+
+    // zero out the state derivatives
+    parameter_manager.zero_all_state_derivatives();
+
+    // collect the posterior samples
+    let thinning = if self.thinning > 0 { self.thinning } else { 1 };
+    if self.iter > self.burn_in && (self.iter - self.burn_in) % thinning == 0 {
+      if self.samples.len() == self.max_samples && self.max_samples > 0 {
+        self.samples.remove(0);
+      }
+      if self.max_samples > 0 {
+        self.samples.push(parameter_manager.get_all_arrays().iter().map(|a| a.copy()).collect());
+      }
+    }
+  }
+
+  fn get_step(&self) -> u64 {
+    self.iter
+  }
+
+  fn get_state(&self) -> HashMap<&str, &[Array]> {
+    let mut state = HashMap::new();
+    state.insert("vt", &self.vt[..]);
+    state
+  }
+
+  fn get_name(&self) -> String {
+    self.name.clone()
+  }
+
+  /// Returns the params that `new` expects
+  fn get_params(&self) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    params.insert("learning_rate".to_string(), self.learning_rate.to_string());
+    params.insert("num_train".to_string(), self.num_train.to_string());
+    params.insert("temperature".to_string(), self.temperature.to_string());
+    params.insert("prior_precision".to_string(), self.prior_precision.to_string());
+    params.insert("preconditioned".to_string(), self.preconditioned.to_string());
+    params.insert("alpha".to_string(), self.alpha.to_string());
+    params.insert("lambda".to_string(), self.lambda.to_string());
+    params.insert("clip_grad".to_string(), self.clip_grad.to_string());
+    params.insert("burn_in".to_string(), self.burn_in.to_string());
+    params.insert("thinning".to_string(), self.thinning.to_string());
+    params.insert("max_samples".to_string(), self.max_samples.to_string());
+    params
+  }
+
+  /// Returns the collected posterior samples [each in the order of `ParamManager::get_all_arrays`]
+  fn get_samples(&self) -> &[Vec<Array>] {
+    &self.samples
+  }
+
+  fn info(&self){
+    println!("optimizer_name: {}", self.name);
+    println!("learning_rate:  {}", self.learning_rate);
+    println!("num_train:      {}", self.num_train);
+    println!("temperature:    {}", self.temperature);
+    println!("preconditioned: {}", self.preconditioned);
+    println!("burn_in:        {}", self.burn_in);
+    println!("thinning:       {}", self.thinning);
+    println!("samples:        {}", self.samples.len());
+    println!("iter:           {}", self.iter);
+  }
+}
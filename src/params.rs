@@ -1,13 +1,14 @@
+use af;
 use af::{Array, Dim4, HasAfEnum, DType};
 use std::default::Default;
 use num::Complex;
-use rand;
 use rand::Rng;
 
 //use itertools::Zip;
 use std::sync::{Arc, Mutex};
 
 use utils;
+use random;
 use initializations;
 use device::{Device, DeviceManager};
 //use error::HAL Error;
@@ -85,22 +86,107 @@ pub struct Params {
   pub biases: Vec<Array>,
   pub activations: Vec<String>,
   pub deltas: Vec<Array>,
+  pub zero_deltas: Vec<Array>,
   pub inputs: Vec<Array>,
   pub outputs: Vec<Array>,
   pub recurrences: Vec<Array>,
   pub state_derivatives: Vec<Array>,
   pub current_unroll: usize,
   pub optional: Vec<Array>,
+  pub dtype: DType,
+  pub gradient_scale: f32,
+  pub frozen: bool,
+}
+
+/// Dynamic loss scaling [Micikevicius et al, 2018]
+///
+/// The derivatives of the loss are multiplied by the scale before the
+/// backward pass, so that the small gradients of low precision layers do not
+/// underflow, and the accumulated gradients are divided by it again before
+/// the optimizer step. Steps whose gradients overflow [inf or nan] are skipped
+/// & the scale is reduced by `backoff_factor`, after `growth_interval` steps
+/// without an overflow the scale grows by `growth_factor`.
+///
+/// Clones share the same scale, eg: the trunk & the heads of a `MultiHead`.
+///
+/// # Parameters
+///
+/// - `growth_factor` multiplies the scale after `growth_interval` finite steps [default: 2]
+/// - `backoff_factor` multiplies the scale after an overflow [default: 0.5]
+/// - `growth_interval` is the number of finite steps before the scale grows [default: 2000]
+/// - `dynamic` keeps the scale constant when false [overflowing steps are still skipped]
+#[derive(Clone)]
+pub struct LossScaler {
+  pub growth_factor: f32,
+  pub backoff_factor: f32,
+  pub growth_interval: u64,
+  pub dynamic: bool,
+  state: Arc<Mutex<(f32, u64, u64)>>, // (scale, finite steps since the last change, skipped steps)
+}
+
+impl LossScaler {
+  pub fn new(initial_scale: f32) -> LossScaler {
+    assert!(initial_scale > 0.0, "the loss scale needs to be positive");
+    LossScaler {
+      growth_factor: 2.0,
+      backoff_factor: 0.5,
+      growth_interval: 2000,
+      dynamic: true,
+      state: Arc::new(Mutex::new((initial_scale, 0, 0))),
+    }
+  }
+
+  /// A constant loss scale
+  pub fn fixed(scale: f32) -> LossScaler {
+    LossScaler { dynamic: false, .. LossScaler::new(scale) }
+  }
+
+  pub fn get_scale(&self) -> f32 {
+    self.state.lock().unwrap().0
+  }
+
+  /// Returns the number of steps that were skipped because of an overflow
+  pub fn num_skipped(&self) -> u64 {
+    self.state.lock().unwrap().2
+  }
+
+  /// Updates the scale after a step whose unscaled gradients were (not) finite
+  ///
+  /// # Return Values
+  ///
+  /// Whether the optimizer step should be applied
+  pub fn update(&self, finite: bool) -> bool {
+    let mut state = self.state.lock().unwrap();
+    match finite {
+      true  => {
+        state.1 += 1;
+        if self.dynamic && state.1 >= self.growth_interval {
+          state.0 *= self.growth_factor;
+          state.1 = 0;
+        }
+      },
+      false => {
+        if self.dynamic {
+          state.0 *= self.backoff_factor;
+        }
+        state.1 = 0;
+        state.2 += 1;
+      },
+    }
+    finite
+  }
 }
 
 pub struct ParamManager {
   layer_storage: Vec<Arc<Mutex<Params>>>,
+  loss_scaler: Option<LossScaler>,
 }
 
 impl Default for ParamManager {
   fn default() -> ParamManager {
     ParamManager {
       layer_storage: Vec::new(),
+      loss_scaler: None,
     }
   }
 }
@@ -164,6 +250,7 @@ impl ParamManager {
       weights: weights,
       biases: biases,
       activations: owned_activations,
+      zero_deltas: deltas.clone(), // shared by the deltas after every reset
       deltas: deltas,
       //inputs: inputs,
       //outputs: outputs,
@@ -173,6 +260,9 @@ impl ParamManager {
       state_derivatives: Vec::new(),
       current_unroll: 0,
       optional: optional,
+      dtype: T::get_af_dtype(),
+      gradient_scale: 1.0,
+      frozen: false,
     })));
   }
 
@@ -204,6 +294,74 @@ impl ParamManager {
     self.num_biases(layer_index) + self.num_weights(layer_index)
   }
 
+  pub fn get_dtype(&self, layer_index: usize) -> DType {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    let layer = self.layer_storage[layer_index].clone();
+    let ltex = layer.lock().unwrap();
+    ltex.dtype
+  }
+
+  /// Sets the factor that the gradients of the layer are multiplied by before every optimizer step
+  ///
+  /// eg: balance the gradient magnitudes of heterogeneous branches [default: 1]
+  pub fn set_gradient_scale(&self, layer_index: usize, scale: f32) {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().gradient_scale = scale;
+  }
+
+  pub fn get_gradient_scale(&self, layer_index: usize) -> f32 {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().gradient_scale
+  }
+
+  /// Excludes the parameters of the layer from the optimizer updates [see `with_mut_trainable_arrays_and_deltas`]
+  pub fn set_frozen(&self, layer_index: usize, frozen: bool) {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().frozen = frozen;
+  }
+
+  pub fn is_frozen(&self, layer_index: usize) -> bool {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().frozen
+  }
+
+  /// Sets the loss scaler of the backward passes [see `LossScaler`], None disables loss scaling
+  pub fn set_loss_scaler(&mut self, scaler: Option<LossScaler>) {
+    self.loss_scaler = scaler;
+  }
+
+  pub fn get_loss_scaler(&self) -> Option<&LossScaler> {
+    self.loss_scaler.as_ref()
+  }
+
+  /// Returns the current loss scale [1 without a loss scaler]
+  pub fn get_loss_scale(&self) -> f32 {
+    self.loss_scaler.as_ref().map(|s| s.get_scale()).unwrap_or(1.0)
+  }
+
+  /// Divides the accumulated deltas by the loss scale & multiplies them by the gradient scale of their layer
+  ///
+  /// # Return Values
+  ///
+  /// Whether all the deltas are finite [only checked with a loss scaler]
+  pub fn unscale_all_deltas(&self) -> bool {
+    let loss_scale = self.get_loss_scale();
+    let mut finite = true;
+    for layer in &self.layer_storage {
+      let mut ltex = layer.lock().unwrap();
+      let factor = ltex.gradient_scale / loss_scale;
+      for delta in ltex.deltas.iter_mut() {
+        if factor != 1.0 {
+          *delta = utils::cast(&af::mul(&*delta, &factor, false), delta.get_type());
+        }
+        if finite && self.loss_scaler.is_some() {
+          finite = af::sum_all(&*delta).0.is_finite();
+        }
+      }
+    }
+    finite
+  }
+
   pub fn num_recurrences(&self, layer_index: usize) -> usize {
     assert!(self.layer_storage.len() - 1 >= layer_index);
     let layer = self.layer_storage[layer_index].clone();
@@ -219,6 +377,28 @@ impl ParamManager {
   }
 
 
+  /// Rewinds the unroll counters of all layers
+  ///
+  /// Forward passes that are not followed by a backward pass (inference)
+  /// leave the counters advanced, this returns them to the first time-step.
+  pub fn reset_all_unrolls(&self) {
+    for layer in &self.layer_storage {
+      layer.lock().unwrap().current_unroll = 0;
+    }
+  }
+
+  /// Returns the number of unrolls that the layer currently holds activations for
+  pub fn get_current_unroll(&self, layer_index: usize) -> usize {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().current_unroll
+  }
+
+  /// Moves the unroll counter of the layer [eg: to re-run the forward pass of a time-step]
+  pub fn set_current_unroll(&self, layer_index: usize, unroll: usize) {
+    assert!(self.layer_storage.len() - 1 >= layer_index);
+    self.layer_storage[layer_index].lock().unwrap().current_unroll = unroll;
+  }
+
   pub fn get_params(&self, layer_index: usize) -> Arc<Mutex<Params>> {
     assert!(self.layer_storage.len() - 1>= layer_index);
     self.layer_storage[layer_index].clone()
@@ -300,6 +480,57 @@ impl ParamManager {
     }
   }
 
+  /// Visits every parameter together with its delta, allowing both to be updated in place
+  ///
+  /// Parameters are visited in the same order as `get_all_arrays` [W0, b0, .. WN, bN]
+  /// and every layer is locked only once. Unlike `get_all_arrays` + `set_array_from_index`
+  /// no parameter vectors are cloned and no index search is needed to write them back.
+  ///
+  /// # Parameters
+  ///
+  /// - `f` is called with the (flat index, parameter, delta) of every parameter
+  pub fn with_mut_arrays_and_deltas<F>(&self, mut f: F)
+    where F: FnMut(usize, &mut Array, &mut Array)
+  {
+    let mut ind = 0;
+    for layer in &self.layer_storage {
+      let mut ltex = layer.lock().unwrap();
+      let ltex = &mut *ltex; // split the borrows of the fields
+      for (param, delta) in ltex.weights.iter_mut().chain(ltex.biases.iter_mut())
+        .zip(ltex.deltas.iter_mut())
+      {
+        f(ind, param, delta);
+        ind += 1;
+      }
+    }
+  }
+
+  /// Visits the parameters of the layers that are not frozen & zeroes all the deltas afterwards
+  ///
+  /// This is the update pass of the optimizers [see `with_mut_arrays_and_deltas`].
+  /// The flat indices still count the parameters of the frozen layers, so that
+  /// per parameter optimizer state lines up with `get_all_dims`. The deltas are
+  /// reset to the zero arrays of their layer, so no arrays are allocated.
+  pub fn with_mut_trainable_arrays_and_deltas<F>(&self, mut f: F)
+    where F: FnMut(usize, &mut Array, &mut Array)
+  {
+    let mut ind = 0;
+    for layer in &self.layer_storage {
+      let mut ltex = layer.lock().unwrap();
+      let ltex = &mut *ltex; // split the borrows of the fields
+      let frozen = ltex.frozen;
+      for ((param, delta), zero) in ltex.weights.iter_mut().chain(ltex.biases.iter_mut())
+        .zip(ltex.deltas.iter_mut()).zip(ltex.zero_deltas.iter())
+      {
+        if !frozen {
+          f(ind, param, delta);
+        }
+        *delta = zero.clone(); // increases the ref count
+        ind += 1;
+      }
+    }
+  }
+
   pub fn get_all_deltas(&self) -> Vec<Array> {
     let mut d = Vec::new();
     for layer_num in 0..self.num_layers() {
@@ -309,14 +540,9 @@ impl ParamManager {
   }
 
   pub fn zero_all_deltas(&self) {
-    for layer_num in 0..self.num_layers() {
-      for delta_num in 0..self.num_arrays(layer_num) {
-        let delta = self.get_delta(layer_num, delta_num);
-        let delta_dims = delta.dims();
-        let dtype = delta.get_type();
-        let zero_tensor = utils::constant(delta_dims, dtype, 0.0f32);
-        self.set_delta(layer_num, delta_num, zero_tensor);
-      }
+    for layer in &self.layer_storage {
+      let mut ltex = layer.lock().unwrap();
+      ltex.deltas = ltex.zero_deltas.clone();
     }
   }
 
@@ -570,6 +796,22 @@ pub trait LSTMGenerator {
                             , b_init: &str);
 }
 
+pub trait OrdinalGenerator {
+  fn add_ordinal<T: HasAfEnum>(&mut self
+                               , manager: DeviceManager
+                               , device: Device
+                               , input_size: usize
+                               , output_size: usize
+                               , w_init: &str
+                               , b_init: &str);
+}
+
+pub trait DropoutGenerator {
+  fn add_dropout<T: HasAfEnum>(&mut self
+                               , manager: DeviceManager
+                               , device: Device);
+}
+
 pub trait UnitaryGenerator {
   fn add_unitary<T: HasAfEnum>(&mut self
                                , manager: DeviceManager
@@ -641,6 +883,37 @@ impl RNNGenerator for ParamManager {
   }
 }
 
+impl OrdinalGenerator for ParamManager {
+  fn add_ordinal<T: HasAfEnum>(&mut self
+                               , manager: DeviceManager
+                               , device: Device
+                               , input_size: usize
+                               , output_size: usize
+                               , w_init: &str
+                               , b_init: &str)
+  {
+    // a single projection shared by all thresholds + one bias per threshold
+    // the outputs are logits, the sigmoid is applied in the loss
+    self.add::<T>(manager, device, "ordinal"
+                  , vec![(w_init, (input_size, 1))]
+                  , vec![(b_init, (output_size, 1))]
+                  , vec!["linear"]
+                  , None, None);
+  }
+}
+
+impl DropoutGenerator for ParamManager {
+  fn add_dropout<T: HasAfEnum>(&mut self
+                               , manager: DeviceManager
+                               , device: Device)
+  {
+    // no weights: the masks of every unroll are kept in the optional params
+    self.add::<T>(manager, device, "dropout"
+                  , vec![], vec![], vec![]
+                  , None, None);
+  }
+}
+
 impl LSTMGenerator for ParamManager {
   fn add_lstm<T: HasAfEnum>(&mut self
                             , manager: DeviceManager
@@ -742,7 +1015,7 @@ impl<'a> UnitaryGenerator for ParamManager {
     }
     let mut permut_inv: Vec<u32> = permut.clone();
     if is_permut_const == false {
-      rand::thread_rng().shuffle(&mut permut);
+      random::rng().shuffle(&mut permut);
       for i in 0..permut.len(){
         permut_inv[permut[i] as usize] = i as u32;
       }
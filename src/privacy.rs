@@ -0,0 +1,201 @@
+use af;
+use af::{Array, Dim4, DType, HasAfEnum};
+use num::Zero;
+
+use utils;
+use random;
+use data::DataSource;
+use device::Device;
+use model::Sequential;
+
+/// Largest Renyi order that the accountant evaluates
+const MAX_ORDER: u64 = 256;
+
+/// Renyi differential privacy accountant of the subsampled Gaussian mechanism
+///
+/// Every step of DP-SGD is a Gaussian mechanism [noise multiplier sigma] applied
+/// to a minibatch sampled with probability q. Its RDP of integer order a is
+/// [Mironov et al, 2019]:
+///
+/// rdp(a) = log(sum_k C(a, k) (1 - q)^(a - k) q^k exp((k^2 - k) / (2 sigma^2))) / (a - 1)
+///
+/// RDP composes additively over steps and converts to (epsilon, delta)-DP as
+/// epsilon = min_a (rdp(a) + log(1 / delta) / (a - 1)).
+#[derive(Clone, Debug)]
+pub struct PrivacyAccountant {
+  /// (sampling rate, noise multiplier, number of steps) of every phase of training
+  pub steps: Vec<(f64, f64, u64)>,
+}
+
+impl PrivacyAccountant {
+  pub fn new() -> PrivacyAccountant {
+    PrivacyAccountant { steps: Vec::new() }
+  }
+
+  /// Records a step with the provided sampling rate [batch_size / num_train] & noise multiplier
+  pub fn step(&mut self, sampling_rate: f64, noise_multiplier: f64) {
+    assert!(sampling_rate > 0.0 && sampling_rate <= 1.0, "the sampling rate needs to be in (0, 1]");
+    assert!(noise_multiplier >= 0.0, "the noise multiplier can not be negative");
+    if let Some(last) = self.steps.last_mut() {
+      if last.0 == sampling_rate && last.1 == noise_multiplier {
+        last.2 += 1;
+        return;
+      }
+    }
+    self.steps.push((sampling_rate, noise_multiplier, 1));
+  }
+
+  /// Returns the number of recorded steps
+  pub fn num_steps(&self) -> u64 {
+    self.steps.iter().map(|s| s.2).sum()
+  }
+
+  /// RDP of a single step of integer order `order` [infinite without noise]
+  pub fn rdp(sampling_rate: f64, noise_multiplier: f64, order: u64) -> f64 {
+    let variance = noise_multiplier * noise_multiplier;
+    if variance == 0.0 {
+      return ::std::f64::INFINITY;
+    }
+    if sampling_rate >= 1.0 {
+      return order as f64 / (2.0 * variance);
+    }
+
+    // log-sum-exp over the binomial expansion
+    let (log_q, log_1mq) = (sampling_rate.ln(), (1.0 - sampling_rate).ln());
+    let mut log_binomial = 0.0;
+    let terms: Vec<f64> = (0..order + 1).map(|k| {
+      if k > 0 {
+        log_binomial += ((order - k + 1) as f64).ln() - (k as f64).ln();
+      }
+      let k = k as f64;
+      log_binomial + (order as f64 - k) * log_1mq + k * log_q + (k * k - k) / (2.0 * variance)
+    }).collect();
+    let max = terms.iter().fold(::std::f64::MIN, |m, &t| m.max(t));
+    let log_a = max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln();
+    log_a / (order as f64 - 1.0)
+  }
+
+  /// Returns the epsilon of the training so far at the provided delta
+  pub fn epsilon(&self, delta: f64) -> f64 {
+    assert!(delta > 0.0 && delta < 1.0, "delta needs to be in (0, 1)");
+    if self.steps.len() == 0 {
+      return 0.0;
+    }
+    (2..MAX_ORDER + 1).map(|order| {
+      let rdp: f64 = self.steps.iter()
+        .map(|&(q, sigma, n)| n as f64 * PrivacyAccountant::rdp(q, sigma, order)).sum();
+      rdp + (1.0 / delta).ln() / (order as f64 - 1.0)
+    }).fold(::std::f64::MAX, |m, e| m.min(e))
+  }
+}
+
+/// Differentially private SGD [Abadi et al, 2016]
+///
+/// The gradient of every sample is clipped to an l2 norm of `l2_norm_clip`
+/// [over all the parameters of the model], the clipped gradients are summed and
+/// gaussian noise of std `noise_multiplier * l2_norm_clip` is added before the
+/// optimizer step of the model. Every step is recorded by the `accountant`.
+///
+/// The per sample gradients are computed with `Sequential::per_sample_gradients`.
+/// Online normalization [see `Sequential::set_online_normalization`]
+/// statistics are not private and should not be used with DP-SGD.
+///
+/// # Parameters
+///
+/// - `l2_norm_clip` is the largest l2 norm of a per sample gradient
+/// - `noise_multiplier` is the ratio of the noise std to the clipping norm
+/// - `num_train` is the number of training samples [for the sampling rate]
+/// - `accountant` tracks the privacy spent so far
+pub struct DPSGD {
+  pub l2_norm_clip: f32,
+  pub noise_multiplier: f32,
+  pub num_train: u64,
+  pub accountant: PrivacyAccountant,
+}
+
+impl DPSGD {
+  pub fn new(l2_norm_clip: f32, noise_multiplier: f32, num_train: u64) -> DPSGD {
+    assert!(l2_norm_clip > 0.0, "the clipping norm needs to be positive");
+    DPSGD {
+      l2_norm_clip: l2_norm_clip,
+      noise_multiplier: noise_multiplier,
+      num_train: num_train,
+      accountant: PrivacyAccountant::new(),
+    }
+  }
+
+  /// Runs a single private optimization step of the model on the minibatch
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the losses of every time-step [averaged over the samples]
+  pub fn partial_fit<E>(&mut self, model: &mut Sequential, batch_input: &Array
+                        , batch_target: &Array, src_device: Device) -> Vec<f32>
+    where E: HasAfEnum + Zero + Clone
+  {
+    let (losses, gradients) = model.per_sample_gradients::<E>(batch_input, batch_target, src_device, None);
+    let batch_size = gradients[0].dims()[0];
+    let param_dims = model.get_param_manager().get_all_dims();
+
+    // clip the gradient of every sample: g / max(1, |g| / C)
+    let squared_norms = gradients.iter().fold(vec![0.0; batch_size as usize], |mut norms, g| {
+      let g = utils::cast(g, DType::F32);
+      for (norm, row) in norms.iter_mut().zip(utils::array_to_vec(&af::sum(&af::mul(&g, &g, false), 1))) {
+        *norm += row;
+      }
+      norms
+    });
+    let scales: Vec<f32> = squared_norms.iter()
+      .map(|n| 1.0 / (n.sqrt() as f32 / self.l2_norm_clip).max(1.0)).collect();
+    let scales = utils::vec_to_array::<f32>(scales, Dim4::new(&[batch_size, 1, 1, 1]));
+    let clipped_sum: Vec<Array> = gradients.iter().zip(param_dims.iter()).map(|(g, &dims)| {
+      let clipped = af::mul(&utils::cast(g, DType::F32), &scales, true);
+      af::moddims(&af::sum(&clipped, 0), dims)
+    }).collect();
+
+    // noisy sum of the clipped gradients --> optimizer
+    let std = self.noise_multiplier * self.l2_norm_clip;
+    random::seed_arrayfire();
+    model.get_param_manager().with_mut_arrays_and_deltas(|ind, _, delta| {
+      let noise = af::mul(&af::randn::<f32>(delta.dims()), &std, false);
+      *delta = utils::cast(&af::add(&clipped_sum[ind], &noise, false), delta.get_type());
+    });
+    // the per sample gradients were unscaled & multiplied by the gradient scales already
+    // [see `Sequential::per_sample_gradients`]
+    model.apply_gradients(batch_size);
+    self.accountant.step(batch_size as f64 / self.num_train as f64, self.noise_multiplier as f64);
+
+    losses
+  }
+
+  /// Fit's the model privately on the training data of the source [see `Model::fit`]
+  ///
+  /// # Return Values
+  ///
+  /// Vector of the losses of every minibatch
+  pub fn fit<T, E>(&mut self, model: &mut Sequential, source: &T, src_device: Device
+                   , epochs: u64, batch_size: u64) -> Vec<f32>
+    where T: DataSource, E: HasAfEnum + Zero + Clone
+  {
+    let iters = source.info().num_train / batch_size;
+    let manager = model.get_manager();
+    let mut lossvec = Vec::new();
+    for epoch in 0..epochs {
+      for _ in 0..iters {
+        manager.swap_device(src_device);
+        let minibatch = source.get_train_iter(batch_size);
+        lossvec.extend(self.partial_fit::<E>(model, &minibatch.input.into_inner()
+                                             , &minibatch.target.into_inner(), src_device));
+      }
+      info!("[epoch: {}/{}] private training with epsilon {} [delta = 1e-5]"
+            , epoch + 1, epochs, self.epsilon(1e-5));
+    }
+    manager.swap_device(src_device);
+    lossvec
+  }
+
+  /// Returns the privacy spent so far at the provided delta [see `PrivacyAccountant`]
+  pub fn epsilon(&self, delta: f64) -> f64 {
+    self.accountant.epsilon(delta)
+  }
+}
@@ -0,0 +1,101 @@
+use af;
+use af::{Array, Dim4};
+use std::cmp::Ordering;
+
+use utils;
+use model::Sequential;
+use params::ParamManager;
+
+/// Returns whether every array of `ParamManager::get_all_arrays` is a weight [biases are never pruned]
+fn weight_flags(params: &ParamManager) -> Vec<bool> {
+  let mut flags = Vec::new();
+  for layer in 0..params.num_layers() {
+    flags.extend(vec![true; params.num_weights(layer)]);
+    flags.extend(vec![false; params.num_biases(layer)]);
+  }
+  flags
+}
+
+/// Zeroes the `sparsity` fraction of smallest magnitude entries of every group
+///
+/// A group is a list of (array index, entry index, |w|)
+fn prune_groups(groups: Vec<Vec<(usize, usize, f64)>>, sparsity: f32, masks: &mut Vec<Vec<f32>>) {
+  for mut group in groups {
+    group.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+    let num_pruned = (sparsity * group.len() as f32).floor() as usize;
+    for &(array, entry, _) in group.iter().take(num_pruned) {
+      masks[array][entry] = 0.0;
+    }
+  }
+}
+
+/// Computes magnitude pruning masks
+///
+/// # Parameters
+///
+/// - `params` is the parameter manager of the model
+/// - `sparsity` is the fraction of weights to remove [0, 1)
+/// - `global` ranks all the weights of the model together instead of per weight array
+///
+/// # Return Values
+///
+/// One {0, 1} mask per array [in the order of `ParamManager::get_all_arrays`],
+/// the masks of the biases are all ones.
+pub fn magnitude_masks(params: &ParamManager, sparsity: f32, global: bool) -> Vec<Array> {
+  assert!(sparsity >= 0.0 && sparsity < 1.0, "sparsity needs to be in [0, 1)");
+  let arrays = params.get_all_arrays();
+  let flags = weight_flags(params);
+  let values: Vec<Vec<f64>> = arrays.iter().map(|a| utils::array_to_vec(a)).collect();
+  let mut masks: Vec<Vec<f32>> = values.iter().map(|v| vec![1.0; v.len()]).collect();
+
+  let mut groups: Vec<Vec<(usize, usize, f64)>> = Vec::new();
+  for (array, v) in values.iter().enumerate().filter(|&(i, _)| flags[i]) {
+    let entries = v.iter().enumerate().map(|(entry, w)| (array, entry, w.abs()));
+    match (global, groups.len()) {
+      (true, 1) => groups[0].extend(entries),
+      _         => groups.push(entries.collect()),
+    }
+  }
+  prune_groups(groups, sparsity, &mut masks);
+
+  masks.into_iter().zip(arrays.iter())
+    .map(|(mask, arr)| utils::cast(&utils::vec_to_array::<f32>(mask, arr.dims()), arr.get_type()))
+    .collect()
+}
+
+/// Multiplies every parameter with its mask
+pub fn apply_masks(params: &ParamManager, masks: &Vec<Array>) {
+  params.with_mut_arrays_and_deltas(|ind, arr, _| {
+    *arr = af::mul(&*arr, &masks[ind], false);
+    arr.eval();
+  });
+}
+
+/// Returns the fraction of zero weights of every layer
+pub fn layer_sparsity(params: &ParamManager) -> Vec<f32> {
+  (0..params.num_layers()).map(|layer| {
+    let (zeros, total) = params.get_weights(layer).iter().fold((0.0, 0.0), |(z, t), w| {
+      let dims: Dim4 = w.dims();
+      let num_zeros = af::sum_all(&af::eq(w, &0.0f32, false)).0;
+      (z + num_zeros, t + dims.elements() as f64)
+    });
+    match total > 0.0 {
+      true  => (zeros / total) as f32,
+      false => 0.0,
+    }
+  }).collect()
+}
+
+/// Prunes the model by magnitude & keeps the pruned weights at zero
+/// during the following fine-tuning [see `Sequential::set_pruning_masks`]
+///
+/// # Parameters
+///
+/// - `model` is the model to prune
+/// - `sparsity` is the fraction of weights to remove [0, 1)
+/// - `global` ranks all the weights of the model together instead of per weight array
+pub fn prune(model: &mut Sequential, sparsity: f32, global: bool) {
+  model.get_manager().swap_device(model.get_device());
+  let masks = magnitude_masks(model.get_param_manager(), sparsity, global);
+  model.set_pruning_masks(Some(masks));
+}
@@ -0,0 +1,217 @@
+use af;
+use af::{Array, Dim4, HasAfEnum};
+use num::Zero;
+use std::cmp::min;
+use std::fs::File;
+use std::io::{Read, Write};
+use rustc_serialize::json;
+
+use utils;
+use activations;
+use checkpoint::Environment;
+use device::Device;
+use error::HALError;
+use model::Sequential;
+
+/// Largest magnitude of a symmetric int8 value
+const INT8_MAX: f32 = 127.0;
+
+/// Maps a value to int8 with the provided scale [x ~ q * scale]
+fn quantize_value(x: f32, scale: f32) -> i8 {
+  match scale > 0.0 {
+    true  => (x / scale).round().max(-INT8_MAX).min(INT8_MAX) as i8,
+    false => 0,
+  }
+}
+
+/// Symmetric per-channel quantization of a column major [rows, cols] matrix
+///
+/// Every column [output channel] gets its own scale max(|w|) / 127
+///
+/// # Return Values
+///
+/// (int8 values in the same layout, scale of every column)
+pub fn quantize_per_channel(values: &[f32], rows: usize, cols: usize) -> (Vec<i8>, Vec<f32>) {
+  assert!(values.len() == rows * cols, "values do not match the provided dims");
+  let scales: Vec<f32> = values.chunks(rows)
+    .map(|col| col.iter().fold(0.0f32, |m, x| m.max(x.abs())) / INT8_MAX)
+    .collect();
+  let quantized = values.iter().enumerate()
+    .map(|(i, &x)| quantize_value(x, scales[i / rows]))
+    .collect();
+  (quantized, scales)
+}
+
+/// Records the largest input magnitude of every layer over the sample set
+///
+/// # Parameters
+///
+/// - `model` is the (trained) model to calibrate
+/// - `samples` is a [num_samples, input_size] array of representative inputs
+/// - `src_device` is the device of the samples
+/// - `batch_size` is the number of samples per forward pass
+///
+/// # Return Values
+///
+/// max(|input|) of every layer, in the order of the layers
+pub fn calibrate<T>(model: &mut Sequential, samples: &Array, src_device: Device
+                    , batch_size: u64) -> Vec<f32>
+  where T: HasAfEnum + Zero + Clone
+{
+  let num_layers = model.get_param_manager().num_layers();
+  let num_samples = samples.dims()[0];
+  let device = model.get_device();
+  let mut ranges = vec![0.0f32; num_layers];
+
+  let mut first = 0;
+  while first < num_samples {
+    let last = min(first + batch_size, num_samples) - 1;
+    let manager = model.get_manager();
+    manager.swap_device(src_device);
+    let batch = af::rows(samples, first, last);
+    model.forward_all_activations::<T>(&batch, src_device, device);
+
+    let params = model.get_param_manager();
+    for (layer, range) in ranges.iter_mut().enumerate() {
+      let input = params.get_input(layer, 0);
+      *range = range.max(af::max_all(&af::abs(&input)).0 as f32);
+    }
+    params.reset_all_unrolls();
+    first = last + 1;
+  }
+  ranges
+}
+
+/// Int8 version of a dense layer
+///
+/// The weights are quantized per output channel and the inputs per tensor
+/// with the calibrated range, so that the matrix product accumulates in int32:
+///
+/// z_j = (sum_k xq_k * wq_kj) * input_scale * weight_scale_j + b_j
+///
+/// # Parameters
+///
+/// - `weights` is the column major [input_size, output_size] int8 weight matrix
+/// - `weight_scales` are the scales of every output channel
+/// - `input_scale` is the scale of the (calibrated) inputs
+/// - `bias` is the float bias of every output
+/// - `activation` is the activation of the layer
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct QuantizedDense {
+  pub input_size: usize,
+  pub output_size: usize,
+  pub weights: Vec<i8>,
+  pub weight_scales: Vec<f32>,
+  pub input_scale: f32,
+  pub bias: Vec<f32>,
+  pub activation: String,
+}
+
+impl QuantizedDense {
+  /// Runs the layer on a column major [batch_size, input_size] host buffer
+  fn forward(&self, inputs: &[f32], batch_size: usize) -> Array {
+    let quantized_inputs: Vec<i8> = inputs.iter()
+      .map(|&x| quantize_value(x, self.input_scale)).collect();
+
+    let mut z = vec![0.0f32; batch_size * self.output_size];
+    for j in 0..self.output_size {
+      let column = &self.weights[j * self.input_size..(j + 1) * self.input_size];
+      let rescale = self.input_scale * self.weight_scales[j];
+      for b in 0..batch_size {
+        let acc = column.iter().enumerate().fold(0i32, |acc, (k, &w)| {
+          acc + quantized_inputs[k * batch_size + b] as i32 * w as i32
+        });
+        z[j * batch_size + b] = acc as f32 * rescale + self.bias[j];
+      }
+    }
+
+    let z = utils::vec_to_array::<f32>(z, Dim4::new(&[batch_size as u64, self.output_size as u64, 1, 1]));
+    activations::get_activation(&self.activation, &z).unwrap()
+  }
+}
+
+/// Post-training int8 quantization of a dense model
+///
+/// The integer products are computed on the host, so the quantized forward
+/// path is meant for deployment on CPU backends. Only dense layers are supported,
+/// online normalization [see `Sequential::set_online_normalization`] is not applied.
+///
+/// Usage:
+///
+/// ```ignore
+/// let ranges = quantize::calibrate::<f32>(&mut model, &samples, cpu, 32);
+/// let quantized = try!(QuantizedModel::from_model(&model, &ranges));
+/// let outputs = quantized.forward(&inputs);
+/// ```
+#[derive(RustcEncodable, RustcDecodable, Clone, Debug)]
+pub struct QuantizedModel {
+  pub layers: Vec<QuantizedDense>,
+  pub environment: Option<Environment>,
+}
+
+impl QuantizedModel {
+  /// Quantizes the weights of the model with the calibrated input ranges [see `calibrate`]
+  pub fn from_model(model: &Sequential, input_ranges: &Vec<f32>) -> Result<QuantizedModel, HALError> {
+    let configs = model.get_layer_configs();
+    assert!(input_ranges.len() == configs.len(), "need one range per layer");
+    if configs.iter().any(|&(ref layer, _)| layer.to_lowercase() != "dense") {
+      return Err(HALError::QUANTIZATION);
+    }
+
+    model.get_manager().swap_device(model.get_device());
+    let params = model.get_param_manager();
+    let layers = input_ranges.iter().enumerate().map(|(i, &range)| {
+      let weight = params.get_weight(i, 0);
+      let (input_size, output_size) = (weight.dims()[0] as usize, weight.dims()[1] as usize);
+      let values: Vec<f32> = utils::array_to_vec(&weight).iter().map(|&x| x as f32).collect();
+      let (weights, weight_scales) = quantize_per_channel(&values, input_size, output_size);
+      QuantizedDense {
+        input_size: input_size,
+        output_size: output_size,
+        weights: weights,
+        weight_scales: weight_scales,
+        input_scale: range / INT8_MAX,
+        bias: utils::array_to_vec(&params.get_bias(i, 0)).iter().map(|&x| x as f32).collect(),
+        activation: params.get_activation(i, 0),
+      }
+    }).collect();
+
+    Ok(QuantizedModel {
+      layers: layers,
+      environment: Some(Environment::capture(&model.get_manager(), model.get_device())),
+    })
+  }
+
+  /// Quantized forward pass of a [batch_size, input_size] array
+  ///
+  /// The outputs [activations of the last layer] are f32 & on the current device
+  pub fn forward(&self, inputs: &Array) -> Array {
+    let batch_size = inputs.dims()[0] as usize;
+    let mut activation = inputs.clone();
+    for layer in self.layers.iter() {
+      let host: Vec<f32> = utils::array_to_vec(&activation).iter().map(|&x| x as f32).collect();
+      activation = layer.forward(&host, batch_size);
+    }
+    activation
+  }
+
+  /// Returns the number of bytes of the weights & biases [int8 weights + f32 scales & biases]
+  pub fn size_in_bytes(&self) -> usize {
+    self.layers.iter().map(|l| l.weights.len() + 4 * (l.weight_scales.len() + l.bias.len() + 1)).sum()
+  }
+
+  /// Writes the quantized model to the provided path as json
+  pub fn save(&self, path: &str) -> Result<(), HALError> {
+    let encoded = try!(json::encode(self).map_err(|_| HALError::CHECKPOINT_IO));
+    let mut file = try!(File::create(path).map_err(|_| HALError::CHECKPOINT_IO));
+    file.write_all(encoded.as_bytes()).map_err(|_| HALError::CHECKPOINT_IO)
+  }
+
+  /// Reads a quantized model that was written with `save`
+  pub fn load(path: &str) -> Result<QuantizedModel, HALError> {
+    let mut file = try!(File::open(path).map_err(|_| HALError::CHECKPOINT_IO));
+    let mut contents = String::new();
+    try!(file.read_to_string(&mut contents).map_err(|_| HALError::CHECKPOINT_IO));
+    json::decode(&contents).map_err(|_| HALError::CHECKPOINT_IO)
+  }
+}
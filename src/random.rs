@@ -0,0 +1,153 @@
+use af;
+use rand;
+use rand::Rng;
+use std::cell::RefCell;
+
+/// Trait that describes a source of random bits
+///
+/// All the randomness of HAL [weight initialization, shuffling, sampling
+/// & the seeds of the ArrayFire generators] is drawn from the generator of
+/// the current thread [see `set_generator`], so a different generator can be
+/// substituted for specific experiments.
+pub trait RandomGenerator {
+  fn name(&self) -> String;
+  fn next_u64(&mut self) -> u64;
+  fn reseed(&mut self, seed: u64);
+
+  /// Returns the seed that the stream started off [None if it can not be reproduced]
+  fn seed(&self) -> Option<u64> {
+    None
+  }
+}
+
+/// Counter based generator [SplitMix64 of seed + counter]
+///
+/// Every output only depends on the seed & the position in the stream,
+/// which makes the streams cheap to reproduce & to split.
+pub struct CounterGenerator {
+  pub seed: u64,
+  pub counter: u64,
+}
+
+impl CounterGenerator {
+  pub fn new(seed: u64) -> CounterGenerator {
+    CounterGenerator { seed: seed, counter: 0 }
+  }
+}
+
+impl RandomGenerator for CounterGenerator {
+  fn name(&self) -> String {
+    "counter".to_string()
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    self.counter = self.counter.wrapping_add(1);
+    let mut z = self.seed.wrapping_add(self.counter.wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+  }
+
+  fn reseed(&mut self, seed: u64) {
+    self.seed = seed;
+    self.counter = 0;
+  }
+
+  fn seed(&self) -> Option<u64> {
+    Some(self.seed)
+  }
+}
+
+/// One dimensional Sobol [quasi-random] sequence in gray code order
+///
+/// The outputs fill [0, 2^64) evenly instead of independently, the
+/// seed is used as a scrambling mask [0: the plain sequence].
+pub struct SobolGenerator {
+  pub scramble: u64,
+  index: u64,
+  state: u64,
+}
+
+impl SobolGenerator {
+  pub fn new(scramble: u64) -> SobolGenerator {
+    SobolGenerator { scramble: scramble, index: 0, state: 0 }
+  }
+}
+
+impl RandomGenerator for SobolGenerator {
+  fn name(&self) -> String {
+    "sobol".to_string()
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    // flip the direction number of the lowest zero bit of the index
+    let bit = (!self.index).trailing_zeros();
+    self.state ^= 1u64 << (63 - bit);
+    self.index += 1;
+    self.state ^ self.scramble
+  }
+
+  fn reseed(&mut self, seed: u64) {
+    self.scramble = seed;
+    self.index = 0;
+    self.state = 0;
+  }
+
+  fn seed(&self) -> Option<u64> {
+    Some(self.scramble)
+  }
+}
+
+thread_local!(static GENERATOR: RefCell<Box<RandomGenerator>> =
+  RefCell::new(Box::new(CounterGenerator::new(rand::thread_rng().gen::<u64>()))));
+
+/// Replaces the generator of the current thread
+pub fn set_generator(generator: Box<RandomGenerator>) {
+  GENERATOR.with(|g| *g.borrow_mut() = generator);
+}
+
+/// Reseeds the generator of the current thread
+///
+/// By default every thread starts off a randomly seeded `CounterGenerator`
+pub fn set_seed(seed: u64) {
+  GENERATOR.with(|g| g.borrow_mut().reseed(seed));
+}
+
+/// Returns the name of the generator of the current thread
+pub fn generator_name() -> String {
+  GENERATOR.with(|g| g.borrow().name())
+}
+
+/// Returns the seed of the generator of the current thread [see `RandomGenerator::seed`]
+pub fn generator_seed() -> Option<u64> {
+  GENERATOR.with(|g| g.borrow().seed())
+}
+
+/// Returns the next 64 random bits of the generator of the current thread
+pub fn next_u64() -> u64 {
+  GENERATOR.with(|g| g.borrow_mut().next_u64())
+}
+
+/// Seeds the ArrayFire generators [randu / randn] from the generator of the current thread
+pub fn seed_arrayfire() {
+  af::set_seed(next_u64());
+}
+
+/// `rand::Rng` view of the generator of the current thread
+///
+/// eg: `random::rng().gen_range(0, 10)` or `random::rng().shuffle(&mut v)`
+pub struct ThreadGenerator;
+
+impl Rng for ThreadGenerator {
+  fn next_u32(&mut self) -> u32 {
+    (next_u64() >> 32) as u32
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    next_u64()
+  }
+}
+
+pub fn rng() -> ThreadGenerator {
+  ThreadGenerator
+}
@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use rustc_serialize::json;
+
+use config;
+use config::ModelConfig;
+use error::HALError;
+use model::{Model, Sequential};
+use tuning::TrialResult;
+
+/// A training run to compare: its per epoch history & its hyperparameters
+///
+/// The hyperparameters are the flattened config of the run [see `flatten`].
+#[derive(Clone, Debug)]
+pub struct Run {
+  pub name: String,
+  pub history: HashMap<String, Vec<f32>>,
+  pub params: BTreeMap<String, String>,
+}
+
+impl Run {
+  pub fn new(name: &str, history: HashMap<String, Vec<f32>>, config: Option<&ModelConfig>) -> Run {
+    Run {
+      name: name.to_string(),
+      history: history,
+      params: config.map(flatten).unwrap_or(BTreeMap::new()),
+    }
+  }
+
+  /// The history & the config of a trained model
+  pub fn from_model(name: &str, model: &Sequential) -> Run {
+    Run::new(name, model.get_history().clone(), Some(&ModelConfig::from_model(model)))
+  }
+
+  /// The history & the hyperparameters of a tuning trial [see `tuning::search`]
+  pub fn from_trial(name: &str, trial: &TrialResult) -> Run {
+    Run {
+      name: name.to_string(),
+      history: trial.history.clone(),
+      params: trial.assignment.clone(),
+    }
+  }
+
+  /// Loads a run from a metrics file of hal-train [a json map of histories]
+  ///
+  /// # Parameters
+  ///
+  /// - `history_path` is the metrics file [see `metrics_file` of hal-train]
+  /// - `config_path` is the optional model config [json, or toml with the "toml" feature],
+  ///   either a `ModelConfig` or a hal-train config with a [model] section
+  pub fn load(name: &str, history_path: &str, config_path: Option<&str>) -> Result<Run, HALError> {
+    let history: HashMap<String, Vec<f32>> = try!(json::decode(&try!(read_string(history_path)))
+                                                  .map_err(|e| {
+      warn!("unable to parse the history {}: {}", history_path, e);
+      HALError::DATA_IO
+    }));
+    let config = match config_path {
+      Some(path) => Some(try!(read_config(path))),
+      None       => None,
+    };
+    Ok(Run::new(name, history, config.as_ref()))
+  }
+}
+
+/// Flattens a config into the hyperparameter names of `tuning::SearchSpace`
+///
+/// eg: "loss", "optimizer", "optimizer.learning_rate", "layers.0.layer" & "layers.0.output_size"
+pub fn flatten(config: &ModelConfig) -> BTreeMap<String, String> {
+  let mut params = BTreeMap::new();
+  params.insert("loss".to_string(), config.loss.clone());
+  params.insert("optimizer".to_string(), config.optimizer.clone());
+  if let Some(ref optimizer_params) = config.optimizer_params {
+    for (k, v) in optimizer_params {
+      params.insert(format!("optimizer.{}", k), v.clone());
+    }
+  }
+  for (i, layer) in config.layers.iter().enumerate() {
+    params.insert(format!("layers.{}.layer", i), layer.layer.clone());
+    for (k, v) in layer.params.iter() {
+      params.insert(format!("layers.{}.{}", i, k), v.clone());
+    }
+  }
+  params
+}
+
+/// The comparison of one run [see `Report::summarize`]
+///
+/// # Parameters
+///
+/// - `epochs` is the number of recorded epochs
+/// - `best_value` & `best_epoch` are the best value of the compared metric & its epoch [1 based]
+/// - `epochs_to_converge` is the first epoch whose value is within the tolerance of the best value
+/// - `time_per_epoch` is the mean of the "epoch_time" history in seconds
+/// - `final_values` are the last values of every history
+/// - `config_diff` are the hyperparameters that differ between the runs ["-" when unset]
+#[derive(RustcEncodable, Clone, Debug)]
+pub struct RunSummary {
+  pub name: String,
+  pub epochs: u64,
+  pub best_value: Option<f32>,
+  pub best_epoch: Option<u64>,
+  pub epochs_to_converge: Option<u64>,
+  pub time_per_epoch: Option<f32>,
+  pub final_values: BTreeMap<String, f32>,
+  pub config_diff: BTreeMap<String, String>,
+}
+
+/// Compares the runs of a hyperparameter sweep by one recorded metric
+///
+/// # Parameters
+///
+/// - `metric` is the history to compare [eg: "val_loss" or "val_roc_auc"]
+/// - `higher_is_better` ranks in descending order of `metric` when set
+/// - `tolerance` is the fraction of the total improvement [first to best value]
+///   that a converged epoch may still be away from the best value [default: 0.01]
+pub struct Report {
+  pub metric: String,
+  pub higher_is_better: bool,
+  pub tolerance: f32,
+  pub runs: Vec<Run>,
+}
+
+impl Report {
+  pub fn new(metric: &str, higher_is_better: bool) -> Report {
+    Report {
+      metric: metric.to_string(),
+      higher_is_better: higher_is_better,
+      tolerance: 0.01,
+      runs: Vec::new(),
+    }
+  }
+
+  pub fn add(&mut self, run: Run) {
+    self.runs.push(run);
+  }
+
+  /// Returns the hyperparameters whose values are not the same in every run
+  pub fn differing_params(&self) -> Vec<String> {
+    let mut names: Vec<&String> = self.runs.iter().flat_map(|r| r.params.keys()).collect();
+    names.sort();
+    names.dedup();
+    names.into_iter().filter(|name| {
+      let values: Vec<Option<&String>> = self.runs.iter().map(|r| r.params.get(*name)).collect();
+      values.iter().any(|v| *v != values[0])
+    }).cloned().collect()
+  }
+
+  /// Summarizes every run, best first [runs without the metric are last]
+  pub fn summarize(&self) -> Vec<RunSummary> {
+    let differing = self.differing_params();
+    let mut summaries: Vec<RunSummary> = self.runs.iter().map(|run| {
+      let values = run.history.get(&self.metric).map(|v| &v[..]).unwrap_or(&[]);
+      let better = |a: f32, b: f32| if self.higher_is_better { a > b } else { a < b };
+      let best = values.iter().enumerate()
+        .fold(None, |best: Option<(usize, f32)>, (i, &v)| match best {
+          Some((_, b)) if !better(v, b) => best,
+          _                             => Some((i, v)),
+        });
+
+      // the first epoch within the tolerance of the best value
+      let epochs_to_converge = best.map(|(best_epoch, best_value)| {
+        let margin = self.tolerance * (values[0] - best_value).abs();
+        values.iter().position(|&v| (v - best_value).abs() <= margin).unwrap_or(best_epoch) as u64 + 1
+      });
+      let time_per_epoch = run.history.get("epoch_time").and_then(|times| match times.len() {
+        0 => None,
+        n => Some(times.iter().sum::<f32>() / n as f32),
+      });
+      let epochs = run.history.get("loss").map(|l| l.len())
+        .unwrap_or(values.len()) as u64;
+
+      RunSummary {
+        name: run.name.clone(),
+        epochs: epochs,
+        best_value: best.map(|(_, v)| v),
+        best_epoch: best.map(|(i, _)| i as u64 + 1),
+        epochs_to_converge: epochs_to_converge,
+        time_per_epoch: time_per_epoch,
+        final_values: run.history.iter().filter_map(|(k, v)| v.last().map(|&l| (k.clone(), l))).collect(),
+        config_diff: differing.iter().map(|name| {
+          (name.clone(), run.params.get(name).cloned().unwrap_or("-".to_string()))
+        }).collect(),
+      }
+    }).collect();
+
+    summaries.sort_by(|a, b| match (a.best_value, b.best_value) {
+      (Some(a), Some(b)) => match self.higher_is_better {
+        true  => b.partial_cmp(&a),
+        false => a.partial_cmp(&b),
+      }.unwrap_or(Ordering::Equal),
+      (Some(_), None)    => Ordering::Less,
+      (None, Some(_))    => Ordering::Greater,
+      (None, None)       => Ordering::Equal,
+    });
+    summaries
+  }
+
+  /// Returns the summaries as a json list [see `summarize`]
+  pub fn to_json(&self) -> String {
+    json::as_pretty_json(&self.summarize()).to_string()
+  }
+
+  /// Prints the ranked comparison table
+  pub fn print(&self) {
+    let summaries = self.summarize();
+    let differing = self.differing_params();
+    let cell = |v: Option<String>| v.unwrap_or("-".to_string());
+    println!("{:<6}{:<16}{:<8}{:<14}{:<10}{:<10}{:<12}params"
+             , "rank", "run", "epochs", format!("best {}", self.metric), "at", "converged", "s/epoch");
+    for (rank, summary) in summaries.iter().enumerate() {
+      let params: Vec<String> = summary.config_diff.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+      println!("{:<6}{:<16}{:<8}{:<14}{:<10}{:<10}{:<12}{}"
+               , rank + 1, summary.name, summary.epochs
+               , cell(summary.best_value.map(|v| format!("{:.6}", v)))
+               , cell(summary.best_epoch.map(|e| e.to_string()))
+               , cell(summary.epochs_to_converge.map(|e| e.to_string()))
+               , cell(summary.time_per_epoch.map(|t| format!("{:.3}", t)))
+               , params.join(" "));
+    }
+    if summaries.len() > 0 && differing.len() == 0 {
+      println!("all the runs share the same hyperparameters");
+    }
+  }
+}
+
+/// Helper to read a whole text file
+fn read_string(path: &str) -> Result<String, HALError> {
+  let mut contents = String::new();
+  try!(File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).map_err(|e| {
+    warn!("unable to read {}: {}", path, e);
+    HALError::DATA_IO
+  }));
+  Ok(contents)
+}
+
+/// Helper to read a model config or the [model] section of a hal-train config
+fn read_config(path: &str) -> Result<ModelConfig, HALError> {
+  let contents = try!(read_string(path));
+  let tree = try!(match path.ends_with(".toml") {
+    true  => parse_toml(&contents),
+    false => config::parse_json(&contents),
+  });
+  match tree.find("model") {
+    Some(model) => ModelConfig::from_value(model),
+    None        => ModelConfig::from_value(&tree),
+  }
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(contents: &str) -> Result<json::Json, HALError> {
+  config::parse_toml(contents)
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_contents: &str) -> Result<json::Json, HALError> {
+  warn!("reading toml configs needs the toml feature");
+  Err(HALError::CONFIG)
+}
@@ -0,0 +1,269 @@
+use af;
+use af::{Array, Dim4, HasAfEnum};
+use num::Zero;
+use std::cmp::{min, Ordering};
+
+use utils;
+use random;
+use data::{ArraySource, DataSource};
+use device::Device;
+use model::{Model, Sequential};
+
+/// Self-training by pseudo-labeling [Lee, 2013]
+///
+/// Every round trains the model on the labeled data & the pseudo-labels of
+/// the previous round, then labels the unlabeled samples whose predicted class
+/// probability reaches the threshold with their most probable class.
+/// The pseudo-labels are recomputed from scratch every round, so samples that
+/// lose their confidence are dropped again. The targets are one-hot rows of
+/// the last time-step [non-recurrent models].
+///
+/// # Parameters
+///
+/// - `threshold` is the minimum probability of a pseudo-label
+/// - `max_per_class` is the optional number of most confident pseudo-labels kept per class
+/// - `balanced` keeps the same number of pseudo-labels for every class [the count of the
+///   class with the fewest confident samples], which avoids drifting towards the dominant classes
+/// - `epochs_per_round` is the number of supervised epochs between two labelings
+/// - `num_pseudo_labels` are the number of pseudo-labels of every round
+pub struct PseudoLabeling {
+  pub threshold: f32,
+  pub max_per_class: Option<usize>,
+  pub balanced: bool,
+  pub epochs_per_round: u64,
+  pub num_pseudo_labels: Vec<u64>,
+}
+
+impl PseudoLabeling {
+  pub fn new(threshold: f32, epochs_per_round: u64) -> PseudoLabeling {
+    assert!(threshold > 0.0 && threshold <= 1.0, "the threshold needs to be in (0, 1]");
+    assert!(epochs_per_round > 0, "need to train at least one epoch per round");
+    PseudoLabeling {
+      threshold: threshold,
+      max_per_class: None,
+      balanced: false,
+      epochs_per_round: epochs_per_round,
+      num_pseudo_labels: Vec::new(),
+    }
+  }
+
+  /// Pseudo-labels the training range of the unlabeled source [its targets are ignored]
+  ///
+  /// # Return Values
+  ///
+  /// The selected sample indices [increasing] & their one-hot labels [num_selected, num_classes]
+  /// on `src_device` [None when no sample is confident enough]
+  pub fn label<T>(&self, model: &mut Sequential, unlabeled: &ArraySource, src_device: Device
+                  , batch_size: u64) -> (Vec<u32>, Option<Array>)
+    where T: HasAfEnum + Zero + Clone
+  {
+    assert!(batch_size > 0, "need a positive batch size");
+    let device = model.get_device();
+    let num_unlabeled = unlabeled.params.num_train;
+    let mut num_classes = 0;
+
+    // (index, class, probability) of every sample above the threshold
+    let mut candidates = Vec::new();
+    let mut first = 0;
+    while first < num_unlabeled {
+      let last = min(first + batch_size, num_unlabeled) - 1;
+      model.get_manager().swap_device(src_device);
+      let input = af::rows(&unlabeled.input, first, last);
+      let p = model.predict_proba::<T>(&input, src_device, device).pop().unwrap();
+      let (num_rows, num_cols) = (p.dims()[0] as usize, p.dims()[1] as usize);
+      num_classes = num_cols;
+      let values = utils::array_to_vec(&p);
+      for row in 0..num_rows {
+        let (class, prob) = (0..num_cols).map(|c| (c, values[c * num_rows + row] as f32))
+          .fold((0, ::std::f32::MIN), |best, cur| if cur.1 > best.1 { cur } else { best });
+        if prob >= self.threshold {
+          candidates.push((first as u32 + row as u32, class, prob));
+        }
+      }
+      first = last + 1;
+    }
+
+    // keep the most confident samples of every class
+    let mut per_class: Vec<Vec<(u32, f32)>> = vec![Vec::new(); num_classes];
+    for &(index, class, prob) in &candidates {
+      per_class[class].push((index, prob));
+    }
+    let mut cap = self.max_per_class.unwrap_or(num_unlabeled as usize);
+    if self.balanced {
+      cap = min(cap, per_class.iter().map(|c| c.len()).min().unwrap_or(0));
+    }
+    let mut selected: Vec<(u32, usize)> = Vec::new();
+    for (class, samples) in per_class.iter_mut().enumerate() {
+      samples.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+      selected.extend(samples.iter().take(cap).map(|&(index, _)| (index, class)));
+    }
+    selected.sort();
+
+    if selected.len() == 0 {
+      return (Vec::new(), None);
+    }
+    let num_selected = selected.len();
+    let mut one_hot = vec![0.0f32; num_selected * num_classes];
+    for (row, &(_, class)) in selected.iter().enumerate() {
+      one_hot[class * num_selected + row] = 1.0;
+    }
+    model.get_manager().swap_device(src_device);
+    let labels = utils::vec_to_array::<f32>(one_hot, Dim4::new(&[num_selected as u64, num_classes as u64, 1, 1]));
+    (selected.iter().map(|&(index, _)| index).collect(), Some(labels))
+  }
+
+  /// Alternates between supervised epochs & pseudo-labeling for `num_rounds` rounds
+  ///
+  /// The test & validation samples of the labeled source are kept as they are,
+  /// the pseudo-labeled samples are appended to its training samples.
+  ///
+  /// # Return Values
+  ///
+  /// Vector of losses of all the epochs
+  pub fn fit<E>(&mut self, model: &mut Sequential, labeled: &ArraySource, unlabeled: &ArraySource
+                , src_device: Device, num_rounds: u64, batch_size: u64, verbose: bool) -> Vec<f32>
+    where E: HasAfEnum + Zero + Clone
+  {
+    let mut losses = Vec::new();
+    let mut pseudo: Option<(Vec<u32>, Array)> = None;
+    for _ in 0..num_rounds {
+      losses.extend(match pseudo {
+        Some((ref indices, ref labels)) => {
+          let merged = merge(labeled, unlabeled, indices, labels, batch_size);
+          model.fit::<ArraySource, E>(&merged, src_device, self.epochs_per_round, batch_size, None, None, verbose)
+        },
+        None => model.fit::<ArraySource, E>(labeled, src_device, self.epochs_per_round, batch_size, None, None, verbose),
+      });
+
+      let (indices, labels) = self.label::<E>(model, unlabeled, src_device, batch_size);
+      self.num_pseudo_labels.push(indices.len() as u64);
+      pseudo = labels.map(|labels| (indices, labels));
+    }
+    losses
+  }
+}
+
+/// Appends the pseudo-labeled samples to the training samples of the labeled source
+fn merge(labeled: &ArraySource, unlabeled: &ArraySource, indices: &Vec<u32>, labels: &Array
+         , batch_size: u64) -> ArraySource
+{
+  let num_train = labeled.params.num_train;
+  let num_held_out = labeled.params.num_test + labeled.params.num_validation.unwrap_or(0);
+  let pseudo_input = *unlabeled.get_rows(indices).input.into_inner();
+  let pseudo_target = utils::cast(labels, labeled.target.get_type());
+
+  let mut input = af::join(0, &af::rows(&labeled.input, 0, num_train - 1)
+                           , &utils::cast(&pseudo_input, labeled.input.get_type()));
+  let mut target = af::join(0, &af::rows(&labeled.target, 0, num_train - 1), &pseudo_target);
+  if num_held_out > 0 {
+    let last = num_train + num_held_out - 1;
+    input = af::join(0, &input, &af::rows(&labeled.input, num_train, last));
+    target = af::join(0, &target, &af::rows(&labeled.target, num_train, last));
+  }
+  ArraySource::with_splits(input, target, batch_size, labeled.params.num_test
+                           , labeled.params.num_validation.unwrap_or(0), labeled.params.shuffle)
+}
+
+/// An augmentation of a [batch, feature] array [see `FixMatch`]
+pub type Augmentation = Box<Fn(&Array) -> Array + Send>;
+
+/// Builds an augmentation that adds gaussian noise of the provided standard deviation
+///
+/// eg: a weak augmentation for tabular features [see `FixMatch`]
+pub fn gaussian_noise(std: f32) -> Augmentation {
+  Box::new(move |input: &Array| {
+    random::seed_arrayfire();
+    let noise = af::mul(&af::randn::<f32>(input.dims()), &std, false);
+    af::add(input, &utils::cast(&noise, input.get_type()), false)
+  })
+}
+
+/// Builds an augmentation that zeroes every feature with probability `rate` & adds gaussian noise
+///
+/// eg: a strong augmentation for tabular features [see `FixMatch`]
+pub fn feature_dropout(rate: f32, std: f32) -> Augmentation {
+  assert!(rate >= 0.0 && rate < 1.0, "the dropout rate needs to be in [0, 1)");
+  let noise = gaussian_noise(std);
+  Box::new(move |input: &Array| {
+    random::seed_arrayfire();
+    let kept = utils::cast(&af::ge(&af::randu::<f32>(input.dims()), &rate, false), input.get_type());
+    noise(&af::mul(input, &kept, false))
+  })
+}
+
+/// Consistency regularization with confidence masked pseudo-labels [FixMatch, Sohn et al, 2020]
+///
+/// Every training step of `fit` also draws `ratio * batch_size` unlabeled samples:
+/// the predictions for their weakly augmented view are the pseudo-labels
+/// [their most probable class], which the strongly augmented view is trained
+/// towards wherever the pseudo-label probability reaches the threshold.
+/// The objective is: supervised loss + weight * consistency loss
+/// [both are means over their batch, see `Sequential::set_consistency`].
+/// The unlabeled data needs to be non-recurrent [time dimension of one].
+///
+/// # Parameters
+///
+/// - `unlabeled` is the unlabeled data [its targets are ignored]
+/// - `weak` & `strong` are the augmentations [see `gaussian_noise` & `feature_dropout`]
+/// - `threshold` is the minimum probability of a pseudo-label
+/// - `weight` is the weight of the consistency loss [lambda]
+/// - `ratio` is the number of unlabeled samples per labeled sample [mu]
+pub struct FixMatch {
+  pub unlabeled: Box<DataSource + Send>,
+  pub weak: Augmentation,
+  pub strong: Augmentation,
+  pub threshold: f32,
+  pub weight: f32,
+  pub ratio: u64,
+}
+
+impl FixMatch {
+  pub fn new(unlabeled: Box<DataSource + Send>, weak: Augmentation
+             , strong: Augmentation) -> FixMatch {
+    FixMatch {
+      unlabeled: unlabeled,
+      weak: weak,
+      strong: strong,
+      threshold: 0.95,
+      weight: 1.0,
+      ratio: 7,
+    }
+  }
+
+  /// Accumulates the gradients of the consistency loss of one unlabeled batch
+  ///
+  /// The gradients are applied by the following optimizer step, whose division
+  /// by the labeled batch size is accounted for in the weight of the loss.
+  ///
+  /// # Return Values
+  ///
+  /// The fraction of unlabeled samples whose pseudo-label was confident enough
+  pub fn backward<E>(&self, model: &mut Sequential, src_device: Device, batch_size: u64) -> f32
+    where E: HasAfEnum + Zero + Clone
+  {
+    let (manager, device) = (model.get_manager(), model.get_device());
+    let num_unlabeled = self.ratio * batch_size;
+    manager.swap_device(src_device);
+    let input = self.unlabeled.get_train_iter(num_unlabeled).input.into_inner();
+    assert!(input.dims()[2] == 1, "consistency regularization needs non-recurrent data");
+    let input = manager.swap_array_backend::<E>(&input, src_device, device);
+
+    // pseudo-labels of the weak view
+    let p = model.predict_proba::<E>(&(self.weak)(&input), device, device).pop().unwrap();
+    let (confidence, classes) = af::imax(&p, 1);
+    let confident: Vec<u32> = utils::array_to_vec(&confidence).iter().enumerate()
+      .filter(|&(_, &c)| c as f32 >= self.threshold).map(|(i, _)| i as u32).collect();
+    if confident.len() == 0 {
+      return 0.0;
+    }
+
+    let num_confident = confident.len() as u64;
+    let idx = utils::vec_to_array::<u32>(confident, Dim4::new(&[num_confident, 1, 1, 1]));
+    // a single class per sample, even when several classes tie
+    let targets = af::lookup(&utils::cast(&utils::one_hot(&classes, p.dims()[1]), p.get_type()), &idx, 0);
+    let strong = af::lookup(&(self.strong)(&input), &idx, 0);
+    let predictions = model.forward::<E>(&strong, device, device);
+    model.backward_inputs_weighted(&predictions, &targets, None, self.weight / self.ratio as f32);
+    num_confident as f32 / num_unlabeled as f32
+  }
+}
@@ -0,0 +1,127 @@
+use af;
+use af::{Array, Dim4};
+use std::sync::{Arc, Mutex};
+
+use utils;
+use random;
+use layer::Layer;
+use params::Params;
+
+/// Makes a test reproducible: reseeds the generator of the current thread
+/// and the ArrayFire generators [see `random::set_seed`]
+pub fn deterministic(seed: u64) {
+  random::set_seed(seed);
+  random::seed_arrayfire();
+}
+
+/// Builds a [rows, cols] f32 array from row major literals
+///
+/// eg: `testing::from_rows(&[[1.0, 2.0], [3.0, 4.0]])`
+pub fn from_rows<R: AsRef<[f32]>>(rows: &[R]) -> Array {
+  assert!(rows.len() > 0, "need at least one row");
+  let num_cols = rows[0].as_ref().len();
+  assert!(rows.iter().all(|r| r.as_ref().len() == num_cols), "rows need to be of the same length");
+
+  // arrayfire is column major
+  let values: Vec<f32> = (0..num_cols)
+    .flat_map(|c| rows.iter().map(move |r| r.as_ref()[c]))
+    .collect();
+  utils::vec_to_array::<f32>(values, Dim4::new(&[rows.len() as u64, num_cols as u64, 1, 1]))
+}
+
+/// Returns the host rows of a [rows, cols] array [the inverse of `from_rows`]
+pub fn to_rows(input: &Array) -> Vec<Vec<f64>> {
+  let num_rows = input.dims()[0] as usize;
+  let values = utils::array_to_vec(input);
+  let num_cols = values.len() / num_rows;
+  (0..num_rows).map(|r| (0..num_cols).map(|c| values[c * num_rows + r]).collect()).collect()
+}
+
+/// Largest elementwise |a - b|
+pub fn max_abs_diff(a: &Array, b: &Array) -> f64 {
+  assert!(a.dims() == b.dims(), "dims {} vs {}", a.dims(), b.dims());
+  af::max_all(&af::abs(&af::sub(&utils::cast(a, af::DType::F64)
+                                , &utils::cast(b, af::DType::F64), false))).0
+}
+
+/// Returns the first element that violates |actual - expected| <= atol + rtol * |expected|
+///
+/// # Return Values
+///
+/// (column major index, actual, expected) or None when all elements are close
+pub fn first_mismatch(actual: &Array, expected: &Array, atol: f64, rtol: f64) -> Option<(usize, f64, f64)> {
+  assert!(actual.dims() == expected.dims(), "dims {} vs {}", actual.dims(), expected.dims());
+  let (actual, expected) = (utils::array_to_vec(actual), utils::array_to_vec(expected));
+  actual.iter().zip(expected.iter()).enumerate()
+    .find(|&(_, (a, e))| !((a - e).abs() <= atol + rtol * e.abs()))
+    .map(|(i, (&a, &e))| (i, a, e))
+}
+
+/// Elementwise comparison with an absolute & a relative tolerance [see `first_mismatch`]
+pub fn all_close(actual: &Array, expected: &Array, atol: f64, rtol: f64) -> bool {
+  first_mismatch(actual, expected, atol, rtol).is_none()
+}
+
+/// Panics with the first mismatching element when the arrays are not close [see `all_close`]
+pub fn assert_close(actual: &Array, expected: &Array, atol: f64, rtol: f64) {
+  if let Some((index, a, e)) = first_mismatch(actual, expected, atol, rtol) {
+    panic!("arrays differ at index {} [of {}]: {} vs expected {} (atol = {}, rtol = {})"
+           , index, actual.dims(), a, e, atol, rtol);
+  }
+}
+
+/// Runs the forward pass of a layer and compares it to a host reference
+///
+/// # Parameters
+///
+/// - `layer` is the layer under test
+/// - `params` are the params of the layer [see `ParamManager::get_params`]
+/// - `inputs` is a [batch_size, input_size] array
+/// - `reference` maps the host input rows to the expected output rows
+/// - `atol` is the absolute tolerance
+///
+/// # Return Values
+///
+/// The outputs of the layer
+pub fn check_forward<F>(layer: &Layer, params: Arc<Mutex<Params>>, inputs: &Array
+                        , reference: F, atol: f64) -> Array
+  where F: Fn(&Vec<Vec<f64>>) -> Vec<Vec<f64>>
+{
+  params.lock().unwrap().current_unroll = 0;
+  let (outputs, _) = layer.forward(params, inputs, None);
+  let expected = rows_to_f32(&reference(&to_rows(inputs)));
+  assert_close(&outputs, &expected, atol, 0.0);
+  outputs
+}
+
+/// Runs the forward & backward pass of a layer and compares the input
+/// derivatives to a host reference
+///
+/// # Parameters
+///
+/// - `layer` is the layer under test
+/// - `params` are the params of the layer [see `ParamManager::get_params`]
+/// - `inputs` is a [batch_size, input_size] array
+/// - `delta` is the [batch_size, output_size] derivative w.r.t. the outputs
+/// - `reference` maps the host (input rows, delta rows) to the expected input delta rows
+/// - `atol` is the absolute tolerance
+///
+/// # Return Values
+///
+/// The input derivatives of the layer
+pub fn check_backward<F>(layer: &Layer, params: Arc<Mutex<Params>>, inputs: &Array, delta: &Array
+                         , reference: F, atol: f64) -> Array
+  where F: Fn(&Vec<Vec<f64>>, &Vec<Vec<f64>>) -> Vec<Vec<f64>>
+{
+  params.lock().unwrap().current_unroll = 0;
+  layer.forward(params.clone(), inputs, None);
+  let input_delta = layer.backward(params, delta);
+  let expected = rows_to_f32(&reference(&to_rows(inputs), &to_rows(delta)));
+  assert_close(&input_delta, &expected, atol, 0.0);
+  input_delta
+}
+
+fn rows_to_f32(rows: &Vec<Vec<f64>>) -> Array {
+  let rows: Vec<Vec<f32>> = rows.iter().map(|r| r.iter().map(|&x| x as f32).collect()).collect();
+  from_rows(&rows)
+}
@@ -0,0 +1,153 @@
+use af::{Array, Dim4, Backend, HasAfEnum};
+use num::Zero;
+use std::cmp::max;
+use std::marker::PhantomData;
+use std::mem;
+use std::slice;
+
+use device::{Device, DeviceManagerFactory};
+
+/// Creates an array of the provided shape from a (column major) host slice
+///
+/// # Parameters
+///
+/// - `values` are the elements of the array [any contiguous host storage, eg: a slice of a `Vec`]
+/// - `dims` is the shape of the array
+pub fn upload<T>(values: &[T], dims: Dim4) -> Array
+  where T: HasAfEnum
+{
+  assert!(values.len() as u64 == dims.elements()
+          , "{} values can not be shaped as {}", values.len(), dims);
+  Array::new::<T>(values, dims)
+}
+
+/// Copies the (column major) elements of the array into a preallocated host buffer
+pub fn download_into<T>(input: &Array, buffer: &mut [T])
+  where T: HasAfEnum
+{
+  assert!(buffer.len() as u64 == input.dims().elements()
+          , "a buffer of {} values can not hold an array of {}", buffer.len(), input.dims());
+  input.host(buffer);
+}
+
+/// Reusable host staging memory for device to device transfers
+///
+/// The buffer only grows, so repeated transfers of batches of the same size
+/// do not allocate [see `swap_arrays`]. The memory can be handed over to
+/// the transfers of another element type with `retype`.
+pub struct HostBuffer<T> {
+  words: Vec<u64>,
+  element: PhantomData<T>,
+}
+
+impl<T> HostBuffer<T>
+  where T: HasAfEnum + Zero + Clone
+{
+  pub fn new() -> HostBuffer<T> {
+    HostBuffer { words: Vec::new(), element: PhantomData }
+  }
+
+  pub fn with_capacity(num_elements: usize) -> HostBuffer<T> {
+    let mut buffer = HostBuffer::new();
+    buffer.reserve(num_elements);
+    buffer
+  }
+
+  /// Returns the number of elements that fit without reallocating
+  pub fn capacity(&self) -> usize {
+    self.words.len() * mem::size_of::<u64>() / max(mem::size_of::<T>(), 1)
+  }
+
+  /// Returns the same memory as a buffer of another element type
+  pub fn retype<U>(self) -> HostBuffer<U>
+    where U: HasAfEnum + Zero + Clone
+  {
+    HostBuffer { words: self.words, element: PhantomData }
+  }
+
+  /// Returns the zeroed staging memory, grown to at least `num_elements`
+  fn reserve(&mut self, num_elements: usize) -> &mut [T] {
+    assert!(mem::align_of::<T>() <= mem::align_of::<u64>()
+            , "unsupported element alignment {}", mem::align_of::<T>());
+    let num_words = (num_elements * mem::size_of::<T>() + 7) / 8;
+    if self.words.len() < num_words {
+      self.words.resize(num_words, 0);
+    }
+    for word in self.words[..num_words].iter_mut() {
+      *word = 0;
+    }
+    // the elements of arrays are plain numbers [or bools] that are valid when all
+    // their bytes are zero & that are at most as aligned as the u64 words
+    unsafe { slice::from_raw_parts_mut(self.words.as_mut_ptr() as *mut T, num_elements) }
+  }
+
+  /// Returns the first `num_elements` of the staging memory [see `reserve`]
+  fn staged(&self, num_elements: usize) -> &[T] {
+    assert!(num_elements <= self.capacity(), "only {} elements are staged", self.capacity());
+    unsafe { slice::from_raw_parts(self.words.as_ptr() as *const T, num_elements) }
+  }
+}
+
+/// Moves several arrays from one device to another with a single device swap each way
+///
+/// All the arrays are staged back to back in the provided host buffer: one
+/// swap to the source device downloads all of them, one swap to the target
+/// device uploads all of them. Arrays are returned as is [ref counted] when the
+/// devices match. The manager is left on the target device.
+///
+/// # Parameters
+///
+/// - `manager` is the device manager
+/// - `buffer` is the host staging memory
+/// - `inputs` are the arrays to move [of type T]
+/// - `input_device` is the device of the inputs
+/// - `target_device` is the device of the returned arrays
+pub fn swap_arrays<T>(manager: &DeviceManagerFactory, buffer: &mut HostBuffer<T>
+                      , inputs: &[&Array], input_device: Device
+                      , target_device: Device) -> Vec<Array>
+  where T: HasAfEnum + Zero + Clone
+{
+  // return if the devices match
+  if input_device.id == target_device.id
+      && input_device.backend == target_device.backend
+  {
+    return inputs.iter().map(|input| (*input).clone()).collect(); // increases the ref counts
+  }
+
+  // we have done something bad if the following triggers
+  if input_device.backend != Backend::DEFAULT {
+    for input in inputs {
+      let ib = input.get_backend();
+      assert!(ib == input_device.backend
+              , "provide src was {:?}, but actually {:?}"
+              , input_device.backend
+              , ib);
+    }
+  }
+
+  // ensure we are on the old device & copy everything to the host
+  manager.swap_device(input_device);
+  let dims: Vec<Dim4> = inputs.iter().map(|input| input.dims()).collect();
+  let sizes: Vec<usize> = dims.iter().map(|d| d.elements() as usize).collect();
+  let total = sizes.iter().fold(0, |acc, s| acc + s);
+  debug!("copying {} arrays [{} elements] from {}/{} to {}/{}", inputs.len(), total
+         , input_device.backend, input_device.id, target_device.backend, target_device.id);
+  {
+    let staging = buffer.reserve(total);
+    let mut offset = 0;
+    for (input, &size) in inputs.iter().zip(sizes.iter()) {
+      download_into(input, &mut staging[offset..offset + size]);
+      offset += size;
+    }
+  }
+
+  // swap to the new device & upload from the staged slices
+  manager.swap_device(target_device);
+  let staging = buffer.staged(total);
+  let mut offset = 0;
+  dims.iter().zip(sizes.iter()).map(|(&d, &size)| {
+    let array = upload(&staging[offset..offset + size], d);
+    offset += size;
+    array
+  }).collect()
+}
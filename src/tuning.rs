@@ -0,0 +1,399 @@
+use af::HasAfEnum;
+use num::Zero;
+use rand::Rng;
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::thread;
+
+use checkpoint;
+use metrics;
+use config;
+use config::{ModelConfig, Registry};
+use data::DataSource;
+use device::{Device, DeviceManager};
+use error::HALError;
+use model::{Model, Sequential};
+
+/// The values that a single hyperparameter can take
+#[derive(Clone, Debug)]
+pub enum ParamRange {
+  /// Explicit list of values
+  Values(Vec<String>),
+  /// Uniformly sampled in [low, high)
+  Uniform(f64, f64),
+  /// Uniformly sampled in log space [eg: learning rates]
+  LogUniform(f64, f64),
+  /// Integer in [low, high] [every value in a grid]
+  Integer(i64, i64),
+}
+
+/// One value per hyperparameter
+pub type Assignment = BTreeMap<String, String>;
+
+/// The hyperparameters to search over
+///
+/// Names address the fields of a `ModelConfig`:
+///
+/// - `optimizer.<param>` is an optimizer param [eg: `optimizer.learning_rate`]
+/// - `layers.<index>.<param>` is a param of a layer [eg: `layers.1.rate` of a dropout layer]
+///
+/// Setting the `output_size` of a layer also sets the `input_size` of the
+/// next one [& the sizes of the dropout layers in between], so hidden sizes
+/// can be searched directly [eg: `layers.0.output_size`].
+#[derive(Clone, Debug)]
+pub struct SearchSpace {
+  pub ranges: Vec<(String, ParamRange)>,
+}
+
+impl SearchSpace {
+  pub fn new() -> SearchSpace {
+    SearchSpace { ranges: Vec::new() }
+  }
+
+  pub fn add(&mut self, name: &str, range: ParamRange) {
+    self.ranges.push((name.to_string(), range));
+  }
+
+  /// Returns every combination of the values of the ranges
+  ///
+  /// Only `Values` & `Integer` ranges can be enumerated
+  pub fn grid(&self) -> Result<Vec<Assignment>, HALError> {
+    let mut assignments = vec![Assignment::new()];
+    for &(ref name, ref range) in self.ranges.iter() {
+      let values = match *range {
+        ParamRange::Values(ref values)   => values.clone(),
+        ParamRange::Integer(low, high)   => (low..high + 1).map(|v| v.to_string()).collect(),
+        _                                => return Err(HALError::CONFIG),
+      };
+      assignments = assignments.iter().flat_map(|assignment| {
+        values.iter().map(move |value| {
+          let mut extended = assignment.clone();
+          extended.insert(name.clone(), value.clone());
+          extended
+        })
+      }).collect();
+    }
+    Ok(assignments)
+  }
+
+  /// Returns `num_trials` randomly sampled assignments
+  pub fn sample<R: Rng>(&self, num_trials: usize, rng: &mut R) -> Vec<Assignment> {
+    (0..num_trials).map(|_| {
+      self.ranges.iter().map(|&(ref name, ref range)| {
+        let value = match *range {
+          ParamRange::Values(ref values)    => values[rng.gen_range(0, values.len())].clone(),
+          ParamRange::Uniform(low, high)    => rng.gen_range(low, high).to_string(),
+          ParamRange::LogUniform(low, high) => rng.gen_range(low.ln(), high.ln()).exp().to_string(),
+          ParamRange::Integer(low, high)    => rng.gen_range(low, high + 1).to_string(),
+        };
+        (name.clone(), value)
+      }).collect()
+    }).collect()
+  }
+}
+
+/// Returns a copy of the config with the hyperparameters set [see `SearchSpace`]
+///
+/// Params that the layer or the optimizer does not accept are refused with
+/// HALError::CONFIG [see `Registry::layer_accepts`].
+pub fn apply(config: &ModelConfig, assignment: &Assignment) -> Result<ModelConfig, HALError> {
+  apply_with(&Registry::default(), config, assignment)
+}
+
+/// Same as `apply` with the accepted params of the provided registry
+pub fn apply_with(registry: &Registry, config: &ModelConfig, assignment: &Assignment)
+                  -> Result<ModelConfig, HALError>
+{
+  let mut config = config.clone();
+  for (name, value) in assignment.iter() {
+    let parts: Vec<&str> = name.split('.').collect();
+    match (parts.get(0).cloned(), parts.len()) {
+      (Some("optimizer"), 2) => {
+        if !registry.optimizer_accepts(&config.optimizer, parts[1]) {
+          warn!("the {} optimizer has no {} param", config.optimizer, parts[1]);
+          return Err(HALError::CONFIG);
+        }
+        if config.optimizer_params.is_none() {
+          config.optimizer_params = Some(BTreeMap::new());
+        }
+        config.optimizer_params.as_mut().unwrap().insert(parts[1].to_string(), value.clone());
+      },
+      (Some("layers"), 3)    => {
+        let index = try!(parts[1].parse::<usize>().map_err(|_| HALError::CONFIG));
+        if index >= config.layers.len() {
+          return Err(HALError::CONFIG);
+        }
+        if !registry.layer_accepts(&config.layers[index].layer, parts[2]) {
+          warn!("the {} layer {} has no {} param", config.layers[index].layer, index, parts[2]);
+          return Err(HALError::CONFIG);
+        }
+        config.layers[index].params.insert(parts[2].to_string(), value.clone());
+        if parts[2] == "output_size" {
+          config::propagate_output_size(config.layers[index + 1..].iter_mut()
+                                        .map(|l| (l.layer.as_str(), &mut l.params)), value);
+        }
+      },
+      _                      => return Err(HALError::CONFIG),
+    }
+  }
+  Ok(config)
+}
+
+/// The outcome of a single training run
+///
+/// # Parameters
+///
+/// - `assignment` are the hyperparameters of the run
+/// - `values` are the validation values [see `Model::evaluate`]
+/// - `history` is the training history [see `Model::get_history`]
+/// - `epochs` is the number of epochs that the run was trained for
+#[derive(Clone, Debug)]
+pub struct TrialResult {
+  pub assignment: Assignment,
+  pub epochs: u64,
+  pub values: HashMap<String, f32>,
+  pub history: HashMap<String, Vec<f32>>,
+}
+
+/// Trains & evaluates one model per assignment and ranks the results
+///
+/// The runs are assigned to the provided devices in a round robin fashion.
+///
+/// # Parameters
+///
+/// - `base` is the config that the hyperparameters are applied to
+/// - `assignments` are the hyperparameters of every run [see `SearchSpace::grid` & `SearchSpace::sample`]
+/// - `manager` is the device manager
+/// - `devices` are the devices to train on
+/// - `source` is the datasource [trained on the training & evaluated on the validation data]
+/// - `src_device` is the source device of the data
+/// - `epochs` is the number of epochs of every run
+/// - `batch_size` is the minibatch size
+/// - `metric_names` are the metrics to evaluate [see `metrics::get_metric`]
+/// - `rank_by` is the value to rank by [eg: "loss" or a metric name]
+/// - `higher_is_better` sorts in descending order of `rank_by` when set
+///
+/// # Return Values
+///
+/// Vector of the trial results, best first
+pub fn search<T, E>(base: &ModelConfig, assignments: &Vec<Assignment>
+                    , manager: DeviceManager, devices: &Vec<Device>
+                    , source: &T, src_device: Device, epochs: u64, batch_size: u64
+                    , metric_names: &Vec<String>, rank_by: &str, higher_is_better: bool)
+                    -> Result<Vec<TrialResult>, HALError>
+  where T: DataSource, E: HasAfEnum + Zero + Clone
+{
+  assert!(devices.len() > 0, "need at least one device");
+  let mut results = Vec::with_capacity(assignments.len());
+  for (i, assignment) in assignments.iter().enumerate() {
+    let device = devices[i % devices.len()];
+    manager.swap_device(device);
+    let mut model = try!(try!(apply(base, assignment)).build(manager.clone(), device));
+    for name in metric_names.iter() {
+      model.add_metric(try!(metrics::get_metric(name)));
+    }
+
+    model.fit::<T, E>(source, src_device, epochs, batch_size, None, None, false);
+    results.push(TrialResult {
+      assignment: assignment.clone(),
+      epochs: epochs,
+      values: model.evaluate::<T, E>(source, src_device, batch_size),
+      history: model.get_history().clone(),
+    });
+  }
+
+  results.sort_by(|a, b| checkpoint::rank_order(a.values.get(rank_by), b.values.get(rank_by)
+                                                , higher_is_better));
+  Ok(results)
+}
+
+/// A run that is being trained on its own device
+struct Trial {
+  index: usize,
+  model: Sequential,
+  values: HashMap<String, f32>,
+}
+
+/// Builds one model per assignment, trial `i` lives on `devices[i % devices.len()]`
+fn build_trials(base: &ModelConfig, assignments: &Vec<Assignment>
+                , manager: &DeviceManager, devices: &Vec<Device>
+                , metric_names: &Vec<String>) -> Result<Vec<Trial>, HALError>
+{
+  assert!(devices.len() > 0, "need at least one device");
+  let mut trials = Vec::with_capacity(assignments.len());
+  for (i, assignment) in assignments.iter().enumerate() {
+    let device = devices[i % devices.len()];
+    manager.swap_device(device);
+    let mut model = try!(try!(apply(base, assignment)).build(manager.clone(), device));
+    for name in metric_names.iter() {
+      model.add_metric(try!(metrics::get_metric(name)));
+    }
+    trials.push(Trial { index: i, model: model, values: HashMap::new() });
+  }
+  Ok(trials)
+}
+
+/// Trains & evaluates the trials with one thread per device
+///
+/// The trials of a device are run one after the other, so every device
+/// only holds a single run in training at any time. Every thread selects
+/// its device & reads from its own source [sources are not shared between threads].
+fn train_parallel<T, E, F>(trials: Vec<Trial>, devices: &Vec<Device>, make_source: &Arc<F>
+                           , src_device: Device, epochs: u64, batch_size: u64) -> Vec<Trial>
+  where T: DataSource + 'static, E: HasAfEnum + Zero + Clone + 'static
+  , F: Fn() -> T + Send + Sync + 'static
+{
+  let mut queues: Vec<Vec<Trial>> = devices.iter().map(|_| Vec::new()).collect();
+  for trial in trials {
+    let device = devices.iter().position(|&d| d == trial.model.get_device()).unwrap();
+    queues[device].push(trial);
+  }
+
+  let handles: Vec<_> = queues.into_iter().filter(|queue| queue.len() > 0).map(|queue| {
+    let make_source = make_source.clone();
+    thread::spawn(move || {
+      let device = queue[0].model.get_device();
+      queue[0].model.get_manager().swap_device(device);
+      let source = make_source();
+      queue.into_iter().map(|mut trial| {
+        trial.model.fit::<T, E>(&source, src_device, epochs, batch_size, None, None, false);
+        trial.values = trial.model.evaluate::<T, E>(&source, src_device, batch_size);
+        trial
+      }).collect::<Vec<Trial>>()
+    })
+  }).collect();
+
+  let mut trained: Vec<Trial> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+  trained.sort_by_key(|trial| trial.index);
+  trained
+}
+
+fn to_result(trial: &Trial, assignments: &Vec<Assignment>, epochs: u64) -> TrialResult {
+  TrialResult {
+    assignment: assignments[trial.index].clone(),
+    epochs: epochs,
+    values: trial.values.clone(),
+    history: trial.model.get_history().clone(),
+  }
+}
+
+/// Same as `search`, but the trials of the different devices are trained concurrently
+///
+/// Trial `i` is trained on `devices[i % devices.len()]`, every device runs
+/// on its own thread and trains its trials one after the other.
+/// `make_source` builds the source of every thread [on the thread], eg:
+/// `Arc::new(move || ArraySource::new(input.clone(), target.clone(), 32, 0.2, 0.0, true))`
+pub fn search_parallel<T, E, F>(base: &ModelConfig, assignments: &Vec<Assignment>
+                                , manager: DeviceManager, devices: &Vec<Device>
+                                , make_source: Arc<F>, src_device: Device, epochs: u64, batch_size: u64
+                                , metric_names: &Vec<String>, rank_by: &str, higher_is_better: bool)
+                                -> Result<Vec<TrialResult>, HALError>
+  where T: DataSource + 'static, E: HasAfEnum + Zero + Clone + 'static
+  , F: Fn() -> T + Send + Sync + 'static
+{
+  let trials = try!(build_trials(base, assignments, &manager, devices, metric_names));
+  let trials = train_parallel::<T, E, F>(trials, devices, &make_source, src_device, epochs, batch_size);
+  let mut results: Vec<TrialResult> = trials.iter()
+    .map(|trial| to_result(trial, assignments, epochs)).collect();
+  results.sort_by(|a, b| checkpoint::rank_order(a.values.get(rank_by), b.values.get(rank_by)
+                                                , higher_is_better));
+  Ok(results)
+}
+
+/// Returns the (number of trials, total epochs) of every round of successive halving
+///
+/// Every round keeps the best 1 / `reduction_factor` of the trials and
+/// multiplies their training budget by `reduction_factor`, until a single trial is left.
+///
+/// eg: 9 trials, 1 epoch & a factor of 3 --> [(9, 1), (3, 3), (1, 9)]
+pub fn halving_schedule(num_trials: usize, min_epochs: u64, reduction_factor: usize) -> Vec<(usize, u64)> {
+  assert!(reduction_factor > 1, "the reduction factor needs to be larger than 1");
+  let mut schedule = Vec::new();
+  let (mut trials, mut epochs) = (num_trials, min_epochs);
+  while trials > 1 {
+    schedule.push((trials, epochs));
+    trials = max(trials / reduction_factor, 1);
+    epochs *= reduction_factor as u64;
+  }
+  schedule.push((trials, epochs));
+  schedule
+}
+
+/// Successive halving [Jamieson & Talwalkar, 2016] over the provided assignments
+///
+/// All trials are trained concurrently [see `search_parallel`] for `min_epochs`,
+/// then the bad trials are terminated and the survivors continue training
+/// [see `halving_schedule`]. The survivors keep their device, so later rounds
+/// might not use all of the devices.
+///
+/// # Parameters
+///
+/// - `min_epochs` is the number of epochs of the first round
+/// - `reduction_factor` is the fraction [1 / reduction_factor] of trials that survives a round
+/// - the remaining parameters are the same as for `search`
+///
+/// # Return Values
+///
+/// Vector of the trial results, best first: trials that were terminated later
+/// rank before trials that were terminated earlier.
+pub fn successive_halving<T, E, F>(base: &ModelConfig, assignments: &Vec<Assignment>
+                                   , manager: DeviceManager, devices: &Vec<Device>
+                                   , make_source: Arc<F>, src_device: Device, min_epochs: u64
+                                   , reduction_factor: usize, batch_size: u64
+                                   , metric_names: &Vec<String>, rank_by: &str, higher_is_better: bool)
+                                   -> Result<Vec<TrialResult>, HALError>
+  where T: DataSource + 'static, E: HasAfEnum + Zero + Clone + 'static
+  , F: Fn() -> T + Send + Sync + 'static
+{
+  let mut trials = try!(build_trials(base, assignments, &manager, devices, metric_names));
+  let mut terminated: Vec<Vec<TrialResult>> = Vec::new();
+  let mut trained_epochs = 0;
+  let mut survivors = Vec::new();
+
+  let schedule = halving_schedule(assignments.len(), min_epochs, reduction_factor);
+  for (round, &(_, epochs)) in schedule.iter().enumerate() {
+    trials = train_parallel::<T, E, F>(trials, devices, &make_source, src_device
+                                       , epochs - trained_epochs, batch_size);
+    trained_epochs = epochs;
+    trials.sort_by(|a, b| checkpoint::rank_order(a.values.get(rank_by), b.values.get(rank_by)
+                                                 , higher_is_better));
+
+    let keep = match schedule.get(round + 1) {
+      Some(&(num_trials, _)) => num_trials,
+      None                   => trials.len(),
+    };
+    let bad = trials.split_off(keep);
+    terminated.push(bad.iter().map(|trial| to_result(trial, assignments, epochs)).collect());
+    survivors = trials.iter().map(|trial| to_result(trial, assignments, epochs)).collect();
+  }
+
+  for round in terminated.into_iter().rev() {
+    survivors.extend(round);
+  }
+  Ok(survivors)
+}
+
+/// Prints the ranked table of trial results
+pub fn print_results(results: &Vec<TrialResult>) {
+  let mut names: Vec<&String> = results.iter().flat_map(|r| r.values.keys()).collect();
+  names.sort();
+  names.dedup();
+
+  print!("{:<6}{:<8}", "rank", "epochs");
+  for name in names.iter() {
+    print!("{:<14}", name);
+  }
+  println!("params");
+  for (rank, result) in results.iter().enumerate() {
+    print!("{:<6}{:<8}", rank + 1, result.epochs);
+    for name in names.iter() {
+      match result.values.get(*name) {
+        Some(value) => print!("{:<14.6}", value),
+        None        => print!("{:<14}", "-"),
+      }
+    }
+    let params: Vec<String> = result.assignment.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    println!("{}", params.join(" "));
+  }
+}
@@ -1,7 +1,6 @@
 use af;
 use std;
 use csv;
-use rand;
 use rand::Rng;
 use conv::{ConvUtil, Saturate};
 //use conv::errors::GeneralErrorKind;
@@ -26,6 +25,7 @@ use hyper::header::Connection;
 
 
 use error::HALError;
+use random;
 
 // allows for let a = hashmap!['key1' => value1, ...];
 // http://stackoverflow.com/questions/28392008/more-concise-hashmap-initialization
@@ -97,6 +97,15 @@ pub fn constant(dims: Dim4, aftype: DType, val: f32) -> Array {
   }
 }
 
+/// Helper to return a type based on a string
+pub fn get_dtype(name: &str) -> Result<DType, HALError> {
+  match name.to_lowercase().as_str() {
+    "f32" | "float"  => Ok(DType::F32),
+    "f64" | "double" => Ok(DType::F64),
+    _                => Err(HALError::UNKNOWN),
+  }
+}
+
 pub fn cast(input: &Array, dest_type: DType) -> Array {
   if input.get_type() == dest_type{
     return input.clone()
@@ -120,6 +129,15 @@ pub fn cast(input: &Array, dest_type: DType) -> Array {
 }
 
 
+/// Convert a [num_samples, 1] array of class indices to [num_samples, num_classes] one-hot rows
+pub fn one_hot(labels: &Array, num_classes: u64) -> Array {
+  let num_samples = labels.dims()[0];
+  let dims = Dim4::new(&[num_samples, num_classes, 1, 1]);
+  let classes = af::range::<f32>(dims, 1);
+  let labels = af::tile(&cast(labels, DType::F32), Dim4::new(&[1, num_classes, 1, 1]));
+  cast(&af::eq(&labels, &classes, false), DType::F32)
+}
+
 /// Convert a vector of elements to a vector of Array
 pub fn vec_to_array<T: HasAfEnum>(vec_values: Vec<T>, dims: Dim4) -> Array {
   raw_to_array(vec_values.as_ref(), dims)
@@ -142,6 +160,8 @@ pub fn array_to_rows(input: &Array) -> Vec<Array> {
 /// convery an array to a single vector [loses dimensions]
 pub fn array_to_vec(input: &Array) -> Vec<f64>
 {
+  // host() copies raw memory, so ensure that we are of the destination type
+  let input = cast(input, DType::F64);
   let elems = input.dims().elements();
   let mut v: Vec<f64> = vec![0f64; elems as usize];
   input.host(&mut v);
@@ -193,7 +213,7 @@ pub fn shuffle_matrix<T>(v: &mut[&mut [T]], cols: &[usize], row_major: bool) {
   assert!(total_length % cols[0] == 0);
   let row_count = total_length / cols[0];
 
-  let mut rng = rand::thread_rng();
+  let mut rng = random::rng();
   for row in 0..row_count {
     let rnd_row = rng.gen_range(0, row_count - row);
     for (mat, col) in Zip::new((v.iter_mut(), cols.iter())) { //swap all matrices similarly
@@ -209,7 +229,7 @@ pub fn shuffle_matrix<T>(v: &mut[&mut [T]], cols: &[usize], row_major: bool) {
 // Randomly shuffle planes of an array
 // SLOOOOOOW
 pub fn shuffle_array(v: &mut[&mut Array], rows: u64) {
-  let mut rng = rand::thread_rng();
+  let mut rng = random::rng();
   for row in 0..rows {
     let rnd_row = rng.gen_range(0, rows - row);
     for mat in v.iter_mut() { //swap all tensors similarly
@@ -346,6 +366,33 @@ pub fn scale(src: &Array, low: f32, high: f32) -> Array {
           , &low, false)
 }
 
+/// Returns the sorted values & the u32 indices that sort every row [or column for dim 0]
+///
+/// The arrays stay on the device of the input [wraps `af::sort_index`].
+pub fn argsort(input: &Array, dim: u32, ascending: bool) -> (Array, Array) {
+  af::sort_index(input, dim, ascending)
+}
+
+/// Returns the k largest values of every row & their u32 column indices, both [batch, k]
+///
+/// The values are in descending order & stay on the device of the input.
+///
+/// eg: the 5 most probable classes of a batch of predictions
+pub fn top_k(input: &Array, k: u64) -> (Array, Array) {
+  let num_cols = input.dims()[1];
+  assert!(k > 0 && k <= num_cols, "k needs to be in [1, {}]", num_cols);
+  let (values, indices) = argsort(input, 1, false);
+  (af::cols(&values, 0, k - 1), af::cols(&indices, 0, k - 1))
+}
+
+/// Gathers one column per row: out[b] = input[b, columns[b]] [columns is a [batch, 1] u32 array]
+pub fn gather_cols(input: &Array, columns: &Array) -> Array {
+  let num_rows = input.dims()[0];
+  let rows = af::range::<u32>(Dim4::new(&[num_rows, 1, 1, 1]), 0);
+  let linear = af::add(&rows, &af::mul(&cast(columns, DType::U32), &(num_rows as u32), false), false);
+  af::lookup(&af::flat(input), &linear, 0)
+}
+
 fn _read_gzip_filename(entire_file: &Vec<u8>) -> String {
   let d = match GzDecoder::new(&entire_file[..]) {
     Err(e) => panic!("Could not read gzip header: {}", e),
@@ -3,18 +3,28 @@ extern crate arrayfire as af;
 extern crate itertools;
 extern crate rand;
 #[macro_use] extern crate timeit;
+#[cfg(feature = "ndarray")]
+extern crate ndarray;
 
 use std::env;
 use af::{Array, Dim4, Backend, DType};
 use itertools::Zip;
 use rand::distributions::{IndependentSample, Range};
 
-use hal::{utils, activations, initializations, loss};
+use hal::{utils, activations, initializations, loss, metrics, quantize, conformal, prune, monitor, tuning, random, testing, explain, privacy, transfer, coreset, active, semisupervised, hub, report, decode};
+use hal::Model;
 use hal::layer;
 use hal::layer::{Layer};
-use hal::params::{DenseGenerator, RNNGenerator, UnitaryGenerator, ParamManager};
+use hal::params::{DenseGenerator, RNNGenerator, UnitaryGenerator, OrdinalGenerator, ParamManager};
 use hal::device::{DeviceManagerFactory, Device};
 use hal::error::HALError;
+use hal::metrics::Metric;
+use hal::data::{DataSource, FeatureStatistics, ArraySource, TimeSeriesSource, RaggedBatch, CollateSource};
+use hal::checkpoint;
+use hal::config::ModelConfig;
+use hal::optimizer::{Optimizer, SGLD, WeightAverage};
+use hal::model::Ensemble;
+use hal::quantize::QuantizedModel;
 
 
 //todo: move all these tests into separate modules
@@ -197,6 +207,1987 @@ fn binary_cross_entropy(){
                    , 2.3955455);
 }
 
+#[test]
+fn ordinal_cross_entropy(){
+  verify_loss_func("ordinal_cross_entropy"
+                   , &[0.0, 0.0]
+                   , &[1.0, 0.0]
+                   , 1.3862944);
+}
+
+#[test]
+fn contrastive(){
+  let dims = Dim4::new(&[1, 2, 1, 1]);
+  let left = Array::new::<f32>(&[0.0, 0.0], dims);
+  let right = Array::new::<f32>(&[3.0, 4.0], dims);
+  let similar = Array::new::<f32>(&[1.0], Dim4::new(&[1, 1, 1, 1]));
+  let dissimilar = Array::new::<f32>(&[0.0], Dim4::new(&[1, 1, 1, 1]));
+
+  // similar pairs are pulled together: 0.5 * d^2
+  let pos = loss::contrastive(&left, &right, &similar, 1.0);
+  assert!((pos - 12.5).abs() <= 1e-4, "contrastive loss of {} vs 12.5", pos);
+
+  // dissimilar pairs past the margin are ignored
+  let neg = loss::contrastive(&left, &right, &dissimilar, 1.0);
+  assert!(neg.abs() <= 1e-6, "contrastive loss of {} vs 0.0", neg);
+}
+
+#[test]
+fn triplet(){
+  let dims = Dim4::new(&[1, 2, 1, 1]);
+  let anchor = Array::new::<f32>(&[0.0, 0.0], dims);
+  let positive = Array::new::<f32>(&[1.0, 0.0], dims);
+  let negative = Array::new::<f32>(&[0.5, 0.0], dims);
+
+  // max(0, 1.0 - 0.25 + 1.0)
+  let l = loss::triplet(&anchor, &positive, &negative, 1.0);
+  assert!((l - 1.75).abs() <= 1e-4, "triplet loss of {} vs 1.75", l);
+}
+
+#[test]
+fn batch_hard_triplet(){
+  // the last sample is the only one of its label: it is only used as a negative
+  let embeddings = testing::from_rows(&[[0.0, 0.0], [2.0, 0.0], [0.5, 0.0]]);
+  let labels = testing::from_rows(&[[0.0], [0.0], [1.0]]);
+
+  // max(0, 4 - 0.25 + 1) + max(0, 4 - 2.25 + 1)
+  let l = loss::batch_hard_triplet(&embeddings, &labels, 1.0);
+  assert!((l - 7.5).abs() <= 1e-4, "batch hard triplet loss of {} vs 7.5", l);
+
+  // anchor + positive + negative terms of the two valid triplets
+  let d = loss::batch_hard_triplet_derivative(&embeddings, &labels, 1.0);
+  testing::assert_close(&d, &testing::from_rows(&[[-7.0, 0.0], [5.0, 0.0], [2.0, 0.0]]), 1e-4, 0.0);
+}
+
+#[test]
+fn distillation(){
+  let dims = Dim4::new(&[1, 2, 1, 1]);
+  let student = Array::new::<f32>(&[0.0, 0.0], dims);
+  let teacher = Array::new::<f32>(&[3.0f32.ln(), 0.0], dims);
+
+  // KL([0.75, 0.25] || [0.5, 0.5])
+  let l = loss::distillation(&student, &teacher, 1.0);
+  assert!((l - 0.130812).abs() <= 1e-4, "distillation loss of {} vs 0.130812", l);
+  let d = utils::array_to_vec(&loss::distillation_derivative(&student, &teacher, 1.0));
+  assert!((d[0] + 0.25).abs() <= 1e-5 && (d[1] - 0.25).abs() <= 1e-5);
+
+  // matching the teacher is optimal at any temperature
+  let l = loss::distillation(&teacher, &teacher, 4.0);
+  assert!(l.abs() <= 1e-5, "distillation loss of {} vs 0.0", l);
+}
+
+#[test]
+fn distillation_frozen_teacher(){
+  let json = |dropout: &str| format!(
+    r#"{{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": 2, "output_size": 2
+                                                  , "activation": "linear"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}{}] }}"#
+    , dropout);
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let manager = DeviceManagerFactory::new();
+  let build = |json: &str| {
+    let mut model = ModelConfig::from_json(json).unwrap().build(manager.clone(), device).unwrap();
+    model.set_params(&vec![testing::from_rows(&[[1.0, 0.0], [0.0, 1.0]])
+                           , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+    model
+  };
+  let teacher = build(&json(r#", { "layer": "dropout", "params": { "input_size": 2, "output_size": 2
+                                                                  , "rate": 0.9 } }"#));
+  let mut distillation = hal::model::Distillation::new(build(&json("")), teacher, 1.0, 0.0);
+
+  // the soft targets of the teacher are its logits without the dropout noise
+  let input = testing::from_rows(&[[1.0, -1.0], [0.5, 2.0], [-3.0, 1.0]]);
+  let target = testing::from_rows(&[[1.0, 0.0], [0.0, 1.0], [0.0, 1.0]]);
+  let losses = distillation.partial_fit::<f32>(&input, &target, device);
+  assert_eq!(losses.len(), 1);
+  assert!(losses[0].1.abs() <= 1e-5, "distillation loss of {} vs 0.0", losses[0].1);
+}
+
+#[test]
+fn ctc(){
+  // two uniform time-steps over [blank, 1]: valid paths are (1,1), (0,1) & (1,0)
+  let dims = Dim4::new(&[1, 2, 1, 1]);
+  let predictions = vec![Array::new::<f32>(&[0.0, 0.0], dims)
+                         , Array::new::<f32>(&[0.0, 0.0], dims)];
+  let labels = vec![vec![1u32]];
+  let l = loss::ctc(&predictions, &labels, 0).unwrap();
+  let truth = -(0.75f32).ln();
+  assert!((l - truth).abs() <= 1e-4, "ctc loss of {} vs {}", l, truth);
+
+  // a label sequence longer than the input has no alignment
+  let too_long = vec![vec![1u32, 1, 1]];
+  assert!(loss::ctc(&predictions, &too_long, 0).is_err());
+}
+
+#[test]
+fn cost_weighted_loss(){
+  // column major cost matrix [[0, 5], [1, 0]]: missing class 0 costs 5x more
+  let cost = Array::new::<f32>(&[0.0, 1.0, 5.0, 0.0], Dim4::new(&[2, 2, 1, 1]));
+  let dims = Dim4::new(&[1, 2, 1, 1]);
+  let pred = Array::new::<f32>(&[0.5, 0.5], dims);
+  let target = Array::new::<f32>(&[1.0, 0.0], dims);
+
+  let weights = loss::cost_weights(&target, &cost);
+  let weighted = loss::get_weighted_loss("l2", &pred, &target, &weights).unwrap();
+  let unweighted = loss::get_loss("l2", &pred, &target).unwrap();
+  assert!((weighted - 5.0 * unweighted).abs() <= 1e-5
+          , "weighted loss of {} vs {}", weighted, 5.0 * unweighted);
+}
+
+
+#[test]
+fn feature_statistics(){
+  // [1, 2] then [3, 4, 5] --> mean 3, population variance 2
+  let mut stats = FeatureStatistics::new(1);
+  stats.update(&Array::new::<f32>(&[1.0, 2.0], Dim4::new(&[2, 1, 1, 1])));
+  stats.update(&Array::new::<f32>(&[3.0, 4.0, 5.0], Dim4::new(&[3, 1, 1, 1])));
+  let mean = af::mean_all(&stats.mean).0 as f32;
+  let variance = af::mean_all(&stats.variance()).0 as f32;
+  assert!((mean - 3.0).abs() <= 1e-5, "running mean of {} vs 3", mean);
+  assert!((variance - 2.0).abs() <= 1e-5, "running variance of {} vs 2", variance);
+}
+
+#[test]
+fn one_hot(){
+  let labels = Array::new::<f32>(&[2.0, 0.0], Dim4::new(&[2, 1, 1, 1]));
+  let encoded = utils::array_to_vec(&utils::one_hot(&labels, 3));
+  assert_eq!(encoded, vec![0.0, 1.0, 0.0, 0.0, 1.0, 0.0]); // column major
+}
+
+#[test]
+fn host_transfers(){
+  let array = transfer::upload(&[1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0], Dim4::new(&[2, 3, 1, 1]));
+  assert_eq!(array.dims(), Dim4::new(&[2, 3, 1, 1]));
+  let mut buffer = vec![0.0f32; 8];
+  transfer::download_into(&array, &mut buffer[1..7]);
+  assert_eq!(buffer, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 0.0]);
+
+  // batched move between two devices through a reused staging buffer
+  let manager = DeviceManagerFactory::new();
+  let (default, other) = (Device{backend: Backend::DEFAULT, id: 0}, manager.get_devices()[0]);
+  let other_array = transfer::upload(&[7.0f32, 8.0], Dim4::new(&[2, 1, 1, 1]));
+  let mut staging = transfer::HostBuffer::<f32>::new();
+  let moved = transfer::swap_arrays(&manager, &mut staging, &[&array, &other_array], default, other);
+  assert_eq!(staging.capacity(), 8);
+  let back = manager.swap_arrays_backend::<f32>(&[&moved[0], &moved[1]], other, default);
+  assert_eq!(utils::array_to_vec(&back[0]), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+  assert_eq!(utils::array_to_vec(&back[1]), vec![7.0, 8.0]);
+
+  // the staging memory of the manager is reused by the next transfers
+  let capacity = manager.staging_capacity();
+  manager.swap_arrays_backend::<f32>(&[&moved[0]], other, default);
+  manager.swap_array_backend::<f32>(&moved[1], other, default);
+  assert_eq!(manager.staging_capacity(), capacity);
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn ndarray_interop(){
+  use std::convert::TryFrom;
+  use ndarray::{ArrayD, IxDyn};
+  use hal::interop::Tensor;
+
+  // row major [[1, 2, 3], [4, 5, 6]] --> column major [2, 3] array
+  let rows = ArrayD::from_shape_vec(IxDyn(&[2, 3]), vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+  let tensor = Tensor::try_from(&rows).unwrap();
+  assert_eq!(tensor.array().dims(), Dim4::new(&[2, 3, 1, 1]));
+  assert_eq!(utils::array_to_vec(tensor.array()), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+  assert_eq!(ArrayD::from(tensor), rows);
+
+  // [batch, 1] predictions keep both axes
+  let column: ArrayD<f32> = Tensor::from(testing::from_rows(&[[1.0], [2.0]])).into();
+  assert_eq!(column.shape(), &[2, 1]);
+
+  let too_many_axes = ArrayD::<f32>::zeros(IxDyn(&[1, 1, 1, 1, 2]));
+  assert!(Tensor::try_from(too_many_axes).is_err());
+}
+
+#[test]
+fn coreset_pruning(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0]])
+                         , utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // 6 train, 2 test & 2 validation samples, the loss of sample x is 0.5 x^2
+  let input = af::range::<f32>(Dim4::new(&[10, 1, 1, 1]), 0);
+  let target = utils::constant(Dim4::new(&[10, 1, 1, 1]), DType::F32, 0.0);
+  let source = ArraySource::new(input, target, 1, 0.2, 0.2, false);
+  let scores = coreset::loss_scores::<f32>(&mut model, &source, device, 4);
+  assert_eq!(scores, vec![0.0, 0.5, 2.0, 4.5, 8.0, 12.5]);
+  assert_eq!(coreset::gradient_norm_scores::<f32>(&mut model, &source, device, Some(&vec![0]), 4)
+             , vec![0.0, 1.0, 4.0, 9.0, 16.0, 25.0]);
+
+  let pruned = coreset::prune(&source, &scores, 0.5, 1);
+  let info = pruned.info();
+  assert_eq!((info.num_train, info.num_test, info.num_validation), (3, 2, Some(2)));
+  let batch = |data: hal::Data| utils::array_to_vec(&data.input.into_inner());
+  assert_eq!(batch(pruned.get_train_iter(3)), vec![3.0, 4.0, 5.0]);
+  assert_eq!(batch(pruned.get_test_iter(2)), vec![6.0, 7.0]);
+
+  // learned then forgotten: 1 event, never learned: the hardest
+  let mut forgetting = coreset::ForgettingEvents::new(3);
+  forgetting.update(&vec![true, true, false]);
+  forgetting.update(&vec![false, true, false]);
+  forgetting.update(&vec![true, true, false]);
+  assert_eq!(forgetting.scores(), vec![1, 0, std::u64::MAX]);
+  assert_eq!(coreset::select(&forgetting.scores(), 0.5), vec![0, 2]);
+}
+
+#[test]
+fn active_learning_queries(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "dropout", "params": { "input_size": 1, "output_size": 1
+                                                             , "rate": 0.5 } },
+                             { "layer": "dense", "params": { "input_size": 1, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0, -1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // logits [x, -x]: the closer x is to 0 the more uncertain the model
+  let input = testing::from_rows(&[[3.0], [0.0], [-2.0], [0.5], [5.0]]);
+  let target = utils::constant(Dim4::new(&[5, 2, 1, 1]), DType::F32, 0.0);
+  let pool = ArraySource::new(input.clone(), target, 1, 0.0, 0.0, false);
+  for strategy in ["entropy", "margin"].iter() {
+    assert_eq!(active::query::<f32>(&mut model, &pool, device, strategy, 0, 3, 2).unwrap()
+               , vec![1, 3, 2]);
+  }
+  assert!(active::query::<f32>(&mut model, &pool, device, "random", 0, 3, 2).is_err());
+
+  // dropout is only sampled when MC dropout is enabled
+  let first = utils::array_to_vec(&model.infer::<f32>(&input, device)[0]);
+  assert_eq!(first, utils::array_to_vec(&model.infer::<f32>(&input, device)[0]));
+  let variances = active::mc_dropout_scores::<f32>(&mut model, &pool, device, 50, 2);
+  assert_eq!(variances[1], 0.0);
+  assert!(variances.iter().enumerate().filter(|&(i, _)| i != 1).all(|(_, &v)| v > 0.0));
+
+  let queried = vec![1, 3];
+  let labels = testing::from_rows(&[[1.0, 0.0], [0.0, 1.0]]);
+  assert_eq!(active::learn::<f32>(&mut model, &pool, &queried, &labels, device, 1).len(), 2);
+  let remaining = active::remove(&pool, &queried, 1);
+  assert_eq!(remaining.info().num_train, 3);
+  assert_eq!(utils::array_to_vec(&remaining.get_train_iter(3).input.into_inner()), vec![3.0, -2.0, 5.0]);
+}
+
+#[test]
+fn model_surgery(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "adam",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 3
+                                                           , "activation": "relu", "name": "trunk"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 3, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  assert_eq!(model.get_layer_names(), vec!["trunk", "dense_1"]);
+  let trunk = utils::array_to_vec(&model.get_param_manager().get_weights(0)[0]);
+  let weights = |model: &hal::model::Sequential, layer: usize|
+    utils::array_to_vec(&model.get_param_manager().get_weights(layer)[0]);
+
+  // a new head for 4 classes, the trunk is untouched
+  model.replace_output::<f32>(4).unwrap();
+  assert_eq!(model.get_param_manager().get_weight_dims(1)[0], Dim4::new(&[3, 4, 1, 1]));
+  assert_eq!(weights(&model, 0), trunk);
+
+  let input = utils::constant(Dim4::new(&[5, 2, 1, 1]), DType::F32, 1.0);
+  let target = utils::constant(Dim4::new(&[5, 4, 1, 1]), DType::F32, 0.25);
+  model.partial_fit::<f32>(&input, &target, device, None, None);
+  let (trunk, head) = (weights(&model, 0), weights(&model, 1));
+
+  model.insert_dropout::<f32>(0.5);
+  model.insert_dropout::<f32>(0.5);
+  assert_eq!(model.get_layer_names(), vec!["trunk", "dropout_1", "dense_2"]);
+  assert_eq!((weights(&model, 0), weights(&model, 2)), (trunk.clone(), head));
+  assert_eq!(model.infer::<f32>(&input, device)[0].dims(), Dim4::new(&[5, 4, 1, 1]));
+
+  // the trunk as a feature extractor
+  assert!(model.truncate::<f32>("head").is_err());
+  model.truncate::<f32>("trunk").unwrap();
+  assert_eq!(model.get_layer_names(), vec!["trunk"]);
+  assert_eq!(weights(&model, 0), trunk);
+  assert_eq!(model.infer::<f32>(&input, device)[0].dims(), Dim4::new(&[5, 3, 1, 1]));
+
+  // a wider trunk resizes the dropout & the inputs of the head
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.insert_dropout::<f32>(0.5);
+  let trunk_params = |input_size: &str, output_size: &str| {
+    let mut params = std::collections::HashMap::new();
+    params.insert("input_size", input_size.to_string());
+    params.insert("output_size", output_size.to_string());
+    params.insert("activation", "relu".to_string());
+    params.insert("w_init", "glorot_uniform".to_string());
+    params.insert("b_init", "zeros".to_string());
+    params
+  };
+  model.replace_layer::<f32>(0, "dense", trunk_params("2", "5")).unwrap();
+  assert_eq!(model.get_param_manager().get_weight_dims(2)[0], Dim4::new(&[5, 2, 1, 1]));
+  assert_eq!(model.infer::<f32>(&input, device)[0].dims(), Dim4::new(&[5, 2, 1, 1]));
+
+  // the inputs of a layer have to match the outputs of the previous one
+  assert!(model.replace_layer::<f32>(2, "dense", trunk_params("3", "2")).is_err());
+  assert_eq!(model.get_param_manager().get_weight_dims(2)[0], Dim4::new(&[5, 2, 1, 1]));
+}
+
+#[test]
+fn pseudo_labeling(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0, -1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // p(class 0) = sigmoid(2x): only x = -0.1 is below the threshold
+  let unlabeled_input = testing::from_rows(&[[-3.0], [-0.1], [2.0], [4.0], [5.0]]);
+  let unlabeled = ArraySource::new(unlabeled_input, utils::constant(Dim4::new(&[5, 2, 1, 1]), DType::F32, 0.0)
+                                   , 1, 0.0, 0.0, false);
+  let mut pseudo = semisupervised::PseudoLabeling::new(0.9, 1);
+  let (indices, labels) = pseudo.label::<f32>(&mut model, &unlabeled, device, 2);
+  assert_eq!(indices, vec![0, 2, 3, 4]);
+  assert_eq!(utils::array_to_vec(&labels.unwrap()), vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+
+  // the most confident sample of every class
+  pseudo.balanced = true;
+  assert_eq!(pseudo.label::<f32>(&mut model, &unlabeled, device, 2).0, vec![0, 4]);
+  pseudo.balanced = false;
+  pseudo.max_per_class = Some(2);
+  assert_eq!(pseudo.label::<f32>(&mut model, &unlabeled, device, 2).0, vec![0, 3, 4]);
+
+  let labeled = ArraySource::new(testing::from_rows(&[[1.0], [-1.0]]), testing::from_rows(&[[1.0, 0.0], [0.0, 1.0]])
+                                 , 1, 0.0, 0.0, false);
+  pseudo.fit::<f32>(&mut model, &labeled, &unlabeled, device, 2, 1, false);
+  assert_eq!(pseudo.num_pseudo_labels.len(), 2);
+}
+
+#[test]
+fn consistency_regularization(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0, -1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // noise free views: 1 of [-3, -0.1] & both of [2, 4] are confident
+  let unlabeled = ArraySource::new(testing::from_rows(&[[-3.0], [-0.1], [2.0], [4.0]])
+                                   , utils::constant(Dim4::new(&[4, 2, 1, 1]), DType::F32, 0.0)
+                                   , 2, 0.0, 0.0, false);
+  let mut fixmatch = semisupervised::FixMatch::new(Box::new(unlabeled), semisupervised::gaussian_noise(0.0)
+                                                   , semisupervised::feature_dropout(0.0, 0.0));
+  fixmatch.threshold = 0.9;
+  fixmatch.ratio = 1;
+  model.set_consistency(Some(fixmatch));
+
+  let labeled = ArraySource::new(testing::from_rows(&[[1.0], [-1.0], [2.0], [-2.0]])
+                                 , testing::from_rows(&[[1.0, 0.0], [0.0, 1.0], [1.0, 0.0], [0.0, 1.0]])
+                                 , 2, 0.0, 0.0, false);
+  model.fit::<ArraySource, f32>(&labeled, device, 1, 2, None, None, false);
+  assert!((model.get_history()["mask_rate"][0] - 0.75).abs() < 1e-6);
+}
+
+#[test]
+fn activation_taps(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 2
+                                                           , "activation": "relu", "name": "embedding"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 2, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0, -1.0], [1.0, 1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)
+                         , testing::from_rows(&[[1.0], [2.0]])
+                         , utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // embedding = relu([x0 + x1, x1 - x0])
+  let input = testing::from_rows(&[[1.0, 2.0], [3.0, -1.0]]);
+  let embedding = model.forward_to::<f32>("embedding", &input, device, device).unwrap();
+  assert_eq!(embedding.len(), 1);
+  assert_eq!(utils::array_to_vec(&embedding[0]), vec![3.0, 2.0, 1.0, 0.0]);
+
+  let taps = model.forward_taps::<f32>(&["embedding", "dense_1"], &input, device, device).unwrap();
+  assert_eq!(utils::array_to_vec(&taps["dense_1"][0]), vec![5.0, 2.0]);
+  assert_eq!(utils::array_to_vec(&taps["dense_1"][0])
+             , utils::array_to_vec(&model.infer::<f32>(&input, device)[0]));
+  assert!(model.forward_to::<f32>("output", &input, device, device).is_err());
+}
+
+#[test]
+fn model_hub(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let dir = env::temp_dir().join("hal_model_hub");
+  std::fs::create_dir_all(&dir).unwrap();
+  let published = dir.join("linear.json").to_str().unwrap().to_string();
+  let digest = hub::export(&model, &published).unwrap();
+
+  let cache = dir.join("cache").to_str().unwrap().to_string();
+  let index = format!(r#"[{{ "name": "linear", "url": "file://{}", "sha256": "{}"
+                           , "description": "a linear regressor" }}]"#, published, digest);
+  let hub = hub::ModelHub::from_index(&cache, &index).unwrap();
+  assert_eq!(hub.list().len(), 1);
+  let loaded = hub.load("linear", DeviceManagerFactory::new(), device).unwrap();
+  let params = |m: &hal::model::Sequential| m.get_param_manager().get_all_arrays().iter()
+    .map(|a| utils::array_to_vec(a)).collect::<Vec<_>>();
+  assert_eq!(params(&loaded), params(&model));
+
+  // a corrupted cache is fetched again, a wrong checksum is rejected
+  let cached = hub.fetch("linear").unwrap();
+  std::fs::write(&cached, "corrupted").unwrap();
+  assert_eq!(hub.fetch("linear").unwrap(), cached);
+  assert_eq!(hub::sha256(&std::fs::read(&cached).unwrap()), digest);
+  let mut tampered = hub::ModelHub::new(&cache);
+  tampered.register(hub::HubEntry { name: "linear".to_string(), url: format!("file://{}", published)
+                                    , sha256: hub::sha256(b"other"), description: String::new() }).unwrap();
+  assert!(tampered.fetch("linear").is_err());
+  assert!(hub.fetch("unknown").is_err());
+
+  // the index can not place files outside of the cache
+  for name in ["../linear", "nested/linear", "nested\\linear", ".."].iter() {
+    tampered.register(hub::HubEntry { name: name.to_string(), url: format!("file://{}", published)
+                                      , sha256: digest.clone(), description: String::new() }).unwrap();
+    assert!(tampered.cache_path(name).is_err());
+    assert!(tampered.fetch(name).is_err());
+  }
+
+  // truncated digests & https urls [no ssl] are rejected when registered
+  let entry = |url: String, sha256: String| hub::HubEntry { name: "linear".to_string(), url: url
+                                                           , sha256: sha256, description: String::new() };
+  let file_url = format!("file://{}", published);
+  assert!(tampered.register(entry(file_url.clone(), digest[..12].to_string())).is_err());
+  assert!(tampered.register(entry(file_url.clone(), format!("{}0", digest))).is_err());
+  assert!(tampered.register(entry("https://example.com/linear.json".to_string(), digest.clone())).is_err());
+  assert!(tampered.register(entry("http://example.com/linear.json".to_string(), digest.clone())).is_ok());
+  let index = format!(r#"[{{ "name": "linear", "url": "file://{}", "sha256": "{}"
+                           , "description": "a truncated digest" }}]"#, published, &digest[..12]);
+  assert!(hub::ModelHub::from_index(&cache, &index).is_err());
+}
+
+#[test]
+fn array_source_splits(){
+  // 10 samples: 6 train, 2 test, 2 validation
+  let dims = Dim4::new(&[10, 1, 1, 1]);
+  let input = af::range::<f32>(dims, 0);
+  let source = ArraySource::new(input.clone(), input, 2, 0.2, 0.2, false);
+  let info = source.info();
+  assert_eq!((info.num_train, info.num_test, info.num_validation), (6, 2, Some(2)));
+
+  let batch = |data: hal::Data| utils::array_to_vec(&data.input.into_inner());
+  assert_eq!(batch(source.get_train_iter(2)), vec![0.0, 1.0]);
+  assert_eq!(batch(source.get_train_iter(2)), vec![2.0, 3.0]);
+  assert_eq!(batch(source.get_test_iter(2)), vec![6.0, 7.0]);
+  assert_eq!(batch(source.get_validation_iter(2).unwrap()), vec![8.0, 9.0]);
+}
+
+#[test]
+fn read_npy_arrays(){
+  // a C order [2, 3] f4 array & a fortran order [3] i8 array, as written by numpy.save
+  let write = |name: &str, header: &str, data: Vec<u8>| {
+    let mut header = header.to_string();
+    while (10 + header.len() + 1) % 64 != 0 { header.push(' '); }
+    header.push('\n');
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&[header.len() as u8, (header.len() >> 8) as u8]);
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend(data);
+    let path = env::temp_dir().join(name).to_str().unwrap().to_string();
+    std::fs::write(&path, bytes).unwrap();
+    path
+  };
+  let floats: Vec<u8> = (0..6).flat_map(|v| (v as f32).to_bits().to_le_bytes().to_vec()).collect();
+  let path = write("hal_read_npy_f4.npy", "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }", floats);
+  let array = hal::data::read_npy(&path).unwrap();
+  assert_eq!((array.dims()[0], array.dims()[1]), (2, 3));
+  assert_eq!(utils::array_to_vec(&array), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+
+  let ints: Vec<u8> = vec![-1i64, 7, 2].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+  let path = write("hal_read_npy_i8.npy", "{'descr': '<i8', 'fortran_order': True, 'shape': (3,), }", ints);
+  let array = hal::data::read_npy(&path).unwrap();
+  assert_eq!((array.dims()[0], array.dims()[1]), (3, 1));
+  assert_eq!(utils::array_to_vec(&array), vec![-1.0, 7.0, 2.0]);
+
+  let path = write("hal_read_npy_c16.npy", "{'descr': '<c16', 'fortran_order': False, 'shape': (1,), }", vec![0; 16]);
+  assert!(hal::data::read_npy(&path).is_err());
+
+  // written arrays read back with the same dims & values
+  let path = env::temp_dir().join("hal_write_npy.npy").to_str().unwrap().to_string();
+  for dims in [Dim4::new(&[2, 3, 4, 1]), Dim4::new(&[5, 1, 1, 1])].iter() {
+    let array = initializations::uniform::<f32>(*dims, -1.0, 1.0);
+    hal::data::write_npy(&path, &array).unwrap();
+    let read = hal::data::read_npy(&path).unwrap();
+    assert_eq!(read.dims(), *dims);
+    testing::assert_close(&read, &array, 0.0, 0.0);
+  }
+}
+
+#[test]
+fn read_csv_arrays(){
+  let write = |name: &str, text: &str| {
+    let path = env::temp_dir().join(name).to_str().unwrap().to_string();
+    std::fs::write(&path, text).unwrap();
+    path
+  };
+  let path = write("hal_read_csv.csv", "a,b,c\n1.5, 2,3\n-4,5,6e1\n");
+  let array = hal::data::read_csv_array(&path, true).unwrap();
+  assert_eq!((array.dims()[0], array.dims()[1]), (2, 3));
+  assert_eq!(utils::array_to_vec(&array), vec![1.5, -4.0, 2.0, 5.0, 3.0, 60.0]);
+
+  // the header is not numeric, ragged rows are rejected
+  assert!(hal::data::read_csv_array(&path, false).is_err());
+  let path = write("hal_read_csv_ragged.csv", "1,2\n3\n");
+  assert!(hal::data::read_csv_array(&path, false).is_err());
+}
+
+#[test]
+fn read_idx_arrays(){
+  // header: 2 zero bytes, the element type, the number of dims & the big endian dims
+  let write = |name: &str, element_type: u8, dims: &[u32], data: Vec<u8>| {
+    let mut bytes = vec![0, 0, element_type, dims.len() as u8];
+    for d in dims {
+      bytes.extend_from_slice(&d.to_be_bytes());
+    }
+    bytes.extend(data);
+    let path = env::temp_dir().join(name).to_str().unwrap().to_string();
+    std::fs::write(&path, bytes).unwrap();
+    path
+  };
+
+  // 2 images of 2x3 pixels are flattened into rows
+  let path = write("hal_read_idx_u8.idx", 0x08, &[2, 2, 3], (0..12).collect());
+  let array = hal::data::read_idx(&path).unwrap();
+  assert_eq!((array.dims()[0], array.dims()[1]), (2, 6));
+  let values = utils::array_to_vec(&array);
+  assert_eq!(values[..6].to_vec(), vec![0.0, 6.0, 1.0, 7.0, 2.0, 8.0]);
+
+  let floats: Vec<u8> = [0.5f32, -2.0, 3.25].iter().flat_map(|v| v.to_bits().to_be_bytes().to_vec()).collect();
+  let path = write("hal_read_idx_f4.idx", 0x0D, &[3], floats);
+  assert_eq!(utils::array_to_vec(&hal::data::read_idx(&path).unwrap()), vec![0.5, -2.0, 3.25]);
+
+  // truncated data & unknown element types
+  let path = write("hal_read_idx_short.idx", 0x08, &[2, 3], vec![0; 5]);
+  assert!(hal::data::read_idx(&path).is_err());
+  let path = write("hal_read_idx_type.idx", 0x0A, &[1], vec![0]);
+  assert!(hal::data::read_idx(&path).is_err());
+}
+
+#[test]
+fn ragged_batches(){
+  // 3 sequences of 2 features & lengths 1, 3 & 2
+  let sequence = |length: u64, start: f32| af::add(&af::range::<f32>(Dim4::new(&[length, 2, 1, 1]), 0)
+                                                  , &start, false);
+  let ragged = RaggedBatch::from_sequences(&[sequence(1, 10.0), sequence(3, 20.0), sequence(2, 30.0)]);
+  assert_eq!((ragged.offsets.clone(), ragged.lengths(), ragged.max_length()), (vec![0, 1, 4, 6], vec![1, 3, 2], 3));
+  assert_eq!(ragged.padding_ratio(), 1.5);
+  assert_eq!(utils::array_to_vec(&ragged.mask()), vec![1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0]);
+
+  // [batch, feature, time] with the padded steps set to -1
+  let padded = ragged.to_padded(-1.0);
+  assert_eq!((padded.dims()[0], padded.dims()[1], padded.dims()[2]), (3, 2, 3));
+  assert_eq!(utils::array_to_vec(&af::slice(&padded, 1)), vec![-1.0, 21.0, 31.0, -1.0, 21.0, 31.0]);
+  let restored = RaggedBatch::from_padded(&padded, &ragged.lengths());
+  assert_eq!(utils::array_to_vec(&restored.values), utils::array_to_vec(&ragged.values));
+  assert_eq!(utils::array_to_vec(&ragged.last_steps()), vec![10.0, 22.0, 31.0, 10.0, 22.0, 31.0]);
+  assert_eq!(ragged.length_batches(2), vec![vec![0, 2], vec![1]]);
+  assert_eq!(ragged.select(&[2, 0]).lengths(), vec![2, 1]);
+
+  // the masked loss only counts the selected samples
+  let pred = utils::vec_to_array::<f32>(vec![1.0, 5.0, 2.0], Dim4::new(&[3, 1, 1, 1]));
+  let target = utils::vec_to_array::<f32>(vec![0.0, 0.0, 0.0], Dim4::new(&[3, 1, 1, 1]));
+  let mask = utils::vec_to_array::<f32>(vec![1.0, 0.0, 1.0], Dim4::new(&[3, 1, 1, 1]));
+  assert_eq!(loss::get_masked_loss("mse", &pred, &target, &mask).unwrap(), 1.25);
+  let derivative = loss::get_masked_loss_derivative("mse", &pred, &target, &mask).unwrap();
+  assert_eq!(utils::array_to_vec(&derivative)[1], 0.0);
+
+  // the padding does not change the outputs of a recurrent model
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "rnn", "params": { "input_size": 2, "hidden_size": 3, "output_size": 1
+                                                         , "inner_activation": "tanh", "outer_activation": "linear"
+                                                         , "w_init": "glorot_uniform", "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let inputs = RaggedBatch::from_sequences(&[sequence(1, 0.1), sequence(3, -0.2), sequence(2, 0.3)]);
+  let outputs = model.predict_proba_ragged::<f32>(&inputs, device, device);
+  assert_eq!(outputs.lengths(), inputs.lengths());
+  let alone = model.predict_proba_ragged::<f32>(&inputs.select(&[1]), device, device);
+  testing::assert_close(&outputs.sequence(1), &alone.values, 1e-6, 1e-5);
+
+  // per step targets & a target at the end of every sequence [sequences 1 & 2 end at t = 2]
+  let per_step = RaggedBatch::new(utils::constant(Dim4::new(&[6, 1, 1, 1]), DType::F32, 0.5), vec![0, 1, 4, 6]);
+  assert_eq!(model.partial_fit_ragged::<f32>(&inputs, &per_step, device).len(), 3);
+  let inputs = RaggedBatch::from_sequences(&[sequence(1, 0.1), sequence(3, -0.2), sequence(3, 0.3)]);
+  let last = RaggedBatch::new(utils::constant(Dim4::new(&[3, 1, 1, 1]), DType::F32, 0.5), vec![0, 1, 2, 3]);
+  let losses = model.partial_fit_ragged::<f32>(&inputs, &last, device);
+  assert_eq!(losses.len(), 2);
+  assert!(losses.iter().all(|l| l.is_finite()));
+}
+
+#[test]
+fn timeseries_windows(){
+  // 10 steps of 2 features [t, 10 + t] --> windows starting at 0, 2 & 4
+  let values: Vec<f32> = (0..20).map(|v| v as f32).collect();
+  let series = utils::vec_to_array::<f32>(values, Dim4::new(&[10, 2, 1, 1]));
+  let source = TimeSeriesSource::new(series.clone(), vec![1], 3, 2, 2, 1, 0.0, 0.0, false, false);
+  let info = source.info();
+  assert_eq!(info.num_train, 3);
+  assert_eq!((info.input_dims[1], info.input_dims[2]), (2, 3));
+  assert_eq!((info.target_dims[1], info.target_dims[2]), (2, 3));
+
+  // [batch, feature, time] column major, every step is followed by the next 2 steps
+  let batch = source.get_train_iter(1);
+  assert_eq!(utils::array_to_vec(&batch.input.into_inner()), vec![0.0, 10.0, 1.0, 11.0, 2.0, 12.0]);
+  assert_eq!(utils::array_to_vec(&batch.target.into_inner()), vec![11.0, 12.0, 12.0, 13.0, 13.0, 14.0]);
+  let batch = source.get_train_iter(1);
+  assert_eq!(utils::array_to_vec(&batch.target.into_inner())[4..].to_vec(), vec![15.0, 16.0]);
+  assert_eq!(source.forecast_indices(), vec![false, false, true]);
+
+  // per window normalization: the targets are scaled with the statistics of their input column
+  let normalized = TimeSeriesSource::new(series.clone(), vec![1], 3, 2, 2, 1, 0.0, 0.0, true, false);
+  let batch = normalized.get_train_iter(1);
+  let std_dev = (2.0f32 / 3.0).sqrt();
+  let target = utils::array_to_vec(&batch.target.into_inner());
+  assert!((target[4] - 2.0 / std_dev).abs() < 1e-4 && (target[5] - 3.0 / std_dev).abs() < 1e-4);
+  let input = utils::array_to_vec(&batch.input.into_inner());
+  assert!((input[0] + 1.0 / std_dev).abs() < 1e-4 && input[2].abs() < 1e-4);
+
+  // a recurrent forecaster fits the windows [lookback 3 != horizon 2]
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "rnn", "params": { "input_size": 2, "hidden_size": 4, "output_size": 2
+                                                         , "inner_activation": "tanh", "outer_activation": "linear"
+                                                         , "w_init": "glorot_uniform", "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let source = TimeSeriesSource::new(series, vec![1], 3, 2, 1, 2, 0.0, 0.0, true, false);
+  let losses = model.fit::<TimeSeriesSource, f32>(&source, device, 2, 2, None
+                                                  , Some(&source.forecast_indices()), false);
+  assert!(losses.len() > 0 && losses.iter().all(|l| l.is_finite()));
+}
+
+#[test]
+fn collate_samples(){
+  use hal::data::{stack_rows, concat_features};
+
+  // samples of a 2 pixel image, a word id [one hot over 3 words] & a label
+  struct Sample { pixels: Vec<f32>, word: u8, label: f32 }
+  let samples: Vec<Sample> = (0..10).map(|i| Sample {
+    pixels: vec![i as f32, 10.0 + i as f32], word: (i % 3) as u8, label: (i % 2) as f32,
+  }).collect();
+  let source = CollateSource::new(samples, Box::new(|batch: &[&Sample]| {
+    let n = batch.len() as u64;
+    let images = stack_rows(&batch.iter()
+                            .map(|s| utils::vec_to_array::<f32>(s.pixels.clone(), Dim4::new(&[1, 2, 1, 1])))
+                            .collect::<Vec<Array>>());
+    let words: Vec<f32> = batch.iter().map(|s| s.word as f32).collect();
+    let words = utils::one_hot(&utils::vec_to_array::<f32>(words, Dim4::new(&[n, 1, 1, 1])), 3);
+    let labels = utils::vec_to_array::<f32>(batch.iter().map(|s| s.label).collect(), Dim4::new(&[n, 1, 1, 1]));
+    (concat_features(&[images, words]), labels)
+  }), 2, 0.2, 0.0, false);
+  let info = source.info();
+  assert_eq!((info.num_train, info.num_test, info.num_validation), (8, 2, None));
+  assert_eq!((info.input_dims[0], info.input_dims[1]), (2, 5));
+
+  // [batch, feature] column major: the pixels then the one hot words
+  let batch = source.get_train_iter(2);
+  assert_eq!(utils::array_to_vec(&batch.input.into_inner())
+             , vec![0.0, 1.0, 10.0, 11.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+  assert_eq!(utils::array_to_vec(&batch.target.into_inner()), vec![0.0, 1.0]);
+  let batch = source.get_test_iter(2);
+  assert_eq!(utils::array_to_vec(&batch.target.into_inner()), vec![0.0, 1.0]);
+  assert_eq!(utils::array_to_vec(&batch.input.into_inner())[..2].to_vec(), vec![8.0, 9.0]);
+  assert!(source.get_validation_iter(2).is_none());
+
+  // shuffled epochs visit every training sample exactly once
+  let ids: Vec<f32> = (0..10).map(|i| i as f32).collect();
+  let shuffled = CollateSource::new(ids, Box::new(|batch: &[&f32]| {
+    let ids = utils::vec_to_array::<f32>(batch.iter().map(|&&id| id).collect(), Dim4::new(&[batch.len() as u64, 1, 1, 1]));
+    (ids.clone(), ids)
+  }), 2, 0.2, 0.0, true);
+  for epoch in 0..3 {
+    let mut seen: Vec<f64> = (0..4).flat_map(|_| utils::array_to_vec(&shuffled.get_train_iter(2).input.into_inner()))
+      .collect();
+    seen.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(seen, (0..8).map(|i| i as f64).collect::<Vec<f64>>());
+    assert_eq!(shuffled.info().current_epoch.get(), epoch + 1);
+  }
+}
+
+#[test]
+fn fit_callbacks(){
+  use std::sync::{Arc, Mutex};
+  use std::collections::HashMap;
+  use hal::callback::{BatchProgress, Callback};
+
+  struct Recorder(Arc<Mutex<(Vec<BatchProgress>, Vec<HashMap<String, f32>>, u32)>>);
+  impl Callback for Recorder {
+    fn on_batch_end(&mut self, progress: &BatchProgress) { self.0.lock().unwrap().0.push(progress.clone()); }
+    fn on_epoch_end(&mut self, _: u64, values: &HashMap<String, f32>) { self.0.lock().unwrap().1.push(values.clone()); }
+    fn on_train_end(&mut self) { self.0.lock().unwrap().2 += 1; }
+  }
+
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let input = af::mul(&af::range::<f32>(Dim4::new(&[10, 1, 1, 1]), 0), &0.1f32, false);
+  let source = ArraySource::new(input.clone(), input, 2, 0.0, 0.0, false);
+  let iters = source.info().num_samples / 2;
+
+  let recorded = Arc::new(Mutex::new((Vec::new(), Vec::new(), 0)));
+  model.add_callback(Box::new(Recorder(recorded.clone())));
+  model.fit::<ArraySource, f32>(&source, device, 2, 2, None, None, false);
+
+  {
+    let (ref batches, ref epochs, train_ends) = *recorded.lock().unwrap();
+    assert_eq!(batches.len() as u64, 2 * iters);
+    assert_eq!((batches[0].epoch, batches[0].iteration, batches[0].num_iterations), (0, 0, iters));
+    assert_eq!(batches.last().unwrap().eta, 0.0);
+    assert_eq!(epochs.len(), 2);
+    assert_eq!(epochs[1]["loss"], model.get_history()["loss"][1]);
+    assert_eq!(train_ends, 1);
+  }
+
+  // the epoch snapshots that can not be saved are recorded
+  let dir = env::temp_dir().join("hal_fit_snapshots");
+  model.set_checkpoint_dir(dir.to_str().unwrap()).unwrap();
+  model.fit::<ArraySource, f32>(&source, device, 1, 2, None, None, false);
+  std::fs::remove_dir_all(&dir).unwrap();
+  model.fit::<ArraySource, f32>(&source, device, 1, 2, None, None, false);
+  assert_eq!(model.get_history()["checkpoint_failed"], vec![0.0, 1.0]);
+  assert_eq!(recorded.lock().unwrap().1.last().unwrap()["checkpoint_failed"], 1.0);
+}
+
+#[test]
+fn training_introspection(){
+  use std::sync::{Arc, Mutex};
+  use hal::callback::{BatchProgress, Callback, TrainingState};
+
+  // (optimizer step, number of moments, output delta) of every batch
+  struct Inspector(Arc<Mutex<Vec<(u64, usize, f64)>>>);
+  impl Callback for Inspector {
+    fn inspect_batch(&mut self, state: &TrainingState, _: &BatchProgress) {
+      let delta = utils::array_to_vec(&state.layer_deltas[0])[0];
+      self.0.lock().unwrap().push((state.optimizer.get_step(), state.optimizer.get_state()["mt"].len(), delta));
+    }
+  }
+
+  let json = r#"{ "loss": "mse", "optimizer": "adam", "optimizer_params": { "learning_rate": 0.0 },
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0]])
+                         , utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+  assert_eq!(model.get_optimizer().get_state()["mt"].len(), 0);
+
+  // mse delta = prediction - target = x - 0
+  let source = ArraySource::new(testing::from_rows(&[[2.0], [3.0]])
+                                , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)
+                                , 1, 0.0, 0.0, false);
+  let recorded = Arc::new(Mutex::new(Vec::new()));
+  model.add_callback(Box::new(Inspector(recorded.clone())));
+  model.fit::<ArraySource, f32>(&source, device, 1, 1, None, None, false);
+  assert_eq!(*recorded.lock().unwrap(), vec![(1, 2, 2.0), (2, 2, 3.0)]);
+
+  // the decay of beta1 does not leak into the params of the config
+  let config = ModelConfig::from_json(&json.replace(r#""learning_rate": 0.0"#, r#""learning_rate": 0.0, "lambda": 0.5"#)).unwrap();
+  let mut decayed = config.build(DeviceManagerFactory::new(), device).unwrap();
+  decayed.fit::<ArraySource, f32>(&source, device, 1, 1, None, None, false);
+  let params = ModelConfig::from_model(&decayed).optimizer_params.unwrap();
+  assert_eq!((params["beta1"].as_str(), params["lambda"].as_str()), ("0.9", "0.5"));
+}
+
+#[test]
+fn optimizer_state_growth(){
+  use std::collections::HashMap;
+
+  let json = r#"{ "loss": "mse", "optimizer": "sgd", "optimizer_params": { "learning_rate": 0.1, "momemtum": 0.9 },
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 3
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let input = testing::from_rows(&[[1.0, -1.0], [0.5, 2.0]]);
+  model.partial_fit::<f32>(&input, &testing::from_rows(&[[1.0, 0.0, -1.0], [0.0, 1.0, 0.0]]), device, None, None);
+  let velocity = model.get_optimizer().get_state()["velocity"][0].copy();
+
+  // a layer added after the first step gets zero state, the state of the others is kept
+  let mut params = HashMap::new();
+  for &(k, v) in [("input_size", "3"), ("output_size", "1"), ("activation", "linear")
+                  , ("w_init", "glorot_uniform"), ("b_init", "zeros")].iter() {
+    params.insert(k, v.to_string());
+  }
+  model.add::<f32>("dense", params);
+  model.partial_fit::<f32>(&input, &testing::from_rows(&[[1.0], [0.0]]), device, None, None);
+
+  let dims = model.get_param_manager().get_all_dims();
+  let state = model.get_optimizer().get_state()["velocity"];
+  assert_eq!(state.len(), dims.len());
+  for (v, dim) in state.iter().zip(dims.iter()) {
+    assert_eq!(v.dims().get(), dim.get());
+  }
+  assert!(utils::array_to_vec(&af::sub(&state[0], &velocity, false)).iter().any(|&d| d != 0.0));
+
+  // the deltas are reset after every step
+  for delta in model.get_param_manager().get_all_deltas() {
+    assert!(utils::array_to_vec(&delta).iter().all(|&d| d == 0.0));
+  }
+}
+
+#[test]
+fn model_warmup(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "rnn", "params": { "input_size": 3, "hidden_size": 4, "output_size": 2
+                                                         , "inner_activation": "tanh", "outer_activation": "linear"
+                                                         , "w_init": "glorot_uniform", "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let params = model.get_param_manager().get_all_arrays();
+  let gradients = model.get_param_manager().get_all_deltas();
+
+  let latencies = model.warmup::<f32>(Dim4::new(&[8, 3, 5, 1]), 3);
+  assert_eq!(latencies.len(), 3);
+  assert!(latencies.iter().all(|&l| l >= 0.0));
+
+  // the warm-up does not train
+  for (arr, expected) in model.get_param_manager().get_all_arrays().iter().zip(params.iter()) {
+    testing::assert_close(arr, expected, 0.0, 0.0);
+  }
+  for (delta, expected) in model.get_param_manager().get_all_deltas().iter().zip(gradients.iter()) {
+    testing::assert_close(delta, expected, 0.0, 0.0);
+  }
+  assert_eq!(model.get_optimizer().get_step(), 0);
+  assert_eq!(model.infer::<f32>(&af::randu::<f32>(Dim4::new(&[8, 3, 5, 1])), device).len(), 5);
+}
+
+#[test]
+fn gradient_scaling(){
+  use hal::params::LossScaler;
+
+  // the scale grows after 2 finite steps, halves on an overflow & is shared by the clones
+  let mut scaler = LossScaler::new(1024.0);
+  scaler.growth_interval = 2;
+  let shared = scaler.clone();
+  assert!(scaler.update(true) && scaler.update(true));
+  assert_eq!(shared.get_scale(), 2048.0);
+  assert!(!scaler.update(false));
+  assert_eq!((shared.get_scale(), shared.num_skipped()), (1024.0, 1));
+
+  // the first layer is frozen by its gradient scale
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 3
+                                                           , "activation": "tanh", "grad_scale": "0"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 3, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  assert_eq!(model.get_param_manager().get_gradient_scale(0), 0.0);
+  let weights = |model: &hal::model::Sequential, layer: usize|
+    utils::array_to_vec(&model.get_param_manager().get_weights(layer)[0]);
+  let (first, second) = (weights(&model, 0), weights(&model, 1));
+
+  // an overflowing loss scale skips the step
+  model.set_loss_scaler(Some(LossScaler::new(1e38)));
+  let input = utils::constant(Dim4::new(&[5, 2, 1, 1]), DType::F32, 1.0);
+  let target = utils::constant(Dim4::new(&[5, 2, 1, 1]), DType::F32, 10.0);
+  model.partial_fit::<f32>(&input, &target, device, None, None);
+  let scaler = model.get_param_manager().get_loss_scaler().unwrap().clone();
+  assert_eq!((scaler.get_scale(), scaler.num_skipped()), (5e37, 1));
+  assert_eq!((weights(&model, 0), weights(&model, 1)), (first.clone(), second.clone()));
+  assert!(model.get_param_manager().get_all_deltas().iter().all(|d| af::sum_all(&af::abs(d)).0 == 0.0));
+
+  model.set_loss_scaler(Some(LossScaler::fixed(256.0)));
+  model.partial_fit::<f32>(&input, &target, device, None, None);
+  assert_eq!(weights(&model, 0), first);
+  assert!(weights(&model, 1) != second);
+  assert_eq!(model.get_param_manager().get_loss_scale(), 256.0);
+
+  // the per sample gradients [eg: of DP-SGD] are scaled like the accumulated ones
+  let (_, gradients) = model.per_sample_gradients::<f32>(&input, &target, device, Some(&vec![0, 2]));
+  assert_eq!(af::sum_all(&af::abs(&gradients[0])).0, 0.0);
+  assert!(af::sum_all(&af::abs(&gradients[1])).0 > 0.0);
+
+  // the scales survive the surgery of the model
+  model.get_param_manager().set_gradient_scale(1, 0.5);
+  model.replace_output::<f32>(3).unwrap();
+  let manager = model.get_param_manager();
+  assert_eq!((manager.get_gradient_scale(0), manager.get_gradient_scale(1), manager.get_loss_scale()), (0.0, 0.5, 256.0));
+}
+
+#[test]
+fn frozen_prefix_cache(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 3
+                                                           , "activation": "tanh", "name": "backbone"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 3, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.freeze(1);
+  assert_eq!(model.get_frozen_layers(), 1);
+  assert!(model.get_param_manager().is_frozen(0) && !model.get_param_manager().is_frozen(1));
+
+  let input = af::randu::<f32>(Dim4::new(&[10, 2, 1, 1]));
+  let target = af::randu::<f32>(Dim4::new(&[10, 1, 1, 1]));
+  let source = ArraySource::new(input.clone(), target, 4, 0.2, 0.0, false);
+  let path = env::temp_dir().join("hal_frozen_cache.npy").to_str().unwrap().to_string();
+  let _ = std::fs::remove_file(&path);
+  let _ = std::fs::remove_file(format!("{}.sha256", path));
+
+  // the cache holds the backbone outputs of every sample [batches of 3 include a partial one]
+  let cached = model.cache_frozen_outputs::<f32>(&source, 3, device, Some(&path)).unwrap();
+  let backbone = model.forward_taps::<f32>(&["backbone"], &input, device, device).unwrap();
+  testing::assert_close(&cached.input, &backbone["backbone"][0], 1e-6, 0.0);
+  let info = cached.info();
+  assert_eq!((info.num_train, info.num_test, info.input_dims), (8, 2, Dim4::new(&[4, 3, 1, 1])));
+  let reloaded = model.cache_frozen_outputs::<f32>(&source, 3, device, Some(&path)).unwrap();
+  testing::assert_close(&reloaded.input, &cached.input, 1e-6, 0.0);
+
+  // the optimizer skips the frozen layers of the full model
+  let weights = |model: &hal::model::Sequential, layer: usize|
+    utils::array_to_vec(&model.get_param_manager().get_weights(layer)[0]);
+  let first = weights(&model, 0);
+  let batch = source.get_train_iter(4);
+  model.partial_fit::<f32>(&batch.input.into_inner(), &batch.target.into_inner(), device, None, None);
+  assert_eq!(weights(&model, 0), first);
+
+  // starting from the cache predicts the same as the full model & only trains the head
+  let full = model.infer::<f32>(&input, device)[0].clone();
+  model.set_cached_prefix(true);
+  testing::assert_close(&model.infer::<f32>(&cached.input, device)[0], &full, 1e-5, 0.0);
+  assert!(model.set_gradient_checkpoints(vec![(0, 1)]).is_err());
+  let second = weights(&model, 1);
+  let batch = cached.get_train_iter(4);
+  model.partial_fit::<f32>(&batch.input.into_inner(), &batch.target.into_inner(), device, None, None);
+  assert_eq!(weights(&model, 0), first);
+  assert!(weights(&model, 1) != second);
+
+  // replacing the head keeps the backbone frozen
+  model.replace_output::<f32>(2).unwrap();
+  assert_eq!(model.get_frozen_layers(), 1);
+  assert!(model.get_param_manager().is_frozen(0) && !model.get_param_manager().is_frozen(1));
+
+  // the cache file is recomputed once the frozen parameters change
+  model.set_cached_prefix(false);
+  model.get_param_manager().set_weight(0, 0, utils::constant(Dim4::new(&[2, 3, 1, 1]), DType::F32, 0.5));
+  let recomputed = model.cache_frozen_outputs::<f32>(&source, 3, device, Some(&path)).unwrap();
+  let backbone = model.forward_taps::<f32>(&["backbone"], &input, device, device).unwrap();
+  testing::assert_close(&recomputed.input, &backbone["backbone"][0], 1e-6, 0.0);
+  let _ = std::fs::remove_file(&path);
+  let _ = std::fs::remove_file(format!("{}.sha256", path));
+}
+
+#[test]
+fn privacy_accountant(){
+  // full batch: rdp(a) = a / (2 sigma^2) --> epsilon = min_a a / 2 + ln(1e5) / (a - 1) [a = 6]
+  let mut accountant = privacy::PrivacyAccountant::new();
+  assert_eq!(accountant.epsilon(1e-5), 0.0);
+  accountant.step(1.0, 1.0);
+  let truth = 3.0 + (1e5f64).ln() / 5.0;
+  assert!((accountant.epsilon(1e-5) - truth).abs() < 1e-9);
+
+  // subsampling amplifies the privacy, more steps spend more of it
+  let mut subsampled = privacy::PrivacyAccountant::new();
+  subsampled.step(0.01, 1.0);
+  assert!(subsampled.epsilon(1e-5) < accountant.epsilon(1e-5));
+  let single = subsampled.epsilon(1e-5);
+  subsampled.step(0.01, 1.0);
+  assert_eq!((subsampled.num_steps(), subsampled.steps.len()), (2, 1));
+  assert!(subsampled.epsilon(1e-5) > single);
+}
+
+#[test]
+fn dp_sgd_step(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 3, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let config = ModelConfig::from_json(json).unwrap();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = config.build(DeviceManagerFactory::new(), device).unwrap();
+  let mut private = config.build(DeviceManagerFactory::new(), device).unwrap();
+  private.set_params(&model.get_param_manager().get_all_arrays());
+
+  // without clipping & noise DP-SGD is a plain step on the sum of the per sample gradients
+  let inputs = initializations::uniform::<f32>(Dim4::new(&[4, 3, 1, 1]), -1.0, 1.0);
+  let targets = initializations::uniform::<f32>(Dim4::new(&[4, 2, 1, 1]), -1.0, 1.0);
+  let mut dp = privacy::DPSGD::new(1e6, 0.0, 100);
+  model.partial_fit::<f32>(&inputs, &targets, device, None, None);
+  let losses = dp.partial_fit::<f32>(&mut private, &inputs, &targets, device);
+  assert_eq!(losses.len(), 1);
+  for (expected, arr) in model.get_param_manager().get_all_arrays().iter()
+    .zip(private.get_param_manager().get_all_arrays().iter())
+  {
+    testing::assert_close(arr, expected, 1e-5, 0.0);
+  }
+}
+
+#[test]
+fn per_sample_gradients(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 3, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let inputs = initializations::uniform::<f32>(Dim4::new(&[4, 3, 1, 1]), -1.0, 1.0);
+  let targets = initializations::uniform::<f32>(Dim4::new(&[4, 2, 1, 1]), -1.0, 1.0);
+
+  // the batch gradient is the sum of the per sample gradients
+  let predictions = model.forward::<f32>(&inputs, device, device);
+  model.backward(&predictions, &targets, None);
+  let batch_deltas = model.get_param_manager().get_all_deltas();
+  let (losses, gradients) = model.per_sample_gradients::<f32>(&inputs, &targets, device, Some(&vec![1, 0]));
+  assert_eq!(losses.len(), 1);
+  assert_eq!(gradients[0].dims(), Dim4::new(&[4, 2, 1, 1]));
+  assert_eq!(gradients[1].dims(), Dim4::new(&[4, 6, 1, 1]));
+  testing::assert_close(&af::moddims(&af::sum(&gradients[1], 0), Dim4::new(&[3, 2, 1, 1]))
+                        , &batch_deltas[0], 1e-5, 1e-5);
+  testing::assert_close(&af::moddims(&af::sum(&gradients[0], 0), batch_deltas[1].dims())
+                        , &batch_deltas[1], 1e-5, 1e-5);
+
+  // the accumulated gradients are left untouched
+  for (delta, expected) in model.get_param_manager().get_all_deltas().iter().zip(batch_deltas.iter()) {
+    testing::assert_close(delta, expected, 0.0, 0.0);
+  }
+}
+
+#[test]
+fn model_config_roundtrip(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd", "optimizer_params": { "learning_rate": 0.01 },
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let config = ModelConfig::from_json(json).unwrap();
+  assert_eq!(config.layers[0].params["input_size"], "4");
+
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let model = config.build(DeviceManagerFactory::new(), device).unwrap();
+  let rebuilt = ModelConfig::from_model(&model);
+  assert_eq!(rebuilt.layers, config.layers);
+  assert_eq!(rebuilt.optimizer_params.unwrap()["learning_rate"], "0.01");
+
+  // unknown names are refused before building anything
+  let unknown = ModelConfig { loss: "hinge".to_string(), .. config.clone() };
+  assert!(unknown.build(DeviceManagerFactory::new(), device).is_err());
+
+  // as are missing & malformed params of the layers & of the optimizer
+  let mut missing = config.clone();
+  missing.layers[0].params.remove("output_size");
+  assert!(missing.build(DeviceManagerFactory::new(), device).is_err());
+  let mut malformed = config.clone();
+  malformed.layers[0].params.insert("input_size".to_string(), "four".to_string());
+  assert!(malformed.build(DeviceManagerFactory::new(), device).is_err());
+  let mut dropout = config.clone();
+  dropout.layers.push(hal::config::LayerConfig {
+    layer: "dropout".to_string(),
+    params: [("input_size", "2"), ("output_size", "2"), ("rate", "abc")].iter()
+      .map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+  });
+  assert!(dropout.build(DeviceManagerFactory::new(), device).is_err());
+  let malformed = ModelConfig::from_json(&json.replace(r#""learning_rate": 0.01"#, r#""learning_rate": "fast""#)).unwrap();
+  assert!(malformed.build(DeviceManagerFactory::new(), device).is_err());
+  let beta1 = [("beta1".to_string(), "abc".to_string())].iter().cloned().collect();
+  let adam = ModelConfig { optimizer: "adam".to_string(), optimizer_params: Some(beta1), .. config };
+  assert!(adam.validate(&hal::config::Registry::default()).is_err());
+}
+
+#[test]
+fn search_space(){
+  let mut space = tuning::SearchSpace::new();
+  space.add("optimizer.learning_rate", tuning::ParamRange::Values(vec!["0.1".to_string(), "0.01".to_string()]));
+  space.add("layers.0.output_size", tuning::ParamRange::Integer(2, 4));
+  let grid = space.grid().unwrap();
+  assert_eq!(grid.len(), 6);
+
+  // hidden sizes are propagated to the input of the next layer
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform", "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 2, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform", "b_init": "zeros" } }] }"#;
+  let config = tuning::apply(&ModelConfig::from_json(json).unwrap(), &grid[5]).unwrap();
+  assert_eq!(config.layers[0].params["output_size"], "4");
+  assert_eq!(config.layers[1].params["input_size"], "4");
+  assert_eq!(config.optimizer_params.unwrap()["learning_rate"], "0.01");
+
+  // through the dropout layers up to the next dense layer, the dropout rate is a layer param
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform", "b_init": "zeros" } },
+                             { "layer": "dropout", "params": { "input_size": 2, "output_size": 2, "rate": 0.5 } },
+                             { "layer": "dense", "params": { "input_size": 2, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform", "b_init": "zeros" } }] }"#;
+  let base = ModelConfig::from_json(json).unwrap();
+  let mut dropout_space = tuning::SearchSpace::new();
+  dropout_space.add("layers.0.output_size", tuning::ParamRange::Integer(3, 4));
+  dropout_space.add("layers.1.rate", tuning::ParamRange::Values(vec!["0.1".to_string()]));
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  for assignment in dropout_space.grid().unwrap() {
+    let config = tuning::apply(&base, &assignment).unwrap();
+    let size = &assignment["layers.0.output_size"];
+    assert_eq!((&config.layers[1].params["input_size"], &config.layers[1].params["output_size"]), (size, size));
+    assert_eq!(&config.layers[2].params["input_size"], size);
+    assert_eq!(config.layers[1].params["rate"], "0.1");
+    assert!(config.build(DeviceManagerFactory::new(), device).is_ok());
+  }
+
+  // params that the layer or the optimizer does not read are refused
+  let unknown = |name: &str| [(name.to_string(), "0.5".to_string())].iter().cloned().collect::<tuning::Assignment>();
+  assert!(tuning::apply(&base, &unknown("layers.1.dropout")).is_err());
+  assert!(tuning::apply(&base, &unknown("layers.0.rate")).is_err());
+  assert!(tuning::apply(&base, &unknown("optimizer.momentum")).is_err());
+  assert!(tuning::apply(&base, &unknown("optimizer.momemtum")).is_ok());
+
+  // continuous ranges can only be sampled
+  space.add("optimizer.momentum", tuning::ParamRange::LogUniform(1e-3, 1e-1));
+  assert!(space.grid().is_err());
+  for trial in space.sample(10, &mut rand::thread_rng()) {
+    let momentum = trial["optimizer.momentum"].parse::<f64>().unwrap();
+    assert!(momentum >= 1e-3 && momentum < 1e-1);
+  }
+}
+
+#[test]
+fn run_report(){
+  use std::collections::HashMap;
+  let history = |val_loss: Vec<f32>, epoch_time: Vec<f32>| {
+    let mut history = HashMap::new();
+    history.insert("loss".to_string(), val_loss.iter().map(|v| v + 0.1).collect::<Vec<f32>>());
+    history.insert("val_loss".to_string(), val_loss);
+    history.insert("epoch_time".to_string(), epoch_time);
+    history
+  };
+  let config = |learning_rate: &str| ModelConfig::from_json(&format!(
+    r#"{{ "loss": "mse", "optimizer": "sgd", "optimizer_params": {{ "learning_rate": {} }},
+          "layers": [{{ "layer": "dense", "params": {{ "input_size": 2, "output_size": 1
+                                                     , "activation": "linear"
+                                                     , "w_init": "glorot_uniform", "b_init": "zeros" }} }}] }}"#
+    , learning_rate)).unwrap();
+
+  let mut comparison = report::Report::new("val_loss", false);
+  comparison.add(report::Run::new("slow", history(vec![1.0, 0.8, 0.6, 0.5], vec![1.0, 1.0, 1.0, 1.0])
+                                  , Some(&config("0.01"))));
+  comparison.add(report::Run::new("fast", history(vec![1.0, 0.5, 0.3, 0.299], vec![1.0, 3.0, 2.0, 2.0])
+                                  , Some(&config("0.1"))));
+  comparison.add(report::Run::new("crashed", HashMap::new(), Some(&config("10"))));
+  assert_eq!(comparison.differing_params(), vec!["optimizer.learning_rate".to_string()]);
+
+  let summaries = comparison.summarize();
+  let names: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+  assert_eq!(names, vec!["fast", "slow", "crashed"]);
+  assert_eq!((summaries[0].best_value, summaries[0].best_epoch), (Some(0.299), Some(4)));
+  assert_eq!(summaries[0].epochs_to_converge, Some(3));
+  assert_eq!(summaries[1].epochs_to_converge, Some(4));
+  assert_eq!((summaries[0].epochs, summaries[0].time_per_epoch), (4, Some(2.0)));
+  assert_eq!(summaries[0].config_diff["optimizer.learning_rate"], "0.1");
+  assert_eq!((summaries[2].best_value, summaries[2].epochs), (None, 0));
+  assert!(comparison.to_json().contains("\"epochs_to_converge\": 3"));
+
+  // the metrics files of hal-train
+  let path = env::temp_dir().join("hal_run_report.json").to_str().unwrap().to_string();
+  std::fs::write(&path, r#"{ "val_loss": [0.4, 0.2], "batch_loss": [0.5, 0.4, 0.3] }"#).unwrap();
+  let loaded = report::Run::load("loaded", &path, None).unwrap();
+  assert_eq!(loaded.history["val_loss"], vec![0.4, 0.2]);
+  assert!(loaded.params.is_empty());
+  assert!(report::Run::load("missing", "/nonexistent/metrics.json", None).is_err());
+}
+
+#[test]
+fn top_k_decoding(){
+  // 2 rows of 4 class probabilities
+  let p = utils::vec_to_array::<f32>(vec![0.1, 0.5, 0.2, 0.15, 0.6, 0.3, 0.1, 0.05], Dim4::new(&[2, 4, 1, 1]));
+  let (values, indices) = utils::top_k(&p, 2);
+  assert_eq!(utils::array_to_vec(&values), vec![0.6, 0.5, 0.2, 0.3].iter().map(|&v: &f32| v as f64).collect::<Vec<_>>());
+  assert_eq!(utils::array_to_vec(&indices), vec![2.0, 0.0, 1.0, 2.0]);
+  let (_, order) = utils::argsort(&p, 1, true);
+  assert_eq!(utils::array_to_vec(&af::col(&order, 3)), vec![2.0, 0.0]);
+  let columns = utils::vec_to_array::<u32>(vec![3, 0], Dim4::new(&[2, 1, 1, 1]));
+  assert_eq!(utils::array_to_vec(&utils::gather_cols(&p, &columns)), vec![0.1f32 as f64, 0.5f32 as f64]);
+
+  // the target class is the second most probable class of row 0 & the third of row 1
+  let target = utils::one_hot(&utils::vec_to_array::<f32>(vec![1.0, 1.0], Dim4::new(&[2, 1, 1, 1])), 4);
+  assert_eq!(metrics::top_k_accuracy(&p, &target, 2), 0.5);
+  let mut metric = metrics::get_metric("top_3_accuracy").unwrap();
+  metric.update(&p, &target);
+  assert_eq!((metric.name(), metric.value()), ("top_3_accuracy".to_string(), 1.0));
+  assert!(metrics::get_metric("top_accuracy").is_err());
+
+  // draws stay among the k most probable classes & in the nucleus
+  random::set_seed(7);
+  for _ in 0..10 {
+    let drawn = utils::array_to_vec(&decode::top_k_sample(&p, 2));
+    assert!(drawn[0] == 2.0 || drawn[0] == 1.0);
+    assert!(drawn[1] == 0.0 || drawn[1] == 2.0);
+    assert_eq!(utils::array_to_vec(&decode::nucleus_sample(&p, 0.5)), vec![2.0, 0.0]);
+  }
+
+  // the second token is drawn from the same distribution for every beam
+  let log_probs = |values: Vec<f32>| af::log(&utils::vec_to_array::<f32>(values, Dim4::new(&[1, 3, 1, 1])));
+  let next = log_probs(vec![0.1, 0.2, 0.7]);
+  let beams = decode::beam_search(&log_probs(vec![0.5, 0.4, 0.1]), 2, 2, |parents, _| {
+    af::tile(&next, Dim4::new(&[parents.dims()[0], 1, 1, 1]))
+  });
+  assert_eq!(beams.iter().map(|b| b.0.clone()).collect::<Vec<_>>(), vec![vec![0, 2], vec![1, 2]]);
+  assert!((beams[0].1 - 0.35f32.ln()).abs() < 1e-5);
+}
+
+#[test]
+fn halving_schedule(){
+  assert_eq!(tuning::halving_schedule(9, 1, 3), vec![(9, 1), (3, 3), (1, 9)]);
+  assert_eq!(tuning::halving_schedule(10, 2, 2), vec![(10, 2), (5, 4), (2, 8), (1, 16)]);
+  assert_eq!(tuning::halving_schedule(1, 5, 3), vec![(1, 5)]);
+}
+
+#[test]
+fn parallel_search(){
+  use std::sync::Arc;
+  use std::thread;
+
+  // every thread tracks & selects its own device
+  let manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let thread_manager = manager.clone();
+  let selected = thread::spawn(move || {
+    thread_manager.swap_device(device);
+    (thread_manager.current_device(), af::get_device())
+  }).join().unwrap();
+  assert_eq!(selected, (device, device.id));
+
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let mut space = tuning::SearchSpace::new();
+  space.add("optimizer.learning_rate", tuning::ParamRange::Values(vec!["0.1".to_string(), "0.01".to_string()]));
+  let assignments = space.grid().unwrap();
+
+  // the sources are built on the threads [their cursors are not shared]
+  let values: Vec<f32> = (0..20).map(|v| v as f32 / 20.0).collect();
+  let make_source = Arc::new(move || {
+    let input = utils::vec_to_array::<f32>(values.clone(), Dim4::new(&[10, 2, 1, 1]));
+    let target = af::sum(&input, 1);
+    ArraySource::new(input, target, 2, 0.0, 0.2, false)
+  });
+  let results = tuning::search_parallel::<ArraySource, f32, _>(&ModelConfig::from_json(json).unwrap(), &assignments
+                                                               , manager, &vec![device], make_source, device, 2, 2
+                                                               , &vec![], "loss", false).unwrap();
+  assert_eq!(results.len(), 2);
+  assert!(results.iter().all(|r| r.history["loss"].len() == 2 && r.values.contains_key("loss")));
+}
+
+#[test]
+fn random_generators(){
+  use hal::random::RandomGenerator;
+
+  // counter based streams only depend on the seed
+  let mut generator = random::CounterGenerator::new(42);
+  let first: Vec<u64> = (0..4).map(|_| generator.next_u64()).collect();
+  generator.reseed(42);
+  let second: Vec<u64> = (0..4).map(|_| generator.next_u64()).collect();
+  assert_eq!(first, second);
+
+  // 0.5, 0.75, 0.25, 0.375, ..
+  let mut sobol = random::SobolGenerator::new(0);
+  let unit = |x: u64| x as f64 / 2f64.powi(64);
+  let points: Vec<f64> = (0..4).map(|_| unit(sobol.next_u64())).collect();
+  assert_eq!(points, vec![0.5, 0.75, 0.25, 0.375]);
+
+  // the initializations are reproducible from the thread generator
+  let dims = Dim4::new(&[4, 4, 1, 1]);
+  random::set_seed(7);
+  let a = initializations::uniform::<f32>(dims, -1.0, 1.0);
+  random::set_seed(7);
+  let b = initializations::uniform::<f32>(dims, -1.0, 1.0);
+  assert_eq!(af::max_all(&af::abs(&af::sub(&a, &b, false))).0, 0.0);
+
+  random::set_generator(Box::new(random::SobolGenerator::new(0)));
+  assert_eq!(random::generator_name(), "sobol");
+  random::set_generator(Box::new(random::CounterGenerator::new(0)));
+}
+
+#[test]
+fn sgld_noise_and_samples(){
+  // with zero gradients every step only injects N(0, lr) noise
+  let mut param_manager = ParamManager::default();
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  param_manager.add_dense::<f32>(device_manager, device, 100, 100, "tanh", "zeros", "zeros");
+
+  let mut sgld = SGLD { learning_rate: 0.02, burn_in: 0, thinning: 1, max_samples: 2, .. SGLD::default() };
+  sgld.setup(param_manager.get_all_dims());
+  for _ in 0..3 {
+    sgld.update(&mut param_manager, 1);
+  }
+  assert_eq!(sgld.get_samples().len(), 2);
+
+  // weights after 3 steps ~ N(0, 3 * lr)
+  let std_dev = (af::var_all(&param_manager.get_weight(0, 0), false).0 as f32).sqrt();
+  let expected = (3.0f32 * 0.02).sqrt();
+  assert!((std_dev - expected).abs() <= 0.05 * expected
+          , "sgld noise std of {} vs {}", std_dev, expected);
+}
+
+#[test]
+fn ensemble_mean_variance(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let config = ModelConfig::from_json(json).unwrap();
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut ensemble = Ensemble::new(&config, device_manager.clone(), &vec![device], 3).unwrap();
+  assert_eq!(ensemble.members.len(), 3);
+
+  let inputs = initializations::uniform::<f32>(Dim4::new(&[5, 4, 1, 1]), -1.0, 1.0);
+  let predictions = ensemble.predict_mean_variance::<f32>(&inputs, device, device);
+  let (ref mean, ref variance) = predictions[0];
+  assert_eq!(mean.dims()[0], 5);
+  assert_eq!(mean.dims()[1], 2);
+  // independently initialized members disagree
+  assert!(af::sum_all(variance).0 > 0.0);
+  assert!(af::min_all(variance).0 > -1e-6);
+
+  // the artifact restores the same members
+  let dir = env::temp_dir().join("hal_ensemble_mean_variance");
+  let dir = dir.to_str().unwrap();
+  ensemble.save(dir).unwrap();
+  ensemble.members[0].save_checkpoint(&format!("{}/epoch_0000.ckpt", dir), 0).unwrap();
+  let mut loaded = Ensemble::load(dir, device_manager, &vec![device]).unwrap();
+  assert_eq!(loaded.members.len(), 3);
+  let loaded_mean = loaded.predict_mean_variance::<f32>(&inputs, device, device).remove(0).0;
+  let diff = af::max_all(&af::abs(&af::sub(mean, &loaded_mean, false))).0;
+  assert!(diff <= 1e-6, "loaded ensemble differs by {}", diff);
+}
+
+#[test]
+fn quantized_dense(){
+  // per channel scales reconstruct every column within half a step
+  let values = vec![0.5f32, -1.0, 0.25, 4.0, -2.0, 1.0];
+  let (quantized, scales) = quantize::quantize_per_channel(&values, 3, 2);
+  assert_eq!(quantized[1], -127);
+  assert_eq!(quantized[3], 127);
+  for (i, &x) in values.iter().enumerate() {
+    assert!((quantized[i] as f32 * scales[i / 3] - x).abs() <= scales[i / 3] / 2.0 + 1e-6);
+  }
+
+  // the quantized forward pass follows the float model
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 8, "output_size": 6
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 6, "output_size": 3
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let samples = initializations::uniform::<f32>(Dim4::new(&[64, 8, 1, 1]), -1.0, 1.0);
+  let ranges = quantize::calibrate::<f32>(&mut model, &samples, device, 16);
+  assert!(ranges[0] > 0.9 && ranges[0] <= 1.0);
+
+  let quantized = QuantizedModel::from_model(&model, &ranges).unwrap();
+  let expected = model.forward::<f32>(&samples, device, device).remove(0);
+  let diff = af::max_all(&af::abs(&af::sub(&expected, &quantized.forward(&samples), false))).0;
+  assert!(diff < 0.05, "quantized outputs differ by {}", diff);
+  assert!(quantized.size_in_bytes() < 4 * (8 * 6 + 6 + 6 * 3 + 3));
+}
+
+#[test]
+fn layerwise_relevance(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 3
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dense", "params": { "input_size": 3, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  let inputs = initializations::uniform::<f32>(Dim4::new(&[3, 4, 1, 1]), 0.1, 1.0);
+
+  // without biases the z-rule conserves the explained output
+  let relevance = explain::lrp::<f32>(&mut model, &inputs, device, Some(1), explain::LRPRule::Epsilon(0.0)).unwrap();
+  assert_eq!(relevance.dims()[1], 4);
+  let outputs = model.forward::<f32>(&inputs, device, device).remove(0);
+  model.get_param_manager().reset_all_unrolls();
+  let diff = testing::max_abs_diff(&af::sum(&relevance, 1), &af::col(&outputs, 1));
+  assert!(diff <= 1e-4, "relevance is not conserved [{}]", diff);
+
+  // z+ distributes the relevance of the best class to the positive contributions
+  let relevance = explain::lrp::<f32>(&mut model, &inputs, device, None, explain::LRPRule::AlphaBeta(1.0, 0.0)).unwrap();
+  assert_eq!(relevance.dims()[0], 3);
+}
+
+#[test]
+fn counterfactual_search(){
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  // the logits are the inputs
+  model.set_params(&vec![testing::from_rows(&[[1.0, 0.0], [0.0, 1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+
+  let input = testing::from_rows(&[[1.0, 0.0]]);
+  let result = explain::counterfactual::<f32>(&mut model, &input, device, 1, 0.01, 0.5, 100);
+  assert!(result.found, "no counterfactual after {} iterations", result.iterations);
+  let x = testing::to_rows(&result.input);
+  assert!(x[0][1] > x[0][0]);
+  assert!(result.l1_distance > 0.0 && result.l1_distance < 2.0);
+  assert!(testing::all_close(&af::add(&input, &result.perturbation, false), &result.input, 1e-6, 0.0));
+
+  // already the desired class: nothing to change
+  let result = explain::counterfactual::<f32>(&mut model, &input, device, 0, 0.01, 0.5, 100);
+  assert!(result.found && result.iterations == 0 && result.l1_distance == 0.0);
+
+  // the stopping test ignores the dropout noise of the logits
+  let json = r#"{ "loss": "cross_entropy_softmax", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 2
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } },
+                             { "layer": "dropout", "params": { "input_size": 2, "output_size": 2
+                                                             , "rate": 0.9 } }] }"#;
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.0, 0.0], [0.0, 1.0]])
+                         , utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+  let input = testing::from_rows(&[[0.0, 1.0], [0.0, 2.0], [0.0, 3.0]]);
+  let result = explain::counterfactual::<f32>(&mut model, &input, device, 1, 0.01, 0.5, 100);
+  assert!(result.found && result.iterations == 0 && result.l1_distance == 0.0);
+}
+
+#[test]
+fn training_influence(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[1.8]])
+                         , utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+
+  // y = 2x with the last sample mislabeled
+  let train_input = testing::from_rows(&[[1.0], [2.0], [3.0], [4.0]]);
+  let train_target = testing::from_rows(&[[2.0], [4.0], [6.0], [-8.0]]);
+  let test_input = testing::from_rows(&[[2.5]]);
+  let test_target = testing::from_rows(&[[5.0]]);
+  let scores = explain::influence::<f32>(&mut model, &train_input, &train_target, &test_input
+                                         , &test_target, device, None, 0.01, 10, 3);
+  assert_eq!(scores.len(), 4);
+  assert!(scores[3] > 0.0, "the mislabeled sample should be harmful: {:?}", scores);
+  assert!(scores[..3].iter().all(|&s| s < 0.0), "the clean samples should be helpful: {:?}", scores);
+}
+
+#[test]
+fn gradient_checkpointing(){
+  let dense = |input_size: u64, output_size: u64| format!(
+    r#"{{ "layer": "dense", "params": {{ "input_size": {}, "output_size": {}, "activation": "tanh"
+                                       , "w_init": "glorot_uniform", "b_init": "zeros" }} }}"#
+    , input_size, output_size);
+  let json = format!(r#"{{ "loss": "mse", "optimizer": "sgd", "layers": [{}, {}, {}, {}] }}"#
+                     , dense(4, 6), dense(6, 6), dense(6, 6), dense(6, 2));
+  let config = ModelConfig::from_json(&json).unwrap();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = config.build(DeviceManagerFactory::new(), device).unwrap();
+  let mut checkpointed = config.build(DeviceManagerFactory::new(), device).unwrap();
+  checkpointed.set_params(&model.get_param_manager().get_all_arrays());
+  assert!(checkpointed.set_gradient_checkpoints(vec![(1, 1)]).is_err());
+  assert!(checkpointed.set_gradient_checkpoints(vec![(0, 2), (2, 3)]).is_err());
+  checkpointed.set_gradient_checkpoints(vec![(0, 2)]).unwrap();
+
+  let inputs = initializations::uniform::<f32>(Dim4::new(&[5, 4, 1, 1]), -1.0, 1.0);
+  let targets = initializations::uniform::<f32>(Dim4::new(&[5, 2, 1, 1]), -1.0, 1.0);
+  let predictions = model.forward::<f32>(&inputs, device, device);
+  let checkpointed_predictions = checkpointed.forward::<f32>(&inputs, device, device);
+  // the inner activations of the segment are released
+  assert_eq!(checkpointed.get_param_manager().get_input(1, 0).elements(), 1);
+
+  // and the recomputed ones give the same gradients
+  model.backward(&predictions, &targets, None);
+  checkpointed.backward(&checkpointed_predictions, &targets, None);
+  let deltas = model.get_param_manager().get_all_deltas();
+  for (expected, delta) in deltas.iter().zip(checkpointed.get_param_manager().get_all_deltas().iter()) {
+    let diff = af::max_all(&af::abs(&af::sub(expected, delta, false))).0;
+    assert!(diff <= 1e-6, "checkpointed gradients differ by {}", diff);
+  }
+
+  // inference passes that read the layer inputs see the real activations
+  let ranges = quantize::calibrate::<f32>(&mut model, &inputs, device, 5);
+  let checkpointed_ranges = quantize::calibrate::<f32>(&mut checkpointed, &inputs, device, 5);
+  for (expected, range) in ranges.iter().zip(checkpointed_ranges.iter()) {
+    assert!((expected - range).abs() <= 1e-6, "checkpointed ranges {:?} != {:?}", checkpointed_ranges, ranges);
+  }
+  let relevance = explain::lrp::<f32>(&mut model, &inputs, device, Some(1), explain::LRPRule::Epsilon(1e-6)).unwrap();
+  let checkpointed_relevance = explain::lrp::<f32>(&mut checkpointed, &inputs, device, Some(1)
+                                                   , explain::LRPRule::Epsilon(1e-6)).unwrap();
+  testing::assert_close(&checkpointed_relevance, &relevance, 1e-5, 0.0);
+}
+
+#[test]
+fn conformal_quantile(){
+  let scores: Vec<f32> = (1..10).map(|x| x as f32).collect();
+  assert_eq!(conformal::conformal_quantile(&scores, 0.1), 9.0);
+  assert_eq!(conformal::conformal_quantile(&scores, 0.2), 8.0);
+  assert_eq!(conformal::conformal_quantile(&scores, 0.5), 5.0);
+  // not enough calibration samples for 95% coverage
+  assert!(conformal::conformal_quantile(&scores, 0.05).is_infinite());
+}
+
+#[test]
+fn conformal_coverage(){
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let build = |loss: &str, output_size: u64| ModelConfig::from_json(&format!(
+    r#"{{ "loss": "{}", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": 1, "output_size": {}
+                                                  , "activation": "linear"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}] }}"#, loss, output_size)).unwrap()
+    .build(DeviceManagerFactory::new(), device).unwrap();
+  let (alpha, num_calibration, num_test) = (0.1, 500, 2000);
+  let uniform = |n: u64, low: f32, high: f32| initializations::uniform::<f32>(Dim4::new(&[n, 1, 1, 1]), low, high);
+  random::set_seed(7);
+
+  // y = x + U(-1, 1) around the identity regressor
+  let mut regressor = build("mse", 1);
+  regressor.set_params(&vec![testing::from_rows(&[[1.0]]), utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+  let sample = |n: u64| {
+    let x = uniform(n, -2.0, 2.0);
+    let y = af::add(&x, &uniform(n, -1.0, 1.0), false);
+    (x, y)
+  };
+  let (x, y) = sample(num_calibration);
+  let intervals = conformal::ConformalRegressor::calibrate::<_, f32>(&mut regressor, &x, &y, device, alpha);
+  let (x, y) = sample(num_test);
+  let (lower, upper) = intervals.predict_intervals::<_, f32>(&mut regressor, &x, device, device);
+  let inside = af::mul(&utils::cast(&af::ge(&y, &lower, false), DType::F32)
+                       , &utils::cast(&af::le(&y, &upper, false), DType::F32), false);
+  let coverage = af::sum_all(&inside).0 as f64 / num_test as f64;
+  assert!((coverage - 0.9).abs() <= 0.05, "regression coverage of {} vs 0.9", coverage);
+
+  // labels drawn from the softmax of the classifier: p(class 0) = sigmoid(2x)
+  let mut classifier = build("cross_entropy_softmax", 2);
+  classifier.set_params(&vec![testing::from_rows(&[[1.0, -1.0]]), utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0)]);
+  let sample = |n: u64| {
+    let x = uniform(n, -2.0, 2.0);
+    let p0 = af::div(&1.0f32, &af::add(&1.0f32, &af::exp(&af::mul(&x, &-2.0f32, false)), false), false);
+    let first = utils::cast(&af::lt(&uniform(n, 0.0, 1.0), &p0, false), DType::F32);
+    let second = af::sub(&1.0f32, &first, false);
+    (x, af::join(1, &first, &second))
+  };
+  let (x, y) = sample(num_calibration);
+  let sets = conformal::ConformalClassifier::calibrate::<_, f32>(&mut classifier, &x, &y, device, alpha);
+  let (x, y) = sample(num_test);
+  let covered = af::mul(&sets.predict_sets::<_, f32>(&mut classifier, &x, device, device), &y, false);
+  let coverage = af::sum_all(&covered).0 as f64 / num_test as f64;
+  assert!((coverage - 0.9).abs() <= 0.05, "classification coverage of {} vs 0.9", coverage);
+}
+
+#[test]
+fn magnitude_pruning(){
+  let mut param_manager = ParamManager::default();
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  param_manager.add_dense::<f32>(device_manager.clone(), device, 10, 10, "tanh", "glorot_uniform", "ones");
+  param_manager.add_dense::<f32>(device_manager, device, 10, 4, "tanh", "glorot_uniform", "ones");
+
+  // per array: every layer loses exactly the requested fraction
+  let masks = prune::magnitude_masks(&param_manager, 0.5, false);
+  assert_eq!(masks.len(), 4);
+  prune::apply_masks(&param_manager, &masks);
+  assert_eq!(prune::layer_sparsity(&param_manager), vec![0.5, 0.5]);
+  assert_eq!(af::sum_all(&param_manager.get_bias(0, 0)).0, 10.0);
+
+  // global: the smallest weights of the whole model
+  let masks = prune::magnitude_masks(&param_manager, 0.75, true);
+  let num_kept: f64 = [0, 2].iter().map(|&i| af::sum_all(&masks[i]).0).sum();
+  assert_eq!(num_kept, ((100 + 40) / 4) as f64);
+}
+
+#[test]
+fn weight_averaging(){
+  let mut param_manager = ParamManager::default();
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  param_manager.add_dense::<f32>(device_manager, device, 2, 2, "linear", "ones", "zeros");
+  let dims = Dim4::new(&[2, 2, 1, 1]);
+  let filled = |v: f32| Array::new::<f32>(&[v; 4], dims);
+
+  // weights 1, 3, 5 --> ema(0.5): 1 -> 2 -> 3.5 | swa from step 2: (3 + 5) / 2 = 4
+  let mut ema = WeightAverage::ema(0.5);
+  let mut swa = WeightAverage::swa(2, 1);
+  for &v in [1.0f32, 3.0, 5.0].iter() {
+    param_manager.set_array_from_index(filled(v), 0);
+    ema.update(&param_manager);
+    swa.update(&param_manager);
+  }
+  assert_eq!(swa.num_averaged, 2);
+  assert_eq!(af::mean_all(&ema.get_arrays()[0]).0, 3.5);
+  assert_eq!(af::mean_all(&swa.get_arrays()[0]).0, 4.0);
+
+  // swapping twice restores the trained weights
+  assert!(swa.swap(&param_manager));
+  assert_eq!(af::mean_all(&param_manager.get_weight(0, 0)).0, 4.0);
+  assert!(swa.swap(&param_manager));
+  assert_eq!(af::mean_all(&param_manager.get_weight(0, 0)).0, 5.0);
+  assert!(!WeightAverage::ema(0.9).swap(&param_manager));
+}
+
+///
+/// test metrics
+///
+
+#[test]
+fn hamming_loss(){
+  // column major [2 samples x 2 labels]
+  let dims = Dim4::new(&[2, 2, 1, 1]);
+  let pred = Array::new::<f32>(&[1.0, 0.0, 1.0, 1.0], dims);
+  let target = Array::new::<f32>(&[1.0, 1.0, 1.0, 0.0], dims);
+  let hamming = metrics::hamming_loss(&pred, &target);
+  assert!((hamming - 0.5).abs() <= 1e-6, "hamming loss of {} vs 0.5", hamming);
+}
+
+#[test]
+fn per_label_f1(){
+  // label 0: tp = 1, fn = 1 --> 2/3 | label 1: tp = 1, fp = 1 --> 2/3
+  let dims = Dim4::new(&[2, 2, 1, 1]);
+  let pred = Array::new::<f32>(&[1.0, 0.0, 1.0, 1.0], dims);
+  let target = Array::new::<f32>(&[1.0, 1.0, 1.0, 0.0], dims);
+  let f1 = metrics::per_label_f1(&pred, &target);
+  for score in f1 {
+    assert!((score - 2.0/3.0).abs() <= 1e-6, "f1 of {} vs 0.667", score);
+  }
+}
+
+#[test]
+fn multihead_loss_weights(){
+  let dense = |input_size: u64, output_size: u64| format!(
+    r#"{{ "loss": "mse", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": {}, "output_size": {}
+                                                  , "activation": "tanh"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}] }}"#
+    , input_size, output_size);
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let manager = DeviceManagerFactory::new();
+  let build = |input_size, output_size| ModelConfig::from_json(&dense(input_size, output_size)).unwrap()
+    .build(manager.clone(), device).unwrap();
+  let mut model = hal::model::MultiHead::new(build(2, 3), vec![build(3, 2), build(3, 1)]);
+
+  // the second head does not contribute to the objective
+  model.set_loss_weights(vec![1.0, 0.0]);
+  model.add_metric(0, Box::new(metrics::BinaryCurve::new("roc_auc", 10, 0)));
+  let frozen = model.heads[1].get_param_manager().get_all_arrays();
+  let input = initializations::uniform::<f32>(Dim4::new(&[10, 2, 1, 1]), -1.0, 1.0);
+  let target = initializations::uniform::<f32>(Dim4::new(&[10, 3, 1, 1]), 0.0, 1.0);
+  let source = ArraySource::new(input, target, 2, 0.0, 0.2, false);
+  let losses = model.fit::<ArraySource, f32>(&source, device, 2, 2, &vec![(0, 1), (2, 2)]);
+  assert_eq!(losses.len(), 8);
+
+  for (arr, expected) in model.heads[1].get_param_manager().get_all_arrays().iter().zip(frozen.iter()) {
+    testing::assert_close(arr, expected, 0.0, 0.0);
+  }
+  let history = model.get_history();
+  assert_eq!(history["loss"], history["head0_loss"]);
+  assert_eq!(history["head1_loss"].len(), 2);
+  assert_eq!(history["val_head0_roc_auc"].len(), 2);
+  assert!(history.contains_key("val_head1_loss"));
+}
+
+#[test]
+#[should_panic(expected = "need at least one minibatch")]
+fn multihead_fit_without_minibatches(){
+  let dense = |input_size: u64, output_size: u64| format!(
+    r#"{{ "loss": "mse", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": {}, "output_size": {}
+                                                  , "activation": "tanh"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}] }}"#
+    , input_size, output_size);
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let manager = DeviceManagerFactory::new();
+  let build = |input_size, output_size| ModelConfig::from_json(&dense(input_size, output_size)).unwrap()
+    .build(manager.clone(), device).unwrap();
+  let mut model = hal::model::MultiHead::new(build(2, 3), vec![build(3, 2), build(3, 1)]);
+
+  // 4 samples never fill a minibatch of 8, the epoch losses would be NaN
+  let input = initializations::uniform::<f32>(Dim4::new(&[4, 2, 1, 1]), -1.0, 1.0);
+  let target = initializations::uniform::<f32>(Dim4::new(&[4, 3, 1, 1]), 0.0, 1.0);
+  let source = ArraySource::new(input, target, 2, 0.0, 0.0, false);
+  model.fit::<ArraySource, f32>(&source, device, 1, 8, &vec![(0, 1), (2, 2)]);
+}
+
+#[test]
+fn multihead_inference(){
+  let dense = |input_size: u64, output_size: u64| format!(
+    r#"{{ "loss": "mse", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": {}, "output_size": {}
+                                                  , "activation": "tanh"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}] }}"#
+    , input_size, output_size);
+  let trunk = r#"{ "loss": "mse", "optimizer": "sgd",
+                   "layers": [{ "layer": "dense", "params": { "input_size": 2, "output_size": 3
+                                                            , "activation": "tanh"
+                                                            , "w_init": "glorot_uniform"
+                                                            , "b_init": "zeros" } },
+                              { "layer": "dropout", "params": { "input_size": 3, "output_size": 3
+                                                              , "rate": 0.5 } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let manager = DeviceManagerFactory::new();
+  let build = |json: &str| ModelConfig::from_json(json).unwrap().build(manager.clone(), device).unwrap();
+  let mut model = hal::model::MultiHead::new(build(trunk), vec![build(&dense(3, 2)), build(&dense(3, 1))]);
+  model.add_metric(0, Box::new(metrics::BinaryCurve::new("roc_auc", 10, 0)));
+
+  // the trunk dropout is disabled & no activations are kept
+  let input = initializations::uniform::<f32>(Dim4::new(&[10, 2, 1, 1]), -1.0, 1.0);
+  let first = model.infer::<f32>(&input, device);
+  let second = model.infer::<f32>(&input, device);
+  for (f, s) in first.iter().zip(second.iter()) {
+    testing::assert_close(&f[0], &s[0], 0.0, 0.0);
+  }
+  assert_eq!(model.trunk.get_param_manager().get_current_unroll(0), 0);
+
+  // so the validation losses & metrics are deterministic
+  let target = initializations::uniform::<f32>(Dim4::new(&[10, 3, 1, 1]), 0.0, 1.0);
+  let source = ArraySource::new(input, target, 2, 0.0, 0.2, false);
+  let columns = vec![(0, 1), (2, 2)];
+  let values = model.evaluate::<ArraySource, f32>(&source, device, 2, &columns);
+  assert_eq!(values, model.evaluate::<ArraySource, f32>(&source, device, 2, &columns));
+}
+
+#[test]
+fn multihead_shared_trunk(){
+  let dense = |input_size: u64, output_size: u64| format!(
+    r#"{{ "loss": "mse", "optimizer": "sgd",
+         "layers": [{{ "layer": "dense", "params": {{ "input_size": {}, "output_size": {}
+                                                  , "activation": "tanh"
+                                                  , "w_init": "glorot_uniform"
+                                                  , "b_init": "zeros" }} }}] }}"#
+    , input_size, output_size);
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let manager = DeviceManagerFactory::new();
+  let build = |input_size, output_size| ModelConfig::from_json(&dense(input_size, output_size)).unwrap()
+    .build(manager.clone(), device).unwrap();
+  let mut model = hal::model::MultiHead::new(build(2, 3), vec![build(3, 2), build(3, 1)]);
+  let input = initializations::uniform::<f32>(Dim4::new(&[4, 2, 1, 1]), -1.0, 1.0);
+  let targets = vec![initializations::uniform::<f32>(Dim4::new(&[4, 2, 1, 1]), 0.0, 1.0)
+                     , initializations::uniform::<f32>(Dim4::new(&[4, 1, 1, 1]), 0.0, 1.0)];
+
+  // trunk deltas of a single forward & backward pass with the provided loss weights
+  let trunk_deltas = |model: &mut hal::model::MultiHead, loss_weights: Vec<f32>| {
+    model.set_loss_weights(loss_weights);
+    let predictions = model.forward::<f32>(&input, device, device);
+
+    // the trunk holds the activations of a single pass for both heads
+    assert_eq!(model.trunk.get_param_manager().get_current_unroll(0), 1);
+    for head in model.heads.iter() {
+      assert_eq!(head.get_param_manager().get_current_unroll(0), 1);
+    }
+    model.backward(&predictions, &targets, None);
+    assert_eq!(model.trunk.get_param_manager().get_current_unroll(0), 0);
+
+    let deltas = model.trunk.get_param_manager().get_all_deltas();
+    model.trunk.get_param_manager().zero_all_deltas();
+    for head in model.heads.iter() {
+      head.get_param_manager().zero_all_deltas();
+    }
+    deltas
+  };
+
+  // the deltas that the heads push into the trunk are summed
+  let first = trunk_deltas(&mut model, vec![1.0, 0.0]);
+  let second = trunk_deltas(&mut model, vec![0.0, 1.0]);
+  let both = trunk_deltas(&mut model, vec![1.0, 1.0]);
+  for ((d1, d2), d) in first.iter().zip(second.iter()).zip(both.iter()) {
+    assert!(utils::array_to_vec(d1).iter().any(|&v| v != 0.0));
+    testing::assert_close(d, &af::add(d1, d2, false), 1e-6, 1e-5);
+  }
+}
+
+#[test]
+fn group_fairness(){
+  use std::collections::HashMap;
+  use hal::metrics::GroupFairness;
+  // column 0 of the inputs is the group: group 0 gets 2 / 3 positives, group 1 gets 1 / 3
+  let inputs = testing::from_rows(&[[0.0, 0.3], [0.0, 0.1], [0.0, 0.7], [1.0, 0.2], [1.0, 0.9], [1.0, 0.4]]);
+  let pred = testing::from_rows(&[[0.9], [0.8], [0.1], [0.7], [0.2], [0.1]]);
+  let target = testing::from_rows(&[[1.0], [0.0], [0.0], [1.0], [1.0], [0.0]]);
+
+  let mut fairness = GroupFairness::new("equalized_odds", 0, 1);
+  fairness.update_with_inputs(&pred, &target, &inputs);
+  assert_eq!(fairness.groups(), vec![0, 1]);
+  assert!((fairness.demographic_parity_gap() - 1.0 / 3.0).abs() < 1e-6);
+  // tpr: 1 vs 1/2 | fpr: 1/2 vs 0
+  assert!((fairness.value() - 0.5).abs() < 1e-6);
+  let values: HashMap<String, f32> = fairness.values().into_iter().collect();
+  assert!((values["accuracy[0]"] - 2.0 / 3.0).abs() < 1e-6);
+  assert_eq!(values["tpr[1]"], 0.5);
+  assert_eq!(values["fpr[1]"], 0.0);
+
+  fairness.reset();
+  assert_eq!(fairness.groups().len(), 0);
+
+  // batches without inputs are skipped instead of attributed to a group
+  fairness.update(&pred, &target);
+  assert_eq!((fairness.groups().len(), fairness.num_skipped()), (0, 6));
+}
+
+#[test]
+fn binary_curve(){
+  // pairs ranked correctly: 3 / 4 | average precision: 0.5 * 1 + 0.5 * 2/3
+  let dims = Dim4::new(&[4, 1, 1, 1]);
+  let pred = Array::new::<f32>(&[0.9, 0.6, 0.4, 0.1], dims);
+  let target = Array::new::<f32>(&[1.0, 0.0, 1.0, 0.0], dims);
+  let mut roc = metrics::BinaryCurve::new("roc_auc", 100, 0);
+  let mut pr = metrics::BinaryCurve::new("pr_auc", 100, 0);
+  roc.update(&pred, &target);
+  pr.update(&pred, &target);
+  assert!((roc.value() - 0.75).abs() <= 1e-6, "roc auc of {} vs 0.75", roc.value());
+  assert!((pr.value() - 5.0/6.0).abs() <= 1e-6, "pr auc of {} vs 0.833", pr.value());
+
+  // streaming the same samples in two batches gives the same area
+  roc.reset();
+  let half = Dim4::new(&[2, 1, 1, 1]);
+  roc.update(&Array::new::<f32>(&[0.9, 0.6], half), &Array::new::<f32>(&[1.0, 0.0], half));
+  roc.update(&Array::new::<f32>(&[0.4, 0.1], half), &Array::new::<f32>(&[1.0, 0.0], half));
+  assert!((roc.value() - 0.75).abs() <= 1e-6, "streaming roc auc of {} vs 0.75", roc.value());
+}
+
+#[test]
+fn rolling_window(){
+  let mut window = monitor::RollingWindow::new(2);
+  window.push(2.0, 4.0);
+  window.push(6.0, 4.0);
+  assert_eq!(window.mean(), 1.0);
+  // the first batch drops out of the window
+  window.push(0.0, 2.0);
+  assert_eq!(window.mean(), 1.0);
+  window.push(0.0, 2.0);
+  assert_eq!(window.mean(), 0.0);
+
+  let dims = Dim4::new(&[4, 2, 1, 1]);
+  let pred = Array::new::<f32>(&[0.9, 0.2, 0.6, 0.4, 0.1, 0.8, 0.4, 0.6], dims);
+  let target = Array::new::<f32>(&[1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0], dims);
+  assert_eq!(metrics::accuracy(&pred, &target), 0.5);
+}
+
+#[test]
+fn streaming_evaluation(){
+  use std::sync::{Arc, Mutex};
+
+  // y = 2x against a target of 0: the per sample mse of x is 0.5 * (2x)^2
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 1, "output_size": 1
+                                                           , "activation": "linear"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut model = ModelConfig::from_json(json).unwrap().build(DeviceManagerFactory::new(), device).unwrap();
+  model.set_params(&vec![testing::from_rows(&[[2.0]])
+                         , utils::constant(Dim4::new(&[1, 1, 1, 1]), DType::F32, 0.0)]);
+
+  let reports = Arc::new(Mutex::new(Vec::new()));
+  let recorded = reports.clone();
+  let mut evaluator = monitor::StreamingEvaluator::new(2, 2);
+  evaluator.add_callback(Box::new(move |report: &monitor::StreamReport| {
+    recorded.lock().unwrap().push(report.clone());
+    report.num_batches < 4
+  }));
+  let zeros = utils::constant(Dim4::new(&[2, 1, 1, 1]), DType::F32, 0.0);
+  assert!(evaluator.update::<f32>(&mut model, &testing::from_rows(&[[1.0], [1.0]]), &zeros, device));
+  assert!(evaluator.update::<f32>(&mut model, &testing::from_rows(&[[2.0], [2.0]]), &zeros, device));
+  let report = evaluator.report();
+  assert_eq!((report.num_batches, report.num_samples), (2, 4));
+  assert!((report.values["loss"] - 5.0).abs() <= 1e-5, "window loss of {} vs 5", report.values["loss"]);
+
+  // the first batch drops out of the window & the callback stops the evaluation
+  assert!(evaluator.update::<f32>(&mut model, &testing::from_rows(&[[2.0], [2.0]]), &zeros, device));
+  assert!(!evaluator.update::<f32>(&mut model, &testing::from_rows(&[[2.0], [2.0]]), &zeros, device));
+  let reports = reports.lock().unwrap();
+  assert_eq!(reports.len(), 2);
+  assert!((reports[1].values["loss"] - 8.0).abs() <= 1e-5, "window loss of {} vs 8", reports[1].values["loss"]);
+}
+
+#[test]
+fn drift_detection(){
+  let gaussian = monitor::ReferenceDistribution::Gaussian(0.0, 1.0);
+  assert!((gaussian.cdf(1.0) - 0.8413).abs() <= 1e-4);
+
+  let reference: Vec<f32> = (0..1000).map(|x| x as f32).collect();
+  let reference = utils::vec_to_array::<f32>(reference, Dim4::new(&[1000, 1, 1, 1]));
+  let mut detector = monitor::DriftDetector::from_samples(&reference, 0.1, 0.25);
+
+  // the training distribution does not drift
+  let report = detector.check(&reference);
+  assert!(report[0].ks <= 2e-3 && report[0].psi <= 1e-3, "{:?}", report);
+
+  // half of the shifted batch is beyond the training range
+  let shifted = af::add(&reference, &500.0f32, false);
+  let report = detector.check(&shifted);
+  assert!((report[0].ks - 0.5).abs() <= 1e-2, "{:?}", report);
+  assert!(report[0].psi > 0.25, "{:?}", report);
+}
+
 
 /// helper to build a layer
 pub fn layer_builder<F>(layer_type: &str, idims: Dim4, hdims:Option<Dim4>, odims: Dim4, loss: &str
@@ -229,6 +2220,10 @@ where F: FnMut(&ParamManager, Box<Layer>)
       input_size: input_size,
       output_size: output_size,
     }),
+    "ordinal" => Box::new(layer::Ordinal {
+      input_size: input_size,
+      output_size: output_size,
+    }),
     //todo: lstm, etc
     _      => panic!("unknown layer type specified"),
   };
@@ -269,6 +2264,12 @@ where F: FnMut(&ParamManager, Box<Layer>)
       //let h_t = utils::constant(hdims, DType::F64, 0.5f32);
       //param_manager.set_recurrences(0, vec![h_t]);
     }
+    "ordinal" => {
+      param_manager.add_ordinal::<f64>(device_manager, device
+                                       , input_size, output_size
+                                       , w_init
+                                       , b_init);
+    }
     //todo: lstm, etc
     _      => panic!("unknown layer type specified"),
   };
@@ -421,6 +2422,31 @@ fn dense_forward(){
   });
 }
 
+#[test]
+fn testing_helpers(){
+  let a = testing::from_rows(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+  assert_eq!(a.dims()[0], 2);
+  assert_eq!(testing::to_rows(&a), vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+  let b = testing::from_rows(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.1]]);
+  assert!((testing::max_abs_diff(&a, &b) - 0.1).abs() < 1e-6);
+  assert_eq!(testing::first_mismatch(&a, &b, 1e-3, 0.0).map(|m| m.0), Some(5));
+  assert!(testing::all_close(&a, &b, 0.0, 0.02));
+
+  // dense layer with unit weights: every output is the sum of the inputs
+  let mut param_manager = ParamManager::default();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  param_manager.add_dense::<f32>(DeviceManagerFactory::new(), device, 3, 2, "linear", "ones", "zeros");
+  let dense = layer::Dense{input_size: 3, output_size: 2};
+  let sum = |rows: &Vec<Vec<f64>>| -> Vec<f64> { rows.iter().map(|r| r.iter().sum()).collect() };
+  testing::check_forward(&dense, param_manager.get_params(0), &a, |x| {
+    sum(x).iter().map(|&s| vec![s, s]).collect()
+  }, 1e-6);
+  let delta = testing::from_rows(&[[1.0, -1.0], [0.5, 0.5]]);
+  testing::check_backward(&dense, param_manager.get_params(0), &a, &delta, |_, d| {
+    sum(d).iter().map(|&s| vec![s, s, s]).collect()
+  }, 1e-6);
+}
+
 #[test]
 fn dense_backward() {
   timeit! ({
@@ -480,6 +2506,84 @@ fn rnn_backward() {
   });
 }
 
+#[test]
+fn ordinal_forward(){
+  let idims = Dim4::new(&[1, 5, 1, 1]);
+  let odims = Dim4::new(&[1, 3, 1, 1]); // 4 ranks --> 3 thresholds
+  layer_forward_helper("ordinal", idims, None, odims, "l2", 1e-4
+                       , "linear"                              // activation [unused]
+                       , "ones"                                // weight init
+                       , "zeros"                               // bias init
+                       , vec![-0.01, 0.00, 1.10, 2.20, 3.15]   //input
+                       , vec![6.4400, 6.4400, 6.4400]);        //target
+}
+
+#[test]
+fn layer_dtype(){
+  let mut param_manager = ParamManager::default();
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  param_manager.add_dense::<f32>(device_manager.clone(), device, 5, 3, "tanh", "ones", "zeros");
+  param_manager.add_dense::<f64>(device_manager, device, 3, 2, "softmax", "ones", "zeros");
+  assert_eq!(param_manager.get_dtype(0), DType::F32);
+  assert_eq!(param_manager.get_dtype(1), DType::F64);
+
+  assert_eq!(utils::get_dtype("f64").unwrap(), DType::F64);
+  assert!(utils::get_dtype("f16").is_err());
+}
+
+#[test]
+fn checkpoint_roundtrip(){
+  let device_manager = DeviceManagerFactory::new();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let mut trained = ParamManager::default();
+  trained.add_dense::<f32>(device_manager.clone(), device, 5, 3, "tanh", "glorot_uniform", "ones");
+  let path = env::temp_dir().join("hal_checkpoint_roundtrip.ckpt");
+  let path = path.to_str().unwrap();
+  checkpoint::save(&checkpoint::Checkpoint::from_params(&trained, 7), path).unwrap();
+
+  let mut restored = ParamManager::default();
+  restored.add_dense::<f32>(device_manager.clone(), device, 5, 3, "tanh", "zeros", "zeros");
+  let loaded = checkpoint::load(path).unwrap();
+  assert_eq!(loaded.epoch, 7);
+  assert!(loaded.environment.is_none());
+  loaded.restore(&restored).unwrap();
+  for (a, b) in trained.get_all_arrays().iter().zip(restored.get_all_arrays().iter()) {
+    let diff = af::max_all(&af::abs(&af::sub(a, b, false))).0;
+    assert!(diff <= 1e-6, "restored params differ by {}", diff);
+  }
+
+  // a different architecture is refused
+  let mut other = ParamManager::default();
+  other.add_dense::<f32>(device_manager, device, 4, 3, "tanh", "zeros", "zeros");
+  assert!(loaded.restore(&other).is_err());
+}
+
+#[test]
+fn checkpoint_environment(){
+  let json = r#"{ "loss": "mse", "optimizer": "sgd",
+                  "layers": [{ "layer": "dense", "params": { "input_size": 4, "output_size": 2
+                                                           , "activation": "tanh"
+                                                           , "w_init": "glorot_uniform"
+                                                           , "b_init": "zeros" } }] }"#;
+  let config = ModelConfig::from_json(json).unwrap();
+  let device = Device{backend: Backend::DEFAULT, id: 0};
+  let trained = config.build(DeviceManagerFactory::new(), device).unwrap();
+  let path = env::temp_dir().join("hal_checkpoint_environment.ckpt");
+  let path = path.to_str().unwrap();
+  random::set_seed(42);
+  trained.save_checkpoint(path, 3).unwrap();
+
+  let mut restored = config.build(DeviceManagerFactory::new(), device).unwrap();
+  assert!(restored.get_environment().is_none());
+  assert_eq!(restored.load_checkpoint(path).unwrap(), 3);
+  let environment = restored.get_environment().unwrap();
+  assert_eq!(environment.hal_version, env!("CARGO_PKG_VERSION"));
+  assert!(environment.devices.len() > 0);
+  assert_eq!(environment.generator, "counter");
+  assert_eq!(environment.seed, Some(42));
+}
+
 #[test]
 fn unitary_forward() {
   let idims = Dim4::new(&[1, 10, 1, 1]);